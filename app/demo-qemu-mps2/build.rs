@@ -0,0 +1,13 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+fn main() {
+    // The kernel linker script includes `device.x`, which is normally provided
+    // by an svd2rust-generated PAC. There's no PAC for the emulated board --
+    // cortex-m-rt's generic vector table covers its interrupts -- so provide
+    // an empty one.
+    let out = build_util::out_dir();
+    std::fs::write(out.join("device.x"), "").unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+}
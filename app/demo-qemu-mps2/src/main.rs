@@ -0,0 +1,27 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+#[cfg(not(any(feature = "panic-semihosting", feature = "panic-halt")))]
+compile_error!("Must have one of panic-{semihosting,halt} enabled");
+
+// Panic behavior controlled by Cargo features:
+#[cfg(feature = "panic-halt")]
+extern crate panic_halt;
+#[cfg(feature = "panic-semihosting")]
+extern crate panic_semihosting; // output appears on QEMU's stdout
+
+use cortex_m_rt::entry;
+
+#[entry]
+fn main() -> ! {
+    // QEMU's MPS2 boards clock the CPU (and thus SysTick) at 25 MHz.
+    const CYCLES_PER_MS: u32 = 25_000;
+
+    const TICK_DIVISOR: u32 = kern::startup::tick_divisor(CYCLES_PER_MS);
+
+    unsafe { kern::startup::start_kernel(TICK_DIVISOR) }
+}
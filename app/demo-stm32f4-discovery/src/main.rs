@@ -33,5 +33,7 @@ fn main() -> ! {
     #[cfg(feature = "stm32f4")]
     const CYCLES_PER_MS: u32 = 16_000;
 
-    unsafe { kern::startup::start_kernel(CYCLES_PER_MS) }
+    const TICK_DIVISOR: u32 = kern::startup::tick_divisor(CYCLES_PER_MS);
+
+    unsafe { kern::startup::start_kernel(TICK_DIVISOR) }
 }
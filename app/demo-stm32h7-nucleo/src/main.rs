@@ -152,7 +152,9 @@ fn main() -> ! {
 
     kern::profiling::configure_events_table(&PROFILING);
 
-    unsafe { kern::startup::start_kernel(CYCLES_PER_MS) }
+    const TICK_DIVISOR: u32 = kern::startup::tick_divisor(CYCLES_PER_MS);
+
+    unsafe { kern::startup::start_kernel(TICK_DIVISOR) }
 }
 
 fn syscall_enter(nr: u32) {
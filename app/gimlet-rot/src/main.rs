@@ -68,6 +68,8 @@ fn main() -> ! {
         let syscon = &*device::SYSCON::ptr();
         syscon.traceclkdiv.modify(|_, w| w.div().bits(div));
 
-        kern::startup::start_kernel(cycles_per_ms * 1_000)
+        kern::startup::start_kernel(kern::startup::tick_divisor(
+            cycles_per_ms * 1_000,
+        ))
     }
 }
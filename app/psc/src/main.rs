@@ -32,7 +32,9 @@ fn main() -> ! {
 
     const CYCLES_PER_MS: u32 = 400_000;
 
-    unsafe { kern::startup::start_kernel(CYCLES_PER_MS) }
+    const TICK_DIVISOR: u32 = kern::startup::tick_divisor(CYCLES_PER_MS);
+
+    unsafe { kern::startup::start_kernel(TICK_DIVISOR) }
 }
 
 fn system_init() {
@@ -18,6 +18,9 @@ pub struct KernelConfig {
 
     /// Interrupts hooked by the application, keyed by IRQ number.
     pub irqs: BTreeMap<u32, InterruptConfig>,
+
+    /// Kernel timer ticks per second.
+    pub tick_hz: u32,
 }
 
 /// Configuration for a single hooked interrupt.
@@ -46,6 +49,9 @@ pub struct InterruptConfig {
 /// Record describing a single task.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TaskConfig {
+    /// Name of the task, as given in `app.toml`.
+    pub name: String,
+
     /// Named memory regions that this task has exclusive access to, keyed by
     /// name.
     ///
@@ -71,6 +77,10 @@ pub struct TaskConfig {
 
     /// Should this task be started automatically on boot?
     pub start_at_boot: bool,
+
+    /// Indices of tasks that this task may send or post to, or `None` if it
+    /// may message any task.
+    pub ipc_targets: Option<BTreeSet<usize>>,
 }
 
 /// An address within an owned region of memory.
@@ -5,6 +5,7 @@
 use anyhow::{anyhow, Context, Result};
 use serde::de::DeserializeOwned;
 use std::collections::BTreeMap;
+use std::fmt::Write;
 
 /// Reads the given environment variable and marks that it's used
 ///
@@ -53,7 +54,8 @@ pub fn has_feature(s: &str) -> bool {
 /// rustc's standard environment.
 ///
 /// This will set one of `cfg(armv6m)`, `cfg(armv7m)`, or `cfg(armv8m)`
-/// depending on the value of the `TARGET` environment variable.
+/// depending on the value of the `TARGET` environment variable. Hosted targets
+/// (used by the kernel simulator) get none of them.
 pub fn expose_m_profile() {
     let target = crate::target();
 
@@ -64,6 +66,9 @@ pub fn expose_m_profile() {
         println!("cargo:rustc-cfg=armv7m");
     } else if target.starts_with("thumbv8m") {
         println!("cargo:rustc-cfg=armv8m");
+    } else if crate::target_os() != "none" {
+        // Hosted build for the kernel simulator; there's no M-profile to
+        // expose.
     } else {
         println!("Don't know the target {}", target);
         std::process::exit(1);
@@ -113,6 +118,106 @@ pub fn task_maybe_config<T: DeserializeOwned>() -> Result<Option<T>> {
     toml_from_env("HUBRIS_TASK_CONFIG")
 }
 
+/// Returns the bits allocated to tasks' named notifications, as a map from task
+/// name to notification name to bit number. Tasks that don't declare any
+/// `notifications` in `app.toml` are left out.
+pub fn notifications() -> Result<BTreeMap<String, BTreeMap<String, u8>>> {
+    Ok(toml_from_env("HUBRIS_NOTIFICATIONS")?.unwrap_or_default())
+}
+
+/// Generates `notifications.rs` in `OUT_DIR`, containing `FOO_BIT` and
+/// `FOO_MASK` constants for each notification `foo` that the current task
+/// declares in `app.toml`. Tasks typically include it as
+///
+/// ```ignore
+/// mod notifications {
+///     include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
+/// }
+/// ```
+pub fn generate_notifications() -> Result<()> {
+    let task_name = crate::env_var("HUBRIS_TASK_NAME")?;
+    let mut bits = notifications()?
+        .remove(&task_name)
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<Vec<_>>();
+    bits.sort_by_key(|&(_, bit)| bit);
+
+    let mut out = String::new();
+    for (name, bit) in bits {
+        let name = name.to_uppercase().replace('-', "_");
+        writeln!(out, "pub const {name}_BIT: u8 = {bit};")?;
+        writeln!(out, "pub const {name}_MASK: u32 = 1 << {name}_BIT;")?;
+    }
+    std::fs::write(out_dir().join("notifications.rs"), out)
+        .context("writing notifications.rs")
+}
+
+/// Returns the regions from the `[shared]` section of `app.toml`, as a map
+/// from region name to the names of the tasks that can access it, each with
+/// whether it can write to the region.
+pub fn shared_regions() -> Result<BTreeMap<String, BTreeMap<String, bool>>> {
+    Ok(toml_from_env("HUBRIS_SHARED_REGIONS")?.unwrap_or_default())
+}
+
+/// Generates `shared_regions.rs` in `OUT_DIR`, containing a function for each
+/// region in the `[shared]` section of `app.toml` that the current task can
+/// access. The function for region `foo-bar` is `foo_bar()`, and returns a
+/// `userlib::shared::SharedRegion`. Tasks typically include it as
+///
+/// ```ignore
+/// mod shared_regions {
+///     include!(concat!(env!("OUT_DIR"), "/shared_regions.rs"));
+/// }
+/// ```
+pub fn generate_shared_regions() -> Result<()> {
+    let task_name = crate::env_var("HUBRIS_TASK_NAME")?;
+
+    let mut out = String::new();
+    for (name, tasks) in shared_regions()? {
+        let writable = match tasks.get(&task_name) {
+            Some(&w) => w,
+            None => continue,
+        };
+        let ident = name.replace('-', "_");
+        let sym = ident.to_uppercase();
+        writeln!(
+            out,
+            "pub fn {ident}() -> userlib::shared::SharedRegion {{
+                // These symbols have no space allocated; `()` isn't a C type,
+                // but we only ever take their addresses.
+                #[allow(improper_ctypes)]
+                extern \"C\" {{
+                    static __SHARED_{sym}_BASE: ();
+                    static __SHARED_{sym}_END: ();
+                }}
+                // Safety: xtask defines these symbols at the bounds of the
+                // region when it links the task, and the kernel maps the
+                // region into the task as `app.toml` says.
+                unsafe {{
+                    userlib::shared::SharedRegion::from_raw(
+                        core::ptr::addr_of!(__SHARED_{sym}_BASE) as usize,
+                        core::ptr::addr_of!(__SHARED_{sym}_END) as usize,
+                        {writable},
+                    )
+                }}
+            }}"
+        )?;
+    }
+    std::fs::write(out_dir().join("shared_regions.rs"), out)
+        .context("writing shared_regions.rs")
+}
+
+/// Returns the kernel timer's tick rate, in ticks per second, as set by
+/// `tick-hz` in the `[kernel]` section of `app.toml`. Builds outside of xtask
+/// get the default of 1000.
+pub fn tick_hz() -> Result<u32> {
+    match crate::env_var("HUBRIS_TICK_HZ") {
+        Ok(hz) => hz.parse().context("parsing HUBRIS_TICK_HZ"),
+        Err(_) => Ok(1000),
+    }
+}
+
 /// Returns a map of task names to their IDs.
 pub fn task_ids() -> TaskIds {
     let tasks = crate::env_var("HUBRIS_TASKS").expect("missing HUBRIS_TASKS");
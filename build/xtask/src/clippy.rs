@@ -73,17 +73,21 @@ pub fn run(
             let kconfig = crate::dist::make_kconfig(
                 &toml,
                 &allocs.tasks,
+                &allocs.shared,
                 &entry_points,
                 &toml.image_names[0],
                 &None,
             )?;
             let kconfig = ron::ser::to_string(&kconfig)?;
+            // Uncompressed SEC1 encoding of a dummy public key
+            let public_key = format!("04{}", "00".repeat(64));
 
             toml.kernel_build_config(
                 verbose,
                 &[
                     ("HUBRIS_KCONFIG", &kconfig),
                     ("HUBRIS_IMAGE_ID", "1234"), // dummy image ID
+                    ("HUBRIS_IMAGE_PUBLIC_KEY", &public_key),
                 ],
                 None,
             )
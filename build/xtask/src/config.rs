@@ -12,6 +12,7 @@ use indexmap::IndexMap;
 use serde::Deserialize;
 
 use crate::auxflash::{build_auxflash, AuxFlash, AuxFlashData};
+use crate::inherit::AppToml;
 use lpc55_areas::{
     BootSpeed, CFPAPage, CMPAPage, DefaultIsp, RKTHRevoke, SecureBootCfg,
 };
@@ -44,6 +45,8 @@ struct RawConfig {
     #[serde(default)]
     extratext: IndexMap<String, Peripheral>,
     #[serde(default)]
+    shared: IndexMap<String, SharedRegion>,
+    #[serde(default)]
     config: Option<ordered_toml::Value>,
     #[serde(default)]
     secure_task: Option<String>,
@@ -68,23 +71,40 @@ pub struct Config {
     pub tasks: IndexMap<String, Task>,
     pub peripherals: IndexMap<String, Peripheral>,
     pub extratext: IndexMap<String, Peripheral>,
+    pub shared: IndexMap<String, SharedRegion>,
     pub config: Option<ordered_toml::Value>,
     pub buildhash: u64,
     pub app_toml_path: PathBuf,
+    /// The `app.toml` as built, with anything it inherits merged in.
+    pub resolved_toml: String,
     pub secure_task: Option<String>,
     pub auxflash: Option<AuxFlashData>,
+    /// Bits allocated to tasks' named notifications, keyed by task name and
+    /// then notification name. Tasks without any are left out.
+    pub notifications: BTreeMap<String, BTreeMap<String, u8>>,
 }
 
 impl Config {
     pub fn from_file(cfg: &Path) -> Result<Self> {
-        let cfg_contents = std::fs::read(&cfg)?;
-        let toml: RawConfig = toml::from_slice(&cfg_contents)?;
+        let app_toml = AppToml::load(cfg)?;
+        let toml: RawConfig = if app_toml.inherits() {
+            ordered_toml::Value::Table(app_toml.table.clone())
+                .try_into()
+                .with_context(|| format!("resolving {}", cfg.display()))?
+        } else {
+            toml::from_slice(&app_toml.files[0].1)?
+        };
         if toml.tasks.contains_key("kernel") {
             bail!("'kernel' is reserved and cannot be used as a task name");
         }
+        if toml.kernel.tick_hz == 0 {
+            bail!("kernel tick-hz must be nonzero");
+        }
 
         let mut hasher = DefaultHasher::new();
-        hasher.write(&cfg_contents);
+        for (_, contents) in &app_toml.files {
+            hasher.write(contents);
+        }
 
         // The app.toml must include a `chip` key, which defines the peripheral
         // register map in a separate file.  We load it then accumulate that
@@ -114,6 +134,8 @@ impl Config {
 
         let buildhash = hasher.finish();
 
+        let notifications = allocate_notifications(&toml.tasks)?;
+
         let img_names = if toml.image_names.is_empty() {
             vec!["default".to_string()]
         } else {
@@ -127,7 +149,7 @@ impl Config {
             None => None,
         };
 
-        Ok(Config {
+        let config = Config {
             name: toml.name,
             target: toml.target,
             board: toml.board,
@@ -144,12 +166,141 @@ impl Config {
             tasks: toml.tasks,
             peripherals,
             extratext: toml.extratext,
+            shared: toml.shared,
             config: toml.config,
             auxflash,
             buildhash,
             app_toml_path: cfg.to_owned(),
+            resolved_toml: app_toml.to_toml(),
             secure_task: toml.secure_task,
-        })
+            notifications,
+        };
+        config.check_shared_regions()?;
+        Ok(config)
+    }
+
+    /// Checks the `[shared]` section: region names must be usable as
+    /// identifiers, sizes must suit the MPU, and the tasks listed must exist.
+    fn check_shared_regions(&self) -> Result<()> {
+        // Names become identifiers with '-' replaced, so they have to be
+        // unique after that.
+        let mut idents = BTreeMap::new();
+        for (name, region) in &self.shared {
+            if let Some(other) = idents.insert(name.replace('-', "_"), name) {
+                bail!("shared region names '{other}' and '{name}' clash");
+            }
+            if !name.starts_with(|c: char| c.is_ascii_lowercase())
+                || !name.chars().all(|c| {
+                    c.is_ascii_lowercase()
+                        || c.is_ascii_digit()
+                        || c == '-'
+                        || c == '_'
+                })
+            {
+                bail!(
+                    "shared region name '{name}' must be lowercase letters, \
+                     digits, '-' and '_', starting with a letter"
+                );
+            }
+            let size = u64::from(region.size);
+            let suggested =
+                self.mpu_alignment().suggest_memory_region_size(size);
+            // Neither MPU can map a region smaller than 32 bytes.
+            if size < 32 || suggested != size {
+                bail!(
+                    "shared region '{name}' has size {size}, which this \
+                     target's MPU can't map; try {}",
+                    suggested.max(32)
+                );
+            }
+            if !self.outputs.contains_key(&region.memory) {
+                bail!(
+                    "shared region '{name}' is in memory '{}', which this \
+                     chip doesn't have",
+                    region.memory
+                );
+            }
+            if region.tasks.is_empty() {
+                bail!("shared region '{name}' isn't shared with any tasks");
+            }
+            for task in region.tasks.keys() {
+                if !self.tasks.contains_key(task) {
+                    bail!(
+                        "shared region '{name}': {}",
+                        self.task_name_suggestion(task)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the shared regions that each task can access, as a map from
+    /// region name to task name to whether the task can write to it.
+    pub fn shared_region_access(
+        &self,
+    ) -> BTreeMap<String, BTreeMap<String, bool>> {
+        self.shared
+            .iter()
+            .map(|(name, region)| {
+                let tasks = region
+                    .tasks
+                    .iter()
+                    .map(|(task, access)| {
+                        (task.clone(), *access == SharedAccess::ReadWrite)
+                    })
+                    .collect();
+                (name.clone(), tasks)
+            })
+            .collect()
+    }
+
+    /// Returns the tasks that `name` may send or post to beyond its task
+    /// slots: its explicit `ipc-grants`, plus those in
+    /// [`Config::config_ipc_grants`].
+    pub fn ipc_grants(&self, name: &str) -> Result<Vec<&str>> {
+        let mut grants: Vec<&str> = self.tasks[name]
+            .ipc_grants
+            .iter()
+            .map(String::as_str)
+            .collect();
+        for target in self.config_ipc_grants(name)? {
+            if !grants.contains(&target) {
+                grants.push(target);
+            }
+        }
+        Ok(grants)
+    }
+
+    /// Returns the tasks that `name` is granted by the rest of the config
+    /// rather than by its `ipc-grants`. Right now that's just the network
+    /// stack, which notifies the owner of each socket in
+    /// `[config.net.sockets]`. These are only ever posted to, so they're
+    /// exempt from the priority checks.
+    pub fn config_ipc_grants(&self, name: &str) -> Result<Vec<&str>> {
+        let mut grants = vec![];
+        if self.tasks[name].name != "task-net" {
+            return Ok(grants);
+        }
+        let sockets = self
+            .config
+            .as_ref()
+            .and_then(|c| c.get("net"))
+            .and_then(|c| c.get("sockets"))
+            .and_then(|c| c.as_table());
+        for (socket, rec) in sockets.into_iter().flatten() {
+            let owner = rec
+                .get("owner")
+                .and_then(|o| o.get("name"))
+                .and_then(|o| o.as_str())
+                .ok_or_else(|| {
+                    anyhow!("net socket {socket} has no owner name")
+                })?;
+            if !grants.contains(&owner) {
+                grants.push(owner);
+            }
+        }
+        Ok(grants)
     }
 
     pub fn task_name_suggestion(&self, name: &str) -> String {
@@ -219,6 +370,10 @@ impl Config {
         );
         env.insert("HUBRIS_BUILD_EPOCH".to_string(), format!("{}", self.epoch));
         env.insert("HUBRIS_BOARD".to_string(), self.board.to_string());
+        env.insert(
+            "HUBRIS_TICK_HZ".to_string(),
+            format!("{}", self.kernel.tick_hz),
+        );
         env.insert(
             "HUBRIS_APP_TOML".to_string(),
             app_toml_path.to_str().unwrap().to_string(),
@@ -256,6 +411,16 @@ impl Config {
             env.insert("HUBRIS_APP_CONFIG".to_string(), app_config);
         }
 
+        if !self.notifications.is_empty() {
+            let notifications = toml::to_string(&self.notifications).unwrap();
+            env.insert("HUBRIS_NOTIFICATIONS".to_string(), notifications);
+        }
+
+        if !self.shared.is_empty() {
+            let shared = toml::to_string(&self.shared_region_access()).unwrap();
+            env.insert("HUBRIS_SHARED_REGIONS".to_string(), shared);
+        }
+
         let out_path = Path::new("")
             .join(&self.target)
             .join("release")
@@ -496,7 +661,15 @@ impl std::fmt::Display for SigningMethod {
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct RoTMfgSettings {
+    /// Certificate chains for the ROM's secure boot. If there aren't any,
+    /// the image isn't signed for the ROM.
+    #[serde(default)]
     pub certs: Vec<lpc55_sign::signed_image::CertChain>,
+    /// PKCS#8 DER P-256 private key, relative to the app.toml. Images are
+    /// signed with it, and stage0 is built to only boot images signed with
+    /// it.
+    #[serde(default)]
+    pub image_key: Option<PathBuf>,
     #[serde(default)]
     pub enable_secure_boot: bool,
     #[serde(default)]
@@ -559,6 +732,13 @@ pub struct Kernel {
     pub stacksize: Option<u32>,
     #[serde(default)]
     pub features: Vec<String>,
+    /// Kernel timer ticks per second.
+    #[serde(default = "default_tick_hz")]
+    pub tick_hz: u32,
+}
+
+fn default_tick_hz() -> u32 {
+    1000
 }
 
 fn default_name() -> String {
@@ -586,7 +766,10 @@ pub struct Output {
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Task {
     pub name: String,
-    #[serde(default)]
+    /// Upper bounds on the task's memory regions, by memory name. Regions are
+    /// sized to fit what the task uses either way; `"auto"`, like leaving
+    /// this out, sets no bounds.
+    #[serde(default, deserialize_with = "deserialize_max_sizes")]
     pub max_sizes: IndexMap<String, u32>,
     pub priority: u8,
     pub stacksize: Option<u32>,
@@ -597,17 +780,37 @@ pub struct Task {
     #[serde(default)]
     pub features: Vec<String>,
     #[serde(default)]
-    pub interrupts: IndexMap<String, u32>,
+    pub notifications: Vec<String>,
+    #[serde(default)]
+    pub interrupts: IndexMap<String, NotificationRef>,
     #[serde(default)]
     pub sections: IndexMap<String, String>,
     #[serde(default, deserialize_with = "deserialize_task_slot")]
     pub task_slots: IndexMap<String, String>,
+    /// Tasks this task may send or post to beyond its `task_slots`, or `"*"`
+    /// to let it message any task.
+    #[serde(default)]
+    pub ipc_grants: Vec<String>,
+    /// Tasks in `task_slots` that this task may send to even though they
+    /// don't run at a higher priority, or even though the slot closes a loop
+    /// of task slots. Each one should say why in a comment.
+    #[serde(default)]
+    pub ipc_exceptions: Vec<String>,
     #[serde(default)]
     pub config: Option<ordered_toml::Value>,
     #[serde(default)]
     pub uses_secure_entry: bool,
 }
 
+/// The notification an interrupt posts to its task: either a raw mask, or the
+/// name of one of the task's `notifications`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum NotificationRef {
+    Mask(u32),
+    Name(String),
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Peripheral {
@@ -617,6 +820,170 @@ pub struct Peripheral {
     pub interrupts: BTreeMap<String, u32>,
 }
 
+/// A buffer in RAM that several tasks can map, declared in the `[shared]`
+/// section of `app.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SharedRegion {
+    /// Size in bytes. This must suit the MPU, just as task regions must.
+    pub size: u32,
+    /// Which of the chip's memories to put the region in.
+    #[serde(default = "SharedRegion::default_memory")]
+    pub memory: String,
+    /// Tasks that can access the region, and how.
+    pub tasks: IndexMap<String, SharedAccess>,
+}
+
+impl SharedRegion {
+    fn default_memory() -> String {
+        "ram".to_string()
+    }
+}
+
+/// How a task can access a `SharedRegion`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SharedAccess {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Allocates a bit for each task's named notifications.
+///
+/// Interrupts given a raw mask keep the bits they asked for (several
+/// interrupts may share a bit), and named notifications get the lowest bits
+/// left over, in the order they're declared. No two named notifications share
+/// a bit, so timers and posts can't collide with interrupts or each other.
+///
+/// The kernel posts bit 0 to the supervisor when a task faults. The supervisor
+/// can name that bit `fault`; otherwise it's unavailable.
+///
+/// The supervisor can also be configured to post raw bit numbers to tasks on
+/// state change (see `state_change_bits`). Those bits are treated like
+/// interrupt masks, except that they mustn't overlap the task's interrupts.
+fn allocate_notifications(
+    tasks: &IndexMap<String, Task>,
+) -> Result<BTreeMap<String, BTreeMap<String, u8>>> {
+    let state_change = state_change_bits(tasks)?;
+
+    let mut out = BTreeMap::new();
+    for (i, (name, task)) in tasks.iter().enumerate() {
+        let supervisor = i == 0;
+
+        let mut taken = 0u32;
+        for (irq, n) in &task.interrupts {
+            match n {
+                NotificationRef::Mask(mask) => taken |= mask,
+                NotificationRef::Name(n) if !task.notifications.contains(n) => {
+                    bail!(
+                        "task {name}: interrupt {irq} posts notification \
+                         '{n}', which isn't in the task's notifications"
+                    );
+                }
+                NotificationRef::Name(_) => (),
+            }
+        }
+        if let Some(&mask) = state_change.get(name.as_str()) {
+            if mask & taken != 0 {
+                bail!(
+                    "task {name}: the supervisor's on-state-change \
+                     bit-number (mask {mask:#x}) is also used by the task's \
+                     interrupts (mask {taken:#x}); use a named notification \
+                     instead"
+                );
+            }
+            taken |= mask;
+        }
+        if supervisor {
+            if taken & 1 != 0 {
+                bail!(
+                    "task {name}: the supervisor's notification bit 0 is \
+                     reserved for task faults"
+                );
+            }
+            taken |= 1;
+        }
+
+        let mut bits = BTreeMap::new();
+        for n in &task.notifications {
+            if !n.starts_with(|c: char| c.is_ascii_lowercase())
+                || !n.chars().all(|c| {
+                    c.is_ascii_lowercase()
+                        || c.is_ascii_digit()
+                        || c == '-'
+                        || c == '_'
+                })
+            {
+                bail!(
+                    "task {name}: notification name '{n}' must be lowercase \
+                     letters, digits, '-' and '_', starting with a letter"
+                );
+            }
+            let bit = if supervisor && n == "fault" {
+                0
+            } else {
+                let bit = (!taken).trailing_zeros();
+                if bit == 32 {
+                    bail!("task {name}: no notification bits left for '{n}'");
+                }
+                taken |= 1 << bit;
+                bit as u8
+            };
+            if bits.insert(n.clone(), bit).is_some() {
+                bail!("task {name}: notification '{n}' is declared twice");
+            }
+        }
+        if !bits.is_empty() {
+            out.insert(name.clone(), bits);
+        }
+    }
+    Ok(out)
+}
+
+/// Returns the notification bits that the supervisor's `on-state-change`
+/// config posts to each task by raw `bit-number`, as a mask per task.
+///
+/// Jefe's build script checks the rest of that config, but it can't see the
+/// other tasks' notifications, so the overlap check has to happen here.
+fn state_change_bits(
+    tasks: &IndexMap<String, Task>,
+) -> Result<BTreeMap<&str, u32>> {
+    let mut out = BTreeMap::new();
+    let Some((supervisor, task)) = tasks.first() else {
+        return Ok(out);
+    };
+    let Some(table) = task
+        .config
+        .as_ref()
+        .and_then(|c| c.get("on-state-change"))
+        .and_then(|c| c.as_table())
+    else {
+        return Ok(out);
+    };
+    for (target, rec) in table {
+        let Some(bit) = rec.get("bit-number") else {
+            continue;
+        };
+        let Some((target, _)) = tasks.get_key_value(target.as_str()) else {
+            bail!(
+                "task {supervisor}: on-state-change names task {target}, \
+                 which doesn't exist"
+            );
+        };
+        let bit = bit
+            .as_integer()
+            .filter(|b| (0..32).contains(b))
+            .ok_or_else(|| {
+                anyhow!(
+                    "task {supervisor}: on-state-change bit-number for \
+                     {target} must be between 0 and 31"
+                )
+            })?;
+        *out.entry(target.as_str()).or_default() |= 1u32 << bit;
+    }
+    Ok(out)
+}
+
 /// In the common case, task slots map back to a task of the same name (e.g.
 /// `gpio_driver`, `rcc_driver`).  However, certain tasks need generic task
 /// slot names, e.g. they'll have a task slot named `spi_driver` which will
@@ -666,6 +1033,36 @@ where
     Ok(out)
 }
 
+/// Parses `max-sizes`, which is either a table of sizes or `"auto"`:
+///
+/// ```toml
+/// max-sizes = {flash = 16384, ram = 4096}
+/// max-sizes = "auto"
+/// ```
+///
+/// `"auto"` comes out as an empty table.
+fn deserialize_max_sizes<'de, D>(
+    deserializer: D,
+) -> Result<IndexMap<String, u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(untagged)]
+    enum MaxSizes {
+        Auto(String),
+        Limits(IndexMap<String, u32>),
+    }
+    match serde::Deserialize::deserialize(deserializer)? {
+        MaxSizes::Auto(s) if s == "auto" => Ok(IndexMap::new()),
+        MaxSizes::Auto(s) => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&s),
+            &"\"auto\" or a table of sizes",
+        )),
+        MaxSizes::Limits(m) => Ok(m),
+    }
+}
+
 /// Stores arguments and environment variables to run on a particular task.
 pub struct BuildConfig<'a> {
     pub crate_name: String,
@@ -730,3 +1127,46 @@ impl BuildConfig<'_> {
         cmd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Sizes {
+        #[serde(default, deserialize_with = "deserialize_max_sizes")]
+        max_sizes: IndexMap<String, u32>,
+    }
+
+    fn max_sizes(text: &str) -> Result<IndexMap<String, u32>, String> {
+        toml::from_str::<Sizes>(text)
+            .map(|s| s.max_sizes)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn max_sizes_limits() {
+        let sizes = max_sizes("max-sizes = {ram = 4096, flash = 8192}");
+        let sizes = sizes.unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [("ram".to_string(), 4096), ("flash".to_string(), 8192)]
+        );
+    }
+
+    #[test]
+    fn max_sizes_auto() {
+        assert!(max_sizes("max-sizes = \"auto\"").unwrap().is_empty());
+        assert!(max_sizes("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn max_sizes_errors() {
+        let e = max_sizes("max-sizes = \"big\"").unwrap_err();
+        assert!(e.contains("\"auto\" or a table of sizes"), "{}", e);
+        assert!(max_sizes("max-sizes = \"Auto\"").is_err());
+        assert!(max_sizes("max-sizes = 4096").is_err());
+        assert!(max_sizes("max-sizes = {ram = \"lots\"}").is_err());
+    }
+}
@@ -0,0 +1,1071 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Comparing two build archives, to show what a firmware release changes.
+//!
+//! Most of what we compare comes from the `app.toml` in each archive, which
+//! `dist` stores with anything it inherits merged in. The configuration
+//! generated for the kernel comes from `info/kconfig.ron`, and memory layout
+//! and Idol interfaces from `info/manifest.json`; older archives have neither.
+//! The image header is read out of the final image itself.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use colored::*;
+use ordered_toml::{value::Table, Value};
+use path_slash::PathBufExt;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use zerocopy::FromBytes;
+
+use build_kconfig::{KernelConfig, RegionConfig, SpecialRole};
+
+use crate::{config::Config, dist::Allocations, inherit};
+
+/// Where `Manifest` lives in a build archive.
+pub const MANIFEST_PATH: &str = "info/manifest.json";
+
+/// Where the kernel's generated configuration lives in a build archive.
+pub const KCONFIG_PATH: &str = "info/kconfig.ron";
+
+/// What `xtask diff` needs to know about a build that its `app.toml` doesn't
+/// say.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub allocations: Allocations,
+    /// SHA3-256 of each Idol interface a task could be built against, by task
+    /// name and then interface path.
+    pub idol: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Finds the Idol interfaces that each task could be built against, and
+/// hashes them.
+///
+/// Idol code is generated by build scripts, so we look for `.idol` paths in
+/// the build scripts of each task's crate and the crates it depends on. We
+/// ask cargo about dependencies with every feature turned on, so this may
+/// include interfaces that the task's features leave out.
+pub fn idol_hashes(
+    toml: &Config,
+) -> Result<BTreeMap<String, BTreeMap<String, String>>> {
+    let deps = BuildScripts::load()?;
+
+    // Many tasks share crates, so remember what each build script uses.
+    let mut scripts: HashMap<&Path, BTreeMap<String, String>> = HashMap::new();
+    let mut out = BTreeMap::new();
+    for (name, task) in &toml.tasks {
+        let paths = deps.scripts.get(&task.name).ok_or_else(|| {
+            anyhow!("no crate named {} for task {}", task.name, name)
+        })?;
+        let mut interfaces = BTreeMap::new();
+        for path in paths {
+            if !scripts.contains_key(path.as_path()) {
+                let found = scan_build_script(path, &deps.root)?;
+                scripts.insert(path, found);
+            }
+            interfaces.extend(scripts[path.as_path()].clone());
+        }
+        out.insert(name.clone(), interfaces);
+    }
+    Ok(out)
+}
+
+/// Where `BuildScripts` is cached between builds.
+const BUILD_SCRIPTS_CACHE: &str = "target/xtask-build-scripts.json";
+
+/// The build scripts each crate in the workspace could run: its own, and
+/// those of the workspace crates it depends on.
+///
+/// Working this out means asking cargo about the whole workspace, which takes
+/// a while, so we cache the answer. It can only change if `Cargo.lock` does,
+/// or one of the workspace's manifests, or a crate gains or loses a
+/// `build.rs`; the cache is keyed on all of those.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BuildScripts {
+    key: String,
+    /// Workspace root, which interface paths are relative to.
+    root: PathBuf,
+    /// Manifest of every crate in the workspace.
+    manifests: BTreeSet<PathBuf>,
+    /// Build script paths, by crate name.
+    scripts: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+impl BuildScripts {
+    fn load() -> Result<Self> {
+        let lockfile = std::fs::read("Cargo.lock").unwrap_or_default();
+        let cached = std::fs::read(BUILD_SCRIPTS_CACHE)
+            .ok()
+            .and_then(|c| serde_json::from_slice::<Self>(&c).ok());
+        if let Some(cached) = cached {
+            if cached.key == Self::key(&lockfile, &cached.manifests) {
+                return Ok(cached);
+            }
+        }
+
+        let mut fresh = Self::from_cargo()?;
+        fresh.key = Self::key(&lockfile, &fresh.manifests);
+        // This is only a cache, so failing to write it isn't fatal.
+        if let Ok(json) = serde_json::to_vec(&fresh) {
+            let _ = std::fs::write(BUILD_SCRIPTS_CACHE, json);
+        }
+        Ok(fresh)
+    }
+
+    fn key(lockfile: &[u8], manifests: &BTreeSet<PathBuf>) -> String {
+        let mut hash = Sha3_256::new();
+        hash.update(lockfile);
+        for m in manifests {
+            hash.update(m.to_string_lossy().as_bytes());
+            // A manifest that's gone hashes differently from an empty one.
+            match std::fs::read(m) {
+                Ok(contents) => {
+                    hash.update([1]);
+                    hash.update(contents);
+                }
+                Err(_) => hash.update([0]),
+            }
+            hash.update([m.with_file_name("build.rs").exists() as u8]);
+        }
+        format!("{:x}", hash.finalize())
+    }
+
+    fn from_cargo() -> Result<Self> {
+        use cargo_metadata::{CargoOpt, DependencyKind, MetadataCommand};
+
+        let metadata = MetadataCommand::new()
+            .features(CargoOpt::AllFeatures)
+            .exec()
+            .context("running cargo metadata")?;
+        let resolve = metadata.resolve.as_ref().ok_or_else(|| {
+            anyhow!("cargo metadata didn't resolve dependencies")
+        })?;
+        let nodes: HashMap<_, _> =
+            resolve.nodes.iter().map(|n| (&n.id, n)).collect();
+
+        let mut out = Self {
+            // Interface paths are canonical, so the root we strip from them
+            // must be.
+            root: metadata.workspace_root.canonicalize()?,
+            ..Self::default()
+        };
+        // Idol files only live in this repo, so we only care about its crates.
+        let local = metadata.packages.iter().filter(|p| p.source.is_none());
+        for package in local {
+            out.manifests.insert(package.manifest_path.clone());
+
+            let mut scripts = BTreeSet::new();
+            let mut seen = HashSet::new();
+            let mut todo = vec![&package.id];
+            while let Some(id) = todo.pop() {
+                if !seen.insert(id) {
+                    continue;
+                }
+                let package = &metadata[id];
+                if package.source.is_some() {
+                    continue;
+                }
+                for target in &package.targets {
+                    if target.kind.iter().any(|k| k == "custom-build") {
+                        scripts.insert(target.src_path.clone());
+                    }
+                }
+                for dep in &nodes[id].deps {
+                    if dep
+                        .dep_kinds
+                        .iter()
+                        .any(|k| k.kind == DependencyKind::Normal)
+                    {
+                        todo.push(&dep.pkg);
+                    }
+                }
+            }
+            out.scripts.insert(package.name.clone(), scripts);
+        }
+        Ok(out)
+    }
+}
+
+/// Hashes the `.idol` files named by string literals in a build script,
+/// which is how all of ours name them.
+fn scan_build_script(
+    path: &Path,
+    root: &Path,
+) -> Result<BTreeMap<String, String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    // Build scripts run in their crate's directory.
+    let dir = path.parent().unwrap();
+    let mut out = BTreeMap::new();
+    for literal in text.split('"').skip(1).step_by(2) {
+        if !literal.ends_with(".idol") {
+            continue;
+        }
+        let file = dir
+            .join(literal)
+            .canonicalize()
+            .with_context(|| format!("{} names {}", path.display(), literal))?;
+        let contents = std::fs::read(&file)?;
+        let name = file.strip_prefix(root).unwrap_or(&file).to_path_buf();
+        out.insert(
+            name.to_slash().unwrap(),
+            format!("{:x}", Sha3_256::digest(&contents)),
+        );
+    }
+    Ok(out)
+}
+
+/// The parts of a build archive that we compare.
+struct Build {
+    git_rev: String,
+    app: Table,
+    kconfig: Option<KernelConfig>,
+    manifest: Option<Manifest>,
+    header: Option<abi::ImageHeader>,
+    image: Vec<u8>,
+}
+
+impl Build {
+    fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        let mut zip = zip::ZipArchive::new(file)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let mut need = |name: &str| {
+            read(&mut zip, name)?.ok_or_else(|| {
+                anyhow!(
+                    "{} has no {}; is it a build archive?",
+                    path.display(),
+                    name
+                )
+            })
+        };
+
+        let git_rev = String::from_utf8_lossy(&need("git-rev")?).into_owned();
+        let app = ordered_toml::from_slice(&need("app.toml")?).with_context(
+            || format!("parsing app.toml in {}", path.display()),
+        )?;
+        let kernel = need("elf/kernel")?;
+        let srec = need("img/final.srec")?;
+        let image = need("img/final.bin")?;
+        let manifest = match read(&mut zip, MANIFEST_PATH)? {
+            Some(m) => Some(serde_json::from_slice(&m).with_context(|| {
+                format!("parsing {} in {}", MANIFEST_PATH, path.display())
+            })?),
+            None => None,
+        };
+        let kconfig = match read(&mut zip, KCONFIG_PATH)? {
+            Some(k) => Some(ron::de::from_bytes(&k).with_context(|| {
+                format!("parsing {} in {}", KCONFIG_PATH, path.display())
+            })?),
+            None => None,
+        };
+        let header = read_header(&kernel, &srec)?;
+
+        Ok(Self {
+            git_rev,
+            app,
+            kconfig,
+            manifest,
+            header,
+            image,
+        })
+    }
+
+    fn tasks(&self) -> Vec<(&String, &Table)> {
+        self.app
+            .get("tasks")
+            .and_then(Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .filter_map(|(name, t)| Some((name, t.as_table()?)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn read(
+    zip: &mut zip::ZipArchive<File>,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    match zip.by_name(name) {
+        Ok(mut f) => {
+            let mut data = vec![];
+            f.read_to_end(&mut data)?;
+            Ok(Some(data))
+        }
+        Err(zip::result::ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the image header out of the final image, finding it through the
+/// kernel's `__header_start` symbol. Returns `None` for images without one.
+fn read_header(kernel: &[u8], srec: &[u8]) -> Result<Option<abi::ImageHeader>> {
+    let elf = goblin::elf::Elf::parse(kernel)?;
+    let addr = elf
+        .syms
+        .iter()
+        .find(|s| elf.strtab.get_at(s.st_name) == Some("__header_start"))
+        .map(|s| s.st_value as u32);
+    let addr = match addr {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+
+    let header = addr..addr + core::mem::size_of::<abi::ImageHeader>() as u32;
+    let mut bytes = vec![0; header.len()];
+    let mut found = 0;
+    for record in srec::reader::read_records(std::str::from_utf8(srec)?) {
+        if let srec::Record::S3(data) = record? {
+            for (a, b) in (data.address.0..).zip(&data.data) {
+                if header.contains(&a) {
+                    bytes[(a - addr) as usize] = *b;
+                    found += 1;
+                }
+            }
+        }
+    }
+    if found < bytes.len() {
+        return Ok(None);
+    }
+    Ok(abi::ImageHeader::read_from(&bytes[..]))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Added(String),
+    Removed(String),
+    Changed(String),
+    Note(String),
+}
+
+pub fn run(old_path: &Path, new_path: &Path) -> Result<()> {
+    let old = Build::load(old_path)?;
+    let new = Build::load(new_path)?;
+
+    println!("{} {} ({})", "---".red(), old_path.display(), old.git_rev);
+    println!("{} {} ({})", "+++".green(), new_path.display(), new.git_rev);
+
+    print_section("Image", &diff_image(&old, &new));
+    print_section("Kernel", &diff_kernel(&old, &new));
+    print_section("Tasks", &diff_tasks(&old, &new));
+    print_section("Memory layout", &diff_memory(&old, &new));
+    print_section("Idol interfaces", &diff_idol(&old, &new));
+    print_section("Image header", &diff_header(&old, &new));
+    Ok(())
+}
+
+fn print_section(title: &str, changes: &[Change]) {
+    println!("\n{}", title.bold());
+    if changes.is_empty() {
+        println!("  no changes");
+    }
+    for c in changes {
+        match c {
+            Change::Added(s) => println!("  {} {}", "+".green(), s),
+            Change::Removed(s) => println!("  {} {}", "-".red(), s),
+            Change::Changed(s) => println!("  {} {}", "~".yellow(), s),
+            Change::Note(s) => println!("  {}", s.dimmed()),
+        }
+    }
+}
+
+/// Compares two maps of things that have been turned into text.
+fn diff_maps(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+    out: &mut Vec<Change>,
+) {
+    for (k, v) in old {
+        match new.get(k) {
+            None => out.push(Change::Removed(format!("{}: {}", k, v))),
+            Some(n) if n != v => {
+                out.push(Change::Changed(format!("{}: {} -> {}", k, v, n)))
+            }
+            Some(_) => (),
+        }
+    }
+    for (k, v) in new {
+        if !old.contains_key(k) {
+            out.push(Change::Added(format!("{}: {}", k, v)));
+        }
+    }
+}
+
+/// Flattens a table into dotted keys and values written as TOML.
+fn flatten(prefix: &str, table: &Table, out: &mut BTreeMap<String, String>) {
+    for (k, v) in table {
+        let key = if prefix.is_empty() {
+            k.clone()
+        } else {
+            format!("{}.{}", prefix, k)
+        };
+        match v {
+            Value::Table(t) => flatten(&key, t, out),
+            v => {
+                out.insert(key, inherit::to_inline(v));
+            }
+        }
+    }
+}
+
+fn diff_image(old: &Build, new: &Build) -> Vec<Change> {
+    let settings = |b: &Build| {
+        let mut out = BTreeMap::new();
+        for (k, v) in &b.app {
+            if !v.is_table() && !matches!(k.as_str(), "tasks" | "kernel") {
+                out.insert(k.clone(), inherit::to_inline(v));
+            }
+        }
+        out
+    };
+    let mut out = vec![];
+    diff_maps(&settings(old), &settings(new), &mut out);
+    if old.image.len() != new.image.len() {
+        out.push(Change::Changed(format!(
+            "contents: {} bytes -> {} bytes",
+            old.image.len(),
+            new.image.len()
+        )));
+    } else if old.image != new.image {
+        out.push(Change::Changed(format!(
+            "contents: same size ({} bytes), different bytes",
+            new.image.len()
+        )));
+    }
+    out
+}
+
+/// Compares the kernel's own settings from `app.toml` (its features, and what
+/// memory it needs), and the configuration `dist` generated for it from the
+/// rest of the app.
+fn diff_kernel(old: &Build, new: &Build) -> Vec<Change> {
+    let settings = |b: &Build| {
+        let mut out = BTreeMap::new();
+        if let Some(k) = b.app.get("kernel").and_then(Value::as_table) {
+            flatten("", k, &mut out);
+        }
+        out
+    };
+    let mut out = vec![];
+    diff_maps(&settings(old), &settings(new), &mut out);
+    match (&old.kconfig, &new.kconfig) {
+        (Some(o), Some(n)) => {
+            diff_maps(&kconfig_entries(o), &kconfig_entries(n), &mut out)
+        }
+        (o, n) => out.push(Change::Note(format!(
+            "generated configuration {}",
+            not_recorded_in(o.is_some(), n.is_some())
+        ))),
+    }
+    out
+}
+
+/// Turns a kernel configuration into text, keyed by what each entry
+/// describes. Tasks are referred to by name, rather than by the indices the
+/// kernel uses, so that moving a task doesn't make everything that refers to
+/// it look changed too.
+fn kconfig_entries(k: &KernelConfig) -> BTreeMap<String, String> {
+    let name = |i: usize| {
+        k.tasks
+            .get(i)
+            .map_or_else(|| format!("#{}", i), |t| t.name.clone())
+    };
+    let region = |r: &RegionConfig| {
+        let a = &r.attributes;
+        let mut attrs = String::new();
+        for (allowed, c) in [(a.read, 'r'), (a.write, 'w'), (a.execute, 'x')] {
+            attrs.push(if allowed { c } else { '-' });
+        }
+        match a.special_role {
+            Some(SpecialRole::Device) => attrs.push_str(" device"),
+            Some(SpecialRole::Dma) => attrs.push_str(" dma"),
+            None => (),
+        }
+        let end = u64::from(r.base) + u64::from(r.size);
+        format!("{:#010x}..{:#010x} {}", r.base, end, attrs)
+    };
+
+    let mut out = BTreeMap::new();
+    out.insert("tick_hz".to_string(), k.tick_hz.to_string());
+    for t in &k.tasks {
+        let key = |what: &str| format!("task {} {}", t.name, what);
+        out.insert(key("priority"), t.priority.to_string());
+        out.insert(key("start at boot"), t.start_at_boot.to_string());
+        for (n, r) in &t.owned_regions {
+            out.insert(key(&format!("region {}", n)), region(r));
+        }
+        if !t.shared_regions.is_empty() {
+            let shared: Vec<_> = t.shared_regions.iter().cloned().collect();
+            out.insert(key("shared regions"), shared.join(", "));
+        }
+        out.insert(
+            key("entry point"),
+            format!(
+                "{}+{:#x}",
+                t.entry_point.region_name, t.entry_point.offset
+            ),
+        );
+        out.insert(
+            key("initial stack"),
+            format!(
+                "{}+{:#x}",
+                t.initial_stack.region_name, t.initial_stack.offset
+            ),
+        );
+        let targets = match &t.ipc_targets {
+            None => "any task".to_string(),
+            Some(t) => {
+                t.iter().map(|&i| name(i)).collect::<Vec<_>>().join(", ")
+            }
+        };
+        out.insert(key("ipc targets"), targets);
+    }
+    for (n, r) in &k.shared_regions {
+        out.insert(format!("shared region {}", n), region(r));
+    }
+    for (irq, i) in &k.irqs {
+        out.insert(
+            format!("irq {}", irq),
+            format!(
+                "{}, notification {:#x}",
+                name(i.task_index),
+                i.notification
+            ),
+        );
+    }
+    out
+}
+
+fn diff_tasks(old: &Build, new: &Build) -> Vec<Change> {
+    let old_tasks = old.tasks();
+    let new_tasks = new.tasks();
+    let new_by_name: BTreeMap<_, _> = new_tasks.iter().cloned().collect();
+    let old_by_name: BTreeMap<_, _> = old_tasks.iter().cloned().collect();
+
+    let mut out = vec![];
+    for (name, task) in &old_tasks {
+        if !new_by_name.contains_key(name) {
+            out.push(Change::Removed(format!(
+                "{} ({})",
+                name,
+                string(task, "name")
+            )));
+        }
+    }
+    for (i, (name, task)) in new_tasks.iter().enumerate() {
+        let old_task = match old_by_name.get(name) {
+            Some(t) => t,
+            None => {
+                out.push(Change::Added(format!(
+                    "{} ({}), priority {}",
+                    name,
+                    string(task, "name"),
+                    string(task, "priority")
+                )));
+                continue;
+            }
+        };
+
+        // Task indices are part of every task ID, so moving a task is worth
+        // knowing about.
+        let old_index = old_tasks.iter().position(|(n, _)| n == name).unwrap();
+        if old_index != i {
+            out.push(Change::Changed(format!(
+                "{}: index {} -> {}",
+                name, old_index, i
+            )));
+        }
+        for key in ["name", "priority"] {
+            let (o, n) = (string(old_task, key), string(task, key));
+            if o != n {
+                out.push(Change::Changed(format!(
+                    "{}: {} {} -> {}",
+                    name, key, o, n
+                )));
+            }
+        }
+
+        let old_features = features(old_task);
+        let new_features = features(task);
+        let mut features = vec![];
+        for f in new_features.difference(&old_features) {
+            features.push(format!("+{}", f));
+        }
+        for f in old_features.difference(&new_features) {
+            features.push(format!("-{}", f));
+        }
+        if !features.is_empty() {
+            out.push(Change::Changed(format!(
+                "{}: features {}",
+                name,
+                features.join(" ")
+            )));
+        }
+
+        let old_slots = task_slots(old_task);
+        let new_slots = task_slots(task);
+        let slot = |slot: &str, task: &str| {
+            if slot == task {
+                format!("{}: task-slot {}", name, slot)
+            } else {
+                format!("{}: task-slot {} = {}", name, slot, task)
+            }
+        };
+        for (s, t) in &old_slots {
+            match new_slots.get(s) {
+                None => out.push(Change::Removed(slot(s, t))),
+                Some(n) if n != t => out.push(Change::Changed(format!(
+                    "{}: task-slot {}: {} -> {}",
+                    name, s, t, n
+                ))),
+                Some(_) => (),
+            }
+        }
+        for (s, t) in &new_slots {
+            if !old_slots.contains_key(s) {
+                out.push(Change::Added(slot(s, t)));
+            }
+        }
+    }
+    out
+}
+
+fn string(task: &Table, key: &str) -> String {
+    match task.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => inherit::to_inline(v),
+        None => "none".to_string(),
+    }
+}
+
+fn features(task: &Table) -> BTreeSet<String> {
+    task.get("features")
+        .and_then(Value::as_array)
+        .map(|a| {
+            a.iter()
+                .filter_map(|f| f.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns a task's slots as slot name to task name, whether they're written
+/// as `"task"` or `{slot = "task"}`.
+fn task_slots(task: &Table) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    for slot in task
+        .get("task-slots")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match slot {
+            Value::String(s) => {
+                out.insert(s.clone(), s.clone());
+            }
+            Value::Table(t) => {
+                for (k, v) in t {
+                    let task = match v {
+                        Value::String(s) => s.clone(),
+                        v => inherit::to_inline(v),
+                    };
+                    out.insert(k.clone(), task);
+                }
+            }
+            _ => (),
+        }
+    }
+    out
+}
+
+fn diff_memory(old: &Build, new: &Build) -> Vec<Change> {
+    let layout = |b: &Build| {
+        let a = &b.manifest.as_ref()?.allocations;
+        let range =
+            |r: &Range<u32>| format!("{:#010x}..{:#010x}", r.start, r.end);
+        let mut out = BTreeMap::new();
+        for (mem, r) in &a.kernel {
+            out.insert(format!("kernel {}", mem), range(r));
+        }
+        for (task, mems) in &a.tasks {
+            for (mem, r) in mems {
+                out.insert(format!("{} {}", task, mem), range(r));
+            }
+        }
+        for (name, r) in &a.shared {
+            out.insert(format!("shared region {}", name), range(r));
+        }
+        Some(out)
+    };
+    match (layout(old), layout(new)) {
+        (Some(o), Some(n)) => {
+            let mut out = vec![];
+            diff_maps(&o, &n, &mut out);
+            out
+        }
+        _ => vec![not_recorded(old, new)],
+    }
+}
+
+/// Turns the manifest's task -> interface -> hash into interface -> (hash,
+/// tasks).
+fn interfaces(b: &Build) -> Option<BTreeMap<&str, (&str, BTreeSet<&str>)>> {
+    let mut out: BTreeMap<&str, (&str, BTreeSet<&str>)> = BTreeMap::new();
+    for (task, interfaces) in &b.manifest.as_ref()?.idol {
+        for (path, hash) in interfaces {
+            out.entry(path)
+                .or_insert((hash, BTreeSet::new()))
+                .1
+                .insert(task);
+        }
+    }
+    Some(out)
+}
+
+fn diff_idol(old: &Build, new: &Build) -> Vec<Change> {
+    let (old, new) = match (interfaces(old), interfaces(new)) {
+        (Some(o), Some(n)) => (o, n),
+        _ => return vec![not_recorded(old, new)],
+    };
+    let list = |tasks: &BTreeSet<&str>| {
+        tasks.iter().cloned().collect::<Vec<_>>().join(", ")
+    };
+
+    let mut out = vec![];
+    for (path, (_, tasks)) in &old {
+        if !new.contains_key(path) {
+            out.push(Change::Removed(format!(
+                "{}, was used by {}",
+                path,
+                list(tasks)
+            )));
+        }
+    }
+    for (path, (hash, tasks)) in &new {
+        match old.get(path) {
+            None => out.push(Change::Added(format!(
+                "{}, used by {}",
+                path,
+                list(tasks)
+            ))),
+            Some((old_hash, old_tasks)) => {
+                if old_hash != hash {
+                    out.push(Change::Changed(format!(
+                        "{} changed, used by {}",
+                        path,
+                        list(tasks)
+                    )));
+                }
+                if tasks != old_tasks {
+                    let mut users = vec![];
+                    for t in tasks.difference(old_tasks) {
+                        users.push(format!("+{}", t));
+                    }
+                    for t in old_tasks.difference(tasks) {
+                        users.push(format!("-{}", t));
+                    }
+                    out.push(Change::Changed(format!(
+                        "{}: users {}",
+                        path,
+                        users.join(" ")
+                    )));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn not_recorded(old: &Build, new: &Build) -> Change {
+    Change::Note(
+        not_recorded_in(old.manifest.is_some(), new.manifest.is_some())
+            .to_string(),
+    )
+}
+
+fn not_recorded_in(old: bool, new: bool) -> &'static str {
+    match (old, new) {
+        (false, false) => "not recorded in either archive",
+        (false, true) => "not recorded in the old archive",
+        _ => "not recorded in the new archive",
+    }
+}
+
+fn diff_header(old: &Build, new: &Build) -> Vec<Change> {
+    let fields = |h: &abi::ImageHeader| {
+        let mut out = BTreeMap::new();
+        out.insert("magic".to_string(), format!("{:#010x}", h.magic));
+        out.insert(
+            "total_image_len".to_string(),
+            format!("{:#x}", h.total_image_len),
+        );
+        out.insert("version".to_string(), h.version.to_string());
+        out.insert("epoch".to_string(), h.epoch.to_string());
+        for (i, e) in h.sau_entries.iter().enumerate() {
+            out.insert(
+                format!("sau_entries[{}]", i),
+                format!("rbar {:#010x} rlar {:#010x}", e.rbar, e.rlar),
+            );
+        }
+        out
+    };
+    match (&old.header, &new.header) {
+        (Some(o), Some(n)) => {
+            let mut out = vec![];
+            diff_maps(&fields(o), &fields(n), &mut out);
+            out
+        }
+        (None, None) => vec![Change::Note("neither image has one".into())],
+        (None, Some(_)) => vec![Change::Added("image header".into())],
+        (Some(_), None) => vec![Change::Removed("image header".into())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use build_kconfig::{
+        InterruptConfig, OwnedAddress, RegionAttributes, TaskConfig,
+    };
+
+    fn build(app: &str) -> Build {
+        Build {
+            git_rev: "test".to_string(),
+            app: ordered_toml::from_str(app).unwrap(),
+            kconfig: None,
+            manifest: None,
+            header: None,
+            image: vec![],
+        }
+    }
+
+    fn changed(s: &str) -> Change {
+        Change::Changed(s.to_string())
+    }
+
+    fn task(name: &str, priority: u8) -> TaskConfig {
+        let region = |base| RegionConfig {
+            base,
+            size: 0x1000,
+            attributes: RegionAttributes {
+                read: true,
+                write: false,
+                execute: true,
+                special_role: None,
+            },
+        };
+        TaskConfig {
+            name: name.to_string(),
+            owned_regions: [("flash".to_string(), region(0x0800_0000))]
+                .into_iter()
+                .collect(),
+            shared_regions: BTreeSet::new(),
+            entry_point: OwnedAddress {
+                region_name: "flash".to_string(),
+                offset: 0,
+            },
+            initial_stack: OwnedAddress {
+                region_name: "flash".to_string(),
+                offset: 0x1000,
+            },
+            priority,
+            start_at_boot: true,
+            ipc_targets: None,
+        }
+    }
+
+    fn kconfig(tasks: Vec<TaskConfig>) -> KernelConfig {
+        KernelConfig {
+            tasks,
+            shared_regions: BTreeMap::new(),
+            irqs: BTreeMap::new(),
+            tick_hz: 1000,
+        }
+    }
+
+    #[test]
+    fn maps() {
+        let map = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let mut out = vec![];
+        diff_maps(
+            &map(&[("a", "1"), ("b", "2"), ("c", "3")]),
+            &map(&[("b", "2"), ("c", "4"), ("d", "5")]),
+            &mut out,
+        );
+        assert_eq!(
+            out,
+            [
+                Change::Removed("a: 1".into()),
+                changed("c: 3 -> 4"),
+                Change::Added("d: 5".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_settings() {
+        let old =
+            build("[kernel]\nname = \"k\"\nrequires = {flash = 1, ram = 2}\n");
+        let new = build(
+            "[kernel]\nname = \"k\"\nrequires = {flash = 1, ram = 3}\n\
+             features = [\"klog\"]\n",
+        );
+        assert_eq!(
+            diff_kernel(&old, &new),
+            [
+                changed("requires.ram: 2 -> 3"),
+                Change::Added("features: [\"klog\"]".into()),
+                Change::Note(
+                    "generated configuration not recorded in either archive"
+                        .into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_config() {
+        let mut old = build("");
+        let mut new = build("");
+        old.kconfig = Some(kconfig(vec![task("a", 0), task("b", 1)]));
+
+        // Tasks are keyed by name, so swapping them only shows up where
+        // something else changed; task indices are translated to names.
+        let mut k = kconfig(vec![task("b", 1), task("a", 2)]);
+        k.tasks[0].ipc_targets = Some([1].into_iter().collect());
+        k.irqs.insert(
+            5,
+            InterruptConfig {
+                task_index: 0,
+                notification: 0b10,
+            },
+        );
+        new.kconfig = Some(k);
+
+        assert_eq!(
+            diff_kernel(&old, &new),
+            [
+                changed("task a priority: 0 -> 2"),
+                changed("task b ipc targets: any task -> a"),
+                Change::Added("irq 5: b, notification 0x2".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_config_missing() {
+        let mut new = build("");
+        new.kconfig = Some(kconfig(vec![]));
+        assert_eq!(
+            diff_kernel(&build(""), &new),
+            [Change::Note(
+                "generated configuration not recorded in the old archive"
+                    .into()
+            )]
+        );
+    }
+
+    #[test]
+    fn tasks() {
+        let old = build(
+            r#"
+            [tasks.jefe]
+            name = "task-jefe"
+            priority = 0
+            [tasks.net]
+            name = "task-net"
+            priority = 2
+            features = ["a", "b"]
+            task-slots = ["sys", {spi = "spi2"}]
+            [tasks.gone]
+            name = "task-gone"
+            priority = 3
+            "#,
+        );
+        let new = build(
+            r#"
+            [tasks.jefe]
+            name = "task-jefe"
+            priority = 0
+            [tasks.new]
+            name = "task-new"
+            priority = 1
+            [tasks.net]
+            name = "task-net"
+            priority = 3
+            features = ["b", "c"]
+            task-slots = [{spi = "spi4"}, "i2c"]
+            "#,
+        );
+        assert_eq!(
+            diff_tasks(&old, &new),
+            [
+                Change::Removed("gone (task-gone)".into()),
+                Change::Added("new (task-new), priority 1".into()),
+                changed("net: index 1 -> 2"),
+                changed("net: priority 2 -> 3"),
+                changed("net: features +c -a"),
+                changed("net: task-slot spi: spi2 -> spi4"),
+                Change::Removed("net: task-slot sys".into()),
+                Change::Added("net: task-slot i2c".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn idol() {
+        let manifest = |idol: &[(&str, &str, &str)]| {
+            let mut m = Manifest {
+                allocations: Allocations::default(),
+                idol: BTreeMap::new(),
+            };
+            for (task, path, hash) in idol {
+                m.idol
+                    .entry(task.to_string())
+                    .or_default()
+                    .insert(path.to_string(), hash.to_string());
+            }
+            Some(m)
+        };
+        let mut old = build("");
+        old.manifest = manifest(&[
+            ("a", "idl/x.idol", "1"),
+            ("b", "idl/x.idol", "1"),
+            ("a", "idl/y.idol", "2"),
+        ]);
+        let mut new = build("");
+        new.manifest = manifest(&[
+            ("a", "idl/x.idol", "3"),
+            ("c", "idl/x.idol", "3"),
+            ("a", "idl/z.idol", "4"),
+        ]);
+        assert_eq!(
+            diff_idol(&old, &new),
+            [
+                Change::Removed("idl/y.idol, was used by a".into()),
+                changed("idl/x.idol changed, used by a, c"),
+                changed("idl/x.idol: users +c -b"),
+                Change::Added("idl/z.idol, used by a".into()),
+            ]
+        );
+
+        assert_eq!(
+            diff_idol(&old, &build("")),
+            [Change::Note("not recorded in the new archive".into())]
+        );
+    }
+}
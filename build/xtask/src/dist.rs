@@ -12,14 +12,15 @@ use std::process::{Command, Stdio};
 
 use anyhow::{anyhow, bail, Context, Result};
 use atty::Stream;
-use colored::*;
 use indexmap::IndexMap;
+use p256::ecdsa::SigningKey;
 use path_slash::PathBufExt;
+use serde::{Deserialize, Serialize};
 use zerocopy::AsBytes;
 
 use crate::{
-    config::{BuildConfig, Config},
-    elf,
+    config::{BuildConfig, Config, NotificationRef, SharedAccess},
+    diff, elf,
     sizes::load_task_size,
     task_slot,
 };
@@ -29,6 +30,11 @@ use crate::{
 /// padded that a bit.
 pub const DEFAULT_KERNEL_STACK: u32 = 1024;
 
+/// Stage0 keeps its A/B boot state in the last two pages of its flash region
+/// (see `lib/lpc55-boot-state`), so nothing may be allocated there. The LPC55's
+/// flash pages are 512 bytes.
+const STAGE0_BOOT_STATE_BYTES: u32 = 2 * 512;
+
 /// `PackageConfig` contains a bundle of data that's commonly used when
 /// building a full app image, grouped together to avoid passing a bunch
 /// of individual arguments to functions.
@@ -36,9 +42,6 @@ pub const DEFAULT_KERNEL_STACK: u32 = 1024;
 /// It should be trivial to calculate and kept constant during the build;
 /// mutable build information should be accumulated elsewhere.
 struct PackageConfig {
-    /// Path to the `app.toml` file being built
-    app_toml_file: PathBuf,
-
     /// Directory containing the `app.toml` file being built
     app_src_dir: PathBuf,
 
@@ -51,6 +54,9 @@ struct PackageConfig {
     /// Run `cargo tree --edges` before compiling, to show dependencies
     edges: bool,
 
+    /// Build tasks with `-Z emit-stack-sizes`, for `xtask stack`
+    stack_sizes: bool,
+
     /// Directory where the build artifacts are placed, in the form
     /// `target/$NAME/dist`.
     dist_dir: PathBuf,
@@ -72,7 +78,12 @@ struct PackageConfig {
 }
 
 impl PackageConfig {
-    fn new(app_toml_file: &Path, verbose: bool, edges: bool) -> Result<Self> {
+    fn new(
+        app_toml_file: &Path,
+        verbose: bool,
+        edges: bool,
+        stack_sizes: bool,
+    ) -> Result<Self> {
         let toml = Config::from_file(app_toml_file)?;
         let dist_dir = Path::new("target").join(&toml.name).join("dist");
         let app_src_dir = app_toml_file
@@ -108,11 +119,11 @@ impl PackageConfig {
         }
 
         Ok(Self {
-            app_toml_file: app_toml_file.to_path_buf(),
             app_src_dir: app_src_dir.to_path_buf(),
             toml,
             verbose,
             edges,
+            stack_sizes,
             dist_dir,
             sysroot,
             host_triple,
@@ -199,11 +210,12 @@ type AllocationMap = (Allocations, IndexMap<String, Range<u32>>);
 pub fn package(
     verbose: bool,
     edges: bool,
+    stack_sizes: bool,
     app_toml: &Path,
     tasks_to_build: Option<Vec<String>>,
     dirty_ok: bool,
 ) -> Result<BTreeMap<String, AllocationMap>> {
-    let cfg = PackageConfig::new(app_toml, verbose, edges)?;
+    let cfg = PackageConfig::new(app_toml, verbose, edges, stack_sizes)?;
 
     // If we're using filters, we change behavior at the end. Record this in a
     // convenient flag, running other checks as well.
@@ -265,6 +277,14 @@ pub fn package(
     // Allocate memories.
     let allocated = allocate_all(&cfg.toml, &task_sizes)?;
 
+    // Recorded in the archive for `xtask diff`. This asks cargo, so only
+    // bother when we're going to build an archive.
+    let idol = if partial_build {
+        BTreeMap::new()
+    } else {
+        diff::idol_hashes(&cfg.toml)?
+    };
+
     for image_name in &cfg.toml.image_names {
         // Build each task.
         let mut all_output_sections = BTreeMap::default();
@@ -344,9 +364,24 @@ pub fn package(
                 percent
             );
         }
+        print_auto_sized_tasks(&cfg.toml, allocs, &task_sizes);
+
+        // Sign the image for stage0, which checks the signature before
+        // booting it. Stage0 itself has external images, and is left to the
+        // ROM's signing below.
+        let (kentry, ksymbol_table) = kern_build.unwrap();
+        if cfg.toml.external_images.is_empty() {
+            if let Some(key) = load_image_key(&cfg)? {
+                sign_image(
+                    &key,
+                    &cfg.toml.memories(image_name)?["flash"],
+                    &ksymbol_table,
+                    &mut all_output_sections,
+                )?;
+            }
+        }
 
         // Generate combined SREC, which is our source of truth for combined images.
-        let (kentry, _ksymbol_table) = kern_build.unwrap();
         write_srec(
             &all_output_sections,
             kentry,
@@ -355,7 +390,9 @@ pub fn package(
 
         translate_srec_to_other_formats(&cfg.img_dir(image_name), "combined")?;
 
-        if let Some(signing) = &cfg.toml.signing {
+        if let Some(signing) =
+            cfg.toml.signing.as_ref().filter(|s| !s.certs.is_empty())
+        {
             let rkth = lpc55_sign::signed_image::sign_chain(
                 &cfg.img_file("combined.bin", image_name),
                 Some(&cfg.app_src_dir),
@@ -410,11 +447,128 @@ pub fn package(
             }
         }
         write_gdb_script(&cfg, image_name)?;
-        build_archive(&cfg, image_name)?;
+        let manifest = diff::Manifest {
+            allocations: allocs.clone(),
+            idol: idol.clone(),
+        };
+        build_archive(&cfg, image_name, &manifest)?;
     }
     Ok(allocated)
 }
 
+/// Reports where tasks without `max-sizes` ended up, and how big they were
+/// made, since that isn't written down anywhere else.
+fn print_auto_sized_tasks(
+    toml: &Config,
+    allocs: &Allocations,
+    task_sizes: &HashMap<&str, IndexMap<&str, u64>>,
+) {
+    let auto: Vec<&str> = toml
+        .tasks
+        .iter()
+        .filter(|(_, task)| task.max_sizes.is_empty())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if auto.is_empty() {
+        return;
+    }
+
+    let pad = auto.iter().map(|name| name.len()).max().unwrap_or(0);
+    println!("Auto-sized:");
+    for name in auto {
+        for (mem, range) in &allocs.tasks[name] {
+            let used = task_sizes[name].get(mem.as_str()).unwrap_or(&0);
+            println!(
+                "  {:<pad$} {:<6} {:#010x}..{:#010x} ({:#x}, {:#x} used)",
+                name,
+                format!("{}:", mem),
+                range.start,
+                range.end,
+                range.end - range.start,
+                used,
+                pad = pad,
+            );
+        }
+    }
+}
+
+/// Loads the private key named by `image-key` in the `[signing]` section, if
+/// there is one.
+fn load_image_key(cfg: &PackageConfig) -> Result<Option<SigningKey>> {
+    use p256::pkcs8::FromPrivateKey;
+
+    let path =
+        match cfg.toml.signing.as_ref().and_then(|s| s.image_key.as_ref()) {
+            Some(path) => cfg.app_src_dir.join(path),
+            None => return Ok(None),
+        };
+    let der = std::fs::read(&path)
+        .with_context(|| format!("reading image key {}", path.display()))?;
+    let key = SigningKey::from_pkcs8_der(&der).map_err(|e| {
+        anyhow!("{} is not a PKCS#8 P-256 key: {}", path.display(), e)
+    })?;
+    Ok(Some(key))
+}
+
+/// Signs the image in `flash`, appending the signature after the
+/// `total_image_len` bytes covered by its header.
+///
+/// Stage0 checks the signature over exactly those bytes as they sit in flash,
+/// so any gaps between sections are signed as zeros, which is how they end up
+/// in the binary.
+fn sign_image(
+    key: &SigningKey,
+    flash: &Range<u32>,
+    ksymbol_table: &BTreeMap<String, u32>,
+    all_output_sections: &mut BTreeMap<u32, LoadSegment>,
+) -> Result<()> {
+    use p256::ecdsa::{signature::Signer, Signature};
+    use zerocopy::FromBytes;
+
+    let mut image = vec![];
+    for (addr, sec) in all_output_sections.range(flash.clone()) {
+        let start = (addr - flash.start) as usize;
+        let end = start + sec.data.len();
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[start..end].copy_from_slice(&sec.data);
+    }
+
+    let header_addr = *ksymbol_table
+        .get("__header_start")
+        .ok_or_else(|| anyhow!("kernel has no image header to sign"))?;
+    let header = image
+        .get((header_addr - flash.start) as usize..)
+        .and_then(abi::ImageHeader::read_from_prefix)
+        .ok_or_else(|| anyhow!("image header is outside of the image"))?;
+
+    let len = header.total_image_len as usize;
+    if image.len() > len {
+        bail!(
+            "image has {} bytes but its header only covers {}",
+            image.len(),
+            len
+        );
+    }
+    image.resize(len, 0);
+
+    let sig_addr = flash.start + header.total_image_len;
+    if sig_addr + abi::IMAGE_SIGNATURE_LEN > flash.end {
+        bail!("no room in flash for the image signature");
+    }
+
+    let signature: Signature = key.sign(&image);
+    all_output_sections.insert(
+        sig_addr,
+        LoadSegment {
+            source_file: "image signature".into(),
+            data: signature.as_ref().to_vec(),
+        },
+    );
+    Ok(())
+}
+
 fn secure_update(
     cfg: &PackageConfig,
     allocs: &Allocations,
@@ -573,7 +727,11 @@ fn write_gdb_script(cfg: &PackageConfig, image_name: &str) -> Result<()> {
     Ok(())
 }
 
-fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
+fn build_archive(
+    cfg: &PackageConfig,
+    image_name: &str,
+    manifest: &diff::Manifest,
+) -> Result<()> {
     // Bundle everything up into an archive.
     let mut archive = Archive::new(
         cfg.img_file(format!("build-{}.zip", cfg.toml.name), image_name),
@@ -583,9 +741,11 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         "README.TXT",
         "\
         This is a build archive containing firmware build artifacts.\n\n\
-        - app.toml is the config file used to build the firmware.\n\
+        - app.toml is the config file used to build the firmware, with\n\
+          anything it inherits merged in.\n\
         - git-rev is the commit it was built from, with optional dirty flag.\n\
-        - info/ contains human-readable data like logs.\n\
+        - info/ contains human-readable data like logs, and manifest.json\n\
+          and kconfig.ron, which `cargo xtask diff` compares.\n\
         - elf/ contains ELF images for all firmware components.\n\
         - elf/tasks/ contains each task by name.\n\
         - elf/kernel is the kernel.\n\
@@ -598,12 +758,17 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         "git-rev",
         format!("{}{}", git_rev, if git_dirty { "-dirty" } else { "" }),
     )?;
-    archive.copy(&cfg.app_toml_file, "app.toml")?;
+    archive.text("app.toml", &cfg.toml.resolved_toml)?;
     let chip_dir = cfg.app_src_dir.join(cfg.toml.chip.clone());
     let chip_file = chip_dir.join("chip.toml");
     let chip_filename = chip_file.file_name().unwrap();
     archive.copy(&chip_file, &chip_filename)?;
 
+    archive
+        .text(diff::MANIFEST_PATH, serde_json::to_string_pretty(manifest)?)?;
+    archive
+        .copy(cfg.img_file("kconfig.ron", image_name), diff::KCONFIG_PATH)?;
+
     let elf_dir = PathBuf::from("elf");
     let tasks_dir = elf_dir.join("task");
     for name in cfg.toml.tasks.keys() {
@@ -778,9 +943,17 @@ fn link_task(
 ) -> Result<()> {
     println!("linking task '{}'", name);
     let task_toml = &cfg.toml.tasks[name];
+    let shared = cfg
+        .toml
+        .shared
+        .iter()
+        .filter(|(_, s)| s.tasks.contains_key(name))
+        .map(|(region, _)| (region.clone(), allocs.shared[region].clone()))
+        .collect();
     generate_task_linker_script(
         "memory.x",
         &allocs.tasks[name],
+        &shared,
         Some(&task_toml.sections),
         task_toml.stacksize.or(cfg.toml.stacksize).ok_or_else(|| {
             anyhow!("{}: no stack size specified and there is no default", name)
@@ -814,9 +987,20 @@ fn link_dummy_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         .into_iter()
         .collect();
 
+    // Shared regions haven't been placed yet, but the task may refer to them,
+    // so give them dummy addresses.
+    let shared = cfg
+        .toml
+        .shared
+        .iter()
+        .filter(|(_, s)| s.tasks.contains_key(name))
+        .map(|(region, s)| (region.clone(), 0..s.size))
+        .collect();
+
     generate_task_linker_script(
         "memory.x",
         &memories, // ALL THE SPACE
+        &shared,
         Some(&task_toml.sections),
         task_toml.stacksize.or(cfg.toml.stacksize).ok_or_else(|| {
             anyhow!("{}: no stack size specified and there is no default", name)
@@ -892,10 +1076,19 @@ fn build_kernel(
     let kconfig = make_kconfig(
         &cfg.toml,
         &allocs.tasks,
+        &allocs.shared,
         entry_points,
         image_name,
         secure,
     )?;
+    // Kept for the build archive, where `xtask diff` compares it.
+    std::fs::write(
+        cfg.img_file("kconfig.ron", image_name),
+        ron::ser::to_string_pretty(
+            &kconfig,
+            ron::ser::PrettyConfig::default(),
+        )?,
+    )?;
     let kconfig = ron::ser::to_string(&kconfig)?;
 
     kconfig.hash(&mut image_id);
@@ -906,21 +1099,39 @@ fn build_kernel(
         &allocs.kernel,
         cfg.toml.kernel.stacksize.unwrap_or(DEFAULT_KERNEL_STACK),
         &cfg.toml.image_memories("flash".to_string())?,
+        &cfg.toml.all_regions("flash".to_string())?,
     )?;
 
     fs::copy("build/kernel-link.x", "target/link.x")?;
 
     let image_id = image_id.finish();
 
+    // A bootloader checks the images it boots against the public half of
+    // the image key.
+    let image_id = format!("{}", image_id);
+    let mut env = vec![
+        ("HUBRIS_KCONFIG", kconfig.as_str()),
+        ("HUBRIS_IMAGE_ID", image_id.as_str()),
+    ];
+    let public_key = match load_image_key(cfg)? {
+        Some(key) if !cfg.toml.external_images.is_empty() => Some(
+            key.verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+        ),
+        _ => None,
+    };
+    if let Some(public_key) = &public_key {
+        env.push(("HUBRIS_IMAGE_PUBLIC_KEY", public_key));
+    }
+
     // Build the kernel.
-    let build_config = cfg.toml.kernel_build_config(
-        cfg.verbose,
-        &[
-            ("HUBRIS_KCONFIG", &kconfig),
-            ("HUBRIS_IMAGE_ID", &format!("{}", image_id)),
-        ],
-        Some(&cfg.sysroot),
-    );
+    let build_config =
+        cfg.toml
+            .kernel_build_config(cfg.verbose, &env, Some(&cfg.sysroot));
     build(cfg, "kernel", build_config, false)?;
     if update_image_header(
         cfg,
@@ -1110,23 +1321,78 @@ fn update_image_header(
     Ok(false)
 }
 
-/// Prints warning messages about priority inversions
+/// Returns the tasks that `name` may block on: its task slots and
+/// `ipc-grants` (other than `"*"`), less any it's exempt from the priority
+/// checks for through `ipc-exceptions` or because the config only grants it
+/// a target to post to.
+fn ipc_callees<'a>(
+    toml: &'a Config,
+    name: &str,
+) -> Result<(Vec<&'a str>, Vec<&'a str>)> {
+    let task = &toml.tasks[name];
+    let mut callees: Vec<&str> = vec![];
+    for callee in task
+        .task_slots
+        .values()
+        .map(String::as_str)
+        .chain(toml.ipc_grants(name)?)
+    {
+        if callee != "*" && !callees.contains(&callee) {
+            callees.push(callee);
+        }
+    }
+    let mut exempt: Vec<&str> =
+        task.ipc_exceptions.iter().map(String::as_str).collect();
+    exempt.extend(toml.config_ipc_grants(name)?);
+    Ok((callees, exempt))
+}
+
+/// Checks task priorities, and that task slots and `ipc-grants` only send
+/// uphill
 fn check_task_priorities(toml: &Config) -> Result<()> {
+    check_task_slot_cycles(toml)?;
+
     let idle_priority = toml.tasks["idle"].priority;
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
-        for callee in task.task_slots.values() {
+        let (callees, exempt) = ipc_callees(toml, name)?;
+        for callee in &task.ipc_exceptions {
+            if !callees.contains(&callee.as_str()) {
+                bail!(
+                    "task {} lists {} in ipc-exceptions, but has no task \
+                     slot or ipc-grant for it",
+                    name,
+                    callee
+                );
+            }
+        }
+        for callee in callees {
             let p = toml
                 .tasks
                 .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
+                .ok_or_else(|| {
+                    anyhow!("Invalid task-slot or ipc-grant: {}", callee)
+                })?
                 .priority;
-            if p >= task.priority && name != callee {
-                // TODO: once all priority inversions are fixed, return an
-                // error so no more can be introduced
-                eprint!("{}", "Priority inversion: ".red());
-                eprintln!(
-                    "task {} (priority {}) calls into {} (priority {})",
-                    name, task.priority, callee, p
+            let inverted = p >= task.priority && name != callee;
+            let excepted = task.ipc_exceptions.iter().any(|c| c == callee);
+            if inverted && !exempt.contains(&callee) {
+                bail!(
+                    "Priority inversion: task {} (priority {}) calls into \
+                     {} (priority {}); fix the priorities, or add {:?} to \
+                     the task's ipc-exceptions",
+                    name,
+                    task.priority,
+                    callee,
+                    p,
+                    callee
+                );
+            } else if excepted && !inverted {
+                bail!(
+                    "task {} lists {} in ipc-exceptions, but {} runs at a \
+                     higher priority and needs no exception",
+                    name,
+                    callee,
+                    callee
                 );
             }
         }
@@ -1142,9 +1408,65 @@ fn check_task_priorities(toml: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Checks that no task can block, through a chain of task slots and
+/// `ipc-grants`, on itself.
+///
+/// A cycle of distinct tasks always includes a priority inversion, so this
+/// runs ahead of the inversion check to say what's actually wrong. Self-slots
+/// and exempt targets are left out, as they are there.
+fn check_task_slot_cycles(toml: &Config) -> Result<()> {
+    #[derive(Copy, Clone, Eq, PartialEq)]
+    enum Visit {
+        Active,
+        Done,
+    }
+
+    fn walk<'a>(
+        toml: &'a Config,
+        name: &'a str,
+        visits: &mut HashMap<&'a str, Visit>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        match visits.get(name) {
+            Some(Visit::Done) => return Ok(()),
+            Some(Visit::Active) => {
+                let start = path.iter().position(|n| *n == name).unwrap();
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name);
+                bail!(
+                    "Task slots and ipc-grants form a cycle: {}",
+                    cycle.join(" -> ")
+                );
+            }
+            None => (),
+        }
+        visits.insert(name, Visit::Active);
+        path.push(name);
+        let (callees, exempt) = ipc_callees(toml, name)?;
+        for callee in callees {
+            if callee != name
+                && !exempt.contains(&callee)
+                && toml.tasks.contains_key(callee)
+            {
+                walk(toml, callee, visits, path)?;
+            }
+        }
+        path.pop();
+        visits.insert(name, Visit::Done);
+        Ok(())
+    }
+
+    let mut visits = HashMap::new();
+    for name in toml.tasks.keys() {
+        walk(toml, name, &mut visits, &mut vec![])?;
+    }
+    Ok(())
+}
+
 fn generate_task_linker_script(
     name: &str,
     map: &BTreeMap<String, Range<u32>>,
+    shared: &BTreeMap<String, Range<u32>>,
     sections: Option<&IndexMap<String, String>>,
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
@@ -1205,6 +1527,13 @@ fn generate_task_linker_script(
             out.end
         )?;
     }
+    // Shared regions this task can access, for
+    // `build_util::generate_shared_regions`.
+    for (name, range) in shared {
+        let name = name.to_ascii_uppercase().replace('-', "_");
+        writeln!(linkscr, "__SHARED_{}_BASE = {:#010x};", name, range.start)?;
+        writeln!(linkscr, "__SHARED_{}_END = {:#010x};", name, range.end)?;
+    }
 
     append_task_sections(&mut linkscr, sections)?;
 
@@ -1234,6 +1563,7 @@ fn generate_kernel_linker_script(
     map: &BTreeMap<String, Range<u32>>,
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
+    all_images: &IndexMap<String, Range<u32>>,
 ) -> Result<()> {
     // Put the linker script somewhere the linker can find it
     let mut linkscr =
@@ -1306,6 +1636,22 @@ fn generate_kernel_linker_script(
         )
         .unwrap();
     }
+    // The same image symbols tasks get, which stage0 uses to find its boot
+    // state.
+    for (name, out) in all_images {
+        writeln!(
+            linkscr,
+            "__IMAGE_{}_BASE = {:#010x};",
+            name.to_ascii_uppercase(),
+            out.start
+        )?;
+        writeln!(
+            linkscr,
+            "__IMAGE_{}_END = {:#010x};",
+            name.to_ascii_uppercase(),
+            out.end
+        )?;
+    }
     Ok(())
 }
 
@@ -1336,6 +1682,13 @@ fn build(
         .iter()
         .map(|r| format!(" --remap-path-prefix={}={}", r.0.display(), r.1))
         .collect();
+    // The `.stack_sizes` section is only read by `xtask stack`. Changing
+    // RUSTFLAGS rebuilds everything, so we don't want to do it otherwise.
+    let stack_sizes = if cfg.stack_sizes {
+        " -Z emit-stack-sizes"
+    } else {
+        ""
+    };
     cmd.env(
         "RUSTFLAGS",
         &format!(
@@ -1344,9 +1697,9 @@ fn build(
              -C llvm-args=--enable-machine-outliner=never \
              -C overflow-checks=y \
              -C metadata={} \
-             {}
+             {}{}
              ",
-            cfg.link_script_hash, remap_path_prefix,
+            cfg.link_script_hash, remap_path_prefix, stack_sizes,
         ),
     );
     cmd.arg("--");
@@ -1511,12 +1864,39 @@ fn link(
     Ok(())
 }
 
-#[derive(Debug, Clone, Default, Hash)]
+#[derive(Debug, Clone, Default, Hash, Serialize, Deserialize)]
 pub struct Allocations {
     /// Map from memory-name to address-range
     pub kernel: BTreeMap<String, Range<u32>>,
     /// Map from task-name to memory-name to address-range
     pub tasks: BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    /// Map from shared-region-name to address-range
+    pub shared: BTreeMap<String, Range<u32>>,
+}
+
+impl Allocations {
+    fn insert(&mut self, requester: Requester, mem: &str, range: Range<u32>) {
+        match requester {
+            Requester::Task(task) => {
+                self.tasks
+                    .entry(task.to_string())
+                    .or_default()
+                    .insert(mem.to_string(), range);
+            }
+            Requester::Shared(name) => {
+                self.shared.insert(name.to_string(), range);
+            }
+        }
+    }
+}
+
+/// Something other than the kernel that `allocate_all` finds memory for.
+#[derive(Copy, Clone, Debug)]
+enum Requester<'a> {
+    /// One of a task's own regions.
+    Task(&'a str),
+    /// A region from the `[shared]` section of the config.
+    Shared(&'a str),
 }
 
 /// Allocates address space from all regions for the kernel and all tasks.
@@ -1562,9 +1942,10 @@ pub fn allocate_all(
     // the naturally-aligned-power-of-two requirement.
     //
     // We keep kernel and task requests separate so we can always service the
-    // kernel first.
+    // kernel first. Shared regions queue up alongside the tasks.
     //
-    // The task map is: memory name -> allocation size -> queue of task name.
+    // The task map is: memory name -> allocation size -> queue of task (or
+    // shared region) name.
     // The kernel map is: memory name -> allocation size
     let kernel = &toml.kernel;
     let tasks = &toml.tasks;
@@ -1578,8 +1959,10 @@ pub fn allocate_all(
         let mut free = toml.memories(image_name)?;
         let kernel_requests = &kernel.requires;
 
-        let mut task_requests: BTreeMap<&str, BTreeMap<u32, VecDeque<&str>>> =
-            BTreeMap::new();
+        let mut task_requests: BTreeMap<
+            &str,
+            BTreeMap<u32, VecDeque<Requester>>,
+        > = BTreeMap::new();
 
         for name in tasks.keys() {
             for (mem, amt) in task_sizes[name.as_str()].iter() {
@@ -1596,17 +1979,26 @@ pub fn allocate_all(
                     .or_default()
                     .entry(bytes.try_into().unwrap())
                     .or_default()
-                    .push_back(name.as_str());
+                    .push_back(Requester::Task(name.as_str()));
             }
         }
 
+        for (name, shared) in &toml.shared {
+            task_requests
+                .entry(shared.memory.as_str())
+                .or_default()
+                .entry(shared.size)
+                .or_default()
+                .push_back(Requester::Shared(name.as_str()));
+        }
+
         // Okay! Do memory types one by one, fitting kernel first.
         for (region, avail) in &mut free {
             let mut k_req = kernel_requests.get(region.as_str());
             let mut t_reqs = task_requests.get_mut(region.as_str());
 
             fn reqs_map_not_empty(
-                om: &Option<&mut BTreeMap<u32, VecDeque<&str>>>,
+                om: &Option<&mut BTreeMap<u32, VecDeque<Requester>>>,
             ) -> bool {
                 om.iter()
                     .flat_map(|map| map.values())
@@ -1640,33 +2032,27 @@ pub fn allocate_all(
 
                 if let Some(t_reqs) = t_reqs.as_mut() {
                     for (&sz, q) in t_reqs.range_mut(..=align).rev() {
-                        if let Some(task) = q.pop_front() {
+                        if let Some(req) = q.pop_front() {
                             // We can pack an equal or smaller one in.
                             let align = toml.task_memory_alignment(sz);
-                            allocs
-                                .tasks
-                                .entry(task.to_string())
-                                .or_default()
-                                .insert(
-                                    region.to_string(),
-                                    allocate_one(region, sz, align, avail)?,
-                                );
+                            allocs.insert(
+                                req,
+                                region,
+                                allocate_one(region, sz, align, avail)?,
+                            );
                             continue 'fitloop;
                         }
                     }
 
                     for (&sz, q) in t_reqs.range_mut(align + 1..) {
-                        if let Some(task) = q.pop_front() {
+                        if let Some(req) = q.pop_front() {
                             // We've gotta use a larger one.
                             let align = toml.task_memory_alignment(sz);
-                            allocs
-                                .tasks
-                                .entry(task.to_string())
-                                .or_default()
-                                .insert(
-                                    region.to_string(),
-                                    allocate_one(region, sz, align, avail)?,
-                                );
+                            allocs.insert(
+                                req,
+                                region,
+                                allocate_one(region, sz, align, avail)?,
+                            );
                             continue 'fitloop;
                         }
                     }
@@ -1679,6 +2065,20 @@ pub fn allocate_all(
             }
         }
 
+        if image_name == "stage0" {
+            if let Some(flash) = free.get("flash") {
+                let boot_state = flash.end - STAGE0_BOOT_STATE_BYTES;
+                if flash.start > boot_state {
+                    bail!(
+                        "stage0 flash is allocated up to {:#x}, which \
+                         overlaps the boot state pages at {:#x}",
+                        flash.start,
+                        boot_state,
+                    );
+                }
+            }
+        }
+
         result.insert(image_name.to_string(), (allocs, free));
     }
     Ok(result)
@@ -1744,6 +2144,7 @@ fn allocate_one(
 pub fn make_kconfig(
     toml: &Config,
     task_allocations: &BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    shared_allocations: &BTreeMap<String, Range<u32>>,
     entry_points: &HashMap<String, u32>,
     image_name: &str,
     secure: &Option<SecureData>,
@@ -1818,6 +2219,34 @@ pub fn make_kconfig(
         );
     }
 
+    // Shared RAM regions get one entry for each kind of access that tasks have
+    // to them; the kernel is fine with regions that overlap.
+    for (name, shared) in &toml.shared {
+        let range = &shared_allocations[name];
+        let dma = toml.outputs[&shared.memory]
+            .iter()
+            .any(|o| o.name == image_name && o.dma);
+        for &access in shared.tasks.values() {
+            flat_shared.insert(
+                shared_region_key(name, access),
+                build_kconfig::RegionConfig {
+                    base: range.start,
+                    size: range.end - range.start,
+                    attributes: build_kconfig::RegionAttributes {
+                        read: true,
+                        write: access == SharedAccess::ReadWrite,
+                        execute: false,
+                        special_role: if dma {
+                            Some(build_kconfig::SpecialRole::Dma)
+                        } else {
+                            None
+                        },
+                    },
+                },
+            );
+        }
+    }
+
     let mut used_shared_regions = BTreeSet::new();
 
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
@@ -1834,14 +2263,18 @@ pub fn make_kconfig(
             );
         };
 
-        // Mark off the regions this task uses.
-        for region in &task.uses {
-            used_shared_regions.insert(region);
+        // Prep this task's shared region name set: the peripherals it uses,
+        // plus any shared RAM it's been given.
+        let mut shared_regions: BTreeSet<String> =
+            task.uses.iter().cloned().collect();
+        for (region, shared) in &toml.shared {
+            if let Some(&access) = shared.tasks.get(name) {
+                shared_regions.insert(shared_region_key(region, access));
+            }
         }
 
-        // Prep this task's shared region name set.
-        let shared_regions: std::collections::BTreeSet<String> =
-            task.uses.iter().cloned().collect();
+        // Mark off the regions this task uses.
+        used_shared_regions.extend(shared_regions.iter().cloned());
 
         let owned_regions = task_allocations[name].iter()
             .map(|(out_name, range)| {
@@ -1876,7 +2309,34 @@ pub fn make_kconfig(
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
 
+        // Work out who this task may talk to: its task slots, plus anything
+        // granted explicitly or by the config. The supervisor is never
+        // restricted.
+        let grants = toml.ipc_grants(name)?;
+        let ipc_targets = if i == 0 || grants.contains(&"*") {
+            None
+        } else {
+            let targets = task
+                .task_slots
+                .values()
+                .map(String::as_str)
+                .chain(grants)
+                .map(|target| {
+                    toml.tasks.get_index_of(target).ok_or_else(|| {
+                        anyhow!(
+                            "task '{}' can't send to '{}': {}",
+                            name,
+                            target,
+                            toml.task_name_suggestion(target)
+                        )
+                    })
+                })
+                .collect::<Result<BTreeSet<_>>>()?;
+            Some(targets)
+        };
+
         tasks.push(build_kconfig::TaskConfig {
+            name: name.clone(),
             owned_regions,
             shared_regions,
             entry_point: build_kconfig::OwnedAddress {
@@ -1889,10 +2349,15 @@ pub fn make_kconfig(
             },
             priority: task.priority,
             start_at_boot: task.start,
+            ipc_targets,
         });
 
         // Interrupts.
-        for (irq_str, &notification) in &task.interrupts {
+        for (irq_str, notification) in &task.interrupts {
+            let notification = match notification {
+                NotificationRef::Mask(mask) => *mask,
+                NotificationRef::Name(n) => 1 << toml.notifications[name][n],
+            };
             // The irq_str can be either a base-ten number, or a reference to a
             // peripheral. Distinguish them based on whether it parses as an
             // integer.
@@ -1985,9 +2450,19 @@ pub fn make_kconfig(
         irqs,
         tasks,
         shared_regions: flat_shared,
+        tick_hz: toml.kernel.tick_hz,
     })
 }
 
+/// Names the kconfig shared region giving `access` to the `[shared]` region
+/// `name`. These can't collide with peripheral names, which have no colons.
+fn shared_region_key(name: &str, access: SharedAccess) -> String {
+    match access {
+        SharedAccess::ReadOnly => format!("shared:{name}:ro"),
+        SharedAccess::ReadWrite => format!("shared:{name}:rw"),
+    }
+}
+
 /// Loads an SREC file into the same representation we use for ELF. This is
 /// currently unused, but I'm keeping it compiling as proof that it's possible,
 /// because we may need it later.
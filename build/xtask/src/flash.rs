@@ -191,6 +191,10 @@ pub fn config(
 
             flash
         }
+        "qemu-mps2-an386" => {
+            // Emulated; there's nothing to flash. See `xtask qemu`.
+            return Ok(None);
+        }
         _ => {
             eprintln!("Warning: unrecognized board, won't know how to flash.");
             return Ok(None);
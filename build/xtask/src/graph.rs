@@ -29,6 +29,7 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
         from: &'a String,
         to: &'a String,
         inverted: bool,
+        exempt: bool,
     }
 
     #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
@@ -55,6 +56,7 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
                 from: &name,
                 to: &callee,
                 inverted,
+                exempt: task.ipc_exceptions.contains(callee),
             });
             let rank = Rank {
                 from: task.priority,
@@ -86,7 +88,9 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
         }
     }
     for edge in edges {
-        let attr = if edge.inverted {
+        let attr = if edge.inverted && edge.exempt {
+            " [color=red, style=dashed]"
+        } else if edge.inverted {
             " [color=red, penwidth=3]"
         } else {
             " [color=green]"
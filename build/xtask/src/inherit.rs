@@ -0,0 +1,439 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Building one `app.toml` on top of another.
+//!
+//! An `app.toml` may say `inherit = "base.toml"`, with the path relative to
+//! itself, to start from everything in the base and only write down what's
+//! different. The two are merged key by key:
+//!
+//! - Tables are merged recursively. That covers `[tasks]`, so a task the base
+//!   doesn't have is added, while a task it does have is only changed where
+//!   the overlay says so, and it covers `config` tables.
+//! - `features` in `[kernel]` and in tasks are appended to the base's,
+//!   skipping any it already has.
+//! - Any other value, arrays included, replaces the base's.
+//! - `remove-tasks = ["name", ...]` drops tasks that the base has.
+//!
+//! Keys keep their place in the base, and new ones go after, so tasks keep
+//! the base's numbering except where some are removed; a base can also hold
+//! placeholder values just to keep their place. A base may itself inherit
+//! from another. Relative paths in any of the files, like `chip`, are still
+//! taken relative to the `app.toml` being built, so bases usually live next
+//! to the files that inherit them.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ordered_toml::{value::Table, Value};
+
+/// An `app.toml` with everything it inherits merged in.
+pub struct AppToml {
+    /// The merged configuration.
+    pub table: Table,
+    /// The files that went into it and their contents, starting with the one
+    /// that was asked for and ending with the base that inherits nothing.
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl AppToml {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut files = vec![];
+        let table = load(path, &mut files)?;
+        Ok(Self { table, files })
+    }
+
+    /// Returns whether this is built on another file.
+    pub fn inherits(&self) -> bool {
+        self.files.len() > 1
+    }
+
+    /// Returns the merged configuration as TOML. If nothing was inherited,
+    /// this is the file as written, comments and all.
+    pub fn to_toml(&self) -> String {
+        if self.inherits() {
+            let mut out = String::new();
+            write_table(&mut out, &mut vec![], &self.table);
+            out
+        } else {
+            String::from_utf8_lossy(&self.files[0].1).into_owned()
+        }
+    }
+}
+
+fn load(path: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) -> Result<Table> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("reading {}", path.display()))?;
+    if files
+        .iter()
+        .any(|(p, _)| p.canonicalize().ok().as_ref() == Some(&canonical))
+    {
+        bail!("{} inherits from itself", path.display());
+    }
+    let contents = std::fs::read(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let table: Table = ordered_toml::from_slice(&contents)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    files.push((path.to_owned(), contents));
+
+    let mut overlay = Table::new();
+    let mut base_path = None;
+    let mut removed: Vec<String> = vec![];
+    for (key, value) in table {
+        match key.as_str() {
+            "inherit" => {
+                let p = value.as_str().ok_or_else(|| {
+                    anyhow!("{}: inherit must be a path", path.display())
+                })?;
+                base_path = Some(path.parent().unwrap().join(p));
+            }
+            "remove-tasks" => {
+                removed = value.try_into().with_context(|| {
+                    format!("{}: remove-tasks", path.display())
+                })?;
+            }
+            _ => {
+                overlay.insert(key, value);
+            }
+        }
+    }
+
+    let base_path = match base_path {
+        Some(p) => p,
+        None if removed.is_empty() => return Ok(overlay),
+        None => bail!(
+            "{} has remove-tasks, but doesn't inherit anything",
+            path.display()
+        ),
+    };
+    let mut base = load(&base_path, files)?;
+
+    if !removed.is_empty() {
+        let tasks = base
+            .get_mut("tasks")
+            .and_then(Value::as_table_mut)
+            .ok_or_else(|| anyhow!("{} has no tasks", base_path.display()))?;
+        if let Some(name) = removed.iter().find(|n| !tasks.contains_key(*n)) {
+            bail!(
+                "{} removes task {}, but {} has no such task",
+                path.display(),
+                name,
+                base_path.display()
+            );
+        }
+        // Rebuild rather than removing in place, which would reorder tasks.
+        *tasks = std::mem::take(tasks)
+            .into_iter()
+            .filter(|(name, _)| !removed.contains(name))
+            .collect();
+    }
+
+    merge(&mut base, overlay, &mut vec![]);
+    Ok(base)
+}
+
+fn merge(base: &mut Table, overlay: Table, path: &mut Vec<String>) {
+    for (key, value) in overlay {
+        let appends = key == "features"
+            && (path == &["kernel"] || (path.len() == 2 && path[0] == "tasks"));
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(b)), Value::Table(o)) => {
+                path.push(key);
+                merge(b, o, path);
+                path.pop();
+            }
+            (Some(Value::Array(b)), Value::Array(o)) if appends => {
+                for f in o {
+                    if !b.contains(&f) {
+                        b.push(f);
+                    }
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// The `toml` serializer mangles arrays that mix tables with other values,
+// which `task-slots` often does, so we write TOML out ourselves: tables as
+// `[headers]`, arrays of tables as `[[headers]]`, and anything within an
+// array inline.
+
+fn write_table(out: &mut String, path: &mut Vec<String>, table: &Table) {
+    // Plain values have to come before any headers.
+    for (k, v) in table {
+        if !v.is_table() && !is_table_array(v) {
+            out.push_str(&format!("{} = {}\n", key(k), to_inline(v)));
+        }
+    }
+    for (k, v) in table {
+        path.push(key(k));
+        if let Value::Table(t) = v {
+            // Only tables that hold nothing but other tables can do without
+            // a header of their own.
+            if t.is_empty() || t.values().any(|v| !v.is_table()) {
+                out.push_str(&format!("\n[{}]\n", path.join(".")));
+            }
+            write_table(out, path, t);
+        } else if is_table_array(v) {
+            for t in v.as_array().unwrap() {
+                out.push_str(&format!("\n[[{}]]\n", path.join(".")));
+                write_table(out, path, t.as_table().unwrap());
+            }
+        }
+        path.pop();
+    }
+}
+
+fn is_table_array(v: &Value) -> bool {
+    match v.as_array() {
+        Some(a) => !a.is_empty() && a.iter().all(Value::is_table),
+        None => false,
+    }
+}
+
+/// Writes a value as it would appear on the right of `=`.
+pub fn to_inline(v: &Value) -> String {
+    match v {
+        Value::Array(a) => {
+            let items = a.iter().map(to_inline).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Table(t) if t.is_empty() => "{}".to_string(),
+        Value::Table(t) => {
+            let items = t
+                .iter()
+                .map(|(k, v)| format!("{} = {}", key(k), to_inline(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", items.join(", "))
+        }
+        v => v.to_string(),
+    }
+}
+
+fn key(k: &str) -> String {
+    let bare = !k.is_empty()
+        && k.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if bare {
+        k.to_string()
+    } else {
+        Value::String(k.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a fresh directory, returning its path.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "xtask-inherit-{}-{}",
+            std::process::id(),
+            test
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    /// Writes out a table, so that comparisons take key order into account.
+    fn resolved(table: &Table) -> String {
+        let mut out = String::new();
+        write_table(&mut out, &mut vec![], table);
+        out
+    }
+
+    fn parse(s: &str) -> String {
+        resolved(&ordered_toml::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn no_inherit() {
+        let text = "# comment\nname = \"x\"\n";
+        let dir = write_files("no-inherit", &[("app.toml", text)]);
+        let app = AppToml::load(&dir.join("app.toml")).unwrap();
+        assert!(!app.inherits());
+        assert_eq!(app.to_toml(), text);
+    }
+
+    #[test]
+    fn overrides() {
+        let dir = write_files(
+            "overrides",
+            &[
+                (
+                    "base.toml",
+                    r#"
+                    name = "base"
+                    target = "thumbv7em-none-eabihf"
+                    board = "base"
+
+                    [kernel]
+                    requires = {flash = 1024, ram = 512}
+                    features = ["itm"]
+
+                    [tasks.a]
+                    priority = 0
+                    features = ["x"]
+                    interrupts = {"usart1.irq" = "usart-irq"}
+
+                    [tasks.b]
+                    priority = 1
+                    "#,
+                ),
+                (
+                    "app.toml",
+                    r#"
+                    inherit = "base.toml"
+                    name = "app"
+                    stacksize = 1024
+
+                    [kernel]
+                    requires = {flash = 2048}
+                    features = ["klog", "itm"]
+
+                    [tasks.a]
+                    features = ["y"]
+                    interrupts = {"usart2.irq" = "usart-irq"}
+
+                    [tasks.c]
+                    priority = 2
+                    "#,
+                ),
+            ],
+        );
+        let app = AppToml::load(&dir.join("app.toml")).unwrap();
+        assert_eq!(app.files.len(), 2);
+        assert_eq!(
+            app.to_toml(),
+            parse(
+                r#"
+                name = "app"
+                target = "thumbv7em-none-eabihf"
+                board = "base"
+                stacksize = 1024
+
+                [kernel]
+                # Inline tables are merged like any other
+                requires = {flash = 2048, ram = 512}
+                features = ["itm", "klog"]
+
+                [tasks.a]
+                priority = 0
+                features = ["x", "y"]
+                interrupts = {"usart1.irq" = "usart-irq", "usart2.irq" = "usart-irq"}
+
+                [tasks.b]
+                priority = 1
+
+                [tasks.c]
+                priority = 2
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn remove_and_nest() {
+        let dir = write_files(
+            "remove-and-nest",
+            &[
+                (
+                    "base.toml",
+                    r#"
+                    name = "base"
+
+                    [tasks.a]
+                    priority = 0
+
+                    [tasks.b]
+                    priority = 1
+
+                    [tasks.c]
+                    priority = 2
+                    config = { depth = 1, nested = { keep = true } }
+                    "#,
+                ),
+                (
+                    "mid.toml",
+                    r#"
+                    inherit = "base.toml"
+                    remove-tasks = ["b"]
+                    name = "mid"
+
+                    [tasks.c.config.nested]
+                    added = "mid"
+                    "#,
+                ),
+                (
+                    "top.toml",
+                    r#"
+                    inherit = "mid.toml"
+                    remove-tasks = ["a"]
+
+                    [tasks.b]
+                    priority = 3
+
+                    [tasks.c.config]
+                    depth = 2
+                    "#,
+                ),
+            ],
+        );
+        let app = AppToml::load(&dir.join("top.toml")).unwrap();
+        let files = app.files.iter().map(|(p, _)| p.file_name().unwrap());
+        assert_eq!(
+            files.collect::<Vec<_>>(),
+            ["top.toml", "mid.toml", "base.toml"]
+        );
+        // `b` was removed by `mid.toml`, so `top.toml` adds it back at the end.
+        assert_eq!(
+            app.to_toml(),
+            parse(
+                r#"
+                name = "mid"
+
+                [tasks.c]
+                priority = 2
+                config = { depth = 2, nested = { keep = true, added = "mid" } }
+
+                [tasks.b]
+                priority = 3
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let dir = write_files(
+            "errors",
+            &[
+                ("base.toml", "[tasks.a]\npriority = 0\n"),
+                (
+                    "missing.toml",
+                    "inherit = \"base.toml\"\nremove-tasks = [\"b\"]\n",
+                ),
+                ("orphan.toml", "remove-tasks = [\"a\"]\n"),
+                ("loop.toml", "inherit = \"loop.toml\"\n"),
+            ],
+        );
+        let err = |name: &str| {
+            let e = AppToml::load(&dir.join(name)).err().unwrap();
+            format!("{:#}", e)
+        };
+        assert!(err("missing.toml").contains("no such task"));
+        assert!(err("orphan.toml").contains("doesn't inherit anything"));
+        assert!(err("loop.toml").contains("inherits from itself"));
+        assert!(err("nonexistent.toml").contains("reading"));
+    }
+}
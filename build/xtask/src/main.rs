@@ -12,12 +12,16 @@ use crate::config::Config;
 mod auxflash;
 mod clippy;
 mod config;
+mod diff;
 mod dist;
 mod elf;
 mod flash;
 mod graph;
 mod humility;
+mod inherit;
+mod qemu;
 mod sizes;
+mod stack;
 mod task_slot;
 
 #[derive(Debug, Parser)]
@@ -87,6 +91,26 @@ enum Xtask {
         args: HumilityArgs,
     },
 
+    /// Runs `xtask dist` and boots the image under QEMU, for boards that can
+    /// be emulated
+    Qemu {
+        /// Request verbosity from tools we shell out to.
+        #[clap(short)]
+        verbose: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Image name to run
+        #[clap(long)]
+        image_name: Option<String>,
+        /// Allow operation in a dirty checkout, i.e. don't clean before
+        /// rebuilding even if it looks like we need to.
+        #[clap(long)]
+        dirty: bool,
+        /// Extra options to pass to QEMU
+        #[clap(last = true)]
+        extra_options: Vec<String>,
+    },
+
     /// Runs `xtask dist` and reports the sizes of resulting tasks
     Sizes {
         /// Request verbosity from tools we shell out to.
@@ -108,18 +132,48 @@ enum Xtask {
         dirty: bool,
     },
 
+    /// Runs `xtask dist` and works out each task's worst-case stack use from
+    /// its call graph, flagging tasks whose `stacksize` is too small or far
+    /// bigger than they need
+    Stack {
+        /// Request verbosity from tools we shell out to.
+        #[clap(short)]
+        verbose: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Print each task's deepest call path, and the calls the analysis
+        /// couldn't follow
+        #[clap(long)]
+        paths: bool,
+        /// Allow operation in a dirty checkout, i.e. don't clean before
+        /// rebuilding even if it looks like we need to.
+        #[clap(long)]
+        dirty: bool,
+    },
+
     /// Runs `humility`, passing any arguments
     Humility {
         #[clap(flatten)]
         args: HumilityArgs,
     },
 
-    /// Runs `xtask dist`, `xtask flash` and then `humility test`
+    /// Runs `xtask dist`, `xtask flash` and then `humility test`, or with
+    /// `--qemu`, runs `xtask dist` and the test image under QEMU
     Test {
         /// Do not flash a new image; just run `humility test`
         #[clap(long, short)]
         noflash: bool,
 
+        /// Run the test image under QEMU rather than on an attached target,
+        /// passing any extra options to QEMU instead of Humility
+        #[clap(long, conflicts_with = "noflash")]
+        qemu: bool,
+
+        /// When running under QEMU, how long to wait (in seconds) for the
+        /// suite to finish
+        #[clap(long, default_value_t = 300)]
+        timeout: u64,
+
         #[clap(flatten)]
         args: HumilityArgs,
     },
@@ -149,7 +203,8 @@ enum Xtask {
 
     /// Generate a graph of task_slot dependencies ordered by priority.
     ///
-    /// Priority inversions are denoted by thick red arrows.
+    /// Priority inversions are denoted by thick red arrows, or dashed red
+    /// arrows if listed in the task's `ipc-exceptions`.
     /// Normal task_slot dependencies are thin green arrows.
     /// Example:
     ///
@@ -163,6 +218,26 @@ enum Xtask {
         /// Path to the image configuration file, in TOML.
         cfg: PathBuf,
     },
+
+    /// Compares two build archives from `xtask dist`: their tasks and how
+    /// they're wired together, memory layout, Idol interfaces, kernel
+    /// configuration and image header
+    Diff {
+        /// The archive to compare against
+        old: PathBuf,
+        /// The archive to compare
+        new: PathBuf,
+    },
+
+    /// Lists the files an image configuration is built from, following
+    /// `inherit`, or prints the configuration they add up to
+    Config {
+        /// Print the configuration with everything it inherits merged in
+        #[clap(long)]
+        resolved: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+    },
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -210,7 +285,8 @@ fn run(xtask: Xtask) -> Result<()> {
             cfg,
             dirty,
         } => {
-            let allocs = dist::package(verbose, edges, &cfg, None, dirty)?;
+            let allocs =
+                dist::package(verbose, edges, false, &cfg, None, dirty)?;
             for (_, (a, _)) in allocs {
                 sizes::run(&cfg, &a, true, false, false)?;
             }
@@ -226,11 +302,11 @@ fn run(xtask: Xtask) -> Result<()> {
             if list {
                 dist::list_tasks(&cfg)?;
             } else {
-                dist::package(verbose, edges, &cfg, Some(tasks), dirty)?;
+                dist::package(verbose, edges, false, &cfg, Some(tasks), dirty)?;
             }
         }
         Xtask::Flash { dirty, mut args } => {
-            dist::package(args.verbose, false, &args.cfg, None, dirty)?;
+            dist::package(args.verbose, false, false, &args.cfg, None, dirty)?;
             let toml = Config::from_file(&args.cfg)?;
             let chip = ["-c", crate::flash::chip_name(&toml.board)?];
             args.extra_options.push("--force".to_string());
@@ -253,11 +329,21 @@ fn run(xtask: Xtask) -> Result<()> {
             save,
             dirty,
         } => {
-            let allocs = dist::package(verbose, false, &cfg, None, dirty)?;
+            let allocs =
+                dist::package(verbose, false, false, &cfg, None, dirty)?;
             for (_, (a, _)) in allocs {
                 sizes::run(&cfg, &a, false, compare, save)?;
             }
         }
+        Xtask::Stack {
+            verbose,
+            cfg,
+            paths,
+            dirty,
+        } => {
+            dist::package(verbose, false, true, &cfg, None, dirty)?;
+            stack::run(&cfg, paths)?;
+        }
         Xtask::Humility { args } => {
             let toml = Config::from_file(&args.cfg)?;
             let image_name = if let Some(ref name) = args.image_name {
@@ -281,14 +367,47 @@ fn run(xtask: Xtask) -> Result<()> {
                 &toml.image_names[0]
             };
             if !noflash {
-                dist::package(args.verbose, false, &args.cfg, None, false)?;
+                dist::package(
+                    args.verbose,
+                    false,
+                    false,
+                    &args.cfg,
+                    None,
+                    false,
+                )?;
                 // Delegate flashing to `humility gdb`, which also modifies
                 // the GDB startup script slightly (adding `stepi`)
                 args.extra_options.push("--load".to_string());
             }
             humility::run(&args, &[], Some("gdb"), true, image_name)?;
         }
-        Xtask::Test { args, noflash } => {
+        Xtask::Qemu {
+            verbose,
+            cfg,
+            image_name,
+            dirty,
+            extra_options,
+        } => {
+            let toml = Config::from_file(&cfg)?;
+            let image_name = if let Some(ref name) = image_name {
+                if !toml.check_image_name(name) {
+                    bail!("Image name {} not declared in TOML", name);
+                }
+                name
+            } else {
+                &toml.image_names[0]
+            };
+            // Check this before spending time on a build.
+            qemu::machine(&toml.board)?;
+            dist::package(verbose, false, false, &cfg, None, dirty)?;
+            qemu::run(&toml, image_name, &extra_options)?;
+        }
+        Xtask::Test {
+            args,
+            noflash,
+            qemu,
+            timeout,
+        } => {
             let toml = Config::from_file(&args.cfg)?;
             let image_name = if let Some(ref name) = args.image_name {
                 if !toml.check_image_name(name) {
@@ -298,6 +417,24 @@ fn run(xtask: Xtask) -> Result<()> {
             } else {
                 &toml.image_names[0]
             };
+            if qemu {
+                qemu::machine(&toml.board)?;
+                dist::package(
+                    args.verbose,
+                    false,
+                    false,
+                    &args.cfg,
+                    None,
+                    false,
+                )?;
+                qemu::test(
+                    &toml,
+                    image_name,
+                    &args.extra_options,
+                    std::time::Duration::from_secs(timeout),
+                )?;
+                return Ok(());
+            }
             if !noflash {
                 run(Xtask::Flash {
                     args: args.clone(),
@@ -320,6 +457,20 @@ fn run(xtask: Xtask) -> Result<()> {
         Xtask::Graph { output, cfg } => {
             graph::task_graph(&cfg, &output)?;
         }
+        Xtask::Diff { old, new } => {
+            diff::run(&old, &new)?;
+        }
+        Xtask::Config { resolved, cfg } => {
+            if resolved {
+                // Go through `Config` so that mistakes in the merged result
+                // are caught here, rather than at the next build.
+                print!("{}", Config::from_file(&cfg)?.resolved_toml);
+            } else {
+                for (path, _) in inherit::AppToml::load(&cfg)?.files {
+                    println!("{}", path.display());
+                }
+            }
+        }
     }
 
     Ok(())
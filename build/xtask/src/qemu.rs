@@ -0,0 +1,193 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::env;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::Config;
+
+/// Returns the QEMU machine that emulates `board`, or an error if there isn't
+/// one.
+pub fn machine(board: &str) -> Result<&'static str> {
+    let m = match board {
+        "qemu-mps2-an386" => "mps2-an386",
+        _ => bail!("board {} can't be run under QEMU", board),
+    };
+
+    Ok(m)
+}
+
+/// Builds a QEMU command line that boots an image built by `xtask dist`.
+fn command(
+    toml: &Config,
+    image_name: &str,
+    extra_options: &[String],
+) -> Result<Command> {
+    let image = Path::new("target")
+        .join(&toml.name)
+        .join("dist")
+        .join(image_name)
+        .join("final.ihex");
+
+    let qemu_path = match env::var("HUBRIS_QEMU_PATH") {
+        Ok(path) => path,
+        _ => "qemu-system-arm".to_string(),
+    };
+
+    let mut qemu = Command::new(qemu_path);
+    qemu.arg("-machine")
+        .arg(machine(&toml.board)?)
+        .arg("-display")
+        .arg("none")
+        // Share our terminal between the serial port, the QEMU monitor and
+        // semihosting. Without a chardev of its own, semihosting output goes
+        // to QEMU's stderr.
+        .arg("-chardev")
+        .arg("stdio,mux=on,id=console")
+        .arg("-serial")
+        .arg("chardev:console")
+        .arg("-mon")
+        .arg("chardev=console,mode=readline")
+        // Tasks log (and the test runner reports) through semihosting, from
+        // unprivileged mode.
+        .arg("-semihosting-config")
+        .arg("enable=on,target=native,userspace=on,chardev=console")
+        // The CPU picks up its initial stack pointer and reset vector from
+        // the vector table at the start of the image when it comes out of
+        // reset, so loading the image is all we need to do.
+        .arg("-device")
+        .arg(format!("loader,file={}", image.display()));
+
+    for opt in extra_options {
+        qemu.arg(opt);
+    }
+
+    Ok(qemu)
+}
+
+/// Boots an image under QEMU, with its console attached to ours, until QEMU
+/// exits.
+pub fn run(
+    toml: &Config,
+    image_name: &str,
+    extra_options: &[String],
+) -> Result<()> {
+    let mut qemu = command(toml, image_name, extra_options)?;
+
+    eprintln!("Starting QEMU; type Ctrl-A X to exit");
+    let status = qemu
+        .status()
+        .with_context(|| format!("failed to run QEMU ({:?})", qemu))?;
+
+    if !status.success() {
+        bail!("QEMU failed");
+    }
+
+    Ok(())
+}
+
+/// Boots a test image (one with `test-runner` as its supervisor) under QEMU,
+/// and follows the runner's report until the suite finishes or `timeout`
+/// elapses. See the `test-runner` docs for the report format.
+pub fn test(
+    toml: &Config,
+    image_name: &str,
+    extra_options: &[String],
+    timeout: Duration,
+) -> Result<()> {
+    let mut qemu = command(toml, image_name, extra_options)?;
+    qemu.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = qemu
+        .spawn()
+        .with_context(|| format!("failed to run QEMU ({:?})", qemu))?;
+
+    // Read QEMU's output on other threads, so that we can give up on it if
+    // the suite hangs. The report should arrive on stdout, but we follow
+    // stderr too, both for QEMU's own errors and in case this version of QEMU
+    // sends semihosting there.
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    forward_lines(stdout, tx.clone());
+    forward_lines(stderr, tx);
+
+    let deadline = Instant::now() + timeout;
+    let mut current = None;
+    let mut passed = 0;
+    let mut failed = vec![];
+
+    let result = loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(left) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let blame = current
+                    .map(|name| format!(" (while running {})", name))
+                    .unwrap_or_default();
+                break Err(format!(
+                    "test suite timed out after {}s{}",
+                    timeout.as_secs(),
+                    blame
+                ));
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                break Err("QEMU exited before the suite finished".to_string());
+            }
+        };
+        println!("{}", line);
+
+        if let Some(name) = line.strip_prefix("start ") {
+            current = Some(name.to_string());
+        } else if line.starts_with("finish ok ") {
+            passed += 1;
+            current = None;
+        } else if let Some(name) = line.strip_prefix("finish FAIL ") {
+            failed.push(name.to_string());
+            current = None;
+        } else if let Some(status) = line.strip_prefix("done ") {
+            break if status == "pass" && failed.is_empty() {
+                Ok(())
+            } else {
+                Err("test suite failed".to_string())
+            };
+        } else if line.starts_with("panicked at") {
+            // This is the kernel's panic handler; tasks' panics are reported
+            // as faults by the runner instead.
+            break Err("kernel panicked".to_string());
+        }
+    };
+
+    // The runner never exits, so we have to stop QEMU ourselves.
+    let _ = child.kill();
+    child.wait()?;
+
+    println!("{} passed, {} failed", passed, failed.len());
+    for name in &failed {
+        println!("  FAIL {}", name);
+    }
+
+    result.map_err(anyhow::Error::msg)
+}
+
+/// Sends each line read from `from` to `tx`, on a thread of its own, until
+/// either end is closed.
+fn forward_lines(from: impl Read + Send + 'static, tx: mpsc::Sender<String>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(from).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
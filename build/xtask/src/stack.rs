@@ -0,0 +1,637 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Static worst-case stack analysis for tasks.
+//!
+//! `xtask stack` builds tasks with `-Z emit-stack-sizes`, which leaves a
+//! `.stack_sizes` section in each ELF recording how much stack every
+//! function's frame takes. We disassemble the task to find the calls between
+//! its functions, then take the deepest path through that call graph from the
+//! entry point.
+//!
+//! This can't follow indirect calls (function pointers and trait objects) or
+//! bound recursion, so the depth of a task that does either is only a lower
+//! bound. Code that wasn't built with the flag, such as the precompiled `core`
+//! and `compiler_builtins`, has no `.stack_sizes` entries; we estimate its
+//! frames from the function prologues instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use capstone::prelude::*;
+use colored::*;
+
+use crate::{config::Config, elf};
+
+/// A stack at least this many times bigger than a task's worst case is
+/// reported as oversized...
+const OVERSIZE_FACTOR: u64 = 2;
+
+/// ...as long as it wastes at least this many bytes.
+const OVERSIZE_SLACK: u64 = 1024;
+
+/// What we learned about one function from the task's ELF.
+struct Function {
+    name: String,
+    /// Bytes of stack used by this function's own frame
+    frame: u64,
+    /// Whether the compiler told us `frame`, rather than us estimating it
+    reported: bool,
+    /// Addresses of the functions this one calls (or tail calls)
+    calls: BTreeSet<u32>,
+    /// Whether this function makes calls we can't follow
+    indirect: bool,
+}
+
+/// Worst-case stack use of a task, and how far to trust it.
+struct StackDepth {
+    bytes: u64,
+    /// The deepest call chain, starting at the entry point, with the frame
+    /// size of each function
+    path: Vec<(String, u64)>,
+    /// Functions that make calls we can't follow
+    indirect: BTreeSet<String>,
+    /// Functions that are part of a recursive cycle
+    recursive: BTreeSet<String>,
+    /// Number of reachable functions whose frames we estimated
+    estimated: usize,
+}
+
+impl StackDepth {
+    /// Returns `true` if `bytes` is only a lower bound.
+    fn is_lower_bound(&self) -> bool {
+        !self.indirect.is_empty() || !self.recursive.is_empty()
+    }
+}
+
+/// Analyzes the stack use of every task in the app, which must already have
+/// been built by `xtask dist`.
+///
+/// Returns an error if any task is known to need more stack than it's given.
+pub fn run(cfg: &Path, paths: bool) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    let exception_frame = exception_frame_size(&toml.target)?;
+
+    let mut depths = vec![];
+    for (name, task) in &toml.tasks {
+        let stacksize = task.stacksize.or(toml.stacksize).unwrap() as u64;
+        let elf = Path::new("target")
+            .join(&toml.name)
+            .join("dist")
+            .join(format!("{}.tmp", name));
+        let depth = task_stack_depth(&elf)
+            .with_context(|| format!("failed to analyze {}", name))?;
+        depths.push((name, stacksize, depth));
+    }
+
+    let pad = toml.tasks.keys().map(|k| k.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:<pad$}  {:>6}  {:>7}",
+        "TASK",
+        "STACK",
+        "NEEDS",
+        pad = pad
+    );
+
+    let mut too_small = vec![];
+    for (name, stacksize, depth) in &depths {
+        // Interrupts and syscalls stack the task's registers on its own stack
+        // before the kernel takes over.
+        let needs = depth.bytes + exception_frame;
+        let needs_str = format!(
+            "{}{}",
+            if depth.is_lower_bound() { ">=" } else { "" },
+            needs
+        );
+        print!(
+            "{:<pad$}  {:>6}  {:>7}",
+            name,
+            stacksize,
+            needs_str,
+            pad = pad
+        );
+
+        if needs > *stacksize {
+            let suggestion = (needs + 7) & !7;
+            print!(
+                "  {} (needs at least {})",
+                "too small".red().bold(),
+                suggestion
+            );
+            too_small.push(name.as_str());
+        } else if !depth.is_lower_bound()
+            && *stacksize >= needs * OVERSIZE_FACTOR
+            && *stacksize - needs >= OVERSIZE_SLACK
+        {
+            print!("  {}", "oversized".yellow());
+        }
+        println!();
+    }
+
+    if depths.iter().any(|(_, _, d)| d.is_lower_bound()) {
+        println!(
+            "\n{}: these tasks make indirect or recursive calls, so their \
+             true needs may be higher",
+            ">=".bold()
+        );
+    }
+    println!(
+        "Each figure includes {} bytes for the exception frame.",
+        exception_frame
+    );
+
+    for (name, stacksize, depth) in &depths {
+        if paths || too_small.contains(&name.as_str()) {
+            print_details(name, *stacksize, depth);
+        }
+    }
+
+    if !too_small.is_empty() {
+        bail!(
+            "{} may overflow {} stack{}; increase `stacksize` in the app.toml",
+            too_small.join(", "),
+            if too_small.len() == 1 { "its" } else { "their" },
+            if too_small.len() == 1 { "" } else { "s" },
+        );
+    }
+    Ok(())
+}
+
+fn print_details(name: &str, stacksize: u64, depth: &StackDepth) {
+    println!("\n{} (stacksize {}):", name.bold(), stacksize);
+    println!("  deepest path:");
+    for (function, frame) in &depth.path {
+        println!("    {:>6}  {}", frame, function);
+    }
+    if depth.estimated > 0 {
+        println!(
+            "  {} frame sizes estimated from function prologues",
+            depth.estimated
+        );
+    }
+    if !depth.indirect.is_empty() {
+        println!("  indirect calls from:");
+        for f in &depth.indirect {
+            println!("    {}", f);
+        }
+    }
+    if !depth.recursive.is_empty() {
+        println!("  recursion through:");
+        for f in &depth.recursive {
+            println!("    {}", f);
+        }
+    }
+}
+
+/// Returns the size of the frame the processor pushes onto the task's stack
+/// on exception entry. On targets with an FPU we assume the extended frame.
+fn exception_frame_size(target: &str) -> Result<u64> {
+    match target {
+        "thumbv6m-none-eabi" => Ok(32),
+        "thumbv7em-none-eabihf" | "thumbv8m.main-none-eabihf" => Ok(104),
+        _ => bail!("no exception frame size for '{}'", target),
+    }
+}
+
+/// Computes the worst-case stack depth of the task in the given ELF file.
+fn task_stack_depth(path: &Path) -> Result<StackDepth> {
+    let buf = std::fs::read(path)
+        .with_context(|| format!("could not read {}", path.display()))?;
+    let elf = goblin::elf::Elf::parse(&buf)?;
+
+    let reported = read_stack_sizes(&elf, &buf)?;
+    if reported.is_empty() {
+        bail!(
+            "{} has no .stack_sizes section; rebuild it with `xtask stack`",
+            path.display()
+        );
+    }
+
+    let cs = disassembler()?;
+
+    let mut functions = BTreeMap::new();
+    for sym in elf.syms.iter() {
+        if sym.st_type() != goblin::elf::sym::STT_FUNC || sym.st_size == 0 {
+            continue;
+        }
+        // Clear the Thumb bit.
+        let addr = (sym.st_value & !1) as u32;
+        if functions.contains_key(&addr) {
+            continue;
+        }
+
+        let name = elf.strtab.get_at(sym.st_name).unwrap_or("?");
+        let section = elf::get_section_by_vma(&elf, addr as u64)
+            .ok_or_else(|| anyhow!("{} is outside of any section", name))?;
+        let start =
+            (section.sh_offset + addr as u64 - section.sh_addr) as usize;
+        let code = buf
+            .get(start..start + sym.st_size as usize)
+            .ok_or_else(|| anyhow!("{} runs past the end of the file", name))?;
+        let insns = cs
+            .disasm_all(code, addr as u64)
+            .map_err(|e| anyhow!("could not disassemble {}: {}", name, e))?;
+
+        let range = addr..addr + sym.st_size as u32;
+        let mut f = scan_function(&insns, range);
+        f.name = format!("{:#}", rustc_demangle::demangle(name));
+        if let Some(&frame) = reported.get(&addr) {
+            // Naked functions (like the syscall stubs) report no frame even
+            // when their assembly pushes registers, so trust whichever is
+            // bigger.
+            f.frame = f.frame.max(frame);
+            f.reported = true;
+        }
+        functions.insert(addr, f);
+    }
+
+    let mut walk = Walk {
+        functions: &functions,
+        memo: BTreeMap::new(),
+        active: BTreeSet::new(),
+        indirect: BTreeSet::new(),
+        recursive: BTreeSet::new(),
+    };
+    let entry = (elf.entry & !1) as u32;
+    let bytes = walk.depth(entry);
+
+    let mut path = vec![];
+    let mut next = Some(entry);
+    while let Some(addr) = next {
+        let name = functions
+            .get(&addr)
+            .map(|f| f.name.clone())
+            .unwrap_or_else(|| format!("{:#010x}", addr));
+        let frame = functions.get(&addr).map(|f| f.frame).unwrap_or(0);
+        path.push((name, frame));
+        next = walk.memo.get(&addr).and_then(|&(_, next)| next);
+    }
+
+    let name = |addr: &u32| {
+        functions
+            .get(addr)
+            .map(|f| f.name.clone())
+            .unwrap_or_else(|| format!("{:#010x}", addr))
+    };
+    Ok(StackDepth {
+        bytes,
+        path,
+        indirect: walk.indirect.iter().map(name).collect(),
+        recursive: walk.recursive.iter().map(name).collect(),
+        estimated: walk
+            .memo
+            .keys()
+            .filter(|a| functions.get(a).map_or(false, |f| !f.reported))
+            .count(),
+    })
+}
+
+/// Returns a disassembler for M-profile Thumb code.
+fn disassembler() -> Result<Capstone> {
+    Capstone::new()
+        .arm()
+        .mode(arch::arm::ArchMode::Thumb)
+        .extra_mode([arch::arm::ArchExtraMode::MClass].iter().copied())
+        .build()
+        .map_err(|e| anyhow!("could not start disassembler: {}", e))
+}
+
+/// Reads the `.stack_sizes` section, which is a sequence of (function
+/// address, ULEB128 frame size) pairs, returning a map from (Thumb bit clear)
+/// address to frame size.
+fn read_stack_sizes(
+    elf: &goblin::elf::Elf,
+    buf: &[u8],
+) -> Result<BTreeMap<u32, u64>> {
+    let mut out = BTreeMap::new();
+    let section = match elf::get_section_by_name(elf, ".stack_sizes") {
+        Some(s) => s,
+        None => return Ok(out),
+    };
+    let start = section.sh_offset as usize;
+    let mut data = buf
+        .get(start..start + section.sh_size as usize)
+        .ok_or_else(|| anyhow!(".stack_sizes runs past the end of the file"))?;
+
+    while !data.is_empty() {
+        if data.len() < 4 {
+            bail!("truncated .stack_sizes entry");
+        }
+        let addr = u32::from_le_bytes(data[..4].try_into().unwrap());
+        data = &data[4..];
+
+        let mut size = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = data
+                .split_first()
+                .ok_or_else(|| anyhow!("truncated .stack_sizes entry"))?;
+            data = rest;
+            size |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        out.insert(addr & !1, size);
+    }
+    Ok(out)
+}
+
+/// Finds the calls a function makes, and estimates its frame size from its
+/// prologue: the registers it pushes, and what it subtracts from `sp`,
+/// before its first branch or call.
+fn scan_function(
+    insns: &capstone::Instructions,
+    range: Range<u32>,
+) -> Function {
+    const CONDITIONS: [&str; 16] = [
+        "eq", "ne", "cs", "hs", "cc", "lo", "mi", "pl", "vs", "vc", "hi", "ls",
+        "ge", "lt", "gt", "le",
+    ];
+
+    let mut f = Function {
+        name: String::new(),
+        frame: 0,
+        reported: false,
+        calls: BTreeSet::new(),
+        indirect: false,
+    };
+    let mut in_prologue = true;
+
+    for insn in insns.iter() {
+        let mnemonic = insn.mnemonic().unwrap_or("");
+        let op = mnemonic.trim_end_matches(".w").trim_end_matches(".n");
+        let operands = insn.op_str().unwrap_or("");
+        // Direct branches and calls have a bare immediate target.
+        let target = operands
+            .strip_prefix("#0x")
+            .and_then(|t| u32::from_str_radix(t, 16).ok())
+            .map(|t| t & !1);
+
+        if in_prologue {
+            match op {
+                "push" => f.frame += 4 * count_registers(operands),
+                "vpush" => {
+                    let width = if operands.contains('d') { 8 } else { 4 };
+                    f.frame += width * count_registers(operands);
+                }
+                "sub" | "subw" if operands.starts_with("sp, ") => {
+                    f.frame += operands
+                        .rsplit(' ')
+                        .next()
+                        .and_then(parse_immediate)
+                        .unwrap_or(0);
+                }
+                _ => (),
+            }
+        }
+
+        let is_branch = op == "b"
+            || op == "cbz"
+            || op == "cbnz"
+            || (op.len() == 3
+                && op.starts_with('b')
+                && CONDITIONS.contains(&&op[1..]));
+        match op {
+            "bl" | "blx" => match target {
+                Some(t) => {
+                    f.calls.insert(t);
+                }
+                None => f.indirect = true,
+            },
+            "bx" if operands != "lr" => f.indirect = true,
+            // Loading `pc` from the stack, or moving `lr` into it, is a
+            // return; anything else is a computed jump.
+            "ldr" | "mov"
+                if operands.starts_with("pc, ")
+                    && !operands.starts_with("pc, [sp]")
+                    && operands != "pc, lr" =>
+            {
+                f.indirect = true
+            }
+            "ldr" | "mov" if operands.starts_with("pc, ") => (),
+            _ if is_branch => {
+                // A branch out of the function is a tail call.
+                if let Some(t) = target.filter(|t| !range.contains(t)) {
+                    f.calls.insert(t);
+                }
+            }
+            "bx" | "pop" => (),
+            _ => continue,
+        }
+        in_prologue = false;
+    }
+    f
+}
+
+/// Counts the registers in a list like `{r4, r5, r7, lr}`.
+fn count_registers(list: &str) -> u64 {
+    list.trim_matches(|c| c == '{' || c == '}')
+        .split(',')
+        .map(|r| match r.trim().split_once('-') {
+            // Ranges, such as `d8-d15`
+            Some((lo, hi)) => {
+                let n = |r: &str| r[1..].parse::<u64>().unwrap_or(0);
+                n(hi).saturating_sub(n(lo)) + 1
+            }
+            None => 1,
+        })
+        .sum()
+}
+
+/// Parses an immediate operand such as `#0x48` or `#8`.
+fn parse_immediate(s: &str) -> Option<u64> {
+    let s = s.strip_prefix('#')?;
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Depth-first walk of a task's call graph, memoizing each function's
+/// worst-case depth and its deepest callee.
+struct Walk<'a> {
+    functions: &'a BTreeMap<u32, Function>,
+    memo: BTreeMap<u32, (u64, Option<u32>)>,
+    /// Functions on the current path, for spotting recursion
+    active: BTreeSet<u32>,
+    indirect: BTreeSet<u32>,
+    recursive: BTreeSet<u32>,
+}
+
+impl Walk<'_> {
+    fn depth(&mut self, addr: u32) -> u64 {
+        if let Some(&(depth, _)) = self.memo.get(&addr) {
+            return depth;
+        }
+        if !self.active.insert(addr) {
+            self.recursive.insert(addr);
+            return 0;
+        }
+
+        let functions = self.functions;
+        let (depth, deepest) = match functions.get(&addr) {
+            Some(f) => {
+                if f.indirect {
+                    self.indirect.insert(addr);
+                }
+                let mut deepest = None;
+                let mut max = 0;
+                for &callee in &f.calls {
+                    let d = self.depth(callee);
+                    if deepest.is_none() || d > max {
+                        deepest = Some(callee);
+                        max = d;
+                    }
+                }
+                (f.frame + max, deepest)
+            }
+            // A call to an address without a function symbol: we can't see
+            // what it does.
+            None => {
+                self.indirect.insert(addr);
+                (0, None)
+            }
+        };
+
+        self.active.remove(&addr);
+        self.memo.insert(addr, (depth, deepest));
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minimal little-endian ELF32 file whose only section (besides
+    /// the section name table) is a `.stack_sizes` holding `data`.
+    fn elf_with_stack_sizes(data: &[u8]) -> Vec<u8> {
+        const EHDR: usize = 52;
+        const SHDR: usize = 40;
+        let names = b"\0.shstrtab\0.stack_sizes\0";
+        let names_at = EHDR;
+        let data_at = names_at + names.len();
+        let shdrs_at = (data_at + data.len() + 3) & !3;
+
+        let mut buf = vec![0u8; shdrs_at + 3 * SHDR];
+        buf[..4].copy_from_slice(b"\x7fELF");
+        buf[4] = 1; // ELFCLASS32
+        buf[5] = 1; // ELFDATA2LSB
+        buf[6] = 1; // EV_CURRENT
+        let mut put = |at: usize, bytes: &[u8]| {
+            buf[at..at + bytes.len()].copy_from_slice(bytes)
+        };
+        put(16, &1u16.to_le_bytes()); // ET_REL
+        put(18, &40u16.to_le_bytes()); // EM_ARM
+        put(20, &1u32.to_le_bytes());
+        put(32, &(shdrs_at as u32).to_le_bytes());
+        put(40, &(EHDR as u16).to_le_bytes());
+        put(46, &(SHDR as u16).to_le_bytes());
+        put(48, &3u16.to_le_bytes());
+        put(50, &1u16.to_le_bytes());
+        put(names_at, names);
+        put(data_at, data);
+
+        // Section 0 is the null section.
+        for (i, (name, ty, offset, size)) in [
+            (1u32, 3u32, names_at, names.len()), // SHT_STRTAB
+            (11, 1, data_at, data.len()),        // SHT_PROGBITS
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let at = shdrs_at + (i + 1) * SHDR;
+            put(at, &name.to_le_bytes());
+            put(at + 4, &ty.to_le_bytes());
+            put(at + 16, &(offset as u32).to_le_bytes());
+            put(at + 20, &(size as u32).to_le_bytes());
+            put(at + 32, &1u32.to_le_bytes());
+        }
+        buf
+    }
+
+    fn stack_sizes(data: &[u8]) -> Result<BTreeMap<u32, u64>> {
+        let buf = elf_with_stack_sizes(data);
+        let elf = goblin::elf::Elf::parse(&buf)?;
+        read_stack_sizes(&elf, &buf)
+    }
+
+    fn scan(code: &[u8], addr: u32) -> Function {
+        let cs = disassembler().unwrap();
+        let insns = cs.disasm_all(code, addr as u64).unwrap();
+        // Make sure the fixture decoded completely.
+        let decoded: usize = insns.iter().map(|i| i.bytes().len()).sum();
+        assert_eq!(decoded, code.len());
+        scan_function(&insns, addr..addr + code.len() as u32)
+    }
+
+    #[test]
+    fn stack_sizes_section() {
+        let sizes = stack_sizes(&[
+            0x01, 0x10, 0x00, 0x00, 24, // 0x1001 (Thumb): 24
+            0x00, 0x20, 0x00, 0x00, 0xac, 0x02, // 0x2000: 300
+        ])
+        .unwrap();
+        assert_eq!(sizes, [(0x1000, 24), (0x2000, 300)].into_iter().collect());
+
+        assert!(stack_sizes(&[]).unwrap().is_empty());
+        // Missing the size, or ending in the middle of one
+        assert!(stack_sizes(&[0x00, 0x20, 0x00, 0x00]).is_err());
+        assert!(stack_sizes(&[0x00, 0x20, 0x00, 0x00, 0x80]).is_err());
+        assert!(stack_sizes(&[0x00, 0x20]).is_err());
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(count_registers("{r4}"), 1);
+        assert_eq!(count_registers("{r4, r5, r7, lr}"), 4);
+        assert_eq!(count_registers("{d8-d15}"), 8);
+        assert_eq!(count_registers("{s16-s17, s20}"), 3);
+    }
+
+    #[test]
+    fn calls_and_prologue() {
+        let f = scan(
+            &[
+                0xb0, 0xb5, // push {r4, r5, r7, lr}
+                0x82, 0xb0, // sub sp, #8
+                0x00, 0xf0, 0xfc, 0xff, // bl #0x2000
+                0x84, 0xb0, // sub sp, #0x10 (after a call: not counted)
+                0x98, 0x47, // blx r3
+                0x00, 0x28, // cmp r0, #0
+                0x00, 0xd0, // beq #0x1012 (within the function)
+                0xf6, 0xe1, // b #0x1400 (tail call)
+                0xb0, 0xbd, // pop {r4, r5, r7, pc}
+            ],
+            0x1000,
+        );
+        assert_eq!(f.frame, 24);
+        assert_eq!(f.calls, [0x2000, 0x1400].into_iter().collect());
+        assert!(f.indirect);
+    }
+
+    #[test]
+    fn leaf() {
+        let f = scan(
+            &[
+                0x2d, 0xed, 0x04, 0x8b, // vpush {d8, d9}
+                0xbd, 0xec, 0x04, 0x8b, // vpop {d8, d9}
+                0x70, 0x47, // bx lr
+            ],
+            0x1000,
+        );
+        assert_eq!(f.frame, 16);
+        assert!(f.calls.is_empty());
+        assert!(!f.indirect);
+
+        // Jumping through a register is a call we can't follow.
+        let f = scan(&[0x87, 0x46], 0x1000); // mov pc, r0
+        assert!(f.calls.is_empty());
+        assert!(f.indirect);
+    }
+}
@@ -12,6 +12,7 @@ use drv_auxflash_api::{
 };
 use idol_runtime::{ClientError, Leased, RequestError, R, W};
 use tlvc::{TlvcRead, TlvcReadError, TlvcReader};
+use userlib::time::Duration;
 use userlib::*;
 
 #[cfg(feature = "h753")]
@@ -117,11 +118,11 @@ fn main() -> ! {
 
     // Ensure hold time for reset in case we just restarted.
     // TODO look up actual hold time requirement
-    hl::sleep_for(1);
+    hl::sleep_for(Duration::from_millis(1));
 
     // Release reset and let it stabilize.
     sys.gpio_set(qspi_reset).unwrap();
-    hl::sleep_for(10);
+    hl::sleep_for(Duration::from_millis(10));
 
     // TODO: check the ID and make sure it's what we expect
     //
@@ -148,9 +149,9 @@ struct ServerImpl {
 impl ServerImpl {
     /// Polls for the "Write Complete" flag.
     ///
-    /// Sleep times are in ticks (typically milliseconds) and are somewhat
-    /// experimentally determined, see hubris#753 for details.
-    fn poll_for_write_complete(&self, sleep: Option<u64>) {
+    /// Sleep times are somewhat experimentally determined, see hubris#753 for
+    /// details.
+    fn poll_for_write_complete(&self, sleep: Option<Duration>) {
         loop {
             let status = self.qspi.read_status();
             if status & 1 == 0 {
@@ -221,7 +222,7 @@ impl ServerImpl {
             if write_addr % SECTOR_SIZE_BYTES == 0 {
                 self.set_and_check_write_enable()?;
                 self.qspi.sector_erase(write_addr as u32);
-                self.poll_for_write_complete(Some(1));
+                self.poll_for_write_complete(Some(Duration::from_millis(1)));
             }
 
             // Write back to the redundant slot
@@ -301,7 +302,7 @@ impl idl::InOrderAuxFlashImpl for ServerImpl {
             self.set_and_check_write_enable()?;
             self.qspi.sector_erase(addr as u32);
             addr += SECTOR_SIZE_BYTES;
-            self.poll_for_write_complete(Some(1));
+            self.poll_for_write_complete(Some(Duration::from_millis(1)));
         }
         Ok(())
     }
@@ -325,7 +326,7 @@ impl idl::InOrderAuxFlashImpl for ServerImpl {
 
         self.set_and_check_write_enable()?;
         self.qspi.sector_erase(addr as u32);
-        self.poll_for_write_complete(Some(1));
+        self.poll_for_write_complete(Some(Duration::from_millis(1)));
         Ok(())
     }
 
@@ -9,6 +9,7 @@
 use core::ops::Deref;
 
 use drv_spi_api::SpiError;
+use userlib::time::Duration;
 use userlib::*;
 use zerocopy::{AsBytes, BigEndian, FromBytes, U32};
 
@@ -310,7 +311,7 @@ impl FpgaUserDesign {
 /// a bitstream or already programmed. The FPGA is reset , resetting the device if needed.
 pub fn await_fpga_ready(
     fpga: &mut Fpga,
-    sleep_ticks: u64,
+    sleep: Duration,
 ) -> Result<DeviceState, FpgaError> {
     loop {
         let state = fpga.state()?;
@@ -325,7 +326,7 @@ pub fn await_fpga_ready(
             }
         }
 
-        userlib::hl::sleep_for(sleep_ticks);
+        userlib::hl::sleep_for(sleep);
     }
 }
 
@@ -7,6 +7,7 @@ use bitfield::bitfield;
 use core::fmt::Debug;
 use drv_fpga_api::{DeviceState, FpgaError};
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::*;
 use zerocopy::{AsBytes, FromBytes};
 
@@ -129,10 +130,10 @@ pub(crate) fn ecp5_trace(t: Trace) {
     ringbuf_entry!(t);
 }
 
-pub const DEVICE_RESET_DURATION: u64 = 25;
-pub const USER_DESIGN_RESET_DURATION: u64 = 25;
-pub const BUSY_DURATION: u64 = 10;
-pub const DONE_DURATION: u64 = 10;
+pub const DEVICE_RESET_DURATION: Duration = Duration::from_millis(25);
+pub const USER_DESIGN_RESET_DURATION: Duration = Duration::from_millis(25);
+pub const BUSY_DURATION: Duration = Duration::from_millis(10);
+pub const DONE_DURATION: Duration = Duration::from_millis(10);
 
 pub trait Ecp5Driver: FpgaUserDesign {
     type Error: Debug;
@@ -185,8 +186,8 @@ pub trait Ecp5Driver: FpgaUserDesign {
         asserted: bool,
     ) -> Result<(), Self::Error>;
 
-    /// Returns the reset duration in ms for the user design.
-    fn user_design_reset_duration(&self) -> u64;
+    /// Returns the reset duration for the user design.
+    fn user_design_reset_duration(&self) -> Duration;
 
     /*
     /// Read/write the user design.
@@ -283,12 +284,12 @@ impl<Driver: Ecp5Driver> Ecp5<Driver> {
     /// given duration between polling events.
     pub fn await_not_busy(
         &self,
-        sleep_ticks: u64,
+        sleep: Duration,
     ) -> Result<Status, Driver::Error> {
         let mut status = self.status()?;
 
         while status.busy() {
-            hl::sleep_for(sleep_ticks);
+            hl::sleep_for(sleep);
             status = self.status()?;
         }
 
@@ -296,9 +297,9 @@ impl<Driver: Ecp5Driver> Ecp5<Driver> {
     }
 
     /// Wait for the DONE flag to go high.
-    pub fn await_done(&self, sleep_ticks: u64) -> Result<(), Driver::Error> {
+    pub fn await_done(&self, sleep: Duration) -> Result<(), Driver::Error> {
         while !self.driver.done()? {
-            hl::sleep_for(sleep_ticks);
+            hl::sleep_for(sleep);
         }
         Ok(())
     }
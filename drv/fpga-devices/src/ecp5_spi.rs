@@ -7,6 +7,7 @@ use crate::FpgaUserDesign;
 use drv_fpga_api::FpgaError;
 use drv_spi_api::{self as spi_api, SpiDevice, SpiError};
 use drv_stm32xx_sys_api::{self as sys_api, GpioError, Sys};
+use userlib::time::Duration;
 
 /// `Ecp5UsingSpi` is the simplest implementation of the Ecp5Impl interface using
 /// the SPI and Sys APIs. It assumes the PROGRAM_N, INIT_N and DONE signals are
@@ -20,7 +21,7 @@ pub struct Ecp5UsingSpi {
     pub init_n: sys_api::PinSet,
     pub program_n: sys_api::PinSet,
     pub user_design_reset_n: sys_api::PinSet,
-    pub user_design_reset_duration: u64,
+    pub user_design_reset_duration: Duration,
 }
 
 /// Impl Error type, with conversion from GpioError and SpiError.
@@ -103,7 +104,7 @@ impl Ecp5Driver for Ecp5UsingSpi {
         Ok(self.sys.gpio_set_to(self.user_design_reset_n, asserted)?)
     }
 
-    fn user_design_reset_duration(&self) -> u64 {
+    fn user_design_reset_duration(&self) -> Duration {
         self.user_design_reset_duration
     }
 
@@ -9,6 +9,7 @@ use drv_i2c_api::ResponseCode;
 use drv_i2c_devices::pca9538;
 use drv_spi_api::{self as spi_api, SpiDevice, SpiError};
 use drv_stm32xx_sys_api::{self as sys_api, GpioError, Sys};
+use userlib::time::Duration;
 
 /// This module implements an ECP5 driver which exposes two physical devices,
 /// which share a single SPI bus using a mux and are controlled through a shared
@@ -71,7 +72,7 @@ pub struct DriverConfig {
     pub user_design: SpiDevice,
     pub spi_mux_select: sys_api::PinSet,
     pub gpio: pca9538::Pca9538,
-    pub user_design_reset_duration: u64,
+    pub user_design_reset_duration: Duration,
 }
 
 pub struct DevicePins {
@@ -167,7 +168,7 @@ impl Driver {
             }
             Ok(()) => {
                 self.device_selected.set(Some(device_id));
-                userlib::hl::sleep_for(1);
+                userlib::hl::sleep_for(Duration::from_millis(1));
                 Ok(())
             }
         };
@@ -236,7 +237,7 @@ impl<'a> Ecp5Driver for DeviceInstance<'a> {
             .set_to(self.pins.user_design_reset_n, val)?)
     }
 
-    fn user_design_reset_duration(&self) -> u64 {
+    fn user_design_reset_duration(&self) -> Duration {
         self.driver.config.user_design_reset_duration
     }
 
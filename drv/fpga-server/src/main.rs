@@ -8,6 +8,7 @@
 #![no_main]
 
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::*;
 use zerocopy::{byteorder, AsBytes, Unaligned, U16};
 
@@ -100,7 +101,7 @@ fn main() -> ! {
                 };
                 match driver.init_devices(device0_pins, device1_pins) {
                     Ok(devices) => break devices,
-                    Err(_) => userlib::hl::sleep_for(10),
+                    Err(_) => userlib::hl::sleep_for(Duration::from_millis(10)),
                 }
             };
         } else if #[cfg(target_board = "gimletlet-2")] {
@@ -20,6 +20,7 @@
 )]
 mod bsp;
 
+use userlib::time::Duration;
 use userlib::*;
 
 use drv_stm32h7_qspi::Qspi;
@@ -93,11 +94,11 @@ fn main() -> ! {
 
     // Ensure hold time for reset in case we just restarted.
     // TODO look up actual hold time requirement
-    hl::sleep_for(1);
+    hl::sleep_for(Duration::from_millis(1));
 
     // Release reset and let it stabilize.
     sys.gpio_set(cfg.reset).unwrap();
-    hl::sleep_for(10);
+    hl::sleep_for(Duration::from_millis(10));
 
     // Check the ID.
     // TODO: If different flash parts are used on the same board name,
@@ -133,7 +134,7 @@ fn main() -> ! {
     if log2_capacity.is_none() {
         loop {
             // We are dead now.
-            hl::sleep_for(1000);
+            hl::sleep_for(Duration::from_millis(1000));
         }
     }
     let log2_capacity = log2_capacity.unwrap();
@@ -219,7 +220,7 @@ impl idl::InOrderHostFlashImpl for ServerImpl {
         self.check_muxed_to_sp()?;
         set_and_check_write_enable(&self.qspi)?;
         self.qspi.bulk_erase();
-        poll_for_write_complete(&self.qspi, Some(100));
+        poll_for_write_complete(&self.qspi, Some(Duration::from_millis(100)));
         Ok(())
     }
 
@@ -265,7 +266,7 @@ impl idl::InOrderHostFlashImpl for ServerImpl {
         self.check_muxed_to_sp()?;
         set_and_check_write_enable(&self.qspi)?;
         self.qspi.sector_erase(addr);
-        poll_for_write_complete(&self.qspi, Some(1));
+        poll_for_write_complete(&self.qspi, Some(Duration::from_millis(1)));
         Ok(())
     }
 
@@ -404,15 +405,15 @@ fn set_and_check_write_enable(
     Ok(())
 }
 
-fn poll_for_write_complete(qspi: &Qspi, sleep_between_polls: Option<u64>) {
+fn poll_for_write_complete(qspi: &Qspi, sleep_between_polls: Option<Duration>) {
     loop {
         let status = qspi.read_status();
         if status & 1 == 0 {
             // ooh we're done
             break;
         }
-        if let Some(ticks) = sleep_between_polls {
-            hl::sleep_for(ticks);
+        if let Some(sleep) = sleep_between_polls {
+            hl::sleep_for(sleep);
         }
     }
 }
@@ -10,6 +10,7 @@
 mod seq_spi;
 
 use ringbuf::*;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 use drv_gimlet_hf_api as hf_api;
@@ -144,7 +145,7 @@ fn main() -> ! {
     // high when you turn the regulator on, and then takes time to drop if
     // there's a problem. So, to ensure that there has been at least 1ms since
     // regulator-on, we will delay for 2.
-    hl::sleep_for(2);
+    hl::sleep_for(Duration::from_millis(2));
 
     // Now, monitor the PG pin.
     loop {
@@ -158,14 +159,14 @@ fn main() -> ! {
         // Do _not_ burn CPU constantly polling, it's rude. We could also set up
         // pin-change interrupts but we only do this once per power on, so it
         // seems like a lot of work.
-        hl::sleep_for(2);
+        hl::sleep_for(Duration::from_millis(2));
     }
 
     // We believe V1P2 is good. Now, for V3P3! Set it active (high).
     sys.gpio_set(ENABLE_V3P3).unwrap();
 
     // Delay to be sure.
-    hl::sleep_for(2);
+    hl::sleep_for(Duration::from_millis(2));
 
     // Now, monitor the PG pin.
     loop {
@@ -177,13 +178,13 @@ fn main() -> ! {
         }
 
         // Do _not_ burn CPU constantly polling, it's rude.
-        hl::sleep_for(2);
+        hl::sleep_for(Duration::from_millis(2));
     }
 
     // Now, V2P5 is chained off V3P3 and comes up on its own with no
     // synchronization. It takes about 500us in practice. We'll delay for 1ms,
     // plus give the iCE40 a good 10ms to come out of power-down.
-    hl::sleep_for(1 + 10);
+    hl::sleep_for(Duration::from_millis(1 + 10));
 
     // Sequencer FPGA power supply sequencing (meta-sequencing?) is complete.
 
@@ -301,14 +302,14 @@ fn main() -> ! {
             break;
         }
 
-        hl::sleep_for(1);
+        hl::sleep_for(Duration::from_millis(1));
     }
 
     //
     // If our clock generator is configured to load from external EEPROM,
     // we need to wait for up to 150 ms here (!).
     //
-    hl::sleep_for(150);
+    hl::sleep_for(Duration::from_millis(150));
 
     //
     // And now load our clock configuration
@@ -338,7 +339,7 @@ fn main() -> ! {
         seq,
         jefe,
         hf,
-        deadline: 0,
+        deadline: Instant::ZERO,
     };
 
     loop {
@@ -351,11 +352,11 @@ struct ServerImpl {
     seq: seq_spi::SequencerFpga,
     jefe: Jefe,
     hf: hf_api::HostFlash,
-    deadline: u64,
+    deadline: Instant,
 }
 
 const TIMER_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 10;
+const TIMER_INTERVAL: Duration = Duration::from_millis(10);
 
 impl NotificationHandler for ServerImpl {
     fn current_notification_mask(&self) -> u32 {
@@ -441,10 +442,10 @@ impl ServerImpl {
     // for a thermtrip or for someone disabling NIC_PWREN_L.  If we are in
     // any other state, we don't need to poll.
     //
-    fn poll_interval(&self) -> Option<u64> {
+    fn poll_interval(&self) -> Option<Duration> {
         match self.state {
-            PowerState::A0 => Some(10),
-            PowerState::A0PlusHP => Some(100),
+            PowerState::A0 => Some(Duration::from_millis(10)),
+            PowerState::A0PlusHP => Some(Duration::from_millis(100)),
             _ => None,
         }
     }
@@ -491,7 +492,7 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                         break;
                     }
 
-                    hl::sleep_for(1);
+                    hl::sleep_for(Duration::from_millis(1));
                 }
 
                 //
@@ -513,13 +514,13 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                         break;
                     }
 
-                    hl::sleep_for(1);
+                    hl::sleep_for(Duration::from_millis(1));
                 }
 
                 //
                 // And establish our timer to check SP3_TO_SP_NIC_PWREN_L.
                 //
-                self.deadline = sys_get_timer().now + TIMER_INTERVAL;
+                self.deadline = Instant::now() + TIMER_INTERVAL;
                 sys_set_timer(Some(self.deadline), TIMER_MASK);
 
                 //
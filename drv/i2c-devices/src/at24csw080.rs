@@ -7,6 +7,7 @@
 use crate::Validate;
 use core::convert::TryInto;
 use drv_i2c_api::*;
+use userlib::time::Duration;
 use userlib::{hl::sleep_for, FromPrimitive, ToPrimitive};
 use zerocopy::{AsBytes, FromBytes};
 
@@ -14,7 +15,7 @@ use zerocopy::{AsBytes, FromBytes};
 pub const EEPROM_SIZE: u16 = 1024;
 
 /// Wait time after performing a write
-const WRITE_TIME_MS: u64 = 5;
+const WRITE_TIME: Duration = Duration::from_millis(5);
 
 /// The AT24CSW080/4 is an I2C EEPROM used as the FRU ID. It includes 8-Kbit of
 /// memory (arranged as 1024 x 8), software write protection, a 256-bit
@@ -162,7 +163,7 @@ impl At24Csw080 {
         // Write the low byte of the address followed by the actual value
         let buffer = [addr as u8, val];
         self.device.eeprom(addr).write(&buffer)?;
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 
@@ -194,7 +195,7 @@ impl At24Csw080 {
         out[0] = addr as u8;
         out[1..=buf.len()].copy_from_slice(buf);
         self.device.eeprom(addr).write(&out[0..=buf.len()])?;
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 
@@ -286,7 +287,7 @@ impl At24Csw080 {
 
         // The datasheet doesn't specify whether the 5ms wait also applies
         // to registers, but experimentally, it does.
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 
@@ -326,7 +327,7 @@ impl At24Csw080 {
             (b.to_u8().unwrap() << 1) | WPR_WRITE | WPR_ENABLE,
         ];
         self.device.registers().write(&cmd)?;
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 
@@ -334,7 +335,7 @@ impl At24Csw080 {
     pub fn disable_eeprom_write_protection(&self) -> Result<(), Error> {
         let cmd = [WPR_WORD_ADDR, WPR_WRITE];
         self.device.registers().write(&cmd)?;
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 
@@ -367,7 +368,7 @@ impl At24Csw080 {
                 | WPR_PERMANENTLY_LOCK,
         ];
         self.device.registers().write(&cmd)?;
-        sleep_for(WRITE_TIME_MS);
+        sleep_for(WRITE_TIME);
         Ok(())
     }
 }
@@ -29,6 +29,7 @@
 use drv_spi_api::{self as spi_api, SpiDevice};
 use drv_stm32xx_sys_api::{self as sys_api, Sys};
 use userlib::hl;
+use userlib::time::Duration;
 
 /// Wiring configuration for the iCE40 FPGA.
 pub struct Config {
@@ -111,14 +112,14 @@ pub fn begin_bitstream_load(
 
     // Minimum duration of reset pulse is 200ns. One of our 1ms ticks will be
     // fine.
-    hl::sleep_for(1);
+    hl::sleep_for(Duration::from_millis(1));
 
     // Deassert reset (active low).
     sys.gpio_set(config.creset).unwrap();
 
     // Minimum time to stabilize here is either 300us or 800us, depending on
     // which Lattice doc you're reading. Give it 2ms to be sure.
-    hl::sleep_for(2);
+    hl::sleep_for(Duration::from_millis(2));
 
     // At this point, the iCE40 is _supposed_ to be chilling in programming mode
     // listening for a bitstream. If this is the case it will be asserting
@@ -10,6 +10,7 @@
 use drv_ignition_api::*;
 use drv_sidecar_mainboard_controller::ignition::*;
 use ringbuf::*;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 task_slot!(FPGA, fpga);
@@ -28,7 +29,7 @@ enum Trace {
 ringbuf!(Trace, 16, Trace::None);
 
 const TIMER_NOTIFICATION_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: Duration = Duration::from_millis(1000);
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -54,7 +55,7 @@ fn main() -> ! {
         // ready.
         ringbuf_entry!(Trace::AwaitingMainboardControllerReady);
         while !sequencer.mainboard_controller_ready().unwrap_or(false) {
-            hl::sleep_for(25);
+            hl::sleep_for(Duration::from_millis(25));
         }
     }
 
@@ -64,7 +65,7 @@ fn main() -> ! {
 
     // Set a timer in the past causing the presence state to be polled and
     // updated as soon as the serving loop starts.
-    sys_set_timer(Some(sys_get_timer().now), TIMER_NOTIFICATION_MASK);
+    sys_set_timer(Some(Instant::now()), TIMER_NOTIFICATION_MASK);
 
     loop {
         idol_runtime::dispatch_n(&mut incoming, &mut server);
@@ -236,7 +237,7 @@ impl idol_runtime::NotificationHandler for ServerImpl {
     }
 
     fn handle_notification(&mut self, _bits: u32) {
-        let start = sys_get_timer().now;
+        let start = Instant::now();
 
         // Only poll the presence summary if the port count seems reasonable. A
         // count of 0xff may occur if the FPGA is running an incorrect
@@ -247,17 +248,19 @@ impl idol_runtime::NotificationHandler for ServerImpl {
             }
         }
 
-        let finish = sys_get_timer().now;
+        let finish = Instant::now();
 
         // We now know when we were notified and when any work was completed.
         // Note that the assumption here is that `start` < `finish` and that
         // this won't hold if the system time rolls over. But, the system timer
-        // is a u64, with each bit representing a ms, so in practice this should
+        // is a u64 count of ticks, so in practice this should
         // be fine. Anyway, armed with this information, find the next deadline
         // some multiple of `TIMER_INTERVAL` in the future.
 
-        let delta = finish - start;
-        let next_deadline = finish + TIMER_INTERVAL - (delta % TIMER_INTERVAL);
+        let interval = TIMER_INTERVAL.as_ticks();
+        let delta = (finish - start).as_ticks();
+        let next_deadline =
+            finish + Duration::from_ticks(interval - (delta % interval));
 
         sys_set_timer(Some(next_deadline), TIMER_NOTIFICATION_MASK);
     }
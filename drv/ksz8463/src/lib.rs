@@ -6,6 +6,7 @@
 use drv_spi_api::{SpiDevice, SpiError};
 use ringbuf::*;
 use userlib::hl::sleep_for;
+use userlib::time::Duration;
 
 mod registers;
 pub use registers::{MIBCounter, Register};
@@ -337,7 +338,7 @@ impl Ksz8463 {
         // Do a full software reset of the chip to put registers into
         // a known state.
         self.write(Register::GRR, 1)?;
-        sleep_for(10);
+        sleep_for(Duration::from_millis(10));
         self.write(Register::GRR, 0)?;
 
         match mode {
@@ -218,6 +218,11 @@ fn main() -> ! {
     // Note this can definitely go higher but that involves
     // turning on PLLs and such
     syscon.hslspiclksel.modify(|_, w| w.sel().enum_0x2());
+    // The watchdog's clock (fro_1m) comes out of reset with its divider
+    // halted. Run it undivided, so that jefe only has to ask us to turn the
+    // watchdog's bus clock on.
+    syscon.wdtclkdiv.write(|w| unsafe { w.bits(0) });
+    while syscon.wdtclkdiv.read().reqflag().bit_is_set() {}
 
     set_reset_reason();
 
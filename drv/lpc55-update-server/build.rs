@@ -7,6 +7,7 @@ use std::io::Write;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
+    build_util::generate_notifications()?;
 
     idol::server::build_server_support(
         "../../idl/update.idol",
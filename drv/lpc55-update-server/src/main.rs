@@ -170,6 +170,20 @@ impl idl::InOrderUpdateImpl for ServerImpl {
     }
 }
 
+impl idol_runtime::NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        notifications::BOOT_HEALTHY_MASK
+    }
+
+    fn handle_notification(&mut self, _bits: u32) {
+        // Jefe has seen this image's tasks run for a while without faulting,
+        // so tell stage0 to stop counting boot attempts and keep it. If the
+        // write fails, we may get rolled back, but there's nobody to report
+        // that to.
+        let _ = unsafe { tz_table!().confirm_boot() };
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let mut server = ServerImpl {
@@ -179,11 +193,16 @@ fn main() -> ! {
     let mut incoming = [0u8; idl::INCOMING_SIZE];
 
     loop {
-        idol_runtime::dispatch(&mut incoming, &mut server);
+        idol_runtime::dispatch_n(&mut incoming, &mut server);
     }
 }
 
 include!(concat!(env!("OUT_DIR"), "/consts.rs"));
+
+mod notifications {
+    include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
+}
+
 mod idl {
     use super::{ImageVersion, UpdateError, UpdateTarget};
 
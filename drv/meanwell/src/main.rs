@@ -17,12 +17,13 @@
 use drv_meanwell_api::MeanwellError;
 use idol_runtime::NotificationHandler;
 use idol_runtime::RequestError;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 use drv_stm32xx_sys_api as sys_api;
 
 struct ServerImpl {
-    deadline: u64,
+    deadline: Instant,
     led_on: bool,
 }
 
@@ -108,8 +109,8 @@ task_slot!(USER_LEDS, user_leds);
 task_slot!(SYS, sys);
 
 const TIMER_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL_LONG: u64 = 900;
-const TIMER_INTERVAL_SHORT: u64 = 100;
+const TIMER_INTERVAL_LONG: Duration = Duration::from_millis(900);
+const TIMER_INTERVAL_SHORT: Duration = Duration::from_millis(100);
 
 impl NotificationHandler for ServerImpl {
     fn current_notification_mask(&self) -> u32 {
@@ -136,7 +137,7 @@ impl NotificationHandler for ServerImpl {
 
 #[export_name = "main"]
 fn main() -> ! {
-    let deadline = sys_get_timer().now;
+    let deadline = Instant::now();
 
     //
     // This will put our timer in the past, and should immediately kick us.
@@ -4,6 +4,7 @@
 
 use crate::{Addr, SIDECAR_IO_BITSTREAM_CHECKSUM};
 use drv_fpga_api::*;
+use userlib::time::Duration;
 
 pub struct FrontIOController {
     fpga: Fpga,
@@ -28,9 +29,9 @@ impl FrontIOController {
     #[inline]
     pub fn await_fpga_ready(
         &mut self,
-        sleep_ticks: u64,
+        sleep: Duration,
     ) -> Result<DeviceState, FpgaError> {
-        await_fpga_ready(&mut self.fpga, sleep_ticks)
+        await_fpga_ready(&mut self.fpga, sleep)
     }
 
     /// Load the front io board controller bitstream, pulling it from the
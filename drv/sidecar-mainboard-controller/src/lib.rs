@@ -5,6 +5,7 @@
 #![no_std]
 
 use drv_fpga_api::*;
+use userlib::time::Duration;
 
 include!(concat!(env!("OUT_DIR"), "/sidecar_mainboard_controller.rs"));
 
@@ -41,9 +42,9 @@ impl MainboardController {
     /// a bitstream, resetting the device if needed.
     pub fn await_fpga_ready(
         &mut self,
-        sleep_ticks: u64,
+        sleep: Duration,
     ) -> Result<DeviceState, FpgaError> {
-        await_fpga_ready(&mut self.fpga, sleep_ticks)
+        await_fpga_ready(&mut self.fpga, sleep)
     }
 
     /// Read the design ident.
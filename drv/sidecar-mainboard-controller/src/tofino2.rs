@@ -5,6 +5,7 @@
 use crate::{Addr, MainboardController, Reg};
 use bitfield::bitfield;
 use drv_fpga_api::{FpgaError, FpgaUserDesign, WriteOp};
+use userlib::time::Duration;
 use userlib::FromPrimitive;
 use zerocopy::{AsBytes, FromBytes};
 
@@ -383,7 +384,7 @@ impl DebugPort {
 
         // Wait for the request to complete.
         while self.state()?.request_in_progress() {
-            userlib::hl::sleep_for(1);
+            userlib::hl::sleep_for(Duration::from_millis(1));
         }
 
         // Read the response. This is done in a loop because the SPI peripheral
@@ -450,7 +451,7 @@ impl DebugPort {
 
         // Wait for the request to complete.
         while self.state()?.request_in_progress() {
-            userlib::hl::sleep_for(1);
+            userlib::hl::sleep_for(Duration::from_millis(1));
         }
 
         Ok(())
@@ -473,7 +474,7 @@ impl DebugPort {
             & 0x80
             != 0
         {
-            userlib::hl::sleep_for(1);
+            userlib::hl::sleep_for(Duration::from_millis(1));
         }
 
         Ok(())
@@ -47,7 +47,8 @@ impl FrontIOBoard {
         let mut controllers_ready = true;
 
         for (i, controller) in self.controllers.iter_mut().enumerate() {
-            let state = controller.await_fpga_ready(25)?;
+            let state =
+                controller.await_fpga_ready(Duration::from_millis(25))?;
             let mut ident;
             let mut ident_valid = false;
             let mut checksum;
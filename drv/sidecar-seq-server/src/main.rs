@@ -22,6 +22,7 @@ use idol_runtime::{
     ClientError, Leased, NotificationHandler, RequestError, R, W,
 };
 use ringbuf::*;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 task_slot!(I2C, i2c_driver);
@@ -88,7 +89,7 @@ enum Trace {
 ringbuf!(Trace, 32, Trace::None);
 
 const TIMER_NOTIFICATION_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: Duration = Duration::from_millis(1000);
 
 struct ServerImpl {
     mainboard_controller: MainboardController,
@@ -396,23 +397,25 @@ impl NotificationHandler for ServerImpl {
     }
 
     fn handle_notification(&mut self, _bits: u32) {
-        let start = sys_get_timer().now;
+        let start = Instant::now();
 
         if let Err(e) = self.tofino.handle_tick() {
             ringbuf_entry!(Trace::TofinoSequencerError(e));
         }
 
-        let finish = sys_get_timer().now;
+        let finish = Instant::now();
 
         // We now know when we were notified and when any work was completed.
         // Note that the assumption here is that `start` < `finish` and that
         // this won't hold if the system time rolls over. But, the system timer
-        // is a u64, with each bit representing a ms, so in practice this should
+        // is a u64 count of ticks, so in practice this should
         // be fine. Anyway, armed with this information, find the next deadline
         // some multiple of `TIMER_INTERVAL` in the future.
 
-        let delta = finish - start;
-        let next_deadline = finish + TIMER_INTERVAL - (delta % TIMER_INTERVAL);
+        let interval = TIMER_INTERVAL.as_ticks();
+        let delta = (finish - start).as_ticks();
+        let next_deadline =
+            finish + Duration::from_ticks(interval - (delta % interval));
 
         sys_set_timer(Some(next_deadline), TIMER_NOTIFICATION_MASK);
     }
@@ -443,7 +446,7 @@ fn main() -> ! {
 
     match server
         .mainboard_controller
-        .await_fpga_ready(25)
+        .await_fpga_ready(Duration::from_millis(25))
         .unwrap_or(DeviceState::Unknown)
     {
         DeviceState::AwaitingBitstream => {
@@ -467,7 +470,7 @@ fn main() -> ! {
                     // reset immediately, matching existing behavior on a failed
                     // FPGA reset.
                     if matches!(e, FpgaError::AuxMissingBlob) {
-                        userlib::hl::sleep_for(100);
+                        userlib::hl::sleep_for(Duration::from_millis(100));
                     }
                     panic!();
                 }
@@ -527,7 +530,7 @@ fn main() -> ! {
         // in order here to prep the driver for what's about to happen.
         if let Ok(()) = server.tofino.power_down() {
             // Give the sequencer some time to shut down the PDN.
-            userlib::hl::sleep_for(25);
+            userlib::hl::sleep_for(Duration::from_millis(25));
         }
 
         // Reset the FPGA and deploy the parashutes. This will cause the
@@ -547,7 +550,7 @@ fn main() -> ! {
     // sufficient for now.
     //
     // TODO (arjen): Implement reset control through the mainboard controller.
-    userlib::hl::sleep_for(100);
+    userlib::hl::sleep_for(Duration::from_millis(100));
 
     if let TofinoSeqState::A0 = server
         .tofino
@@ -575,7 +578,7 @@ fn main() -> ! {
         phy_smi.set_phy_power_enabled(true).unwrap();
 
         while !phy_smi.phy_powered_up_and_ready().unwrap() {
-            userlib::hl::sleep_for(10);
+            userlib::hl::sleep_for(Duration::from_millis(10));
         }
 
         ringbuf_entry!(Trace::FrontIOVsc8562Ready);
@@ -600,7 +603,7 @@ fn main() -> ! {
     //
     // This will put our timer in the past, and should immediately kick us.
     //
-    let deadline = sys_get_timer().now;
+    let deadline = Instant::now();
     sys_set_timer(Some(deadline), TIMER_NOTIFICATION_MASK);
 
     loop {
@@ -68,7 +68,7 @@ impl Tofino {
         for i in 1..4 {
             // Sleep first since there is a delay between the sequencer
             // receiving the EN bit and the VID being valid.
-            hl::sleep_for(i * 25);
+            hl::sleep_for(Duration::from_millis(i * 25));
 
             let maybe_vid = self.sequencer.vid().map_err(|e| {
                 if let FpgaError::InvalidValue = e {
@@ -89,7 +89,7 @@ impl Tofino {
                 // to load and the peripheral to initialize, and log the latched
                 // IDCODE.
                 self.sequencer.set_pcie_reset(TofinoPcieReset::Deasserted)?;
-                hl::sleep_for(200);
+                hl::sleep_for(Duration::from_millis(200));
                 ringbuf_entry!(Trace::TofinoEepromIdCode(
                     self.debug_port.spi_eeprom_idcode()?
                 ));
@@ -58,7 +58,7 @@ pub struct Ethernet {
 /// required in most cases, but whaddayagonnado.
 fn crappy_spin_until(pred: impl Fn() -> bool) {
     while !pred() {
-        userlib::hl::sleep_for(1);
+        userlib::hl::sleep_for(userlib::time::Duration::from_millis(1));
     }
 }
 
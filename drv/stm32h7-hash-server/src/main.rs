@@ -11,6 +11,7 @@
 #![no_main]
 
 // use core::convert::TryInto;
+use userlib::time::Duration;
 use userlib::*;
 
 use drv_stm32h7_hash::Hash;
@@ -30,10 +31,10 @@ fn hash_hw_reset() {
     let sys = sys_api::Sys::from(SYS.get_task_id());
     sys.enter_reset(sys_api::Peripheral::Hash);
     sys.disable_clock(sys_api::Peripheral::Hash);
-    hl::sleep_for(1);
+    hl::sleep_for(Duration::from_millis(1));
     sys.enable_clock(sys_api::Peripheral::Hash);
     sys.leave_reset(sys_api::Peripheral::Hash);
-    hl::sleep_for(1);
+    hl::sleep_for(Duration::from_millis(1));
 }
 
 #[export_name = "main"]
@@ -31,6 +31,7 @@ use stm32h7::stm32h753 as device;
 
 use core::convert::TryInto;
 use core::mem::size_of;
+use userlib::time::Duration;
 use userlib::*;
 use zerocopy::AsBytes;
 
@@ -112,7 +113,7 @@ impl Hash {
         self.idx = 0;
         if self.is_busy() {
             while self.is_busy() {
-                hl::sleep_for(1);
+                hl::sleep_for(Duration::from_millis(1));
             }
         }
         unsafe {
@@ -156,7 +157,7 @@ impl Hash {
             // XXX do i need to check DINIS? || !is_dinis_set() {
             while self.is_busy() {
                 // || !is_dinis_set() {
-                hl::sleep_for(1);
+                hl::sleep_for(Duration::from_millis(1));
             }
         }
 
@@ -298,7 +299,7 @@ impl Hash {
 
         if self.is_busy() {
             while self.is_busy() {
-                hl::sleep_for(1);
+                hl::sleep_for(Duration::from_millis(1));
             }
         }
         sys_irq_control(self.interrupt, true);
@@ -308,7 +309,7 @@ impl Hash {
         loop {
             if self.is_busy() {
                 while self.is_busy() {
-                    hl::sleep_for(1);
+                    hl::sleep_for(Duration::from_millis(1));
                 }
             }
             let _rm = sys_recv_closed(&mut [], self.interrupt, TaskId::KERNEL)
@@ -317,7 +318,7 @@ impl Hash {
                 break;
             }
             sys_irq_control(self.interrupt, true); // XXX need this again?
-            hl::sleep_for(1);
+            hl::sleep_for(Duration::from_millis(1));
         }
         // DCAL is supposedly not clearable by SW.
         // self.reg.str.modify(|_, w| w.dcal().clear_bit());
@@ -19,6 +19,7 @@ use stm32h7::stm32h743 as device;
 #[cfg(feature = "h753")]
 use stm32h7::stm32h753 as device;
 
+use userlib::time::Duration;
 use userlib::*;
 
 task_slot!(SYS, sys);
@@ -55,7 +56,7 @@ impl Stm32h7Rng {
     fn read(&mut self) -> Result<u32, RngError> {
         let mut retries = 10;
         while !self.is_data_ready() && retries > 0 {
-            hl::sleep_for(1);
+            hl::sleep_for(Duration::from_millis(1));
             retries -= 1;
         }
         if !self.is_data_ready() {
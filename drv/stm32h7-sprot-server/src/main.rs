@@ -10,6 +10,7 @@ use drv_stm32xx_sys_api as sys_api;
 use drv_update_api::{ImageVersion, UpdateError, UpdateTarget};
 use idol_runtime::{ClientError, Leased, RequestError, R, W};
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::*;
 #[cfg(feature = "sink_test")]
 use zerocopy::{ByteOrder, LittleEndian};
@@ -226,7 +227,7 @@ impl ServerImpl {
                 .lock(CsState::Asserted)
                 .map_err(|_| MsgError::SpiServerError)?;
             if PART1_DELAY != 0 {
-                hl::sleep_for(PART1_DELAY);
+                hl::sleep_for(Duration::from_millis(PART1_DELAY));
             }
         }
         if self.spi.write(part1).is_err() {
@@ -237,7 +238,7 @@ impl ServerImpl {
             return Err(MsgError::SpiServerError);
         }
         if !part2.is_empty() {
-            hl::sleep_for(PART2_DELAY); // TODO: configurable
+            hl::sleep_for(Duration::from_millis(PART2_DELAY)); // TODO: configurable
             ringbuf_entry!(Trace::CSnDeassert);
             if self.spi.write(part2).is_err() {
                 _ = self.spi.release();
@@ -285,7 +286,7 @@ impl ServerImpl {
             .lock(CsState::Asserted)
             .map_err(|_| MsgError::SpiServerError)?;
         if PART1_DELAY != 0 {
-            hl::sleep_for(PART1_DELAY);
+            hl::sleep_for(Duration::from_millis(PART1_DELAY));
         }
 
         // We can fetch FIFO size number of bytes reliably.
@@ -308,7 +309,7 @@ impl ServerImpl {
                 Ok(buf) => {
                     ringbuf_entry!(Trace::RxPart2(buf.len()));
                     // Allow RoT time to rouse itself.
-                    hl::sleep_for(PART2_DELAY);
+                    hl::sleep_for(Duration::from_millis(PART2_DELAY));
                     if self.spi.read(buf).is_err() {
                         Err(MsgError::SpiServerError)
                     } else {
@@ -482,12 +483,12 @@ impl ServerImpl {
             .lock(CsState::Asserted)
             .map_err(|_| MsgError::CannotAssertCSn)?;
         if delay != 0 {
-            hl::sleep_for(delay);
+            hl::sleep_for(Duration::from_millis(delay));
         }
         ringbuf_entry!(Trace::CSnDeassert);
         self.spi.release().unwrap_lite();
         if delay_after != 0 {
-            hl::sleep_for(delay_after);
+            hl::sleep_for(Duration::from_millis(delay_after));
         }
         let rot_irq_end = self.is_rot_irq_asserted();
         let status = PulseStatus {
@@ -509,7 +510,7 @@ impl ServerImpl {
                 ringbuf_entry!(Trace::RotReadyTimeout);
                 return false;
             }
-            hl::sleep_for(1);
+            hl::sleep_for(Duration::from_millis(1));
             slept += 1;
         }
         true
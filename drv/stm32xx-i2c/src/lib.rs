@@ -36,6 +36,7 @@ pub mod max7358;
 pub mod pca9548;
 
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::*;
 
 use drv_stm32xx_sys_api as sys_api;
@@ -452,7 +453,7 @@ impl I2cController<'_> {
                 // amount of time we would expect the controller to be busy...
                 //
                 ringbuf_entry!(Trace::BusySleep);
-                hl::sleep_for(2);
+                hl::sleep_for(Duration::from_millis(2));
             } else if laps > BUSY_SLEEP_THRESHOLD {
                 //
                 // We have already taken BUSY_SLEEP_THRESHOLD laps AND a two
@@ -19,6 +19,7 @@ cfg_if::cfg_if! {
 }
 
 use derive_idol_err::IdolError;
+use userlib::time::Duration;
 use userlib::*;
 
 pub use drv_stm32xx_gpio_common::{
@@ -286,9 +287,9 @@ impl Sys {
             Speed::Low,
             Pull::None,
         )?;
-        userlib::hl::sleep_for(low_time_ms as u64);
+        userlib::hl::sleep_for(Duration::from_millis(low_time_ms.into()));
         self.gpio_set(pinset)?;
-        userlib::hl::sleep_for(wait_time_ms as u64);
+        userlib::hl::sleep_for(Duration::from_millis(wait_time_ms.into()));
         Ok(())
     }
 }
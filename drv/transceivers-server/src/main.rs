@@ -17,6 +17,7 @@ use idol_runtime::{
     ClientError, Leased, NotificationHandler, RequestError, R, W,
 };
 use ringbuf::*;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 task_slot!(I2C, i2c_driver);
@@ -51,7 +52,7 @@ struct ServerImpl {
 }
 
 const TIMER_NOTIFICATION_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 500;
+const TIMER_INTERVAL: Duration = Duration::from_millis(500);
 
 impl idl::InOrderTransceiversImpl for ServerImpl {
     fn get_modules_status(
@@ -242,7 +243,7 @@ impl NotificationHandler for ServerImpl {
             ringbuf_entry!(Trace::ModulePresenceUpdate(presence));
         }
 
-        let next_deadline = sys_get_timer().now + TIMER_INTERVAL;
+        let next_deadline = Instant::now() + TIMER_INTERVAL;
         sys_set_timer(Some(next_deadline), TIMER_NOTIFICATION_MASK)
     }
 }
@@ -269,7 +270,7 @@ fn main() -> ! {
                 }
                 _ => {
                     ringbuf_entry!(Trace::FrontIOReady(false));
-                    userlib::hl::sleep_for(10)
+                    userlib::hl::sleep_for(Duration::from_millis(10))
                 }
             }
         }
@@ -315,7 +316,7 @@ fn main() -> ! {
         }
 
         // This will put our timer in the past, immediately forcing an update
-        let deadline = sys_get_timer().now;
+        let deadline = Instant::now();
         sys_set_timer(Some(deadline), TIMER_NOTIFICATION_MASK);
 
         let mut buffer = [0; idl::INCOMING_SIZE];
@@ -16,6 +16,7 @@ mod serdes10g;
 mod serdes1g;
 
 use crate::config::{PortConfig, PortDev, PortMap, PortMode, PortSerdes};
+use userlib::time::Duration;
 use userlib::{hl::sleep_for, UnwrapLite};
 use vsc7448_pac::{types::RegisterAddress, *};
 
@@ -427,7 +428,7 @@ impl<'a, R: Vsc7448Rw> Vsc7448<'a, R> {
 
         // The RAM initialization should take about 40 µs, according to
         // the datasheet.
-        sleep_for(1);
+        sleep_for(Duration::from_millis(1));
 
         // Confirm that the RAM_INIT bits have cleared themselves.
         // This should never fail, and there's not much we can do about it
@@ -454,7 +455,7 @@ impl<'a, R: Vsc7448Rw> Vsc7448<'a, R> {
 
         self.high_speed_mode()?;
 
-        sleep_for(105); // Minimum time between reset and SMI access
+        sleep_for(Duration::from_millis(105)); // Minimum time between reset and SMI access
 
         Ok(())
     }
@@ -482,12 +483,12 @@ impl<'a, R: Vsc7448Rw> Vsc7448<'a, R> {
             self.modify(pll5g.PLL5G_CFG2(), |r| {
                 r.set_disable_fsm(0);
             })?;
-            sleep_for(10);
+            sleep_for(Duration::from_millis(10));
             let v = self
                 .read(HSIO().PLL5G_STATUS(i).PLL5G_STATUS1())?
                 .gain_stat();
             if v > 2 && v < 0xa {
-                sleep_for(5);
+                sleep_for(Duration::from_millis(5));
                 return Ok(());
             }
         }
@@ -4,6 +4,7 @@
 //
 use crate::{Vsc7448Rw, VscError};
 use userlib::hl;
+use userlib::time::Duration;
 use vsc7448_pac::*;
 
 /// Represents an entry in the VSC7448's MAC tables
@@ -44,7 +45,7 @@ pub fn next_mac(
         r.set_mac_table_access_shot(0x1); // run
     })?;
     while v.read(ctrl)?.mac_table_access_shot() == 1 {
-        hl::sleep_for(1);
+        hl::sleep_for(Duration::from_millis(1));
     }
 
     let msb = v
@@ -11,6 +11,7 @@ use crate::{
     Vsc7448Rw, VscError,
 };
 use userlib::hl;
+use userlib::time::Duration;
 use vsc7448_pac::*;
 
 /// Flushes a particular 1G port.  This is equivalent to `jr2_port_flush`
@@ -117,7 +118,7 @@ fn port_flush_inner(port: u8, v: &impl Vsc7448Rw) -> Result<(), VscError> {
     v.modify(QRES().RES_QOS_ADV().PFC_CFG(port), |r| r.set_tx_pfc_ena(0))?;
 
     // 6: Wait a worst case time 8ms (jumbo/10Mbit)
-    hl::sleep_for(8);
+    hl::sleep_for(Duration::from_millis(8));
 
     // 7: Flush the queues accociated with the port
     v.modify(HSCH().HSCH_MISC().FLUSH_CTRL(), |r| {
@@ -160,7 +161,7 @@ fn port_flush_wait(port: u8, v: &impl Vsc7448Rw) -> Result<(), VscError> {
         if empty {
             return Ok(());
         }
-        hl::sleep_for(1);
+        hl::sleep_for(Duration::from_millis(1));
     }
     Err(VscError::PortFlushTimeout { port })
 }
@@ -5,6 +5,7 @@
 /// Tools for working with the 10G SERDES (sd10g65 in the SDK)
 use crate::{Vsc7448Rw, VscError};
 use userlib::hl;
+use userlib::time::Duration;
 use vsc7448_pac::*;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -266,7 +267,7 @@ impl Config {
             r.set_pllf_oor_recal_ena(1);
         })?;
 
-        hl::sleep_for(10);
+        hl::sleep_for(Duration::from_millis(10));
         v.modify(tx_rcpll.SD10G65_TX_RCPLL_CFG0(), |r| {
             r.set_pllf_ena(1);
         })?;
@@ -274,7 +275,7 @@ impl Config {
             r.set_pllf_oor_recal_ena(0);
         })?;
 
-        hl::sleep_for(2);
+        hl::sleep_for(Duration::from_millis(2));
 
         let stat0 = v.read(tx_rcpll.SD10G65_TX_RCPLL_STAT0())?;
         if stat0.pllf_lock_stat() != 1 {
@@ -467,7 +468,7 @@ impl Config {
             r.set_pllf_oor_recal_ena(0);
         })?;
 
-        hl::sleep_for(2);
+        hl::sleep_for(Duration::from_millis(2));
         let stat0 = v.read(rx_rcpll.SD10G65_RX_RCPLL_STAT0())?;
         if stat0.pllf_lock_stat() != 1 {
             return Err(VscError::RxPllLockFailed);
@@ -674,7 +675,7 @@ impl Config {
         v.modify(apc.APC_COMMON_CFG0(), |r| {
             r.set_reset_apc(0); // Release reset
         })?;
-        hl::sleep_for(1);
+        hl::sleep_for(Duration::from_millis(1));
         v.modify(apc.APC_IS_CAL_CFG1(), |r| {
             r.set_start_offscal(1);
         })?;
@@ -683,17 +684,17 @@ impl Config {
             // This is the calculation for `calibration_time_ms[1]` in the SDK
             let cal_clk_div = 3;
             let cal_num_iterations = 1;
-            hl::sleep_for(
+            hl::sleep_for(Duration::from_millis(
                 ((1u64 << (2 * cal_clk_div))
                     * (cal_num_iterations + 1)
                     * 156500
                     * self.if_width as u64
                     + (self.f_pll_khz_plain as u64 - 1))
                     / (self.f_pll_khz_plain as u64),
-            );
+            ));
             // TODO: why is this needed?  It's not in the SDK, but the system
             // doesn't configure without this pause.
-            hl::sleep_for(100);
+            hl::sleep_for(Duration::from_millis(100));
         }
         let cfg1 = v.read(apc.APC_IS_CAL_CFG1())?;
         if cfg1.offscal_done() != 1 {
@@ -4,6 +4,7 @@
 
 use crate::{Vsc7448Rw, VscError};
 use userlib::hl;
+use userlib::time::Duration;
 use vsc7448_pac::*;
 
 pub enum Mode {
@@ -138,13 +139,13 @@ impl Config {
         // Enable the PLL then wait 20 ms for bringup
         v.modify(ana_cfg.SERDES6G_PLL_CFG(), |r| r.set_pll_fsm_ena(1))?;
         serdes6g_write(v, instance)?;
-        hl::sleep_for(20);
+        hl::sleep_for(Duration::from_millis(20));
 
         // Start IB calibration, then wait 60 ms for it to complete
         v.modify(ana_cfg.SERDES6G_IB_CFG(), |r| r.set_ib_cal_ena(1))?;
         v.modify(dig_cfg.SERDES6G_MISC_CFG(), |r| r.set_lane_rst(0))?;
         serdes6g_write(v, instance)?;
-        hl::sleep_for(60);
+        hl::sleep_for(Duration::from_millis(60));
 
         // "Set ib_tsdet and ib_reg_pat_sel_offset back to correct value"
         // (according to the SDK)
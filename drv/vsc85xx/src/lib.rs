@@ -25,6 +25,7 @@ pub mod vsc85x2;
 use core::cell::Cell;
 use ringbuf::*;
 use userlib::hl::sleep_for;
+use userlib::time::Duration;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 pub use vsc_err::VscError;
 
@@ -174,7 +175,7 @@ impl<'a, P: PhyRw> Phy<'a, P> {
             if f(r)? {
                 return Ok(());
             }
-            sleep_for(1)
+            sleep_for(Duration::from_millis(1))
         }
         Err(VscError::PhyInitTimeout)
     }
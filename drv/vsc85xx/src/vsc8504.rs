@@ -5,6 +5,7 @@
 use crate::{Phy, PhyRw, Trace, VscError};
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use userlib::hl::sleep_for;
+use userlib::time::Duration;
 use vsc7448_pac::phy;
 
 pub const VSC8504_ID: u32 = 0x704c2;
@@ -126,12 +127,12 @@ impl<'a, P: PhyRw> Vsc8504Phy<'a, P> {
         if is_base_port {
             self.phy.cmd(0x80E0)?;
         }
-        sleep_for(10); // (line 5928)
+        sleep_for(Duration::from_millis(10)); // (line 5928)
 
         // We are running with fiber media, so
         // "Setup media in micro program" (5946)
         self.phy.cmd(0x80C1 | (0x0100 << phy_port))?;
-        sleep_for(10);
+        sleep_for(Duration::from_millis(10));
 
         // "Setup Media interface" (5952)
         self.phy
@@ -162,7 +163,7 @@ impl<'a, P: PhyRw> Vsc8504Phy<'a, P> {
         // "Tesla PHY Only - Writing 0xc040, See Bug_9450" (919)
         self.phy
             .write(phy::STANDARD::MODE_CONTROL(), 0xC040.into())?;
-        sleep_for(1); // line 934
+        sleep_for(Duration::from_millis(1)); // line 934
 
         // We are now roughly at line 948, doing
         //      phy_reset_private
@@ -9,6 +9,7 @@ use zerocopy::AsBytes;
 use crate::{Phy, PhyRw, Trace};
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use userlib::hl::sleep_for;
+use userlib::time::Duration;
 use vsc7448_pac::phy;
 use vsc_err::VscError;
 
@@ -129,7 +130,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
                 self.sd6g_patch(true)?;
             }
         }
-        sleep_for(10); // line 5928
+        sleep_for(Duration::from_millis(10)); // line 5928
 
         // We are running with copper media, so take the branch
         // `conf->media_if == VTSS_PHY_MEDIA_IF_CU`
@@ -139,7 +140,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
 
         // "Turn off SerDes for 1000Base-FX" (line 5937)
         self.phy.cmd(0x80E1 | (0x0100 << phy_port))?;
-        sleep_for(10); // vtss_phy.c:5941
+        sleep_for(Duration::from_millis(10)); // vtss_phy.c:5941
 
         // "Setup Media interface" (line 5952)
         // Not needed, since we're running in copper mode
@@ -393,7 +394,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
                 timed_out = false;
                 break;
             }
-            sleep_for(1);
+            sleep_for(Duration::from_millis(1));
         }
         if timed_out {
             return Err(VscError::PhyPllCalTimeout);
@@ -446,7 +447,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
                 timed_out = false;
                 break;
             }
-            sleep_for(1);
+            sleep_for(Duration::from_millis(1));
         }
         if timed_out {
             return Err(VscError::PhyIbCalTimeout);
@@ -527,7 +528,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
                 timed_out = false;
                 break;
             }
-            sleep_for(1);
+            sleep_for(Duration::from_millis(1));
         }
         if timed_out {
             return Err(VscError::PhyPllCalTimeout);
@@ -558,7 +559,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
             if (r & 0x40000000) == 0 {
                 return Ok(());
             }
-            sleep_for(1);
+            sleep_for(Duration::from_millis(1));
         }
         Err(VscError::McbReadTimeout)
     }
@@ -582,7 +583,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
             if (r & 0x80000000) == 0 {
                 return Ok(());
             }
-            sleep_for(1);
+            sleep_for(Duration::from_millis(1));
         }
         Err(VscError::McbWriteTimeout)
     }
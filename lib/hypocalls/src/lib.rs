@@ -8,7 +8,7 @@
 //! Hypovisor calls
 
 pub use lpc55_flash::{
-    HypoStatus, UpdateTarget, __write_block, FLASH_PAGE_SIZE,
+    HypoStatus, UpdateTarget, __confirm_boot, __write_block, FLASH_PAGE_SIZE,
 };
 
 pub const TABLE_MAGIC: u32 = 0xabcd_abcd;
@@ -22,6 +22,7 @@ macro_rules! declare_tz_table {
         static TZ_TABLE: SecureTable = SecureTable {
             magic: 0,
             write_to_flash: None,
+            confirm_boot: None,
         };
     };
 }
@@ -35,6 +36,7 @@ macro_rules! declare_not_tz_table {
         static TZ_TABLE: SecureTable = SecureTable {
             magic: TABLE_MAGIC,
             write_to_flash: Some(__write_block),
+            confirm_boot: Some(__confirm_boot),
         };
     };
 }
@@ -54,6 +56,7 @@ pub struct SecureTable {
     // function
     pub write_to_flash:
         Option<unsafe extern "C" fn(UpdateTarget, u32, *mut u8) -> HypoStatus>,
+    pub confirm_boot: Option<unsafe extern "C" fn() -> HypoStatus>,
 }
 
 impl SecureTable {
@@ -84,4 +87,20 @@ impl SecureTable {
         }
         unreachable!()
     }
+
+    /// Tells stage0 that the running image works, so it shouldn't fall back
+    /// to the other image on the next boot.
+    pub unsafe fn confirm_boot(&self) -> HypoStatus {
+        // SAFETY
+        // As for `write_to_flash`, the magic tells us whether the table has
+        // been filled in.
+        let magic = core::ptr::read_volatile(&self.magic);
+        if magic != TABLE_MAGIC {
+            panic!();
+        }
+        if let Some(func) = core::ptr::read_volatile(&self.confirm_boot) {
+            return func();
+        }
+        unreachable!()
+    }
 }
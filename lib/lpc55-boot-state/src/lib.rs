@@ -0,0 +1,499 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Persistent A/B boot state on the LPC55
+//!
+//! Stage0 boots one of two images, A or B. To fall back to the other image
+//! when a freshly written one doesn't work, it keeps a record of how each
+//! image has fared in the last flash pages of stage0's own region:
+//!
+//! - An image that stage0 has never booted has no flags set.
+//! - Booting an image that hasn't confirmed itself marks it _pending_ and
+//!   counts the attempt.
+//! - A running image _confirms_ itself through a hypocall once it's happy,
+//!   which clears the attempt count. Jefe decides when that is: it tells the
+//!   update server once the image's tasks have run for a while without
+//!   faulting.
+//! - Stage0 _rejects_ a pending image once it's used up its attempts without
+//!   confirming, and boots the other image instead.
+//! - Writing an image slot through the update hypocall forgets what we knew
+//!   about that slot.
+//!
+//! The record also holds the minimum epoch: stage0 raises it to the epoch of
+//! any confirmed image it boots, and refuses to boot images from an earlier
+//! one.
+//!
+//! The record is kept in two pages, and each write goes to the page that
+//! doesn't hold the newest copy. If we lose power while erasing or programming
+//! one page, the other still has the state from before, so we never fall all
+//! the way back to a fresh record (and a minimum epoch of zero).
+//!
+//! Both stage0 and the running image's hypocalls use this crate, and find
+//! the pages through the `__IMAGE_STAGE0_END` symbol that xtask defines in
+//! their linker scripts. Stage0 itself must leave the pages free, which
+//! `xtask dist` checks when it lays out the stage0 image.
+
+#![cfg_attr(not(test), no_std)]
+// Off the target, there's no flash to use the record logic on; it's only
+// built there to be tested.
+#![cfg_attr(not(target_os = "none"), allow(dead_code))]
+
+#[cfg(target_os = "none")]
+use lpc55_romapi::{FlashStatus, FLASH_PAGE_SIZE};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Marks a programmed boot state page as ours.
+pub const BOOT_STATE_MAGIC: u32 = 0xb007_57a7;
+
+/// The slot has been booted without confirming itself.
+pub const SLOT_PENDING: u32 = 1 << 0;
+/// The running image in this slot has confirmed that it works.
+pub const SLOT_CONFIRMED: u32 = 1 << 1;
+/// The slot used up its boot attempts without confirming itself.
+pub const SLOT_REJECTED: u32 = 1 << 2;
+
+/// How many times stage0 boots an image that hasn't confirmed itself before
+/// giving up on it and falling back to the other one.
+pub const MAX_BOOT_ATTEMPTS: u32 = 3;
+
+/// Number of flash pages the boot state takes up, at the end of stage0's
+/// region.
+pub const BOOT_STATE_PAGES: usize = 2;
+
+// The symbol is defined with no space allocated; see `lpc55_flash` for why
+// this is a zero sized type.
+#[cfg(target_os = "none")]
+#[allow(improper_ctypes)]
+extern "C" {
+    static __IMAGE_STAGE0_END: ();
+}
+
+/// One of the two image slots.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Slot {
+    A = 0,
+    B = 1,
+}
+
+/// What stage0 needs to know about a valid image to decide whether to boot it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Candidate {
+    pub epoch: u32,
+    pub version: u32,
+}
+
+/// What we know about one slot.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, AsBytes, FromBytes)]
+pub struct SlotState {
+    /// Some combination of `SLOT_PENDING`, `SLOT_CONFIRMED` and
+    /// `SLOT_REJECTED`.
+    pub flags: u32,
+    /// Boots of this slot since it last confirmed itself, or since it was
+    /// written.
+    pub attempts: u32,
+}
+
+/// The contents of a boot state page.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, AsBytes, FromBytes)]
+pub struct BootState {
+    magic: u32,
+    /// Bumped each time the state is stored, so that we can tell which of the
+    /// two pages is newer.
+    seq: u32,
+    /// Images with an epoch below this won't be booted.
+    pub min_epoch: u32,
+    slots: [SlotState; 2],
+    /// Checksum of the fields above, to catch a page that was only partly
+    /// programmed.
+    check: u32,
+}
+
+impl BootState {
+    /// Returns the state of a part that's never been booted, with nothing
+    /// known about either slot.
+    pub const fn new() -> Self {
+        Self {
+            magic: BOOT_STATE_MAGIC,
+            seq: 0,
+            min_epoch: 0,
+            slots: [SlotState {
+                flags: 0,
+                attempts: 0,
+            }; 2],
+            check: 0,
+        }
+    }
+
+    /// Reads the newer of the boot state pages, returning `None` if neither
+    /// holds a boot state.
+    #[cfg(target_os = "none")]
+    pub fn load() -> Option<Self> {
+        newest(read_pages()).map(|(_, state)| state)
+    }
+
+    /// Reads the boot state, starting afresh if there isn't one.
+    #[cfg(target_os = "none")]
+    pub fn load_or_new() -> Self {
+        Self::load().unwrap_or_else(Self::new)
+    }
+
+    /// Writes this state over the older of the boot state pages, so that it
+    /// becomes the newer one.
+    ///
+    /// # Safety
+    ///
+    /// This erases and programs flash through the ROM, so nothing else may be
+    /// using the flash controller at the same time.
+    #[cfg(target_os = "none")]
+    pub unsafe fn store(&self) -> Result<(), FlashStatus> {
+        let (index, record) = self.next_record(read_pages());
+        let addr = page_addrs()[index];
+        let mut page = [0u8; FLASH_PAGE_SIZE];
+        page[..core::mem::size_of::<Self>()].copy_from_slice(record.as_bytes());
+
+        lpc55_romapi::flash_erase(addr, FLASH_PAGE_SIZE as u32)?;
+        lpc55_romapi::flash_write(
+            addr,
+            page.as_mut_ptr(),
+            FLASH_PAGE_SIZE as u32,
+        )
+    }
+
+    /// Decides which slot to boot, given the valid image (if any) in each of
+    /// A and B, and updates the state to record the decision. Returns `None`
+    /// if there's nothing we're willing to boot.
+    ///
+    /// We boot the newest image that hasn't been rejected and isn't from an
+    /// epoch before the minimum. If it has confirmed itself, that's that.
+    /// Otherwise, it gets `MAX_BOOT_ATTEMPTS` boots to do so before we reject
+    /// it and move on to the other image.
+    pub fn select(&mut self, images: [Option<Candidate>; 2]) -> Option<Slot> {
+        loop {
+            let eligible = |slot: Slot| {
+                images[slot as usize].filter(|i| {
+                    i.epoch >= self.min_epoch
+                        && self.slot(slot).flags & SLOT_REJECTED == 0
+                })
+            };
+            let (slot, image) =
+                match newer(eligible(Slot::A), eligible(Slot::B)) {
+                    Some(choice) => choice,
+                    None => break,
+                };
+
+            let s = self.slot_mut(slot);
+            if s.flags & SLOT_CONFIRMED != 0 {
+                // Don't boot anything older than a known-good image from now
+                // on.
+                self.min_epoch = self.min_epoch.max(image.epoch);
+                return Some(slot);
+            }
+
+            if s.attempts >= MAX_BOOT_ATTEMPTS {
+                s.flags = SLOT_REJECTED;
+                continue;
+            }
+
+            s.flags |= SLOT_PENDING;
+            s.attempts += 1;
+            return Some(slot);
+        }
+
+        // Every image has been rejected, which probably means they predate
+        // confirmation. Booting something beats booting nothing, so ignore
+        // the slot states (but not the epoch) and go back to picking the
+        // newest image.
+        let current = |slot: Slot| {
+            images[slot as usize].filter(|i| i.epoch >= self.min_epoch)
+        };
+        newer(current(Slot::A), current(Slot::B)).map(|(slot, _)| slot)
+    }
+
+    /// Works out which page to write this state to, given what's in them
+    /// now, and what to write there.
+    fn next_record(&self, pages: [Option<Self>; 2]) -> (usize, Self) {
+        let (index, seq) = match newest(pages) {
+            Some((newest, state)) => (1 - newest, state.seq.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let mut record = Self {
+            magic: BOOT_STATE_MAGIC,
+            seq,
+            ..*self
+        };
+        record.check = record.checksum();
+        (index, record)
+    }
+
+    /// Checks that this was read from a page that we wrote in full.
+    fn is_valid(&self) -> bool {
+        self.magic == BOOT_STATE_MAGIC && self.check == self.checksum()
+    }
+
+    fn checksum(&self) -> u32 {
+        let bytes = self.as_bytes();
+        let body = &bytes[..bytes.len() - core::mem::size_of::<u32>()];
+        body.chunks(4).fold(!BOOT_STATE_MAGIC, |sum, word| {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            sum.rotate_left(7) ^ word
+        })
+    }
+
+    pub fn slot(&self, slot: Slot) -> &SlotState {
+        &self.slots[slot as usize]
+    }
+
+    pub fn slot_mut(&mut self, slot: Slot) -> &mut SlotState {
+        &mut self.slots[slot as usize]
+    }
+}
+
+impl Default for BootState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Picks the newer of two images, preferring B if they're the same version.
+fn newer(
+    a: Option<Candidate>,
+    b: Option<Candidate>,
+) -> Option<(Slot, Candidate)> {
+    match (a, b) {
+        (None, None) => None,
+        (Some(a), None) => Some((Slot::A, a)),
+        (None, Some(b)) => Some((Slot::B, b)),
+        (Some(a), Some(b)) => {
+            if a.version > b.version {
+                Some((Slot::A, a))
+            } else {
+                Some((Slot::B, b))
+            }
+        }
+    }
+}
+
+/// Returns the valid record with the later sequence number, along with which
+/// page it came from.
+fn newest(pages: [Option<BootState>; 2]) -> Option<(usize, BootState)> {
+    match pages {
+        [None, None] => None,
+        [Some(a), None] => Some((0, a)),
+        [None, Some(b)] => Some((1, b)),
+        // Compare sequence numbers so that this survives them wrapping.
+        [Some(a), Some(b)] => {
+            if (b.seq.wrapping_sub(a.seq) as i32) > 0 {
+                Some((1, b))
+            } else {
+                Some((0, a))
+            }
+        }
+    }
+}
+
+/// Returns the address of the first boot state page. The pages run to the
+/// end of stage0's flash region.
+#[cfg(target_os = "none")]
+pub fn base_addr() -> u32 {
+    // Safety: this is a linker-defined symbol with no storage; we only take
+    // its address.
+    let end = unsafe { core::ptr::addr_of!(__IMAGE_STAGE0_END) as u32 };
+    end - (BOOT_STATE_PAGES * FLASH_PAGE_SIZE) as u32
+}
+
+#[cfg(target_os = "none")]
+fn page_addrs() -> [u32; BOOT_STATE_PAGES] {
+    let base = base_addr();
+    [base, base + FLASH_PAGE_SIZE as u32]
+}
+
+/// Reads both boot state pages, leaving out any that don't hold a valid
+/// record.
+#[cfg(target_os = "none")]
+fn read_pages() -> [Option<BootState>; 2] {
+    page_addrs().map(|addr| {
+        // Reading erased flash faults, so check first.
+        if !lpc55_romapi::validate_programmed(addr, FLASH_PAGE_SIZE as u32) {
+            return None;
+        }
+
+        // Safety: the page is programmed, page aligned, and any bit pattern
+        // is a valid `BootState`.
+        let state =
+            unsafe { core::ptr::read_volatile(addr as *const BootState) };
+        Some(state).filter(BootState::is_valid)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(min_epoch: u32) -> BootState {
+        BootState {
+            min_epoch,
+            ..BootState::new()
+        }
+    }
+
+    #[test]
+    fn first_store() {
+        let (index, record) = state(3).next_record([None, None]);
+        assert_eq!(index, 0);
+        assert!(record.is_valid());
+        assert_eq!(record.min_epoch, 3);
+        assert_eq!(newest([Some(record), None]), Some((0, record)));
+    }
+
+    #[test]
+    fn stores_alternate() {
+        let mut pages = [None, None];
+        for epoch in 0..5 {
+            let (index, record) = state(epoch).next_record(pages);
+            assert_eq!(index, epoch as usize % 2);
+            pages[index] = Some(record);
+            let (_, loaded) = newest(pages).unwrap();
+            assert_eq!(loaded.min_epoch, epoch);
+        }
+    }
+
+    #[test]
+    fn torn_store() {
+        // Both pages hold a record, and we lose power part way through
+        // replacing the older one. We're left with the newer one, rather than
+        // nothing.
+        let (_, first) = state(1).next_record([None, None]);
+        let (_, second) = state(2).next_record([Some(first), None]);
+        let (index, mut third) =
+            state(3).next_record([Some(first), Some(second)]);
+        assert_eq!(index, 0);
+        third.min_epoch = 0;
+        assert!(!third.is_valid());
+
+        let pages = [Some(third).filter(BootState::is_valid), Some(second)];
+        assert_eq!(newest(pages).unwrap().1.min_epoch, 2);
+        // And the next store goes back over the broken page.
+        assert_eq!(state(3).next_record(pages).0, 0);
+    }
+
+    #[test]
+    fn checksum_covers_fields() {
+        let (_, record) = state(1).next_record([None, None]);
+        let mut bytes = [0u8; core::mem::size_of::<BootState>()];
+        bytes.copy_from_slice(record.as_bytes());
+        for i in 0..bytes.len() {
+            let mut bad = bytes;
+            bad[i] ^= 1;
+            let bad = BootState::read_from(&bad[..]).unwrap();
+            assert!(!bad.is_valid(), "flipping byte {i} went unnoticed");
+        }
+        assert!(!BootState::new().is_valid());
+    }
+
+    fn image(epoch: u32, version: u32) -> Option<Candidate> {
+        Some(Candidate { epoch, version })
+    }
+
+    #[test]
+    fn nothing_to_boot() {
+        let mut s = BootState::new();
+        assert_eq!(s.select([None, None]), None);
+        assert_eq!(s, BootState::new());
+    }
+
+    #[test]
+    fn newer_version_wins() {
+        let mut s = BootState::new();
+        assert_eq!(s.select([image(0, 2), image(0, 1)]), Some(Slot::A));
+        let mut s = BootState::new();
+        assert_eq!(s.select([image(0, 1), image(0, 2)]), Some(Slot::B));
+        // Ties go to B.
+        let mut s = BootState::new();
+        assert_eq!(s.select([image(0, 1), image(0, 1)]), Some(Slot::B));
+        // An empty slot never wins.
+        let mut s = BootState::new();
+        assert_eq!(s.select([image(0, 1), None]), Some(Slot::A));
+    }
+
+    #[test]
+    fn pending_until_rejected() {
+        let images = [image(0, 1), image(0, 2)];
+        let mut s = BootState::new();
+        for attempt in 1..=MAX_BOOT_ATTEMPTS {
+            assert_eq!(s.select(images), Some(Slot::B));
+            assert_eq!(s.slot(Slot::B).flags, SLOT_PENDING);
+            assert_eq!(s.slot(Slot::B).attempts, attempt);
+        }
+        assert_eq!(*s.slot(Slot::A), SlotState::default());
+
+        // B never confirmed, so we give up on it and try A.
+        assert_eq!(s.select(images), Some(Slot::A));
+        assert_eq!(s.slot(Slot::B).flags, SLOT_REJECTED);
+        assert_eq!(s.slot(Slot::A).flags, SLOT_PENDING);
+        assert_eq!(s.slot(Slot::A).attempts, 1);
+        // And B stays rejected.
+        assert_eq!(s.select(images), Some(Slot::A));
+        assert_eq!(s.slot(Slot::A).attempts, 2);
+    }
+
+    #[test]
+    fn confirmed_raises_min_epoch() {
+        let mut s = BootState::new();
+        *s.slot_mut(Slot::A) = SlotState {
+            flags: SLOT_CONFIRMED,
+            attempts: 0,
+        };
+        assert_eq!(s.select([image(2, 1), image(1, 0)]), Some(Slot::A));
+        assert_eq!(s.min_epoch, 2);
+        assert_eq!(s.slot(Slot::A).flags, SLOT_CONFIRMED);
+        assert_eq!(s.slot(Slot::A).attempts, 0);
+
+        // Booting it again changes nothing.
+        let before = s;
+        assert_eq!(s.select([image(2, 1), image(1, 0)]), Some(Slot::A));
+        assert_eq!(s, before);
+    }
+
+    #[test]
+    fn old_epochs_ignored() {
+        // B is newer, but from before the minimum epoch.
+        let mut s = state(2);
+        assert_eq!(s.select([image(2, 1), image(1, 5)]), Some(Slot::A));
+        assert_eq!(*s.slot(Slot::B), SlotState::default());
+
+        let mut s = state(2);
+        assert_eq!(s.select([image(1, 1), image(0, 5)]), None);
+    }
+
+    #[test]
+    fn all_rejected() {
+        let mut s = state(1);
+        for slot in [Slot::A, Slot::B] {
+            *s.slot_mut(slot) = SlotState {
+                flags: SLOT_REJECTED,
+                attempts: MAX_BOOT_ATTEMPTS,
+            };
+        }
+        // We fall back to the newest image, leaving the state alone...
+        let before = s;
+        assert_eq!(s.select([image(1, 2), image(1, 1)]), Some(Slot::A));
+        assert_eq!(s, before);
+        // ...but still respect the minimum epoch.
+        assert_eq!(s.select([image(0, 2), image(1, 1)]), Some(Slot::B));
+        assert_eq!(s.select([image(0, 2), None]), None);
+    }
+
+    #[test]
+    fn sequence_wraps() {
+        let old = BootState {
+            seq: u32::MAX,
+            ..state(1)
+        };
+        let new = BootState { seq: 0, ..state(2) };
+        assert_eq!(newest([Some(old), Some(new)]).unwrap().1.min_epoch, 2);
+        assert_eq!(newest([Some(new), Some(old)]).unwrap().1.min_epoch, 2);
+    }
+}
@@ -3,6 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 #![no_std]
+use lpc55_boot_state::{BootState, Slot, SLOT_CONFIRMED};
 use lpc55_romapi::*;
 
 pub use drv_update_api::UpdateTarget;
@@ -30,7 +31,6 @@ extern "C" {
     static __IMAGE_B_END: ();
 
     static __IMAGE_STAGE0_BASE: ();
-    static __IMAGE_STAGE0_END: ();
 
     // This references the base of the currently running image
     static __this_image: ();
@@ -72,12 +72,6 @@ macro_rules! image_stage0_base {
     };
 }
 
-macro_rules! image_stage0_end {
-    () => {
-        core::ptr::addr_of!(__IMAGE_STAGE0_END) as u32
-    };
-}
-
 fn get_base(which: UpdateTarget) -> u32 {
     match which {
         UpdateTarget::ImageA => unsafe { image_a_base!() },
@@ -91,7 +85,9 @@ fn get_end(which: UpdateTarget) -> u32 {
     match which {
         UpdateTarget::ImageA => unsafe { image_a_end!() },
         UpdateTarget::ImageB => unsafe { image_b_end!() },
-        UpdateTarget::Bootloader => unsafe { image_stage0_end!() },
+        // The last pages of stage0's region hold the boot state, which
+        // updates mustn't touch.
+        UpdateTarget::Bootloader => lpc55_boot_state::base_addr(),
         _ => unreachable!(),
     }
 }
@@ -100,6 +96,14 @@ fn same_image(which: UpdateTarget) -> bool {
     get_base(which) == unsafe { this_image!() }
 }
 
+fn boot_slot(which: UpdateTarget) -> Option<Slot> {
+    match which {
+        UpdateTarget::ImageA => Some(Slot::A),
+        UpdateTarget::ImageB => Some(Slot::B),
+        _ => None,
+    }
+}
+
 fn target_addr(
     image_target: UpdateTarget,
     page_num: u32,
@@ -140,6 +144,20 @@ pub unsafe extern "C" fn __write_block(
         Err(e) => return e,
     };
 
+    // Whatever stage0 knew about the image we're replacing no longer applies.
+    // Do this before touching the image, so that a half-written image is
+    // never taken for a confirmed one.
+    if let Some(slot) = boot_slot(image_num) {
+        if let Some(mut state) = BootState::load() {
+            if *state.slot(slot) != Default::default() {
+                *state.slot_mut(slot) = Default::default();
+                if let Err(result) = state.store() {
+                    return HypoStatus::FlashError(result);
+                }
+            }
+        }
+    }
+
     // We expect this to be called from non-secure (running on 28) and
     // non-privileged mode (called from hubris task). The tt instructions
     // are mostly useless for doing any kind of checking on the buffer
@@ -156,3 +174,31 @@ pub unsafe extern "C" fn __write_block(
 
     HypoStatus::Success
 }
+
+/// Confirms that the running image works, so that stage0 keeps booting it
+/// rather than falling back to the other image.
+#[no_mangle]
+pub unsafe extern "C" fn __confirm_boot() -> HypoStatus {
+    let slot = if same_image(UpdateTarget::ImageA) {
+        Slot::A
+    } else if same_image(UpdateTarget::ImageB) {
+        Slot::B
+    } else {
+        // Only images A and B are subject to rollback.
+        return HypoStatus::OutOfBounds;
+    };
+
+    let mut state = BootState::load_or_new();
+    let s = state.slot_mut(slot);
+    if s.flags == SLOT_CONFIRMED && s.attempts == 0 {
+        // Nothing to do; spare the flash.
+        return HypoStatus::Success;
+    }
+    s.flags = SLOT_CONFIRMED;
+    s.attempts = 0;
+
+    match state.store() {
+        Ok(()) => HypoStatus::Success,
+        Err(result) => HypoStatus::FlashError(result),
+    }
+}
@@ -23,6 +23,9 @@
 //! - When you're ready to process timer events (which may or may not be
 //!   immediately after the notification), call `Multitimer::iter_fired`.
 //!
+//! With the `exec` feature, a future running under `userlib::exec` can instead
+//! `wait` for the next timer to fire, which takes care of the notifications.
+//!
 //! **Note:** the `Multitimer` assumes that it has sole control of the
 //! underlying timer. If you create two `Multitimer`s using the same underlying
 //! timer, they will fight and the results will be unpleasant. API like
@@ -32,6 +35,7 @@
 #![cfg_attr(target_os = "none", no_std)]
 
 use enum_map::{EnumArray, EnumMap};
+use userlib::time::{Duration, Instant};
 
 // Import the actual syscalls if we're targeting actual Hubris; otherwise we use
 // some stub functions defined below.
@@ -40,7 +44,7 @@ use userlib::{sys_get_timer, sys_set_timer};
 
 pub struct Multitimer<E: EnumArray<Timer>> {
     notification_bit: u8,
-    current_setting: Option<u64>,
+    current_setting: Option<Instant>,
     timers: EnumMap<E, Timer>,
 }
 
@@ -55,7 +59,7 @@ impl<E: EnumArray<Timer> + Copy> Multitimer<E> {
 
     // Any time we call `sys_set_timer` we also need to record that setting in
     // `self.current_setting`; all timer sets should go through this helper.
-    fn set_system_timer(&mut self, deadline: Option<u64>) {
+    fn set_system_timer(&mut self, deadline: Option<Instant>) {
         sys_set_timer(deadline, 1 << self.notification_bit);
         self.current_setting = deadline;
     }
@@ -70,7 +74,7 @@ impl<E: EnumArray<Timer> + Copy> Multitimer<E> {
     pub fn set_timer(
         &mut self,
         which: E,
-        deadline: u64,
+        deadline: Instant,
         repeat: Option<Repeat>,
     ) {
         // If the timer has previously fired without us noticing it, preserve
@@ -89,7 +93,7 @@ impl<E: EnumArray<Timer> + Copy> Multitimer<E> {
         }
     }
 
-    pub fn get_timer(&self, which: E) -> Option<(u64, Option<Repeat>)> {
+    pub fn get_timer(&self, which: E) -> Option<(Instant, Option<Repeat>)> {
         self.timers[which].deadline
     }
 
@@ -125,7 +129,7 @@ impl<E: EnumArray<Timer> + Copy> Multitimer<E> {
             return;
         }
 
-        let t = sys_get_timer().now;
+        let t = Instant::from_ticks(sys_get_timer().now);
 
         // As a premature optimization, we'll keep track of the new earliest
         // deadline after the timers have fired and only make one pass over the
@@ -188,18 +192,36 @@ impl<E: EnumArray<Timer> + Copy> Multitimer<E> {
             }
         })
     }
+
+    /// Waits for a timer to fire, and returns it, for use from a future
+    /// running under `userlib::exec`. This takes care of the notifications;
+    /// there's no need to call `handle_notification`.
+    ///
+    /// If several timers have fired, they're returned one per call, in the
+    /// same order as `iter_fired`.
+    #[cfg(feature = "exec")]
+    pub async fn wait(&mut self) -> E {
+        loop {
+            if let Some(which) = self.iter_fired().next() {
+                return which;
+            }
+            let bits =
+                userlib::exec::notification(1 << self.notification_bit).await;
+            self.handle_notification(bits);
+        }
+    }
 }
 
 #[derive(Copy, Clone, Default)]
 pub struct Timer {
-    deadline: Option<(u64, Option<Repeat>)>,
+    deadline: Option<(Instant, Option<Repeat>)>,
     fired_but_not_observed: bool,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Repeat {
-    AfterWake(u64),
-    AfterDeadline(u64),
+    AfterWake(Duration),
+    AfterDeadline(Duration),
 }
 
 // Syscall fakes for testing!
@@ -207,13 +229,15 @@ pub enum Repeat {
 #[cfg(not(target_os = "none"))]
 mod fakes {
     use core::cell::Cell;
+    use userlib::time::Instant;
 
     thread_local! {
         pub static CURRENT_TIME: Cell<u64> = Cell::new(0);
-        pub static TIMER_SETTING: Cell<(Option<u64>, u32)> = Cell::default();
+        pub static TIMER_SETTING: Cell<(Option<Instant>, u32)> =
+            Cell::default();
     }
 
-    pub fn sys_set_timer(deadline: Option<u64>, not: u32) {
+    pub fn sys_set_timer(deadline: Option<Instant>, not: u32) {
         TIMER_SETTING.with(|s| s.set((deadline, not)));
     }
 
@@ -230,7 +254,7 @@ mod fakes {
     #[allow(dead_code)]
     pub struct TimerState {
         pub now: u64,
-        pub deadline: Option<u64>,
+        pub deadline: Option<Instant>,
         pub on_dl: u32,
     }
 }
@@ -245,6 +269,14 @@ mod tests {
         CURRENT_TIME.with(|t| t.set(time));
     }
 
+    fn at(time: u64) -> Instant {
+        Instant::from_ticks(time)
+    }
+
+    fn ticks(n: u64) -> Duration {
+        Duration::from_ticks(n)
+    }
+
     use enum_map::Enum;
 
     #[derive(Copy, Clone, Debug, Eq, PartialEq, Enum)]
@@ -272,10 +304,10 @@ mod tests {
     fn setting_timer_propagates() {
         let mut uut = make_uut(0);
 
-        uut.set_timer(Timers::A, 1234, None);
+        uut.set_timer(Timers::A, at(1234), None);
 
         let s = sys_get_timer();
-        assert_eq!(s.deadline, Some(1234));
+        assert_eq!(s.deadline, Some(at(1234)));
         assert_eq!(s.on_dl, 1 << 0);
     }
 
@@ -283,11 +315,11 @@ mod tests {
     fn earlier_timer_overrides() {
         let mut uut = make_uut(0);
 
-        uut.set_timer(Timers::A, 1234, None);
-        uut.set_timer(Timers::B, 12, None);
+        uut.set_timer(Timers::A, at(1234), None);
+        uut.set_timer(Timers::B, at(12), None);
 
         let s = sys_get_timer();
-        assert_eq!(s.deadline, Some(12));
+        assert_eq!(s.deadline, Some(at(12)));
         assert_eq!(s.on_dl, 1 << 0);
     }
 
@@ -295,12 +327,12 @@ mod tests {
     fn clear_timer_resets_undertimer() {
         let mut uut = make_uut(0);
 
-        uut.set_timer(Timers::A, 1234, None);
-        uut.set_timer(Timers::B, 12, None);
+        uut.set_timer(Timers::A, at(1234), None);
+        uut.set_timer(Timers::B, at(12), None);
         uut.clear_timer(Timers::B);
 
         let s = sys_get_timer();
-        assert_eq!(s.deadline, Some(1234));
+        assert_eq!(s.deadline, Some(at(1234)));
         assert_eq!(s.on_dl, 1 << 0);
     }
 
@@ -308,8 +340,8 @@ mod tests {
     fn clear_all_timers_disables() {
         let mut uut = make_uut(0);
 
-        uut.set_timer(Timers::A, 1234, None);
-        uut.set_timer(Timers::B, 12, None);
+        uut.set_timer(Timers::A, at(1234), None);
+        uut.set_timer(Timers::B, at(12), None);
         uut.clear_timer(Timers::A);
         uut.clear_timer(Timers::B);
 
@@ -321,8 +353,8 @@ mod tests {
         change_time(0);
         let mut uut = make_uut(0);
 
-        uut.set_timer(Timers::A, 1234, None);
-        uut.set_timer(Timers::B, 12, None);
+        uut.set_timer(Timers::A, at(1234), None);
+        uut.set_timer(Timers::B, at(12), None);
 
         // The time hasn't yet reached our earliest deadline, so notifications
         // should be no-ops.
@@ -357,10 +389,14 @@ mod tests {
         let mut uut = make_uut(0);
 
         // Timer A will go off at 1234, 2234, 3234, ...
-        uut.set_timer(Timers::A, 1234, Some(Repeat::AfterDeadline(1000)));
+        uut.set_timer(
+            Timers::A,
+            at(1234),
+            Some(Repeat::AfterDeadline(ticks(1000))),
+        );
         // Timer B will go off at 12, and then every 1000 ticks _after the
         // firing was observed._
-        uut.set_timer(Timers::B, 12, Some(Repeat::AfterWake(2000)));
+        uut.set_timer(Timers::B, at(12), Some(Repeat::AfterWake(ticks(2000))));
 
         // The time hasn't yet reached our earliest deadline, so notifications
         // should be no-ops.
@@ -382,7 +418,7 @@ mod tests {
         // Timer B should now be set 2000 ticks _from now_ and still repeating.
         assert_eq!(
             uut.get_timer(Timers::B),
-            Some((100 + 2000, Some(Repeat::AfterWake(2000)))),
+            Some((at(100 + 2000), Some(Repeat::AfterWake(ticks(2000))))),
         );
 
         // Advance past the other but before timer B recurs
@@ -394,7 +430,7 @@ mod tests {
         // precisely 2234, and _not_ 2300.
         assert_eq!(
             uut.get_timer(Timers::A),
-            Some((2234, Some(Repeat::AfterDeadline(1000)))),
+            Some((at(2234), Some(Repeat::AfterDeadline(ticks(1000))))),
         );
 
         // Trigger both timers again.
@@ -412,18 +448,18 @@ mod tests {
         let mut uut = make_uut(0);
 
         // Set A to go off at 10 and B to go off at 20
-        uut.set_timer(Timers::A, 10, None);
-        uut.set_timer(Timers::B, 20, None);
+        uut.set_timer(Timers::A, at(10), None);
+        uut.set_timer(Timers::B, at(20), None);
 
         // System timer should be set to 10, the earliest deadline.
-        assert_eq!(sys_get_timer().deadline, Some(10));
+        assert_eq!(sys_get_timer().deadline, Some(at(10)));
 
         // Clear A, then reset it for 15.
         uut.clear_timer(Timers::A);
-        uut.set_timer(Timers::A, 15, None);
+        uut.set_timer(Timers::A, at(15), None);
 
         // System timer should be set to 15, the new earliest deadline.
-        assert_eq!(sys_get_timer().deadline, Some(15));
+        assert_eq!(sys_get_timer().deadline, Some(at(15)));
 
         // Advance to T=16; A should fire.
         change_time(16);
@@ -432,7 +468,54 @@ mod tests {
 
         // Set A to go off at 18, and check that the system timer is set
         // accordingly.
-        uut.set_timer(Timers::A, 18, None);
-        assert_eq!(sys_get_timer().deadline, Some(18));
+        uut.set_timer(Timers::A, at(18), None);
+        assert_eq!(sys_get_timer().deadline, Some(at(18)));
+    }
+
+    #[cfg(feature = "exec")]
+    #[test]
+    fn wait() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct NoWake;
+        impl Wake for NoWake {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        // Polls a fresh `wait` once. There's no executor here, so
+        // notifications are handed over with `exec::notify`.
+        fn poll_wait(uut: &mut Multitimer<Timers>) -> Poll<Timers> {
+            let waker = Waker::from(Arc::new(NoWake));
+            let mut wait = Box::pin(uut.wait());
+            wait.as_mut().poll(&mut Context::from_waker(&waker))
+        }
+
+        change_time(0);
+        let mut uut = make_uut(3);
+        uut.set_timer(Timers::A, at(10), None);
+        uut.set_timer(Timers::B, at(5), None);
+        assert_eq!(poll_wait(&mut uut), Poll::Pending);
+
+        // A notification before any deadline doesn't end the wait.
+        change_time(3);
+        userlib::exec::notify(1 << 3);
+        assert_eq!(poll_wait(&mut uut), Poll::Pending);
+
+        change_time(5);
+        userlib::exec::notify(1 << 3);
+        assert_eq!(poll_wait(&mut uut), Poll::Ready(Timers::B));
+        assert_eq!(poll_wait(&mut uut), Poll::Pending);
+        assert_eq!(sys_get_timer().deadline, Some(at(10)));
+
+        // Timers that fire together come out one per wait, with no need for
+        // another notification.
+        uut.set_timer(Timers::B, at(10), None);
+        change_time(12);
+        userlib::exec::notify(1 << 3);
+        assert_eq!(poll_wait(&mut uut), Poll::Ready(Timers::A));
+        assert_eq!(poll_wait(&mut uut), Poll::Ready(Timers::B));
+        assert_eq!(poll_wait(&mut uut), Poll::Pending);
     }
 }
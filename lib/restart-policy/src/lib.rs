@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Restart policies for tasks that keep faulting.
+//!
+//! The supervisor keeps track of when each task's current window started and
+//! how many times it has faulted within it; a `RestartPolicy` turns that count
+//! into what to do about the latest fault. This is kept apart from the
+//! supervisor so that the arithmetic can be tested on the host.
+
+#![cfg_attr(not(test), no_std)]
+
+/// Restart policy for a task that keeps faulting, from the `restart-policy`
+/// table in jefe's config.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RestartPolicy {
+    /// Restarts allowed per window before we call it a crash loop.
+    pub max_restarts: u32,
+    pub window_ms: u64,
+    /// Delay before the first restart in a window; it doubles for each
+    /// further restart, up to `max_backoff_ms`.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub on_crash_loop: OnCrashLoop,
+}
+
+/// What to do about a task that has exceeded its restart policy.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OnCrashLoop {
+    /// Give up and leave the task faulted, as if its disposition were Hold.
+    Hold,
+    /// Reset the system.
+    Reset,
+}
+
+/// What a `RestartPolicy` says to do about a fault.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Restart the task after this many milliseconds, which may be zero.
+    Restart { delay_ms: u64 },
+    /// The task is crash-looping; do what the policy says about that.
+    CrashLoop(OnCrashLoop),
+}
+
+impl RestartPolicy {
+    /// Decides what to do about a task's latest fault, given the number of
+    /// faults (including this one) in its current window. `window_faults` is
+    /// expected to be at least 1.
+    pub fn action(&self, window_faults: u32) -> Action {
+        if window_faults > self.max_restarts {
+            return Action::CrashLoop(self.on_crash_loop);
+        }
+        let doublings = window_faults.saturating_sub(1);
+        let delay_ms = 1u64
+            .checked_shl(doublings)
+            .map_or(u64::MAX, |m| self.backoff_ms.saturating_mul(m))
+            .min(self.max_backoff_ms);
+        Action::Restart { delay_ms }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(backoff_ms: u64, max_backoff_ms: u64) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: u32::MAX,
+            window_ms: 60_000,
+            backoff_ms,
+            max_backoff_ms,
+            on_crash_loop: OnCrashLoop::Hold,
+        }
+    }
+
+    fn delay(policy: &RestartPolicy, window_faults: u32) -> u64 {
+        match policy.action(window_faults) {
+            Action::Restart { delay_ms } => delay_ms,
+            a => panic!("expected a restart, got {a:?}"),
+        }
+    }
+
+    #[test]
+    fn no_backoff() {
+        let p = policy(0, 0);
+        for n in [1, 2, 10, 1000, u32::MAX] {
+            assert_eq!(delay(&p, n), 0);
+        }
+    }
+
+    #[test]
+    fn doubles_up_to_cap() {
+        let p = policy(100, 1000);
+        let delays: Vec<u64> = (1..=6).map(|n| delay(&p, n)).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+    }
+
+    #[test]
+    fn fixed_backoff() {
+        // With the cap at the initial delay, as when `max-backoff-ms` is
+        // left out, the delay never grows.
+        let p = policy(250, 250);
+        for n in [1, 2, 3, 100] {
+            assert_eq!(delay(&p, n), 250);
+        }
+    }
+
+    #[test]
+    fn no_overflow() {
+        // Shifts past the width of a u64, and products that don't fit in
+        // one, both land on the cap.
+        let p = policy(1 << 40, u64::MAX);
+        assert_eq!(delay(&p, 24), 1 << 63);
+        assert_eq!(delay(&p, 25), u64::MAX);
+        assert_eq!(delay(&p, 64), u64::MAX);
+        assert_eq!(delay(&p, 65), u64::MAX);
+        assert_eq!(delay(&p, u32::MAX), u64::MAX);
+
+        let p = policy(3, 1_000_000);
+        assert_eq!(delay(&p, 100), 1_000_000);
+    }
+
+    #[test]
+    fn crash_loop() {
+        for on_crash_loop in [OnCrashLoop::Hold, OnCrashLoop::Reset] {
+            let p = RestartPolicy {
+                max_restarts: 3,
+                on_crash_loop,
+                ..policy(10, 100)
+            };
+            assert_eq!(p.action(3), Action::Restart { delay_ms: 40 });
+            assert_eq!(p.action(4), Action::CrashLoop(on_crash_loop));
+            assert_eq!(p.action(u32::MAX), Action::CrashLoop(on_crash_loop));
+        }
+    }
+
+    #[test]
+    fn no_restarts_allowed() {
+        let p = RestartPolicy {
+            max_restarts: 0,
+            ..policy(10, 100)
+        };
+        assert_eq!(p.action(1), Action::CrashLoop(OnCrashLoop::Hold));
+    }
+}
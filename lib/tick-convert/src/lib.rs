@@ -0,0 +1,139 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Conversions between kernel ticks and real-world units.
+//!
+//! `userlib::time` does these at the rate the image was built with; they live
+//! here, with the rate as a parameter, so that they can be tested on the host
+//! at rates other than the one any given image uses.
+//!
+//! Conversions into ticks round up, so that a timeout or sleep is never
+//! shorter than asked for; conversions out of ticks round down.
+
+#![cfg_attr(not(test), no_std)]
+
+/// Converts `n` units of `1 / per_sec` seconds into ticks at `hz` ticks per
+/// second, rounding up and saturating at `u64::MAX`.
+pub const fn to_ticks(n: u64, per_sec: u64, hz: u64) -> u64 {
+    if hz % per_sec == 0 {
+        n.saturating_mul(hz / per_sec)
+    } else if per_sec % hz == 0 {
+        let d = per_sec / hz;
+        n / d + (n % d != 0) as u64
+    } else {
+        // Split off whole seconds first, so that only the remainder gets
+        // scaled and can't overflow.
+        let rem = (n % per_sec) * hz;
+        let part = rem / per_sec + (rem % per_sec != 0) as u64;
+        (n / per_sec).saturating_mul(hz).saturating_add(part)
+    }
+}
+
+/// Converts `ticks` at `hz` ticks per second into units of `1 / per_sec`
+/// seconds, rounding down and saturating at `u64::MAX`.
+pub const fn from_ticks(ticks: u64, per_sec: u64, hz: u64) -> u64 {
+    if per_sec % hz == 0 {
+        ticks.saturating_mul(per_sec / hz)
+    } else if hz % per_sec == 0 {
+        ticks / (hz / per_sec)
+    } else {
+        let part = (ticks % hz) * per_sec / hz;
+        (ticks / hz).saturating_mul(per_sec).saturating_add(part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECS: u64 = 1;
+    const MILLIS: u64 = 1_000;
+    const MICROS: u64 = 1_000_000;
+
+    #[test]
+    fn millisecond_ticks() {
+        // One tick per millisecond: exact for millis, a whole multiple for
+        // seconds, and a whole fraction for micros.
+        assert_eq!(to_ticks(5, SECS, 1000), 5000);
+        assert_eq!(to_ticks(250, MILLIS, 1000), 250);
+        assert_eq!(to_ticks(1, MICROS, 1000), 1);
+        assert_eq!(to_ticks(1000, MICROS, 1000), 1);
+        assert_eq!(to_ticks(1001, MICROS, 1000), 2);
+        assert_eq!(to_ticks(0, MICROS, 1000), 0);
+
+        assert_eq!(from_ticks(5999, SECS, 1000), 5);
+        assert_eq!(from_ticks(250, MILLIS, 1000), 250);
+        assert_eq!(from_ticks(3, MICROS, 1000), 3000);
+    }
+
+    #[test]
+    fn binary_ticks() {
+        // 1024 Hz divides into neither millis nor micros.
+        assert_eq!(to_ticks(2, SECS, 1024), 2048);
+        assert_eq!(to_ticks(1000, MILLIS, 1024), 1024);
+        assert_eq!(to_ticks(1, MILLIS, 1024), 2);
+        assert_eq!(to_ticks(125, MILLIS, 1024), 128);
+        assert_eq!(to_ticks(126, MILLIS, 1024), 130);
+        assert_eq!(to_ticks(1, MICROS, 1024), 1);
+
+        assert_eq!(from_ticks(2047, SECS, 1024), 1);
+        assert_eq!(from_ticks(1024, MILLIS, 1024), 1000);
+        assert_eq!(from_ticks(1, MILLIS, 1024), 0);
+        assert_eq!(from_ticks(130, MILLIS, 1024), 126);
+        assert_eq!(from_ticks(1, MICROS, 1024), 976);
+    }
+
+    #[test]
+    fn rtc_ticks() {
+        assert_eq!(to_ticks(1, SECS, 32768), 32768);
+        assert_eq!(to_ticks(1, MILLIS, 32768), 33);
+        assert_eq!(to_ticks(1000, MILLIS, 32768), 32768);
+        assert_eq!(to_ticks(31, MICROS, 32768), 2);
+        assert_eq!(to_ticks(30, MICROS, 32768), 1);
+
+        assert_eq!(from_ticks(32767, SECS, 32768), 0);
+        assert_eq!(from_ticks(33, MILLIS, 32768), 1);
+        assert_eq!(from_ticks(32768, MICROS, 32768), 1_000_000);
+    }
+
+    #[test]
+    fn coarse_ticks() {
+        // 100 Hz: millis and micros are whole fractions of a tick.
+        assert_eq!(to_ticks(3, SECS, 100), 300);
+        assert_eq!(to_ticks(10, MILLIS, 100), 1);
+        assert_eq!(to_ticks(11, MILLIS, 100), 2);
+        assert_eq!(to_ticks(1, MILLIS, 100), 1);
+        assert_eq!(to_ticks(0, MILLIS, 100), 0);
+        assert_eq!(to_ticks(10_000, MICROS, 100), 1);
+        assert_eq!(to_ticks(10_001, MICROS, 100), 2);
+
+        assert_eq!(from_ticks(199, SECS, 100), 1);
+        assert_eq!(from_ticks(3, MILLIS, 100), 30);
+        assert_eq!(from_ticks(3, MICROS, 100), 30_000);
+    }
+
+    #[test]
+    fn saturates() {
+        for hz in [100, 1000, 1024, 32768] {
+            assert_eq!(to_ticks(u64::MAX, SECS, hz), u64::MAX, "{hz}");
+            assert_eq!(from_ticks(u64::MAX, MICROS, hz), u64::MAX, "{hz}");
+        }
+        assert_eq!(to_ticks(u64::MAX, MILLIS, 100), u64::MAX / 10 + 1);
+        assert_eq!(to_ticks(u64::MAX, MILLIS, 1024), u64::MAX);
+        assert_eq!(
+            from_ticks(u64::MAX, MILLIS, 1024),
+            u64::MAX / 1024 * 1000 + 999
+        );
+    }
+
+    #[test]
+    fn round_trip_never_shortens() {
+        for hz in [100, 1000, 1024, 32768] {
+            for ms in 0..3000 {
+                let back = from_ticks(to_ticks(ms, MILLIS, hz), MILLIS, hz);
+                assert!(back >= ms, "{ms} ms at {hz} Hz came back as {back}");
+            }
+        }
+    }
+}
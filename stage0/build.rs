@@ -11,6 +11,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let image_id: u64 = build_util::env_var("HUBRIS_IMAGE_ID")?.parse()?;
 
+    // The key images must be signed with, as an uncompressed SEC1 P-256
+    // point in hex. xtask derives this from `image-key` in `[signing]`.
+    let public_key =
+        build_util::env_var("HUBRIS_IMAGE_PUBLIC_KEY").map_err(|_| {
+            "stage0 needs `image-key` set in the app.toml's [signing] section"
+        })?;
+    let public_key = (0..public_key.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(public_key.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|key| key.len() == 65)
+        .ok_or("HUBRIS_IMAGE_PUBLIC_KEY is not a 65-byte hex string")?;
+
     writeln!(const_file, "// See build.rs for details")?;
 
     writeln!(const_file, "#[used]")?;
@@ -22,5 +35,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         image_id
     )?;
 
+    writeln!(
+        const_file,
+        "const IMAGE_PUBLIC_KEY: [u8; 65] = {:?};",
+        public_key
+    )?;
+
     Ok(())
 }
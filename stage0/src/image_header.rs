@@ -2,8 +2,9 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use abi::{ImageHeader, ImageVectors};
+use abi::{ImageHeader, ImageVectors, IMAGE_SIGNATURE_LEN};
 use lpc55_romapi::FLASH_PAGE_SIZE;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
 
 extern "C" {
     static IMAGEA: abi::ImageVectors;
@@ -20,9 +21,19 @@ extern "C" {
     // allow an improper ctype here.
     #[allow(improper_ctypes)]
     static __vector_size: ();
+    // The ends of the image slots, which bound how long an image can claim
+    // to be. These are zero sized for the same reason as `__vector_size`.
+    #[allow(improper_ctypes)]
+    static __IMAGE_A_END: ();
+    #[allow(improper_ctypes)]
+    static __IMAGE_B_END: ();
 }
 
-pub struct Image(&'static ImageVectors);
+#[derive(Copy, Clone)]
+pub struct Image {
+    vectors: &'static ImageVectors,
+    slot_end: u32,
+}
 
 // FLASH_PAGE_SIZE is a usize so redefine the constant here to avoid having
 // to do the u32 change everywhere
@@ -44,8 +55,12 @@ const PAGE_SIZE: u32 = FLASH_PAGE_SIZE as u32;
 
 pub fn get_image_b() -> Option<Image> {
     let imageb = unsafe { &IMAGEB };
+    let slot_end = unsafe { core::ptr::addr_of!(__IMAGE_B_END) as u32 };
 
-    let img = Image(imageb);
+    let img = Image {
+        vectors: imageb,
+        slot_end,
+    };
 
     if img.validate() {
         Some(img)
@@ -56,8 +71,12 @@ pub fn get_image_b() -> Option<Image> {
 
 pub fn get_image_a() -> Option<Image> {
     let imagea = unsafe { &IMAGEA };
+    let slot_end = unsafe { core::ptr::addr_of!(__IMAGE_A_END) as u32 };
 
-    let img = Image(imagea);
+    let img = Image {
+        vectors: imagea,
+        slot_end,
+    };
 
     if img.validate() {
         Some(img)
@@ -68,23 +87,31 @@ pub fn get_image_a() -> Option<Image> {
 
 impl Image {
     fn get_img_start(&self) -> u32 {
-        self.0 as *const ImageVectors as u32
+        self.vectors as *const ImageVectors as u32
     }
 
-    #[cfg(any(feature = "dice-mfg", feature = "dice-self"))]
-    fn get_img_size(&self) -> Option<usize> {
-        usize::try_from((unsafe { &*self.get_header() }).total_image_len).ok()
+    fn get_img_size(&self) -> usize {
+        // SAFETY: Only called once the header has been checked
+        (unsafe { &*self.get_header() }).total_image_len as usize
     }
 
-    #[cfg(any(feature = "dice-mfg", feature = "dice-self"))]
     pub fn as_bytes(&self) -> &[u8] {
-        use unwrap_lite::UnwrapLite;
-
         let img_ptr = self.get_img_start() as *const u8;
-        let img_size = self.get_img_size().unwrap_lite();
+        let img_size = self.get_img_size();
+        // SAFETY: validate() checks that all of this is programmed
         unsafe { core::slice::from_raw_parts(img_ptr, img_size) }
     }
 
+    /// The signature `xtask dist` appends to the image.
+    fn signature_bytes(&self) -> &[u8] {
+        let sig_ptr =
+            (self.get_img_start() as usize + self.get_img_size()) as *const u8;
+        // SAFETY: validate() checks that all of this is programmed
+        unsafe {
+            core::slice::from_raw_parts(sig_ptr, IMAGE_SIGNATURE_LEN as usize)
+        }
+    }
+
     fn get_header(&self) -> *const ImageHeader {
         // SAFETY: This generated by the linker script which we trust
         // Note that this is generated from _this_ image's linker script
@@ -94,7 +121,8 @@ impl Image {
         (self.get_img_start() + vector_size) as *const ImageHeader
     }
 
-    /// Make sure all of the image flash is programmed
+    /// Make sure all of the image flash is programmed, and that the image was
+    /// signed with our key
     fn validate(&self) -> bool {
         let img_start = self.get_img_start();
 
@@ -120,10 +148,18 @@ impl Image {
         // which we trust.
         let header = unsafe { &*header_ptr };
 
-        // Next make sure the marked image length is programmed
+        // The marked image length, and the signature after it, had better
+        // fit in the slot before we go reading them
+        let signed_len =
+            match header.total_image_len.checked_add(IMAGE_SIGNATURE_LEN) {
+                Some(len) if len <= self.slot_end - img_start => len,
+                _ => return false,
+            };
+
+        // Next make sure the marked image length and signature are programmed
         let valid = lpc55_romapi::validate_programmed(
             img_start,
-            (header.total_image_len + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1),
+            (signed_len + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1),
         );
 
         if !valid {
@@ -135,7 +171,23 @@ impl Image {
             return false;
         }
 
-        return true;
+        self.verify_signature()
+    }
+
+    /// Checks the image against the signature after it, using the public key
+    /// `xtask dist` built us with.
+    fn verify_signature(&self) -> bool {
+        let key = match VerifyingKey::from_sec1_bytes(&crate::IMAGE_PUBLIC_KEY)
+        {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+        let signature = match Signature::try_from(self.signature_bytes()) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+
+        key.verify(self.as_bytes(), &signature).is_ok()
     }
 
     pub fn get_vectors(&self) -> u32 {
@@ -143,11 +195,11 @@ impl Image {
     }
 
     pub fn get_pc(&self) -> u32 {
-        self.0.entry
+        self.vectors.entry
     }
 
     pub fn get_sp(&self) -> u32 {
-        self.0.sp
+        self.vectors.sp
     }
 
     pub fn get_version(&self) -> u32 {
@@ -157,6 +209,13 @@ impl Image {
         header.version
     }
 
+    pub fn get_epoch(&self) -> u32 {
+        // SAFETY: We checked this previously
+        let header = unsafe { &*self.get_header() };
+
+        header.epoch
+    }
+
     #[cfg(feature = "tz_support")]
     pub fn get_sau_entry<'a>(&self, i: usize) -> Option<&'a abi::SAUEntry> {
         // SAFETY: We checked this previously
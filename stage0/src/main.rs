@@ -30,6 +30,7 @@ cfg_if::cfg_if! {
 mod image_header;
 
 use crate::image_header::Image;
+use lpc55_boot_state::{BootState, Candidate};
 
 /// Initial entry point for handling a memory management fault.
 #[allow(non_snake_case)]
@@ -145,6 +146,39 @@ unsafe fn branch_to_image(image: Image) -> ! {
     );
 }
 
+/// Decides which image to boot, and records the decision in the boot state.
+/// See `BootState::select` for how.
+fn select_image() -> Image {
+    let mut state = BootState::load_or_new();
+
+    // Checking an image's signature takes a while, so only do it once.
+    let images = [image_header::get_image_a(), image_header::get_image_b()];
+    let candidates = images.map(|image| {
+        image.map(|i| Candidate {
+            epoch: i.get_epoch(),
+            version: i.get_version(),
+        })
+    });
+
+    let before = state;
+    let slot = state.select(candidates);
+    if state != before {
+        store(&state);
+    }
+
+    match slot.and_then(|slot| images[slot as usize]) {
+        Some(image) => image,
+        None => panic!(),
+    }
+}
+
+/// Writes out the boot state. If that fails we press on regardless: the worst
+/// outcome is that we try an image more times than we meant to.
+fn store(state: &BootState) {
+    // Safety: nothing else is running yet to contend for the flash.
+    let _ = unsafe { state.store() };
+}
+
 #[entry]
 fn main() -> ! {
     // This is the SYSCON_DIEID register on LPC55 which contains the ROM
@@ -155,23 +189,7 @@ fn main() -> ! {
         panic!()
     }
 
-    let (imagea, imageb) =
-        (image_header::get_image_a(), image_header::get_image_b());
-
-    // Image selection is very simple at the moment
-    // Future work: check persistent state and epochs
-    let image = match (imagea, imageb) {
-        (None, None) => panic!(),
-        (Some(a), None) => a,
-        (None, Some(b)) => b,
-        (Some(a), Some(b)) => {
-            if a.get_version() > b.get_version() {
-                a
-            } else {
-                b
-            }
-        }
-    };
+    let image = select_image();
 
     #[cfg(any(feature = "dice-mfg", feature = "dice-self"))]
     dice::run(&image);
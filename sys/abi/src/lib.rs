@@ -69,7 +69,9 @@ impl TaskId {
 }
 
 /// Type used to track generation numbers.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize,
+)]
 #[repr(transparent)]
 pub struct Generation(u8);
 
@@ -218,6 +220,13 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
+/// Response code returned by the kernel if a `SEND_WITH_DEADLINE` is not
+/// answered by its deadline.
+///
+/// This sits just below the range used by `dead_response_code`, so that it
+/// can't be confused with a peer generation.
+pub const TIMED_OUT: u32 = FIRST_DEAD_CODE - 1;
+
 /// State used to make scheduling decisions.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum TaskState {
@@ -292,6 +301,183 @@ impl From<SchedState> for TaskState {
     }
 }
 
+/// Execution counters the kernel keeps for each task, as read by
+/// `Kipcnum::ReadTaskStats`.
+///
+/// The counters start at zero at boot and are *not* reset when the task
+/// restarts. They wrap on overflow, so to get a rate, sample them twice and use
+/// `wrapping_sub`.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskStats {
+    /// Number of kernel timer ticks that ended with this task on the CPU. This
+    /// is a sampled (rather than measured) estimate of CPU time. With a
+    /// tickless kernel, the ticks of a long timer period are all charged to
+    /// the task running when it ends, so the estimate is rougher.
+    pub ticks: u32,
+    /// Number of times the kernel has switched to this task from another.
+    pub switches: u32,
+    /// Number of syscalls the task has made.
+    pub syscalls: u32,
+    /// Number of hardware interrupts that have been delivered to the task as
+    /// notifications.
+    pub irqs: u32,
+}
+
+/// A task's stack usage, as read by `Kipcnum::ReadStackUsage`.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct StackUsage {
+    /// Size of the task's stack, in bytes.
+    pub size: u32,
+    /// Greatest depth the stack has reached since the task last started, in
+    /// bytes.
+    pub max_depth: u32,
+}
+
+/// Processor state the kernel captures when a task faults, as read by
+/// `Kipcnum::ReadFaultRegisters`. This is enough to symbolize where the task
+/// was without a debugger attached.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct FaultRegisters {
+    /// The task's stack pointer at the point of the fault, not counting the
+    /// exception frame the processor pushed on the way into the kernel. If
+    /// `stacked` is `None`, this is the stack pointer as the kernel found it.
+    pub sp: u32,
+    /// Registers the processor stacked on the task's stack, or `None` if it
+    /// couldn't stack them -- usually because the task overflowed its stack.
+    pub stacked: Option<StackedRegisters>,
+    /// - ARMv7/8-M: the Configurable Fault Status Register at the time of the
+    ///   fault. This is zero for faults detected by the kernel rather than the
+    ///   processor.
+    /// - ARMv6-M: always zero.
+    pub cfsr: u32,
+    /// - ARMv7/8-M: the HardFault Status Register at the time of the fault.
+    /// - ARMv6-M: always zero.
+    pub hfsr: u32,
+}
+
+/// Registers stacked by the processor on a task's stack at fault time.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct StackedRegisters {
+    /// Address of the faulting instruction -- or, for faults detected by the
+    /// kernel during a syscall, of the instruction after the syscall.
+    pub pc: u32,
+    /// The task's link register.
+    pub lr: u32,
+    /// The task's program status register.
+    pub xpsr: u32,
+}
+
+/// Longest task name the kernel will hold, in bytes.
+pub const TASK_NAME_BYTES: usize = 32;
+
+/// Number of memory regions in a task's descriptor, including the null region
+/// that pads out unused entries.
+pub const REGIONS_PER_TASK: usize = 8;
+
+/// Number of memory region slots in a `TaskInfo`. This matches
+/// `REGIONS_PER_TASK`, so every region a task has fits.
+pub const TASK_INFO_REGIONS: usize = REGIONS_PER_TASK;
+
+/// Static description of a task, as read by `Kipcnum::ReadTaskInfo`. This
+/// comes from the application's `app.toml`, and lets tools and tasks on the
+/// target enumerate tasks without the build archive.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskInfo {
+    /// The task's name, padded with zeros. Use `TaskInfo::name` to get the
+    /// name without padding.
+    pub name: [u8; TASK_NAME_BYTES],
+    /// Priority the task starts with. Lower numbers are more important.
+    pub priority: u8,
+    /// Flags from the task's descriptor. Bit 0 means the task is started at
+    /// boot; the rest are reserved.
+    pub flags: u8,
+    /// Number of memory regions the task can access, which are described by
+    /// the start of `regions`.
+    pub region_count: u8,
+    /// Memory regions the task can access. Unused slots are all zeros.
+    pub regions: [TaskRegion; TASK_INFO_REGIONS],
+}
+
+impl TaskInfo {
+    /// Returns the task's name, without padding.
+    pub fn name(&self) -> &[u8] {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(TASK_NAME_BYTES);
+        &self.name[..len]
+    }
+}
+
+/// A memory region a task can access, as described in a `TaskInfo`.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskRegion {
+    /// Address of the start of the region.
+    pub base: u32,
+    /// Size of the region, in bytes.
+    pub size: u32,
+    /// What the task can do with the region: bit 0 is read, 1 is write, 2 is
+    /// execute, 3 marks device memory and 4 marks DMA memory.
+    pub attributes: u32,
+}
+
+/// Number of bytes of a task's most recent panic message that the kernel keeps
+/// for it, as read by `Kipcnum::ReadPanicMessage`.
+pub const PANIC_MESSAGE_BYTES: usize = 64;
+
+/// Number of bytes of a task's panic message that the kernel keeps in its
+/// event log.
+pub const KERNEL_LOG_PANIC_BYTES: usize = 32;
+
+/// An entry in the kernel's event log, as read by `Kipcnum::ReadKernelLog`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct KernelLogEntry {
+    /// Position of this entry in the log. Entries are numbered consecutively
+    /// from zero at boot, so a gap in the numbers seen by a reader means that
+    /// entries were overwritten before they could be read.
+    pub seq: u32,
+    /// Kernel time at which the event happened.
+    pub timestamp: u64,
+    /// What happened.
+    pub event: KernelEvent,
+}
+
+/// An event recorded in the kernel's event log.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum KernelEvent {
+    /// A task took a fault. Panics are recorded as `Panic` instead.
+    Fault { task: u16, fault: FaultInfo },
+    /// A task panicked. `message` holds the start of its panic message,
+    /// padded with zeroes if it's short. (There's no separate length, so that
+    /// a serialized `Option<KernelLogEntry>` fits in the size of one.)
+    Panic {
+        task: u16,
+        message: [u8; KERNEL_LOG_PANIC_BYTES],
+    },
+    /// A task was restarted at the request of task `by`, and now has
+    /// generation `generation`.
+    Restart {
+        task: u16,
+        generation: Generation,
+        by: u16,
+    },
+    /// A task asked the kernel to reset the system.
+    Reset { task: u16 },
+}
+
 /// A record describing a fault taken by a task.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum FaultInfo {
@@ -388,6 +574,9 @@ pub enum UsageError {
     NoIrq,
     BadKernelMessage,
     BadReplyFaultReason,
+    /// A program tried to send or post to a task that the application's IPC
+    /// policy doesn't let it talk to.
+    IpcNotPermitted,
 }
 
 /// Origin of a fault.
@@ -445,6 +634,7 @@ pub enum Sysnum {
     RefreshTaskId = 10,
     Post = 11,
     ReplyFault = 12,
+    SendWithDeadline = 13,
 }
 
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
@@ -468,6 +658,7 @@ impl core::convert::TryFrom<u32> for Sysnum {
             10 => Ok(Self::RefreshTaskId),
             11 => Ok(Self::Post),
             12 => Ok(Self::ReplyFault),
+            13 => Ok(Self::SendWithDeadline),
             _ => Err(()),
         }
     }
@@ -480,6 +671,12 @@ pub enum Kipcnum {
     FaultTask = 3,
     ReadImageId = 4,
     Reset = 5,
+    ReadTaskStats = 6,
+    ReadStackUsage = 7,
+    ReadKernelLog = 8,
+    ReadPanicMessage = 9,
+    ReadFaultRegisters = 10,
+    ReadTaskInfo = 11,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -492,6 +689,12 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             3 => Ok(Self::FaultTask),
             4 => Ok(Self::ReadImageId),
             5 => Ok(Self::Reset),
+            6 => Ok(Self::ReadTaskStats),
+            7 => Ok(Self::ReadStackUsage),
+            8 => Ok(Self::ReadKernelLog),
+            9 => Ok(Self::ReadPanicMessage),
+            10 => Ok(Self::ReadFaultRegisters),
+            11 => Ok(Self::ReadTaskInfo),
             _ => Err(()),
         }
     }
@@ -506,6 +709,10 @@ pub struct SAUEntry {
 
 pub const HEADER_MAGIC: u32 = 0x1535_6637;
 
+/// Length of the P-256 ECDSA signature (r followed by s) that `xtask dist`
+/// appends to an image, at `total_image_len` bytes from its start.
+pub const IMAGE_SIGNATURE_LEN: u32 = 64;
+
 #[repr(C)]
 #[derive(Default, AsBytes, FromBytes)]
 pub struct ImageHeader {
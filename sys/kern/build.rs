@@ -27,8 +27,11 @@ fn main() -> Result<()> {
 
 struct Generated {
     tasks: Vec<TokenStream>,
+    task_names: Vec<String>,
     regions: Vec<TokenStream>,
+    ipc_acl: Vec<TokenStream>,
     irq_code: TokenStream,
+    tick_hz: u32,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -90,6 +93,17 @@ fn process_config() -> Result<Generated> {
     // because they address it by index at the moment.
     let mut task_descs = vec![];
 
+    // Each task also gets a row in the IPC permission table: a bitmap, one bit
+    // per task, of the tasks it may send or post to.
+    let task_count = kconfig.tasks.len();
+    let acl_words = (task_count + 31) / 32;
+    let mut ipc_acl = vec![];
+
+    // Task names aren't otherwise needed by the kernel, but it keeps them so
+    // that it can describe tasks to whoever asks. Everything else it reports
+    // about a task comes from the task's `TaskDesc`.
+    let mut task_names = vec![];
+
     for (i, task) in kconfig.tasks.iter().enumerate() {
         // Work out the region indices for each of this task's regions.
         let mut regions = vec![
@@ -113,10 +127,10 @@ fn process_config() -> Result<Generated> {
             );
         }
 
-        if regions.len() > 8 {
+        if regions.len() > abi::REGIONS_PER_TASK {
             bail!("too many regions ({}) for task {i}", regions.len());
         }
-        regions.resize(8, 0usize);
+        regions.resize(abi::REGIONS_PER_TASK, 0usize);
 
         // Translate abstract addresses in the task description into concrete
         // addresses.
@@ -142,6 +156,34 @@ fn process_config() -> Result<Generated> {
                 flags: #flags,
             }
         });
+
+        let mut acl = vec![0u32; acl_words];
+        match &task.ipc_targets {
+            None => acl.fill(!0),
+            // The supervisor has to be able to talk to everyone, whatever the
+            // config says.
+            Some(_) if i == 0 => acl.fill(!0),
+            Some(targets) => {
+                for &t in targets {
+                    if t >= task_count {
+                        bail!(
+                            "task {i} has IPC target {t}, which isn't a task"
+                        );
+                    }
+                    acl[t / 32] |= 1 << (t % 32);
+                }
+            }
+        }
+        ipc_acl.push(quote::quote! { [#(#acl),*] });
+
+        if task.name.len() > abi::TASK_NAME_BYTES {
+            bail!(
+                "task name '{}' is longer than {} bytes",
+                task.name,
+                abi::TASK_NAME_BYTES
+            );
+        }
+        task_names.push(task.name.clone());
     }
 
     let region_descs = region_table
@@ -179,7 +221,9 @@ fn process_config() -> Result<Generated> {
     let task_irq_map = per_task_irqs.into_iter().collect::<Vec<_>>();
 
     let target = build_util::target();
-    let irq_code = if target.starts_with("thumbv6m") {
+    let irq_code = if target.starts_with("thumbv6m")
+        || build_util::target_os() != "none"
+    {
         // On ARMv6-M we have no hardware division, which the perfect hash table
         // relies on (to get efficient integer remainder). Fall back to a good
         // old sorted list with binary search instead.
@@ -187,6 +231,9 @@ fn process_config() -> Result<Generated> {
         // This means our dispatch time for interrupts on ARMv6-M is O(log N)
         // instead of O(1), but these parts also tend to have few interrupts,
         // so, not the end of the world.
+        //
+        // Hosted (simulator) builds use the same representation, since it's
+        // the simplest and they have no real interrupt latency to speak of.
 
         let task_irq_map = phash_gen::OwnedSortedList::build(task_irq_map)
             .context("building task-to-IRQ map")?;
@@ -282,8 +329,11 @@ fn process_config() -> Result<Generated> {
 
     Ok(Generated {
         tasks: task_descs,
+        task_names,
         regions: region_descs,
+        ipc_acl,
         irq_code,
+        tick_hz: kconfig.tick_hz,
     })
 }
 
@@ -402,11 +452,15 @@ fn generate_statics(gen: &Generated) -> Result<()> {
     // Basic constants and empty space
 
     let task_count = gen.tasks.len();
+    let tick_hz = gen.tick_hz;
     writeln!(
         file,
         "{}",
         quote::quote! {
             const HUBRIS_TASK_COUNT: usize = #task_count;
+            /// Kernel timer ticks per second, from `tick-hz` in the
+            /// `[kernel]` section of `app.toml`.
+            pub const TICK_HZ: u32 = #tick_hz;
             #[no_mangle]
             pub static HUBRIS_IMAGE_ID: u64 = #image_id;
 
@@ -431,6 +485,20 @@ fn generate_statics(gen: &Generated) -> Result<()> {
         },
     )?;
 
+    /////////////////////////////////////////////////////////
+    // Task names
+
+    let task_names = &gen.task_names;
+    writeln!(
+        file,
+        "{}",
+        quote::quote! {
+            static HUBRIS_TASK_NAMES: [&str; HUBRIS_TASK_COUNT] = [
+                #(#task_names,)*
+            ];
+        },
+    )?;
+
     /////////////////////////////////////////////////////////
     // Region descriptors
 
@@ -446,6 +514,23 @@ fn generate_statics(gen: &Generated) -> Result<()> {
         },
     )?;
 
+    /////////////////////////////////////////////////////////
+    // IPC permissions
+
+    let ipc_acl = &gen.ipc_acl;
+    let acl_words = (task_count + 31) / 32;
+    writeln!(
+        file,
+        "{}",
+        quote::quote! {
+            const HUBRIS_IPC_ACL_WORDS: usize = #acl_words;
+            static HUBRIS_IPC_ACL:
+                [[u32; HUBRIS_IPC_ACL_WORDS]; HUBRIS_TASK_COUNT] = [
+                #(#ipc_acl,)*
+            ];
+        },
+    )?;
+
     /////////////////////////////////////////////////////////
     // Interrupt table
 
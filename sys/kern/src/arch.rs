@@ -19,12 +19,16 @@ cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
     // complexity; this chain seems to be the best compromise.
 
-    if #[cfg(not(target_pointer_width = "32"))] {
-        compile_error!("non-32-bit targets not supported (even for simulation)");
-    } else if #[cfg(target_arch = "arm")] {
+    if #[cfg(target_arch = "arm")] {
         #[macro_use]
         pub mod arm_m;
         pub use arm_m::*;
+    } else if #[cfg(target_os = "linux")] {
+        // Hosted simulation. This works on 64-bit hosts too, as long as task
+        // memory is mapped below 4 GiB; see the module docs.
+        #[macro_use]
+        pub mod sim;
+        pub use sim::*;
     } else {
         compile_error!("support for this architecture not implemented");
     }
@@ -13,6 +13,14 @@
 //! interrupts to maintain `TICKS`, but has the upside that we don't need
 //! special SoC support for timing.
 //!
+//! With the `tickless` feature, we instead reprogram SysTick after each
+//! interrupt to fire at the next timer deadline in the task table (or as late
+//! as its 24-bit counter allows), and `now` works out how many ticks have
+//! passed since `TICKS` was last updated by reading the counter. An idle system
+//! then sleeps until something is actually due. Restarting the counter loses
+//! the few cycles it takes, so the kernel clock runs a little slow in this mode
+//! -- though by less than a typical crystal's tolerance.
+//!
 //! # Notes on ARM-M interrupts
 //!
 //! For performance and (believe it or not) simplicity, this implementation uses
@@ -81,9 +89,9 @@ use crate::startup::with_task_table;
 use crate::task;
 use crate::time::Timestamp;
 use crate::umem::USlice;
-use abi::FaultInfo;
 #[cfg(any(armv7m, armv8m))]
 use abi::FaultSource;
+use abi::{FaultInfo, FaultRegisters, StackedRegisters};
 use unwrap_lite::UnwrapLite;
 
 macro_rules! uassert {
@@ -157,6 +165,17 @@ pub struct SavedState {
     // NOTE: the above fields must be kept contiguous!
 }
 
+impl SavedState {
+    /// Reads the exception frame that the hardware pushed onto the process
+    /// stack when the task last entered the kernel.
+    fn stacked_frame(&self) -> BaseExceptionFrame {
+        // Safety: the hardware wrote this frame at `psp` using the task's own
+        // permissions, so it's in task memory that the kernel can read, and it
+        // stays put while the task is in the kernel or blocked.
+        unsafe { core::ptr::read(self.psp as *const BaseExceptionFrame) }
+    }
+}
+
 /// Map the volatile registers to (architecture-independent) syscall argument
 /// and return slots.
 impl task::ArchState for SavedState {
@@ -186,6 +205,14 @@ impl task::ArchState for SavedState {
     fn arg6(&self) -> u32 {
         self.r10
     }
+    /// Syscall arguments 7 and 8 are passed in r0 and r1, which the hardware
+    /// stacked on the process stack on the way into the kernel.
+    fn arg7(&self) -> u32 {
+        self.stacked_frame().r0
+    }
+    fn arg8(&self) -> u32 {
+        self.stacked_frame().r1
+    }
 
     fn syscall_descriptor(&self) -> u32 {
         self.r11
@@ -283,25 +310,9 @@ pub fn reinitialize(task: &mut task::Task) {
     // Ok. Generate a uslice for the task's starting stack frame.
     let mut frame_uslice: USlice<ExtendedExceptionFrame> =
         USlice::from_raw(initial_stack - frame_size, 1).unwrap_lite();
-    // Before we set our frame, find the region that contains our initial stack
-    // pointer, and zap the region from the base to the stack pointer with a
-    // distinct (and storied) pattern.
-    if let Some(region) = task
-        .region_table()
-        .iter()
-        .find(|region| region.contains(initial_stack))
-    {
-        let mut uslice: USlice<u32> = USlice::from_raw(
-            region.base as usize,
-            (initial_stack - frame_size - region.base as usize) >> 2,
-        )
-        .unwrap_lite();
-
-        let zap = task.try_write(&mut uslice).unwrap_lite();
-        for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
-        }
-    }
+    // Before we set our frame, paint the rest of the stack so we can later
+    // tell how much of it the task uses.
+    task.paint_stack(initial_stack - frame_size);
 
     let descriptor = task.descriptor();
     let frame = &mut task.try_write(&mut frame_uslice).unwrap_lite()[0];
@@ -648,6 +659,7 @@ pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
         mpu.ctrl.write(ENABLE | PRIVDEFENA);
     }
 
+    task.count_switch();
     CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
 
     extern "C" {
@@ -897,6 +909,10 @@ pub unsafe extern "C" fn SVCall() {
 /// pointer while you have access to `task`, and as long as the `task` being
 /// stored is actually in the task table, you'll be okay.
 pub unsafe fn set_current_task(task: &mut task::Task) {
+    // Only compare the previous pointer, since it may alias `task`.
+    if !core::ptr::eq(CURRENT_TASK_PTR.load(Ordering::Relaxed), task) {
+        task.count_switch();
+    }
     CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
     crate::profiling::event_context_switch(task as *mut _ as usize);
 }
@@ -905,12 +921,30 @@ pub unsafe fn set_current_task(task: &mut task::Task) {
 pub fn now() -> Timestamp {
     // Recall that we expect the systick interrupt cannot preempt kernel code,
     // so we're safe to read this in two nonatomic parts here.
-    Timestamp::from([
+    let ticks = Timestamp::from([
         TICKS[0].load(Ordering::Relaxed),
         TICKS[1].load(Ordering::Relaxed),
-    ])
+    ]);
+    #[cfg(feature = "tickless")]
+    let ticks =
+        Timestamp::from(u64::from(ticks) + u64::from(tickless::elapsed()));
+    ticks
+}
+
+/// Makes sure the kernel timer fires no later than `deadline`, which a task
+/// has just set.
+#[cfg(feature = "tickless")]
+pub fn request_wakeup(deadline: Timestamp) {
+    if deadline < tickless::period_end() {
+        tickless::reprogram(Some(deadline));
+    }
 }
 
+/// Makes sure the kernel timer fires no later than `deadline`, which a task
+/// has just set. With a periodic tick, it always will.
+#[cfg(not(feature = "tickless"))]
+pub fn request_wakeup(_deadline: Timestamp) {}
+
 /// Kernel global for tracking the current timestamp, measured in ticks.
 ///
 /// This is a pair of `AtomicU32` because (1) we want the interior mutability of
@@ -931,31 +965,34 @@ static TICKS: [AtomicU32; 2] = {
 #[no_mangle]
 pub unsafe extern "C" fn SysTick() {
     crate::profiling::event_timer_isr_enter();
+
+    let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    uassert!(!current.is_null()); // irq before kernel started?
+
+    // Safety: we're dereferencing the current task pointer, which we're
+    // trusting the rest of this module to maintain correctly.
+    let current = usize::from(unsafe { (*current).descriptor().index });
+
     with_task_table(|tasks| {
-        // Load the time before this tick event.
-        let t0 = TICKS[0].load(Ordering::Relaxed);
-        let t1 = TICKS[1].load(Ordering::Relaxed);
-
-        // Advance the kernel's notion of time by adding 1. Laboriously.
-        let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
-            // Incrementing t0 did not roll over, no need to update t1.
-            TICKS[0].store(t0p, Ordering::Relaxed);
-            (t0p, t1)
-        } else {
-            // Incrementing t0 overflowed. We need to also increment t1. We use
-            // normal checked addition for this, not wrapping, because this
-            // should not be able to overflow under normal operation, and would
-            // almost certainly indicate state corruption that we'd like to
-            // discover.
-            TICKS[0].store(0, Ordering::Relaxed);
-            TICKS[1].store(t1 + 1, Ordering::Relaxed);
-            (0, t1 + 1)
-        };
+        // Advance the kernel's notion of time past the end of this tick (or,
+        // in tickless mode, of however many ticks the timer was set for).
+        #[cfg(not(feature = "tickless"))]
+        let (now, ticks) = (advance_ticks(1), 1);
+        #[cfg(feature = "tickless")]
+        let (now, ticks) = tickless::end_period();
+
+        // Charge the ticks to whoever this interrupted. In tickless mode, we
+        // can't tell which tasks ran during the earlier ticks of a long period,
+        // so this is rougher, but every tick is still charged to someone.
+        tasks[current].count_ticks(ticks);
 
         // Process any timers.
-        let now = Timestamp::from([t0, t1]);
         let switch = task::process_timers(tasks, now);
 
+        // Arrange to be back in time for whatever's due next.
+        #[cfg(feature = "tickless")]
+        tickless::reprogram(task::next_deadline(tasks));
+
         // If any timers fired, we need to defer a context switch, because the entry
         // sequence to this ISR doesn't save state correctly for efficiency.
         if switch != task::NextTask::Same {
@@ -965,6 +1002,176 @@ pub unsafe extern "C" fn SysTick() {
     crate::profiling::event_timer_isr_exit();
 }
 
+/// Adds `n` to `TICKS` and returns the result.
+fn advance_ticks(n: u32) -> Timestamp {
+    // Load the time before this tick event.
+    let t0 = TICKS[0].load(Ordering::Relaxed);
+    let t1 = TICKS[1].load(Ordering::Relaxed);
+
+    // Advance the kernel's notion of time by adding n. Laboriously.
+    let (t0, t1) = if let Some(t0p) = t0.checked_add(n) {
+        // Incrementing t0 did not roll over, no need to update t1.
+        TICKS[0].store(t0p, Ordering::Relaxed);
+        (t0p, t1)
+    } else {
+        // Incrementing t0 overflowed. We need to also increment t1. We use
+        // normal checked addition for this, not wrapping, because this
+        // should not be able to overflow under normal operation, and would
+        // almost certainly indicate state corruption that we'd like to
+        // discover.
+        let t0p = t0.wrapping_add(n);
+        TICKS[0].store(t0p, Ordering::Relaxed);
+        TICKS[1].store(t1 + 1, Ordering::Relaxed);
+        (t0p, t1 + 1)
+    };
+    Timestamp::from([t0, t1])
+}
+
+/// SysTick management for the `tickless` feature.
+///
+/// Each SysTick period runs from the time in `TICKS` to `PERIOD` ticks later,
+/// when the interrupt fires. Periods don't generally start on a tick boundary
+/// -- we restart the counter whenever we reprogram it -- so `PHASE` records how
+/// far into its first tick a period started, and the reload value is shortened
+/// to match.
+#[cfg(feature = "tickless")]
+mod tickless {
+    use super::{advance_ticks, now, TICKS};
+    use crate::time::Timestamp;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use cortex_m::peripheral::{syst, SCB, SYST};
+
+    /// Largest value SysTick's reload register can hold.
+    const RVR_MAX: u32 = 0x00FF_FFFF;
+
+    /// Length of the current period, in ticks. The first period is the one
+    /// `start_first_task` programs, which is a single tick long.
+    static PERIOD: AtomicU32 = AtomicU32::new(1);
+
+    /// Number of cycles of its first tick that had already passed when the
+    /// current period started.
+    static PHASE: AtomicU32 = AtomicU32::new(0);
+
+    /// Low word of the time at which `end_period` last ran. Periods are much
+    /// shorter than 2^32 ticks, so this is enough to tell how long it's been.
+    static LAST_END: AtomicU32 = AtomicU32::new(0);
+
+    fn syst() -> &'static syst::RegisterBlock {
+        // Safety: the kernel owns SysTick, and only this module touches it
+        // once the first task is running.
+        unsafe { &*SYST::PTR }
+    }
+
+    /// Returns the number of whole ticks that have passed since `TICKS` was
+    /// last updated.
+    pub fn elapsed() -> u32 {
+        let syst = syst();
+        let div = super::CLOCK_FREQ_KHZ.load(Ordering::Relaxed);
+        let rvr = syst.rvr.read();
+        let cvr = syst.cvr.read();
+        if SCB::is_pendst_pending() {
+            // The period is over, but SysTick can't preempt the kernel, so it
+            // hasn't accounted for it yet. The counter has since reloaded and
+            // started on another period (or will on its next cycle, if it still
+            // reads zero), so we have to read it again.
+            let cvr = syst.cvr.read();
+            let since_reload = if cvr == 0 { 0 } else { rvr - cvr };
+            PERIOD.load(Ordering::Relaxed) + since_reload / div
+        } else {
+            (PHASE.load(Ordering::Relaxed) + rvr - cvr) / div
+        }
+    }
+
+    /// Returns the time at which the current period ends.
+    pub fn period_end() -> Timestamp {
+        let start = Timestamp::from([
+            TICKS[0].load(Ordering::Relaxed),
+            TICKS[1].load(Ordering::Relaxed),
+        ]);
+        Timestamp::from(
+            u64::from(start) + u64::from(PERIOD.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Accounts for the end of the current period. Called from SysTick;
+    /// returns the current time, and the number of ticks since the last call.
+    /// That includes any ticks that `reprogram` folded into `TICKS` along the
+    /// way.
+    pub fn end_period() -> (Timestamp, u32) {
+        advance_ticks(PERIOD.load(Ordering::Relaxed));
+        // The counter reloaded at the end of the period, so we're now partway
+        // into a new one that started exactly on a tick boundary. Its length
+        // doesn't matter, since we're about to reprogram it.
+        PHASE.store(0, Ordering::Relaxed);
+        let now = now();
+        let low = u64::from(now) as u32;
+        let ticks = low.wrapping_sub(LAST_END.load(Ordering::Relaxed));
+        LAST_END.store(low, Ordering::Relaxed);
+        (now, ticks)
+    }
+
+    /// Starts a new period ending at `deadline`, or as late as the counter
+    /// allows if there's no deadline or it's too far off.
+    pub fn reprogram(deadline: Option<Timestamp>) {
+        if SCB::is_pendst_pending() {
+            // The current period is already over. SysTick will start the next
+            // one as soon as we leave the kernel, and will take `deadline`
+            // into account when it does.
+            return;
+        }
+        let syst = syst();
+        let div = super::CLOCK_FREQ_KHZ.load(Ordering::Relaxed);
+
+        // Fold the whole ticks that have passed into `TICKS`, and carry the
+        // rest into the new period so that we don't lose it.
+        let rvr = syst.rvr.read();
+        let cvr = syst.cvr.read();
+        let elapsed = if SCB::is_pendst_pending() {
+            // The period ran out since we checked above, either before or
+            // after we read the counter, so the value we read can't be
+            // trusted. Read it again; as in `elapsed`, it's now counting down
+            // through the next period.
+            let cvr = syst.cvr.read();
+            let since_reload = if cvr == 0 { 0 } else { rvr - cvr };
+            PERIOD.load(Ordering::Relaxed) * div + since_reload
+        } else {
+            PHASE.load(Ordering::Relaxed) + rvr - cvr
+        };
+        let start = u64::from(advance_ticks(elapsed / div));
+        let phase = elapsed % div;
+
+        // The period has to be at least a tick, and its reload value has to
+        // fit in the counter.
+        let longest = (RVR_MAX + 1 + phase) / div;
+        let mut period = match deadline {
+            Some(deadline) => {
+                let ticks = u64::from(deadline).saturating_sub(start);
+                ticks.clamp(1, u64::from(longest)) as u32
+            }
+            None => longest,
+        };
+        // A reload value of zero stops SysTick altogether, which happens if
+        // we're one cycle short of the end of a one-tick period. Take another
+        // tick instead.
+        if period * div - phase - 1 == 0 {
+            period += 1;
+        }
+        PERIOD.store(period, Ordering::Relaxed);
+        PHASE.store(phase, Ordering::Relaxed);
+
+        // Safety: see `syst`.
+        unsafe {
+            syst.rvr.write(period * div - phase - 1);
+            // Writing any value clears the counter, which then reloads from
+            // RVR.
+            syst.cvr.write(0);
+        }
+        // If the old period ran out while we were busy above, we've already
+        // counted it in `elapsed`; don't let SysTick count it again.
+        SCB::clear_pendst();
+    }
+}
+
 fn pend_context_switch_from_isr() {
     // This sets the bit to pend a PendSV interrupt. PendSV will happen after
     // the current ISR (and any chained ISRs) returns, and perform the context
@@ -1134,7 +1341,9 @@ pub unsafe extern "C" fn DefaultHandler() {
                 // Now, post the notification and return the
                 // scheduling hint.
                 let n = task::NotificationSet(owner.notification);
-                tasks[owner.task as usize].post(n)
+                let owner = &mut tasks[owner.task as usize];
+                owner.count_irq();
+                owner.post(n)
             });
             if switch {
                 pend_context_switch_from_isr()
@@ -1430,6 +1639,66 @@ pub fn reset() -> ! {
     cortex_m::peripheral::SCB::sys_reset()
 }
 
+/// Captures the registers of `task`, which is being faulted, so that they can
+/// be read back later with `Kipcnum::ReadFaultRegisters`.
+///
+/// This reads the fault status registers as they stand, so for faults taken by
+/// the processor it must run before `handle_fault` clears them.
+pub fn capture_fault_registers(task: &task::Task) -> Option<FaultRegisters> {
+    #[cfg(any(armv7m, armv8m))]
+    let (cfsr, hfsr) = {
+        // Safety: as in `handle_fault`, this is a shared reference to a
+        // static-scoped Sync thing.
+        let scb = unsafe { &*cortex_m::peripheral::SCB::PTR };
+        (scb.cfsr.read(), scb.hfsr.read())
+    };
+    // ARMv6-M has neither register.
+    #[cfg(armv6m)]
+    let (cfsr, hfsr) = (0, 0);
+
+    // If the processor couldn't push the exception frame, PSP points at where
+    // it would have gone, and there's nothing useful there. Otherwise, we still
+    // make sure that the frame is in the task's memory before reading it: a
+    // faulting task is not one whose stack pointer we want to trust.
+    let save = task.save();
+    let stacking_failed =
+        Cfsr::from_bits_truncate(cfsr).intersects(Cfsr::MSTKERR | Cfsr::STKERR);
+    let frame = USlice::<BaseExceptionFrame>::from_raw(save.psp as usize, 1);
+    let stacked = match frame {
+        Ok(frame) if !stacking_failed => {
+            task.try_read(&frame).ok().map(|f| StackedRegisters {
+                pc: f[0].pc,
+                lr: f[0].lr,
+                xpsr: f[0].xpsr,
+            })
+        }
+        _ => None,
+    };
+
+    // Work out where the stack pointer was before the frame was pushed: past
+    // the FPU state if EXC_RETURN says it was stacked, and past the alignment
+    // padding if the stacked xPSR says there was some.
+    let mut sp = save.psp;
+    if let Some(regs) = &stacked {
+        let frame_size = if save.exc_return & 0b1_0000 == 0 {
+            core::mem::size_of::<ExtendedExceptionFrame>()
+        } else {
+            core::mem::size_of::<BaseExceptionFrame>()
+        };
+        sp = sp.wrapping_add(frame_size as u32);
+        if regs.xpsr & 1 << 9 != 0 {
+            sp = sp.wrapping_add(4);
+        }
+    }
+
+    Some(FaultRegisters {
+        sp,
+        stacked,
+        cfsr,
+        hfsr,
+    })
+}
+
 /// Common implementation of fault handling.
 ///
 /// # Safety
@@ -1459,6 +1728,7 @@ unsafe extern "C" fn handle_fault(
     // that crate comes with _ideas_ about peripheral ownership management.
     let scb = unsafe { &*cortex_m::peripheral::SCB::PTR };
     let cfsr = Cfsr::from_bits_truncate(scb.cfsr.read());
+    let hfsr = scb.hfsr.read();
 
     // Who faulted? Collect some parameters from the task.
     //
@@ -1543,16 +1813,6 @@ unsafe extern "C" fn handle_fault(
         ),
     };
 
-    // Because we are responsible for clearing all conditions, we write back
-    // the value of CFSR that we read
-    //
-    // Safety: this is a traditional write-one-to-clear register that, when
-    // written, clears recorded fault states. It is not at _all_ clear why its
-    // write function is unsafe.
-    unsafe {
-        scb.cfsr.write(cfsr.bits());
-    }
-
     if stackinvalid {
         // We know that we have an invalid stack; to prevent our subsequent
         // save of the dead task's floating point registers from storing
@@ -1602,6 +1862,19 @@ unsafe extern "C" fn handle_fault(
             set_current_task(next);
         }
     });
+
+    // Because we are responsible for clearing all conditions, we write back
+    // the values of CFSR and HFSR that we read. (We do this only now because
+    // `force_fault` captures them for the task's fault record.) Leaving HFSR
+    // set would make every later fault record look escalated.
+    //
+    // Safety: these are traditional write-one-to-clear registers that, when
+    // written, clear recorded fault states. It is not at _all_ clear why their
+    // write functions are unsafe.
+    unsafe {
+        scb.cfsr.write(cfsr.bits());
+        scb.hfsr.write(hfsr);
+    }
 }
 
 cfg_if::cfg_if! {
@@ -0,0 +1,746 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for running the kernel as a hosted (Linux) process.
+//!
+//! This exists so that the portable parts of the kernel -- syscalls, KIPC, the
+//! scheduler, timers -- and tasks built against `userlib` can be exercised by
+//! `cargo test` on a workstation, without a dev board. `test/tests-sim` uses
+//! it to run the kernel test suite.
+//!
+//! # Simulation model
+//!
+//! Each task runs on its own host thread. The thread for task `i` calls the
+//! function registered at index `i` with `set_task_entry_points`, which plays
+//! the role of the task's `_start`.
+//!
+//! Only one task is "on the CPU" at a time. The kernel's notion of the current
+//! task (set by `set_current_task`) acts as a baton: a task thread that wants to
+//! enter the kernel waits until it holds the baton, and a task thread that
+//! blocks in the kernel waits until the scheduler hands the baton back. All
+//! kernel entry -- syscalls, timer ticks, simulated interrupts -- is
+//! serialized by `KERNEL_LOCK`, which stands in for the fact that on hardware
+//! all kernel entry points run at the same exception priority.
+//!
+//! Because we can't stop a host thread in its tracks, preemption takes effect
+//! at the preempted task's next kernel entry. Until then it may continue
+//! executing user code alongside the newly scheduled task. Tasks share no
+//! memory except through the kernel, so this isn't observable to them.
+//!
+//! When a task is restarted, its thread notices on its next trip through the
+//! kernel (the incarnation recorded in its `SavedState` will have changed),
+//! unwinds back to the top, and calls the entry point afresh.
+//!
+//! # Memory
+//!
+//! The regions in each task's region table are mapped into the host process
+//! at their configured addresses, and each task thread's stack is placed in
+//! the region that contains its initial stack pointer, just as on hardware.
+//! This lets the kernel's access checks in `task.rs` work unmodified, but has
+//! two consequences for simulated applications:
+//!
+//! - Region addresses must be free in the host address space (and, since the
+//!   syscall ABI passes addresses in 32 bits, below 4 GiB).
+//! - Task stacks need to be large enough for host code (including `std`'s
+//!   unwinding machinery), and buffers lent to the kernel must live on the
+//!   task's stack, since the task's statics live in the host executable.
+//!
+//! There's no MPU. The kernel still checks every access it makes on behalf of
+//! a task, but a task's own loads and stores are unchecked.
+//!
+//! # Time
+//!
+//! A host thread advances the kernel timestamp once every `tick_divisor`
+//! microseconds of wall-clock time.
+
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering,
+};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::atomic::AtomicExt;
+use crate::descs::RegionDesc;
+use crate::startup::with_task_table;
+use crate::task;
+use crate::time::Timestamp;
+use abi::{FaultInfo, FaultRegisters};
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Exit status used by the process when the kernel is asked to reset the
+/// system.
+pub const RESET_EXIT_CODE: i32 = 0x5e;
+
+/// Pointer to the current task, as on ARM. This is only read or written with
+/// `KERNEL_LOCK` held.
+static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Index of the current task, or `usize::MAX` before the kernel has started.
+/// This is what task threads wait on.
+static CURRENT_TASK_INDEX: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// The tick period in microseconds, which is what the simulator takes as its
+/// tick divisor. Stored for parity with the other architectures.
+static TICK_MICROS: AtomicU32 = AtomicU32::new(0);
+
+/// Kernel timestamp, measured in ticks. Unlike on ARMv7-M we have 64-bit
+/// atomics, so this needn't be split.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Serializes all kernel entry.
+static KERNEL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Signaled (with `KERNEL_LOCK` held) whenever the current task changes.
+static TASK_SWITCHED: Condvar = Condvar::new();
+
+/// Functions to run as each task's entry point, indexed like the task table.
+static ENTRY_POINTS: Mutex<&'static [fn() -> !]> = Mutex::new(&[]);
+
+/// Host pages we've mapped on behalf of task regions, so that regions that
+/// share pages (or are shared between tasks) are only mapped once.
+static MAPPED_PAGES: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Simulated interrupt enable and pending bits, in the style of the NVIC.
+static IRQ_ENABLED: [AtomicU32; 16] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; 16]
+};
+static IRQ_PENDING: [AtomicU32; 16] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; 16]
+};
+
+thread_local! {
+    /// For task threads, the index of the task and the incarnation currently
+    /// running. `None` on other threads.
+    static THIS_TASK: Cell<Option<(usize, u32)>> = Cell::new(None);
+}
+
+/// Payload used to unwind a task thread whose task has been restarted out from
+/// under it.
+struct Restarted;
+
+/// Simulated volatile state. The syscall ABI is the same as on ARM, with
+/// `regs` standing in for r4-r10, `descriptor` for r11, and `extra` for the
+/// stacked r0-r1 that carry syscall arguments 7 and 8.
+#[derive(Debug, Default)]
+pub struct SavedState {
+    regs: [u32; 7],
+    descriptor: u32,
+    extra: [u32; 2],
+    sp: u32,
+    /// Bumped each time the task is reinitialized. Zero means the task has
+    /// never been initialized, and has no thread yet.
+    incarnation: u32,
+    /// Set by `reinitialize` on a restart, to have the task's thread repaint
+    /// its stack before it runs the task again.
+    repaint: bool,
+}
+
+/// Map the simulated registers to (architecture-independent) syscall argument
+/// and return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+    fn arg7(&self) -> u32 {
+        self.extra[0]
+    }
+    fn arg8(&self) -> u32 {
+        self.extra[1]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.descriptor
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+/// Registers the functions that will be run as each task's entry point. This
+/// must be called before `start_kernel`, with one entry per task in the task
+/// table.
+pub fn set_task_entry_points(entries: &'static [fn() -> !]) {
+    *ENTRY_POINTS.lock().unwrap() = entries;
+}
+
+pub unsafe fn set_clock_freq(tick_divisor: u32) {
+    TICK_MICROS.store(tick_divisor, Ordering::Relaxed);
+}
+
+pub fn reinitialize(task: &mut task::Task) {
+    let initial_stack = task.descriptor().initial_stack as usize;
+
+    // Keep the same alignment requirement as ARM, so that an image that works
+    // here doesn't trip over it on hardware.
+    uassert!(initial_stack & 0x7 == 0);
+
+    for region in task.region_table().iter() {
+        map_region(region);
+    }
+
+    // On a restart, the task's thread is still using its stack: it's parked
+    // in the kernel, or still running task code if it was preempted, and
+    // unwinds when it's next scheduled. Painting the stack from here could
+    // overwrite its frames, so we leave that to the thread (see `task_thread`)
+    // once it has unwound.
+    let first_start = task.save().incarnation == 0;
+    let incarnation = task.save().incarnation.wrapping_add(1).max(1);
+    *task.save_mut() = SavedState {
+        sp: initial_stack as u32,
+        incarnation,
+        repaint: !first_start,
+        ..SavedState::default()
+    };
+
+    if first_start {
+        task.paint_stack(initial_stack);
+        let stack_region = task
+            .region_table()
+            .iter()
+            .find(|region| {
+                region.size != 0
+                    && (region.base as usize) < initial_stack
+                    && initial_stack
+                        <= region.base.wrapping_add(region.size) as usize
+            })
+            .unwrap_or_else(|| {
+                panic!("initial stack {initial_stack:#x} not in any region")
+            });
+        spawn_task_thread(
+            usize::from(task.descriptor().index),
+            stack_region.base as usize,
+            initial_stack,
+        );
+    }
+}
+
+pub fn apply_memory_protection(_task: &task::Task) {
+    // There is no MPU to program; see the module docs.
+}
+
+pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
+    // Safety: this is a member of the task table, as required, and we're done
+    // with it before any task thread can enter the kernel.
+    {
+        let _guard = lock_kernel();
+        unsafe {
+            set_current_task(task);
+        }
+    }
+
+    let period = Duration::from_micros(u64::from(tick_divisor.max(1)));
+    std::thread::Builder::new()
+        .name("kernel-tick".into())
+        .spawn(move || tick_thread(period))
+        .unwrap();
+
+    // Task threads do all the work from here on; the boot thread has nothing
+    // left to do but keep the process alive. (`reset` ends the process.)
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Records the address of `task` as the current user task, and lets its thread
+/// run.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. As long as you don't read that
+/// pointer while you have access to `task`, and as long as the `task` being
+/// stored is actually in the task table, you'll be okay.
+///
+/// This must be called with `KERNEL_LOCK` held.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    let index = usize::from(task.descriptor().index);
+    if CURRENT_TASK_INDEX.swap(index, Ordering::Relaxed) != index {
+        task.count_switch();
+    }
+    CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
+    crate::profiling::event_context_switch(task as *mut _ as usize);
+    TASK_SWITCHED.notify_all();
+}
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    Timestamp::from(TICKS.load(Ordering::Relaxed))
+}
+
+/// Captures the registers of a faulting task. Simulated tasks are host
+/// threads, and have no registers we can usefully report.
+pub fn capture_fault_registers(_task: &task::Task) -> Option<FaultRegisters> {
+    None
+}
+
+/// Makes sure the kernel timer fires no later than `deadline`, which a task
+/// has just set. The simulated timer ticks periodically, so it always will.
+pub fn request_wakeup(_deadline: Timestamp) {}
+
+pub fn disable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize]
+        .fetch_and(!(1 << (n % 32)), Ordering::Relaxed);
+}
+
+pub fn enable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize].fetch_or(1 << (n % 32), Ordering::Relaxed);
+}
+
+/// Simulates hardware interrupt `n` firing.
+///
+/// As with the NVIC, an interrupt that fires while disabled is held pending
+/// and delivered once the owning task re-enables it.
+pub fn raise_irq(n: u32) {
+    IRQ_PENDING[(n / 32) as usize].fetch_or(1 << (n % 32), Ordering::Relaxed);
+    let _guard = lock_kernel();
+    deliver_pending_irqs();
+}
+
+pub fn reset() -> ! {
+    eprintln!("kernel: system reset requested");
+    std::process::exit(RESET_EXIT_CODE)
+}
+
+/// Entry point for syscalls from task threads; this is what `userlib`'s
+/// syscall stubs call in simulated builds.
+///
+/// `regs` holds the argument registers on the way in, and the return registers
+/// on the way out. `extra` holds arguments 7 and 8, which only a few syscalls
+/// use.
+///
+/// This uses the Rust ABI, rather than C, because restarting a task unwinds its
+/// thread through here.
+///
+/// # Safety
+///
+/// This must only be called from a task thread, and not reentrantly.
+#[no_mangle]
+pub unsafe fn hubris_sim_syscall(
+    nr: u32,
+    regs: &mut [u32; 7],
+    extra: [u32; 2],
+) {
+    let (index, incarnation) = THIS_TASK
+        .with(Cell::get)
+        .expect("syscall from a thread that isn't a task");
+
+    let guard = wait_for_cpu(lock_kernel(), index, incarnation);
+
+    kernel_context(|| {
+        with_task_table(|tasks| {
+            let save = tasks[index].save_mut();
+            save.regs = *regs;
+            save.descriptor = nr;
+            save.extra = extra;
+        });
+        // Safety: we hold the kernel lock, so we're not reentrant, and the
+        // current task pointer is maintained by this module.
+        unsafe {
+            crate::syscalls::syscall_entry(
+                nr,
+                CURRENT_TASK_PTR.load(Ordering::Relaxed),
+            );
+        }
+        deliver_pending_irqs();
+    });
+
+    // If the syscall blocked us or switched away, this waits until we're
+    // scheduled again (or unwinds, if we were restarted in the meantime).
+    let _guard = wait_for_cpu(guard, index, incarnation);
+    with_task_table(|tasks| *regs = tasks[index].save().regs);
+}
+
+/// Output routine behind `userlib`'s `sys_log!` (and the test runner's report)
+/// in simulated builds: prints one line to the process's standard output.
+#[no_mangle]
+pub fn hubris_sim_print(args: core::fmt::Arguments<'_>) {
+    use std::io::Write;
+
+    // Write the line in one go, so that lines from different tasks don't
+    // interleave, and flush it so that whoever's reading sees it promptly.
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{args}");
+    let _ = out.flush();
+}
+
+/// Resolves a task slot in simulated builds, where there is no post-link step
+/// to fill them in: returns the index of the task with the given name.
+#[no_mangle]
+pub fn hubris_sim_task_index(name: &str) -> Option<u16> {
+    (0..crate::startup::task_count())
+        .find(|&i| crate::startup::task_name(i) == name)
+        .map(|i| i as u16)
+}
+
+fn lock_kernel() -> MutexGuard<'static, ()> {
+    KERNEL_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `body`, which is kernel code, converting a panic into process abort.
+/// A kernel panic on hardware halts the system; unwinding into a task thread
+/// would instead leave the rest of the simulation running on corrupt state.
+fn kernel_context<R>(body: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(r) => r,
+        Err(_) => {
+            eprintln!("kernel panic; aborting");
+            std::process::abort()
+        }
+    }
+}
+
+/// Blocks the calling thread until task `index` is current. If the task has
+/// been reinitialized since `incarnation` started, unwinds the thread so it
+/// can start over.
+fn wait_for_cpu(
+    mut guard: MutexGuard<'static, ()>,
+    index: usize,
+    incarnation: u32,
+) -> MutexGuard<'static, ()> {
+    while CURRENT_TASK_INDEX.load(Ordering::Relaxed) != index {
+        guard = TASK_SWITCHED.wait(guard).unwrap_or_else(|e| e.into_inner());
+    }
+    let current = with_task_table(|tasks| tasks[index].save().incarnation);
+    if current != incarnation {
+        drop(guard);
+        panic::resume_unwind(Box::new(Restarted));
+    }
+    guard
+}
+
+/// Picks a task to run after a timer or interrupt has changed the scheduling
+/// picture, like `PendSV` on ARM. Must be called with `KERNEL_LOCK` held.
+fn reschedule() {
+    crate::profiling::event_secondary_syscall_enter();
+    let current = CURRENT_TASK_INDEX.load(Ordering::Relaxed);
+    with_task_table(|tasks| {
+        let next = task::select(current, tasks);
+        let next = &mut tasks[next];
+        apply_memory_protection(next);
+        // Safety: next comes from the task table and we don't use it again
+        // until next kernel entry, so we meet set_current_task's requirements.
+        unsafe {
+            set_current_task(next);
+        }
+    });
+    crate::profiling::event_secondary_syscall_exit();
+}
+
+/// Posts notifications for any interrupts that are both pending and enabled,
+/// disabling them as `DefaultHandler` does on ARM. Must be called with
+/// `KERNEL_LOCK` held.
+fn deliver_pending_irqs() {
+    let mut switch = false;
+    for (word, (pending, enabled)) in
+        IRQ_PENDING.iter().zip(&IRQ_ENABLED).enumerate()
+    {
+        let ready =
+            pending.load(Ordering::Relaxed) & enabled.load(Ordering::Relaxed);
+        for bit in (0..32).filter(|b| ready & (1 << b) != 0) {
+            let irq_num = (word * 32 + bit) as u32;
+            let owner = crate::startup::HUBRIS_IRQ_TASK_LOOKUP
+                .get(abi::InterruptNum(irq_num))
+                .unwrap_or_else(|| panic!("unhandled IRQ {irq_num}"));
+
+            pending.fetch_and(!(1 << bit), Ordering::Relaxed);
+            switch |= with_task_table(|tasks| {
+                disable_irq(irq_num);
+                let n = task::NotificationSet(owner.notification);
+                let owner = &mut tasks[owner.task as usize];
+                owner.count_irq();
+                owner.post(n)
+            });
+        }
+    }
+    if switch {
+        reschedule();
+    }
+}
+
+/// Body of the thread that stands in for SysTick.
+fn tick_thread(period: Duration) -> ! {
+    let mut next = Instant::now();
+    loop {
+        next += period;
+        if let Some(delay) = next.checked_duration_since(Instant::now()) {
+            std::thread::sleep(delay);
+        }
+
+        let _guard = lock_kernel();
+        crate::profiling::event_timer_isr_enter();
+        kernel_context(|| {
+            let now =
+                Timestamp::from(TICKS.fetch_add(1, Ordering::Relaxed) + 1);
+            let current = CURRENT_TASK_INDEX.load(Ordering::Relaxed);
+            let switch = with_task_table(|tasks| {
+                // Charge this tick to whoever holds the CPU.
+                tasks[current].count_ticks(1);
+                task::process_timers(tasks, now)
+            });
+            if switch != task::NextTask::Same {
+                reschedule();
+            }
+        });
+        crate::profiling::event_timer_isr_exit();
+    }
+}
+
+/// Maps the pages covering `region` into the host address space, if they
+/// aren't already.
+fn map_region(region: &RegionDesc) {
+    if region.size == 0 {
+        return;
+    }
+    // Safety: sysconf has no preconditions.
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = region.base as usize / page;
+    let end = (region.base as usize + region.size as usize + page - 1) / page;
+
+    let mut mapped = MAPPED_PAGES.lock().unwrap();
+    for p in start..end {
+        if mapped.contains(&p) {
+            continue;
+        }
+        // Safety: MAP_FIXED_NOREPLACE ensures we can't clobber anything
+        // already mapped in the host process.
+        let addr = unsafe {
+            libc::mmap(
+                (p * page) as *mut libc::c_void,
+                page,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if addr != (p * page) as *mut libc::c_void {
+            panic!(
+                "can't map task memory at {:#x}: {}",
+                p * page,
+                std::io::Error::last_os_error()
+            );
+        }
+        mapped.insert(p);
+    }
+}
+
+extern "C" {
+    // Not bound by the version of the libc crate we use.
+    fn pthread_attr_setstack(
+        attr: *mut libc::pthread_attr_t,
+        stackaddr: *mut libc::c_void,
+        stacksize: libc::size_t,
+    ) -> libc::c_int;
+}
+
+/// Starts the host thread for task `index`, with its stack occupying
+/// `stack_base..stack_top`.
+fn spawn_task_thread(index: usize, stack_base: usize, stack_top: usize) {
+    extern "C" fn trampoline(arg: *mut libc::c_void) -> *mut libc::c_void {
+        // Nothing may unwind out of an `extern "C"` function. Task threads
+        // catch their own panics (and restarts), so anything that gets here is
+        // a problem with the simulation itself.
+        match panic::catch_unwind(|| task_thread(arg as usize)) {
+            Ok(never) => never,
+            Err(_) => {
+                eprintln!("task thread panicked; aborting");
+                std::process::abort()
+            }
+        }
+    }
+
+    // Safety: the attribute object is initialized before use and destroyed
+    // after, and the stack memory was mapped by `reinitialize` and is never
+    // unmapped.
+    unsafe {
+        let mut attr = core::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+        let mut thread = core::mem::MaybeUninit::<libc::pthread_t>::uninit();
+        uassert!(libc::pthread_attr_init(attr.as_mut_ptr()) == 0);
+        let rc = pthread_attr_setstack(
+            attr.as_mut_ptr(),
+            stack_base as *mut libc::c_void,
+            stack_top - stack_base,
+        );
+        if rc != 0 {
+            panic!(
+                "task {index} stack of {} bytes is too small to simulate",
+                stack_top - stack_base
+            );
+        }
+        uassert!(
+            libc::pthread_create(
+                thread.as_mut_ptr(),
+                attr.as_ptr(),
+                trampoline,
+                index as *mut libc::c_void,
+            ) == 0
+        );
+        libc::pthread_attr_destroy(attr.as_mut_ptr());
+    }
+}
+
+/// Body of each task's host thread.
+fn task_thread(index: usize) -> ! {
+    let entry = ENTRY_POINTS
+        .lock()
+        .unwrap()
+        .get(index)
+        .copied()
+        .unwrap_or_else(|| {
+            panic!("no entry point registered for task {index}")
+        });
+
+    loop {
+        // Wait until we're scheduled, and note which incarnation of the task
+        // we're about to run.
+        let incarnation = {
+            let mut guard = lock_kernel();
+            while CURRENT_TASK_INDEX.load(Ordering::Relaxed) != index {
+                guard = TASK_SWITCHED
+                    .wait(guard)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            with_task_table(|tasks| {
+                let task = &mut tasks[index];
+                if core::mem::take(&mut task.save_mut().repaint) {
+                    repaint_stack(task);
+                }
+                task.save().incarnation
+            })
+        };
+        THIS_TASK.with(|t| t.set(Some((index, incarnation))));
+
+        let payload = match panic::catch_unwind(|| entry()) {
+            Ok(never) => never,
+            Err(payload) => payload,
+        };
+        if payload.is::<Restarted>() {
+            continue;
+        }
+
+        // The task panicked outside of the `sys_panic` path (e.g. in host
+        // code). Treat it like an explicit panic once we have the CPU; if the
+        // task was restarted in the meantime, `wait_for_cpu` sends us around
+        // again.
+        let message = panic_message(&*payload);
+        let result = panic::catch_unwind(|| {
+            let _guard = wait_for_cpu(lock_kernel(), index, incarnation);
+            kernel_context(|| {
+                with_task_table(|tasks| {
+                    tasks[index].record_panic(message.as_bytes());
+                    let next =
+                        match task::force_fault(tasks, index, FaultInfo::Panic)
+                        {
+                            task::NextTask::Specific(i) => i,
+                            task::NextTask::Other => task::select(index, tasks),
+                            task::NextTask::Same => index,
+                        };
+                    // Safety: next is in the task table.
+                    unsafe { set_current_task(&mut tasks[next]) }
+                });
+                deliver_pending_irqs();
+            });
+        });
+        // Either way, we now wait to be restarted.
+        let _ = result;
+    }
+}
+
+/// Stack left unpainted below the frame of a task thread that's repainting its
+/// own stack, to hold the frames of the code doing the painting.
+const REPAINT_MARGIN: usize = 16 * 1024;
+
+/// Repaints the part of `task`'s stack below the caller's frame, for a
+/// restarted task whose thread has unwound and is about to start the task
+/// again. This must be called on that thread.
+#[inline(never)]
+fn repaint_stack(task: &mut task::Task) {
+    let here = &task as *const _ as usize;
+    if let Some((base, _)) = task.stack_bounds() {
+        let limit = here.saturating_sub(REPAINT_MARGIN) & !0x7;
+        if limit > base {
+            task.paint_stack(limit);
+        }
+    }
+}
+
+/// Extracts the message from a host panic's payload, if it has one.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::new()
+    }
+}
+
+impl AtomicExt for AtomicBool {
+    type Primitive = bool;
+
+    #[inline(always)]
+    fn swap_polyfill(
+        &self,
+        value: Self::Primitive,
+        ordering: Ordering,
+    ) -> Self::Primitive {
+        self.swap(value, ordering)
+    }
+}
@@ -6,7 +6,7 @@
 
 use crate::umem::USlice;
 
-pub(crate) const REGIONS_PER_TASK: usize = 8;
+pub(crate) use abi::REGIONS_PER_TASK;
 
 /// Indicates priority of a task.
 ///
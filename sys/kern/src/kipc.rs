@@ -4,10 +4,15 @@
 
 //! Implementation of IPC operations on the virtual kernel task.
 
-use abi::{FaultInfo, Kipcnum, SchedState, TaskState, UsageError};
+use abi::{
+    FaultInfo, KernelEvent, Kipcnum, SchedState, TaskInfo, TaskRegion,
+    TaskState, UsageError,
+};
 
 use crate::arch;
 use crate::err::UserError;
+use crate::klog;
+use crate::startup;
 use crate::task::{current_id, ArchState, NextTask, Task};
 use crate::umem::USlice;
 use core::convert::TryFrom;
@@ -30,6 +35,24 @@ pub fn handle_kernel_message(
             read_image_id(tasks, caller, args.response?)
         }
         Ok(Kipcnum::Reset) => reset(tasks, caller, args.message?),
+        Ok(Kipcnum::ReadTaskStats) => {
+            read_task_stats(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadStackUsage) => {
+            read_stack_usage(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadKernelLog) => {
+            read_kernel_log(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadPanicMessage) => {
+            read_panic_message(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadFaultRegisters) => {
+            read_fault_registers(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskInfo) => {
+            read_task_info(tasks, caller, args.message?, args.response?)
+        }
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
             Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
@@ -38,7 +61,10 @@ pub fn handle_kernel_message(
         }
     }
 }
-fn reset(_tasks: &mut [Task], _caller: usize, _message: USlice<u8>) -> ! {
+fn reset(_tasks: &mut [Task], caller: usize, _message: USlice<u8>) -> ! {
+    klog::record(KernelEvent::Reset {
+        task: caller as u16,
+    });
     arch::reset()
 }
 
@@ -97,6 +123,157 @@ fn read_task_status(
     Ok(NextTask::Same)
 }
 
+fn read_task_stats(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let stats = *tasks[index as usize].stats();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &stats)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = tasks[index as usize].stack_usage();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_kernel_log(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let seq: u32 = deserialize_message(&tasks[caller], message)?;
+    let entry = klog::read(seq);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &entry)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_panic_message(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    // Copy the message out first, since the caller may be asking about
+    // itself.
+    let mut panic_message = [0; abi::PANIC_MESSAGE_BYTES];
+    let src = tasks[index as usize].panic_message();
+    panic_message[..src.len()].copy_from_slice(src);
+    let len = src.len();
+
+    // Unlike most kernel messages, this one's response is raw bytes. If the
+    // caller's buffer is short, they get a prefix.
+    let dest = tasks[caller].try_write(&mut response)?;
+    let n = len.min(dest.len());
+    dest[..n].copy_from_slice(&panic_message[..n]);
+    tasks[caller].save_mut().set_send_response_and_length(0, n);
+    Ok(NextTask::Same)
+}
+
+fn read_fault_registers(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let regs = tasks[index as usize].fault_registers();
+
+    let response_len = serialize_response(&mut tasks[caller], response, &regs)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_task_info(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    // Unlike most operations here, an out-of-range index is fine: it's how a
+    // caller enumerating tasks finds the end of the table.
+    let info = tasks.get(index as usize).map(|task| {
+        let desc = task.descriptor();
+        let mut info = TaskInfo {
+            priority: desc.priority,
+            flags: desc.flags.bits(),
+            ..TaskInfo::default()
+        };
+        let name = startup::task_name(index as usize).as_bytes();
+        info.name[..name.len()].copy_from_slice(name);
+        // Leave out the null region, and the unused slots that refer to it.
+        // There are as many slots in `info` as in the descriptor, so this
+        // can't drop any regions.
+        let regions = desc.regions.iter().filter(|r| !r.attributes.is_empty());
+        for (region, slot) in regions.zip(&mut info.regions) {
+            *slot = TaskRegion {
+                base: region.base,
+                size: region.size,
+                attributes: region.attributes.bits(),
+            };
+            info.region_count += 1;
+        }
+        info
+    });
+
+    let response_len = serialize_response(&mut tasks[caller], response, &info)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
 fn restart_task(
     tasks: &mut [Task],
     caller: usize,
@@ -112,6 +289,11 @@ fn restart_task(
     }
     let old_id = current_id(tasks, index);
     tasks[index].reinitialize();
+    klog::record(KernelEvent::Restart {
+        task: index as u16,
+        generation: tasks[index].generation(),
+        by: caller as u16,
+    });
     if start {
         tasks[index].set_healthy_state(SchedState::Runnable);
     }
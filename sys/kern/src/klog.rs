@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel event log.
+//!
+//! The kernel keeps a short history of notable events -- faults, panics,
+//! restarts, and resets -- so that the supervisor can find out what happened
+//! to a task even after it's been restarted, rather than seeing only its latest
+//! `TaskState`. The supervisor reads entries out using the `ReadKernelLog`
+//! kernel IPC.
+//!
+//! The log is a fixed-size ring. Once it fills, each new event overwrites the
+//! oldest one, whether or not anyone has read it.
+//!
+//! The ring costs about half a kilobyte of kernel RAM, so it's only kept if
+//! the `klog` feature is enabled. Without it, events are dropped, and the log
+//! always reads as empty.
+
+#[cfg(feature = "klog")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use abi::{KernelEvent, KernelLogEntry};
+
+#[cfg(feature = "klog")]
+use crate::atomic::AtomicExt;
+
+#[cfg(feature = "klog")]
+/// Number of events retained. This costs kernel RAM, so it's kept small;
+/// readers that care about every event need to keep up.
+const CAPACITY: usize = 8;
+
+#[cfg(feature = "klog")]
+struct Ring {
+    entries: [Option<KernelLogEntry>; CAPACITY],
+    /// Sequence number to assign to the next event.
+    next_seq: u32,
+}
+
+#[cfg(feature = "klog")]
+static mut LOG: Ring = Ring {
+    entries: [None; CAPACITY],
+    next_seq: 0,
+};
+
+#[cfg(feature = "klog")]
+static LOG_IN_USE: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "klog")]
+/// Runs `body` with a reference to the log.
+///
+/// To preserve uniqueness of the `&mut` reference passed into `body`, this
+/// function will detect any attempts to call it recursively and panic.
+fn with_log<R>(body: impl FnOnce(&mut Ring) -> R) -> R {
+    if LOG_IN_USE.swap_polyfill(true, Ordering::Acquire) {
+        panic!(); // recursive use of with_log
+    }
+    // Safety: we have observed `LOG_IN_USE` being false, which means we're not
+    // already within a call to with_log, so we can produce a reference to the
+    // log without aliasing.
+    let r = body(unsafe { &mut LOG });
+    LOG_IN_USE.store(false, Ordering::Release);
+    r
+}
+
+/// Records `event` in the log, stamped with the current kernel time.
+#[cfg(feature = "klog")]
+pub fn record(event: KernelEvent) {
+    let timestamp = u64::from(crate::arch::now());
+    with_log(|log| {
+        let seq = log.next_seq;
+        log.entries[seq as usize % CAPACITY] = Some(KernelLogEntry {
+            seq,
+            timestamp,
+            event,
+        });
+        log.next_seq = seq.wrapping_add(1);
+    })
+}
+
+/// Returns the oldest entry still in the log whose sequence number is at least
+/// `seq`, or `None` if there isn't one yet.
+#[cfg(feature = "klog")]
+pub fn read(seq: u32) -> Option<KernelLogEntry> {
+    with_log(|log| {
+        if seq >= log.next_seq {
+            return None;
+        }
+        let oldest = log.next_seq.saturating_sub(CAPACITY as u32);
+        log.entries[seq.max(oldest) as usize % CAPACITY]
+    })
+}
+
+#[cfg(not(feature = "klog"))]
+pub fn record(_event: KernelEvent) {}
+
+#[cfg(not(feature = "klog"))]
+pub fn read(_seq: u32) -> Option<KernelLogEntry> {
+    None
+}
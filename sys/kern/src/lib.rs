@@ -41,6 +41,7 @@ mod descs;
 pub mod err;
 pub mod header;
 pub mod kipc;
+pub mod klog;
 pub mod profiling;
 pub mod startup;
 pub mod syscalls;
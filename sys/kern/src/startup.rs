@@ -21,6 +21,29 @@ static TASK_TABLE_IN_USE: AtomicBool = AtomicBool::new(true);
 
 pub const HUBRIS_FAULT_NOTIFICATION: u32 = 1;
 
+/// Computes the `tick_divisor` to pass to `start_kernel` on ARM M-profile, so
+/// that the kernel ticks `TICK_HZ` times a second on a CPU running at
+/// `cycles_per_ms`.
+///
+/// This panics if `TICK_HZ` doesn't divide the clock evenly, or if the result
+/// won't fit in SysTick's 24-bit reload register. Where the clock is known
+/// ahead of time, call it from a `const` so that's a build failure instead.
+pub const fn tick_divisor(cycles_per_ms: u32) -> u32 {
+    let cycles_per_sec = cycles_per_ms as u64 * 1000;
+    let hz = TICK_HZ as u64;
+    assert!(
+        cycles_per_sec % hz == 0,
+        "tick-hz doesn't divide the CPU clock evenly"
+    );
+    let divisor = cycles_per_sec / hz;
+    // SysTick is reloaded with `divisor - 1`, which must fit in 24 bits.
+    assert!(
+        divisor >= 1 && divisor <= 1 << 24,
+        "tick-hz is out of range for this CPU clock"
+    );
+    divisor as u32
+}
+
 /// The main kernel entry point.
 ///
 /// We currently expect an application to provide its own `main`-equivalent
@@ -29,8 +52,9 @@ pub const HUBRIS_FAULT_NOTIFICATION: u32 = 1;
 /// Parameters:
 ///
 /// - `tick_divisor`: a platform-specific way of converting "machine ticks" into
-///   "kernel ticks." On ARM M-profile, this is CPU cycles per tick, where a
-///   tick is typically a millisecond.
+///   "kernel ticks." On ARM M-profile, this is CPU cycles per tick; use
+///   `tick_divisor` to get a tick of `1 / TICK_HZ` seconds, which is what
+///   tasks expect.
 ///
 /// # Safety
 ///
@@ -120,5 +144,28 @@ pub(crate) fn with_task_table<R>(body: impl FnOnce(&mut [Task]) -> R) -> R {
     r
 }
 
+/// Checks whether the application's IPC policy lets task `caller` send or post
+/// to task `target`.
+///
+/// Out-of-range targets are let through, so that the caller's later check
+/// against the task table can fault it with the more useful `TaskOutOfRange`.
+pub(crate) fn ipc_permitted(caller: usize, target: usize) -> bool {
+    if target >= HUBRIS_TASK_COUNT {
+        return true;
+    }
+    HUBRIS_IPC_ACL[caller][target / 32] & 1 << (target % 32) != 0
+}
+
+/// Returns the number of tasks in the image.
+#[cfg(target_os = "linux")]
+pub(crate) fn task_count() -> usize {
+    HUBRIS_TASK_COUNT
+}
+
+/// Returns the name of task `index`, as given in `app.toml`.
+pub(crate) fn task_name(index: usize) -> &'static str {
+    HUBRIS_TASK_NAMES[index]
+}
+
 use crate::descs::*;
 include!(concat!(env!("OUT_DIR"), "/kconfig.rs"));
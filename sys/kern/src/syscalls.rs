@@ -37,7 +37,7 @@ use unwrap_lite::UnwrapLite;
 
 use crate::arch;
 use crate::err::{InteractFault, UserError};
-use crate::startup::with_task_table;
+use crate::startup::{ipc_permitted, with_task_table};
 use crate::task::{self, current_id, ArchState, NextTask, Task};
 use crate::time::Timestamp;
 use crate::umem::{safe_copy, USlice};
@@ -96,6 +96,8 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *mut Task) {
 /// Factored out of `syscall_entry` to encapsulate the bits that don't need
 /// unsafe.
 fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
+    tasks[current].count_syscall();
+
     let res = match Sysnum::try_from(nr) {
         Ok(Sysnum::Send) => send(tasks, current),
         Ok(Sysnum::Recv) => recv(tasks, current).map_err(UserError::from),
@@ -112,6 +114,9 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
         Ok(Sysnum::ReplyFault) => {
             reply_fault(tasks, current).map_err(UserError::from)
         }
+        Ok(Sysnum::SendWithDeadline) => {
+            send_with_deadline(tasks, current, arch::now())
+        }
         Err(_) => {
             // Bogus syscall number! That's a fault.
             Err(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber).into())
@@ -140,15 +145,16 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     // Extract callee.
     let callee_id = tasks[caller].save().as_send_args().callee;
 
-    // Check IPC filter - TODO
-    // Open question: should out-of-range task IDs be handled by faulting below,
-    // or by failing the IPC filter? Either condition will fault...
-
     // Route kernel messages.
     if callee_id == TaskId::KERNEL {
         return crate::kipc::handle_kernel_message(tasks, caller);
     }
 
+    // Check IPC filter. Out-of-range task IDs pass, and fault below instead.
+    if !ipc_permitted(caller, callee_id.index()) {
+        return Err(FaultInfo::SyscallUsage(UsageError::IpcNotPermitted).into());
+    }
+
     // Verify the given callee ID, converting it into a table index on success.
     let callee = task::check_task_id_against_table(tasks, callee_id)?;
 
@@ -186,6 +192,40 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     Ok(NextTask::Other.combine(next_task))
 }
 
+/// Implementation of the `SEND_WITH_DEADLINE` IPC primitive, which is SEND
+/// with a limit on how long the caller is willing to wait for a reply.
+///
+/// `caller` is a valid task index (i.e. not directly from user code).
+///
+/// # Panics
+///
+/// If `caller` is out of range for `tasks`.
+fn send_with_deadline(
+    tasks: &mut [Task],
+    caller: usize,
+    now: Timestamp,
+) -> Result<NextTask, UserError> {
+    let deadline = tasks[caller].save().as_send_deadline_args().deadline;
+    if deadline <= now {
+        // Too late to even start.
+        return Err(UserError::Recoverable(abi::TIMED_OUT, NextTask::Same));
+    }
+
+    let next_task = send(tasks, caller)?;
+
+    // If the SEND completed (or failed) immediately, there's nothing to time
+    // out. Otherwise, we're now waiting on the callee, either to receive our
+    // message or to reply to it; `task::process_timers` will stop us waiting
+    // at the deadline.
+    if let TaskState::Healthy(SchedState::InSend(_) | SchedState::InReply(_)) =
+        tasks[caller].state()
+    {
+        tasks[caller].set_send_deadline(deadline);
+        arch::request_wakeup(deadline);
+    }
+    Ok(next_task)
+}
+
 /// Implementation of the RECV IPC primitive.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
@@ -399,6 +439,7 @@ fn set_timer(task: &mut Task, now: Timestamp) -> NextTask {
             let _ = task.post(args.notification);
             return NextTask::Same;
         }
+        arch::request_wakeup(deadline);
     }
     task.set_timer(args.deadline, args.notification);
     NextTask::Same
@@ -722,7 +763,18 @@ fn explicit_panic(
     tasks: &mut [Task],
     caller: usize,
 ) -> Result<NextTask, UserError> {
-    // It's the easiest syscall!
+    // Keep as much of the message as we have room for. A bogus message slice
+    // doesn't stop the task from panicking, it just goes unrecorded.
+    let mut message = [0; abi::PANIC_MESSAGE_BYTES];
+    let mut len = 0;
+    if let Ok(slice) = tasks[caller].save().as_panic_args().message {
+        if let Ok(bytes) = tasks[caller].try_read(&slice) {
+            len = bytes.len().min(message.len());
+            message[..len].copy_from_slice(&bytes[..len]);
+        }
+    }
+    tasks[caller].record_panic(&message[..len]);
+
     Ok(task::force_fault(tasks, caller, FaultInfo::Panic))
 }
 
@@ -748,6 +800,10 @@ fn post(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     let args = tasks[caller].save().as_post_args();
     let peer_id = args.task_id;
 
+    if !ipc_permitted(caller, peer_id.index()) {
+        return Err(FaultInfo::SyscallUsage(UsageError::IpcNotPermitted).into());
+    }
+
     let peer_idx = task::check_task_id_against_table(tasks, peer_id)?;
 
     let woke = tasks[peer_idx].post(args.notification_bits);
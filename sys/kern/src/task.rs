@@ -6,9 +6,12 @@
 
 use core::convert::TryFrom;
 
+#[cfg(feature = "fault-capture")]
+use abi::PANIC_MESSAGE_BYTES;
 use abi::{
-    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState, TaskId,
-    TaskState, ULease, UsageError,
+    FaultInfo, FaultRegisters, FaultSource, Generation, KernelEvent,
+    ReplyFaultReason, SchedState, StackUsage, TaskId, TaskState, TaskStats,
+    ULease, UsageError, KERNEL_LOG_PANIC_BYTES,
 };
 use zerocopy::FromBytes;
 
@@ -17,9 +20,16 @@ use crate::descs::{
     REGIONS_PER_TASK,
 };
 use crate::err::UserError;
+use crate::klog;
 use crate::startup::HUBRIS_FAULT_NOTIFICATION;
 use crate::time::Timestamp;
 use crate::umem::USlice;
+use unwrap_lite::UnwrapLite;
+
+/// Pattern written over unused task stack when a task is (re)started, so that
+/// `Task::stack_usage` can later tell how deep the stack has gone. (This is the
+/// value Humility expects, too.)
+pub const STACK_PAINT: u32 = 0xbaddcafe;
 
 /// Internal representation of a task.
 ///
@@ -46,6 +56,25 @@ pub struct Task {
     /// Notification status.
     notifications: u32,
 
+    /// Execution counters. Unlike most task state, these survive restarts.
+    stats: TaskStats,
+
+    /// Start of the message from the task's most recent panic, if that was its
+    /// most recent fault; the first `panic_message_len` bytes are valid. Like
+    /// `stats`, this survives restarts, so that we can tell why a task died.
+    ///
+    /// This and `fault_registers` are only kept with the `fault-capture`
+    /// feature, since they cost about 100 bytes per task.
+    #[cfg(feature = "fault-capture")]
+    panic_message: [u8; PANIC_MESSAGE_BYTES],
+    #[cfg(feature = "fault-capture")]
+    panic_message_len: u8,
+
+    /// Processor state captured at the task's most recent fault, if the
+    /// architecture can provide it. This also survives restarts.
+    #[cfg(feature = "fault-capture")]
+    fault_registers: Option<FaultRegisters>,
+
     /// Pointer to the ROM descriptor used to create this task, so it can be
     /// restarted.
     descriptor: &'static TaskDesc,
@@ -67,6 +96,13 @@ impl Task {
 
             generation: 0,
             notifications: 0,
+            stats: TaskStats::default(),
+            #[cfg(feature = "fault-capture")]
+            panic_message: [0; PANIC_MESSAGE_BYTES],
+            #[cfg(feature = "fault-capture")]
+            panic_message_len: 0,
+            #[cfg(feature = "fault-capture")]
+            fault_registers: None,
             save: crate::arch::SavedState::default(),
             timer: crate::task::TimerState::default(),
         }
@@ -257,6 +293,21 @@ impl Task {
         self.timer.to_post = notifications;
     }
 
+    /// Sets a deadline for the SEND that this task is currently blocked in. If
+    /// the SEND hasn't completed by `deadline`, `process_timers` will give up
+    /// on it and return `abi::TIMED_OUT` to the task.
+    ///
+    /// # Panics
+    ///
+    /// If the task isn't blocked in SEND (or waiting for its reply).
+    pub fn set_send_deadline(&mut self, deadline: Timestamp) {
+        uassert!(matches!(
+            self.state,
+            TaskState::Healthy(SchedState::InSend(_) | SchedState::InReply(_))
+        ));
+        self.timer.send_deadline = Some(deadline);
+    }
+
     /// Reads out the state of this task's timer, as previously set by
     /// `set_timer`.
     pub fn timer(&self) -> (Option<Timestamp>, NotificationSet) {
@@ -326,6 +377,137 @@ impl Task {
         if let TaskState::Faulted { .. } = last {
             panic!();
         }
+        // Any deadline on a SEND only applies while we're waiting on it.
+        if !matches!(s, SchedState::InSend(_) | SchedState::InReply(_)) {
+            self.timer.send_deadline = None;
+        }
+    }
+
+    /// Returns the bounds of this task's stack, as `(base, initial_stack)`, or
+    /// `None` if no region in the task's region table contains the top of its
+    /// stack.
+    ///
+    /// The build system places the stack at the bottom of the region holding
+    /// the initial stack pointer, so the stack is assumed to span from there.
+    pub fn stack_bounds(&self) -> Option<(usize, usize)> {
+        let initial_stack = self.descriptor.initial_stack as usize;
+        // The stack often ends flush with the top of its region, so look for
+        // the region holding its topmost word rather than `initial_stack`.
+        let top_word = initial_stack.wrapping_sub(4);
+        self.region_table()
+            .iter()
+            .find(|region| region.contains(top_word))
+            .map(|region| (region.base as usize, initial_stack))
+    }
+
+    /// Fills this task's stack, from its base up to (but not including)
+    /// `limit`, with `STACK_PAINT`. This is used by `arch::reinitialize`,
+    /// which knows how much of the top of the stack it's about to use.
+    pub fn paint_stack(&mut self, limit: usize) {
+        if let Some((base, _)) = self.stack_bounds() {
+            let mut uslice: USlice<u32> =
+                USlice::from_raw(base, (limit - base) >> 2).unwrap_lite();
+            let zap = self.try_write(&mut uslice).unwrap_lite();
+            for word in zap.iter_mut() {
+                *word = STACK_PAINT;
+            }
+        }
+    }
+
+    /// Measures how much of this task's stack has been used since it was last
+    /// painted by `paint_stack`, by looking for the lowest word that no longer
+    /// holds `STACK_PAINT`.
+    ///
+    /// This can under-report if the task happens to write the paint pattern
+    /// itself, or skips over stack it doesn't write (e.g. a large uninitialized
+    /// buffer), but is otherwise exact.
+    pub fn stack_usage(&self) -> StackUsage {
+        let (base, initial_stack) = match self.stack_bounds() {
+            Some(bounds) => bounds,
+            None => return StackUsage::default(),
+        };
+        let uslice: USlice<u32> =
+            USlice::from_raw(base, (initial_stack - base) >> 2).unwrap_lite();
+        // The task can always read its own stack; if that's no longer the case
+        // the task table is corrupt.
+        let stack = self.try_read(&uslice).unwrap_lite();
+        let unused = stack.iter().take_while(|&&w| w == STACK_PAINT).count();
+        StackUsage {
+            size: (initial_stack - base) as u32,
+            max_depth: ((stack.len() - unused) * 4) as u32,
+        }
+    }
+
+    /// Returns this task's execution counters.
+    pub fn stats(&self) -> &TaskStats {
+        &self.stats
+    }
+
+    /// Records that `n` timer ticks ended while this task was running.
+    pub fn count_ticks(&mut self, n: u32) {
+        self.stats.ticks = self.stats.ticks.wrapping_add(n);
+    }
+
+    /// Records that the kernel is switching to this task from another.
+    pub fn count_switch(&mut self) {
+        self.stats.switches = self.stats.switches.wrapping_add(1);
+    }
+
+    /// Records that this task has made a syscall.
+    pub fn count_syscall(&mut self) {
+        self.stats.syscalls = self.stats.syscalls.wrapping_add(1);
+    }
+
+    /// Records that a hardware interrupt is being delivered to this task.
+    pub fn count_irq(&mut self) {
+        self.stats.irqs = self.stats.irqs.wrapping_add(1);
+    }
+
+    /// Returns the start of the message from this task's most recent panic,
+    /// or an empty slice if its most recent fault wasn't a panic (or it has
+    /// never faulted, or the `fault-capture` feature is off).
+    pub fn panic_message(&self) -> &[u8] {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "fault-capture")] {
+                let len = usize::from(self.panic_message_len);
+                &self.panic_message[..len.min(PANIC_MESSAGE_BYTES)]
+            } else {
+                &[]
+            }
+        }
+    }
+
+    /// Returns the processor state captured at this task's most recent fault,
+    /// or `None` if it has never faulted (or the architecture doesn't capture
+    /// any, or the `fault-capture` feature is off).
+    pub fn fault_registers(&self) -> Option<FaultRegisters> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "fault-capture")] {
+                self.fault_registers
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Records that this task is panicking with `message`, keeping the start
+    /// of it both in the task and in the kernel log. This doesn't fault the
+    /// task; use `force_fault` with `FaultInfo::Panic` for that.
+    pub fn record_panic(&mut self, message: &[u8]) {
+        #[cfg(feature = "fault-capture")]
+        {
+            let len = message.len().min(PANIC_MESSAGE_BYTES);
+            self.panic_message[..len].copy_from_slice(&message[..len]);
+            self.panic_message_len = len as u8;
+        }
+
+        let mut logged = [0; KERNEL_LOG_PANIC_BYTES];
+        let len = message.len().min(KERNEL_LOG_PANIC_BYTES);
+        logged[..len].copy_from_slice(&message[..len]);
+        klog::record(KernelEvent::Panic {
+            task: self.descriptor.index,
+            message: logged,
+        });
     }
 
     /// Returns a reference to the saved machine state for the task.
@@ -364,6 +546,12 @@ pub trait ArchState: Default {
     fn arg5(&self) -> u32;
     /// Reads syscall argument register 6.
     fn arg6(&self) -> u32;
+    /// Reads syscall argument 7. Only `SEND_WITH_DEADLINE` needs more than
+    /// seven arguments, so this (and `arg8`) may be more expensive to read
+    /// than the others, and need not be preserved across a context switch.
+    fn arg7(&self) -> u32;
+    /// Reads syscall argument 8. See `arg7`.
+    fn arg8(&self) -> u32;
 
     /// Reads the syscall descriptor (number).
     fn syscall_descriptor(&self) -> u32;
@@ -406,6 +594,17 @@ pub trait ArchState: Default {
         }
     }
 
+    /// Interprets the arguments to the `SEND_WITH_DEADLINE` syscall that it
+    /// doesn't share with SEND, and returns the results. The rest can be
+    /// read using `as_send_args`.
+    fn as_send_deadline_args(&self) -> SendDeadlineArgs {
+        SendDeadlineArgs {
+            deadline: Timestamp::from(
+                u64::from(self.arg8()) << 32 | u64::from(self.arg7()),
+            ),
+        }
+    }
+
     /// Interprets arguments as for the RECV syscall and returns the results.
     ///
     /// This is inlined because it's called from several places, and most of
@@ -592,6 +791,13 @@ pub struct SendArgs {
     pub lease_table: Result<USlice<ULease>, UsageError>,
 }
 
+/// Decoded arguments for the `SEND_WITH_DEADLINE` syscall, beyond those in
+/// `SendArgs`.
+#[derive(Clone, Debug)]
+pub struct SendDeadlineArgs {
+    pub deadline: Timestamp,
+}
+
 /// Decoded arguments for the `RECV` syscall.
 #[derive(Clone, Debug)]
 pub struct RecvArgs {
@@ -668,6 +874,10 @@ pub struct TimerState {
     /// Set of notification bits to post to the owning task when this timer
     /// fires.
     to_post: NotificationSet,
+    /// Deadline, in kernel time, for the `SEND_WITH_DEADLINE` that the owning
+    /// task is blocked in, if any. This is independent of `deadline`, and is
+    /// cleared whenever the task stops waiting on its SEND.
+    send_deadline: Option<Timestamp>,
 }
 
 /// Collection of bits that may be posted to a task's notification word.
@@ -713,6 +923,9 @@ impl NextTask {
 
 /// Processes all enabled timers in the task table, posting notifications for
 /// any that have expired by `current_time` (and disabling them atomically).
+///
+/// This also gives up on any `SEND_WITH_DEADLINE` whose deadline has expired,
+/// unblocking the sender with `abi::TIMED_OUT`.
 pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
     let mut sched_hint = NextTask::Same;
     for (index, task) in tasks.iter_mut().enumerate() {
@@ -727,10 +940,38 @@ pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
                 sched_hint = sched_hint.combine(task_hint)
             }
         }
+        if let Some(deadline) = task.timer.send_deadline {
+            if deadline <= current_time {
+                task.timer.send_deadline = None;
+                // If the task has faulted since it started sending, there's
+                // nothing left to time out.
+                if let TaskState::Healthy(_) = task.state {
+                    // Whether or not the peer has received the message, we
+                    // just stop waiting for it. If it later tries to reply,
+                    // it'll find us not waiting; if it tries to borrow from
+                    // us, it'll find we have defected.
+                    task.set_healthy_state(SchedState::Runnable);
+                    task.save_mut()
+                        .set_send_response_and_length(abi::TIMED_OUT, 0);
+                    sched_hint = sched_hint.combine(NextTask::Specific(index));
+                }
+            }
+        }
     }
     sched_hint
 }
 
+/// Returns the earliest deadline among all enabled timers in the task table,
+/// that is, the next time `process_timers` will have anything to do.
+#[cfg(feature = "tickless")]
+pub fn next_deadline(tasks: &[Task]) -> Option<Timestamp> {
+    tasks
+        .iter()
+        .flat_map(|task| [task.timer.deadline, task.timer.send_deadline])
+        .flatten()
+        .min()
+}
+
 /// Checks a user-provided `TaskId` for validity against `table`.
 ///
 /// On success, returns an index that can be used to dereference `table` without
@@ -816,6 +1057,10 @@ pub fn priority_scan(
 /// what state the task was in *before* it faulted, and *erase* the last
 /// fault. These kinds of double-faults are expected to be super rare.
 ///
+/// The task's registers are captured for `Task::fault_registers` at this point,
+/// so for processor faults this must be called before the architecture clears
+/// its fault status.
+///
 /// Returns a `NextTask` under the assumption that, if you're hitting tasks
 /// with faults, at least one of them is probably the current task; this
 /// makes it harder to forget to request rescheduling. If you're faulting
@@ -825,7 +1070,31 @@ pub fn force_fault(
     index: usize,
     fault: FaultInfo,
 ) -> NextTask {
+    // Panics are recorded by `Task::record_panic`, which has their message.
+    if fault != FaultInfo::Panic {
+        klog::record(KernelEvent::Fault {
+            task: index as u16,
+            fault,
+        });
+        // The message from any earlier panic no longer explains the task's
+        // state.
+        #[cfg(feature = "fault-capture")]
+        {
+            tasks[index].panic_message_len = 0;
+        }
+    }
+
     let task = &mut tasks[index];
+    // An injected fault wasn't taken by the task, and the fault status
+    // registers describe whatever was running instead, so there's nothing
+    // worth keeping.
+    #[cfg(feature = "fault-capture")]
+    {
+        task.fault_registers = match fault {
+            FaultInfo::Injected(_) => None,
+            _ => crate::arch::capture_fault_registers(task),
+        };
+    }
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
             original_state: sched,
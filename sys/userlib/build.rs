@@ -5,17 +5,11 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
 
-    // Do an architecture check.
-    if build_util::target_os() != "none" {
-        eprintln!("***********************************************");
-        eprintln!("Hi!");
-        eprintln!("You appear to be building this natively,");
-        eprintln!("i.e. for your workstation. This won't work.");
-        eprintln!("Please specify --target=some-triple, e.g.");
-        eprintln!("--target=thumbv7em-none-eabihf");
-        eprintln!("***********************************************");
-        panic!()
-    }
+    let tick_hz = build_util::tick_hz()?;
+    std::fs::write(
+        build_util::out_dir().join("tick_hz.rs"),
+        format!("pub const TICK_HZ: u32 = {tick_hz};\n"),
+    )?;
 
     Ok(())
 }
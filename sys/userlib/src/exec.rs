@@ -0,0 +1,556 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A cooperative executor for tasks written as async code.
+//!
+//! `run` takes a fixed set of futures, each of which is usually an endless
+//! `async` loop, and polls them whenever they're woken, for the life of the
+//! task. There's no allocation: the futures live wherever the caller puts them
+//! (typically pinned on `main`'s stack), and the executor's own bookkeeping is
+//! a few words in a static.
+//!
+//! When every future is waiting, the executor blocks in RECV. Futures can wait
+//! for
+//!
+//! - notification bits, with `notification`;
+//! - timer deadlines, through `multitimer::Multitimer::wait` (with
+//!   `multitimer`'s `exec` feature), which is built on `notification`;
+//! - incoming messages, with `recv`, or by serving an Idol interface from
+//!   inside a future (see below).
+//!
+//! Anything else that blocks -- `sys_send` to another task, or
+//! `hl::sleep_for` -- blocks the whole task, as it always has. The executor
+//! doesn't preempt; a future runs until it next returns `Pending`.
+//!
+//! # Idol servers
+//!
+//! Only one thing can block in RECV at a time, and for a server that's the
+//! generated `idol_runtime` dispatch loop. Wrap it in a future that asks for
+//! the executor's `idle` turn, and have the server's `NotificationHandler` hand
+//! notifications back to the executor:
+//!
+//! ```ignore
+//! impl idol_runtime::NotificationHandler for ServerImpl {
+//!     fn current_notification_mask(&self) -> u32 {
+//!         exec::notification_mask()
+//!     }
+//!
+//!     fn handle_notification(&mut self, bits: u32) {
+//!         exec::notify(bits);
+//!     }
+//! }
+//!
+//! let serve = pin!(async {
+//!     loop {
+//!         exec::idle().await;
+//!         idol_runtime::dispatch_n(&mut buffer, &mut server);
+//!     }
+//! });
+//! let poll_things = pin!(async {
+//!     loop {
+//!         let fired = timers.wait().await;
+//!         // ...
+//!     }
+//! });
+//! exec::run(&mut [serve, poll_things]);
+//! ```
+//!
+//! The server's state can't be borrowed by the other futures while the serving
+//! future holds it, so state they share needs a `Cell` or `RefCell`. (On our
+//! toolchain, `core::pin::pin!` also needs `#![feature(pin_macro)]`.)
+
+use core::convert::Infallible;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+#[cfg(armv6m)]
+use armv6m_atomic_hack::{AtomicBoolExt, AtomicU32Ext};
+
+use crate::{RecvMessage, TaskId};
+
+// The real RECV, except in our own tests, which replay a script instead.
+#[cfg(test)]
+use self::fakes::{sys_recv_closed, sys_recv_open};
+#[cfg(not(test))]
+use crate::{sys_recv_closed, sys_recv_open};
+
+/// Most futures `run` can manage; each gets one bit of a `u32`.
+pub const MAX_FUTURES: usize = 32;
+
+// The executor's state. A task has one thread, so none of this is ever
+// contended; the atomics just let it live in a static without `unsafe`.
+
+/// Set once `run` has been called.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Futures that need polling, one bit each.
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+/// Index of the future being polled.
+static CURRENT: AtomicU32 = AtomicU32::new(0);
+/// Futures waiting for the idle turn.
+static IDLERS: AtomicU32 = AtomicU32::new(0);
+/// One more than the index of the future holding the idle turn, or zero.
+static IDLE_TURN: AtomicU32 = AtomicU32::new(0);
+/// Notification mask in force when the idle turn was handed out.
+static IDLE_MASK: AtomicU32 = AtomicU32::new(0);
+/// Notification bits received but not yet claimed by `notification`.
+static ARRIVED: AtomicU32 = AtomicU32::new(0);
+/// Notification bits each future is waiting on, as of its last poll.
+static INTEREST: [AtomicU32; MAX_FUTURES] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: AtomicU32 = AtomicU32::new(0);
+    [NONE; MAX_FUTURES]
+};
+
+fn set_bits(a: &AtomicU32, bits: u32) {
+    a.store(a.load(Ordering::Relaxed) | bits, Ordering::Relaxed);
+}
+
+fn current() -> usize {
+    CURRENT.load(Ordering::Relaxed) as usize
+}
+
+/// Runs `futures` for the rest of the task's life.
+///
+/// Each future is polled once to start with, and afterwards whenever it's
+/// woken. Futures are polled in index order, so earlier futures get first
+/// claim on notifications they share with later ones.
+///
+/// # Panics
+///
+/// If called more than once, or with more than `MAX_FUTURES` futures, or if
+/// every future ends up waiting on something that can never happen (no
+/// notifications, no idle turn, and nothing left to wake them).
+pub fn run(futures: &mut [Pin<&mut dyn Future<Output = Infallible>>]) -> ! {
+    if futures.len() > MAX_FUTURES || RUNNING.swap(true, Ordering::Relaxed) {
+        panic!();
+    }
+
+    let all = u32::MAX
+        .checked_shr((MAX_FUTURES - futures.len()) as u32)
+        .unwrap_or(0);
+    WOKEN.store(all, Ordering::Relaxed);
+
+    loop {
+        let woken = WOKEN.swap(0, Ordering::Relaxed) & all;
+        if woken == 0 {
+            wait();
+            continue;
+        }
+
+        for (i, future) in futures.iter_mut().enumerate() {
+            if woken & 1 << i == 0 {
+                continue;
+            }
+            // The future re-registers whatever it's still waiting on.
+            INTEREST[i].store(0, Ordering::Relaxed);
+            IDLERS.store(
+                IDLERS.load(Ordering::Relaxed) & !(1 << i),
+                Ordering::Relaxed,
+            );
+            CURRENT.store(i as u32, Ordering::Relaxed);
+
+            let waker = waker_for(i);
+            match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(never) => match never {},
+                Poll::Pending => (),
+            }
+            // An idle turn that went unused is forfeit.
+            IDLE_TURN.store(0, Ordering::Relaxed);
+            IDLE_MASK.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Called when no future is ready to run: either hands the idle turn to a
+/// future that wants it, or blocks for notifications ourselves.
+fn wait() {
+    let mask = notification_mask();
+    let idlers = IDLERS.load(Ordering::Relaxed);
+    if idlers != 0 {
+        let i = idlers.trailing_zeros();
+        IDLE_MASK.store(mask, Ordering::Relaxed);
+        IDLE_TURN.store(i + 1, Ordering::Relaxed);
+        set_bits(&WOKEN, 1 << i);
+        return;
+    }
+
+    if mask == 0 {
+        // Nothing can ever wake us.
+        panic!();
+    }
+    match sys_recv_closed(&mut [], mask, TaskId::KERNEL) {
+        Ok(rm) => deliver(rm.operation),
+        // The kernel doesn't die.
+        Err(_) => panic!(),
+    }
+}
+
+/// Returns the notification bits that some future is waiting for.
+///
+/// A future blocking in RECV during its `idle` turn should use this as its
+/// notification mask, and pass whatever it receives to `notify`.
+pub fn notification_mask() -> u32 {
+    INTEREST
+        .iter()
+        .fold(IDLE_MASK.load(Ordering::Relaxed), |m, i| {
+            m | i.load(Ordering::Relaxed)
+        })
+}
+
+/// Hands notification bits received by a future (usually during its `idle`
+/// turn) to the futures waiting for them.
+pub fn notify(bits: u32) {
+    deliver(bits);
+    // The caller may be waiting on these bits too, but not have said so yet
+    // in this poll.
+    set_bits(&WOKEN, 1 << current());
+}
+
+fn deliver(bits: u32) {
+    set_bits(&ARRIVED, bits);
+    for (i, interest) in INTEREST.iter().enumerate() {
+        if interest.load(Ordering::Relaxed) & bits != 0 {
+            set_bits(&WOKEN, 1 << i);
+        }
+    }
+}
+
+/// Waits for any of the notification bits in `mask`, and returns those that
+/// have arrived.
+///
+/// Each notification is delivered to one waiter: if several futures are
+/// waiting on the same bit, the first to be polled gets it.
+pub fn notification(mask: u32) -> impl Future<Output = u32> {
+    core::future::poll_fn(move |_| {
+        let arrived = ARRIVED.load(Ordering::Relaxed);
+        let bits = arrived & mask;
+        if bits != 0 {
+            ARRIVED.store(arrived & !bits, Ordering::Relaxed);
+            Poll::Ready(bits)
+        } else {
+            set_bits(&INTEREST[current()], mask);
+            Poll::Pending
+        }
+    })
+}
+
+/// Waits until every other future is waiting, and then completes, giving the
+/// caller the task's one chance to block in RECV.
+///
+/// The caller should block before it next awaits anything, accepting the
+/// notifications in `notification_mask` and passing any it gets to `notify`.
+/// If several futures want the idle turn, the one with the lowest index gets
+/// it.
+pub fn idle() -> impl Future<Output = ()> {
+    core::future::poll_fn(|_| {
+        let me = current() as u32;
+        if IDLE_TURN.load(Ordering::Relaxed) == me + 1 {
+            IDLE_TURN.store(0, Ordering::Relaxed);
+            Poll::Ready(())
+        } else {
+            set_bits(&IDLERS, 1 << me);
+            Poll::Pending
+        }
+    })
+}
+
+/// Waits for a message from another task, receiving it into `buffer`.
+///
+/// Notifications that arrive in the meantime go to the futures waiting for
+/// them.
+pub async fn recv(buffer: &mut [u8]) -> RecvMessage {
+    loop {
+        idle().await;
+        let rm = sys_recv_open(buffer, notification_mask());
+        if rm.sender == TaskId::KERNEL {
+            notify(rm.operation);
+        } else {
+            return rm;
+        }
+    }
+}
+
+/// Returns `Pending` once, letting the other futures run before the caller
+/// continues.
+pub fn yield_now() -> impl Future<Output = ()> {
+    let mut yielded = false;
+    core::future::poll_fn(move |cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+}
+
+// Our wakers carry the index of the future they wake in their data pointer.
+
+static VTABLE: RawWakerVTable =
+    RawWakerVTable::new(waker_clone, waker_wake, waker_wake, waker_drop);
+
+fn waker_for(i: usize) -> Waker {
+    // Safety: the vtable functions below uphold the `RawWaker` contract; the
+    // data pointer is just an integer and owns nothing.
+    unsafe { Waker::from_raw(RawWaker::new(i as *const (), &VTABLE)) }
+}
+
+fn waker_clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &VTABLE)
+}
+
+fn waker_wake(data: *const ()) {
+    set_bits(&WOKEN, 1 << data as usize);
+}
+
+fn waker_drop(_: *const ()) {}
+
+// Syscall fakes for testing!
+
+#[cfg(test)]
+mod fakes {
+    use crate::{ClosedRecvError, RecvMessage, TaskId};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Something for RECV to return.
+    #[derive(Copy, Clone, Debug)]
+    pub enum Incoming {
+        /// Notification bits, from the kernel.
+        Notification(u32),
+        /// A message with this operation, from some other task.
+        Message(u32),
+    }
+
+    /// Unwinds out of `run` once the script is used up.
+    pub struct ScriptDone;
+
+    thread_local! {
+        /// What the next RECVs return, in order.
+        pub static SCRIPT: RefCell<VecDeque<Incoming>> = RefCell::default();
+        /// The notification mask passed to each RECV so far, and whether it
+        /// was open.
+        pub static RECVS: RefCell<Vec<(u32, bool)>> = RefCell::default();
+    }
+
+    fn next(mask: u32, open: bool) -> Incoming {
+        RECVS.with(|r| r.borrow_mut().push((mask, open)));
+        match SCRIPT.with(|s| s.borrow_mut().pop_front()) {
+            Some(Incoming::Notification(bits)) => {
+                assert_eq!(bits & !mask, 0, "notification outside RECV mask");
+                Incoming::Notification(bits)
+            }
+            Some(incoming) => incoming,
+            // Skip the panic hook; this isn't a failure.
+            None => std::panic::resume_unwind(Box::new(ScriptDone)),
+        }
+    }
+
+    fn message(sender: TaskId, operation: u32) -> RecvMessage {
+        RecvMessage {
+            sender,
+            operation,
+            message_len: 0,
+            response_capacity: 0,
+            lease_count: 0,
+        }
+    }
+
+    pub fn sys_recv_open(_buffer: &mut [u8], mask: u32) -> RecvMessage {
+        match next(mask, true) {
+            Incoming::Notification(bits) => message(TaskId::KERNEL, bits),
+            Incoming::Message(op) => {
+                message(TaskId::for_index_and_gen(1, Default::default()), op)
+            }
+        }
+    }
+
+    pub fn sys_recv_closed(
+        _buffer: &mut [u8],
+        mask: u32,
+        sender: TaskId,
+    ) -> Result<RecvMessage, ClosedRecvError> {
+        assert_eq!(sender, TaskId::KERNEL);
+        match next(mask, false) {
+            Incoming::Notification(bits) => Ok(message(TaskId::KERNEL, bits)),
+            Incoming::Message(_) => panic!("message in closed RECV"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fakes::*;
+    use super::*;
+    use std::cell::RefCell;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::Mutex;
+
+    /// The executor's state is global, so its tests take turns.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Runs `futures` until RECV has returned everything in `script`, and
+    /// returns the mask passed to each RECV, and whether it was open.
+    fn run_script(
+        script: &[Incoming],
+        futures: &mut [Pin<&mut dyn Future<Output = Infallible>>],
+    ) -> Vec<(u32, bool)> {
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for a in [&WOKEN, &CURRENT, &IDLERS, &IDLE_TURN, &IDLE_MASK, &ARRIVED]
+            .into_iter()
+            .chain(&INTEREST)
+        {
+            a.store(0, Ordering::Relaxed);
+        }
+        RUNNING.store(false, Ordering::Relaxed);
+        SCRIPT.with(|s| *s.borrow_mut() = script.iter().copied().collect());
+        RECVS.with(|r| r.borrow_mut().clear());
+
+        let e = std::panic::catch_unwind(AssertUnwindSafe(|| run(futures)))
+            .unwrap_err();
+        if !e.is::<ScriptDone>() {
+            std::panic::resume_unwind(e);
+        }
+        RECVS.with(|r| r.take())
+    }
+
+    /// Never completes, without asking to be woken.
+    async fn forever() -> Infallible {
+        core::future::pending().await
+    }
+
+    #[test]
+    fn notifications_go_to_waiters() {
+        let log = RefCell::new(Vec::new());
+        let mut f0 = Box::pin(async {
+            loop {
+                let bits = notification(0b01).await;
+                log.borrow_mut().push((0, bits));
+            }
+        });
+        let mut f1 = Box::pin(async {
+            loop {
+                let bits = notification(0b10).await;
+                log.borrow_mut().push((1, bits));
+            }
+        });
+
+        let recvs = run_script(
+            &[
+                Incoming::Notification(0b10),
+                Incoming::Notification(0b01),
+                Incoming::Notification(0b11),
+            ],
+            &mut [f0.as_mut(), f1.as_mut()],
+        );
+        // With nobody asking for the idle turn, we block ourselves, for
+        // everything anyone's waiting on.
+        assert_eq!(recvs, [(0b11, false); 4]);
+        assert_eq!(*log.borrow(), [(1, 0b10), (0, 0b01), (0, 0b01), (1, 0b10)]);
+    }
+
+    #[test]
+    fn notification_hand_off() {
+        let log = RefCell::new(Vec::new());
+        // f0 and f1 both want bit 0, but f0 only wants it once; after that,
+        // f1 gets it.
+        let mut f0 = Box::pin(async {
+            let bits = notification(0b01).await;
+            log.borrow_mut().push((0, bits));
+            notification(0b10).await;
+            forever().await
+        });
+        let mut f1 = Box::pin(async {
+            loop {
+                let bits = notification(0b01).await;
+                log.borrow_mut().push((1, bits));
+            }
+        });
+
+        let recvs = run_script(
+            &[Incoming::Notification(0b01), Incoming::Notification(0b01)],
+            &mut [f0.as_mut(), f1.as_mut()],
+        );
+        assert_eq!(recvs, [(0b01, false), (0b11, false), (0b11, false)]);
+        assert_eq!(*log.borrow(), [(0, 0b01), (1, 0b01)]);
+    }
+
+    #[test]
+    fn idle_turn_forfeit() {
+        let log = RefCell::new(Vec::new());
+        let mut f0 = Box::pin(async {
+            // Ask for the idle turn, but don't take it when it comes.
+            let mut turn = Box::pin(idle());
+            core::future::poll_fn(|cx| {
+                let _ = turn.as_mut().poll(cx);
+                Poll::Ready(())
+            })
+            .await;
+            notification(0b01).await;
+            log.borrow_mut().push("0: notified");
+
+            // Having let that turn go, we don't get to block until f1 has
+            // had a chance to run.
+            idle().await;
+            log.borrow_mut().push("0: idle");
+            notification(0b01).await;
+            forever().await
+        });
+        let mut f1 = Box::pin(async {
+            notification(0b10).await;
+            log.borrow_mut().push("1: notified");
+            forever().await
+        });
+
+        let recvs = run_script(
+            &[Incoming::Notification(0b11)],
+            &mut [f0.as_mut(), f1.as_mut()],
+        );
+        // f0's turn went unused, so we blocked ourselves.
+        assert_eq!(recvs, [(0b11, false), (0b01, false)]);
+        assert_eq!(*log.borrow(), ["0: notified", "1: notified", "0: idle"]);
+    }
+
+    #[test]
+    fn recv_redelivers_notifications() {
+        let log = RefCell::new(Vec::new());
+        let mut server = Box::pin(async {
+            let mut buffer = [0u8; 4];
+            loop {
+                let rm = recv(&mut buffer).await;
+                log.borrow_mut().push(("message", rm.operation));
+            }
+        });
+        let mut waiter = Box::pin(async {
+            loop {
+                let bits = notification(0b100).await;
+                log.borrow_mut().push(("notification", bits));
+            }
+        });
+
+        let recvs = run_script(
+            &[
+                Incoming::Notification(0b100),
+                Incoming::Message(7),
+                Incoming::Notification(0b100),
+                Incoming::Message(8),
+            ],
+            &mut [server.as_mut(), waiter.as_mut()],
+        );
+        // The server does all the blocking, on the waiter's behalf.
+        assert_eq!(recvs, [(0b100, true); 5]);
+        assert_eq!(
+            *log.borrow(),
+            [
+                ("notification", 0b100),
+                ("message", 7),
+                ("notification", 0b100),
+                ("message", 8),
+            ]
+        );
+    }
+}
@@ -17,6 +17,8 @@ use crate::{
     BorrowInfo, ClosedRecvError, FromPrimitive,
 };
 
+use crate::time::{Duration, Instant};
+
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
 
 /// Receives a message, or a notification, and handles it.
@@ -415,10 +417,7 @@ impl Borrow<'_> {
 }
 
 /// Suspends the calling task until the kernel time is `>= time`.
-///
-/// TODO: once we figure out how to convert between ticks and seconds here, this
-/// should take a real unit instead of a tick count.
-pub fn sleep_until(time: u64) {
+pub fn sleep_until(time: Instant) {
     let prev = sys_get_timer();
     sys_set_timer(Some(time), INTERNAL_TIMER_NOTIFICATION);
     loop {
@@ -434,21 +433,19 @@ pub fn sleep_until(time: u64) {
 
         // We do, however, need to check for the possibility of spurious
         // wakeups, by reading the time back.
-        if sys_get_timer().now >= time {
+        if Instant::now() >= time {
             break;
         }
     }
     // Restore previous timer deadline and notifications
     if let Some(deadline) = prev.deadline {
-        sys_set_timer(Some(deadline), prev.on_dl);
+        sys_set_timer(Some(Instant::from_ticks(deadline)), prev.on_dl);
     }
 }
 
-/// Suspends the calling task until the kernel time has increased by `ticks`.
-///
-/// TODO: once we figure out how to convert between ticks and seconds here, this
-/// needs to take `Duration`.
-pub fn sleep_for(ticks: u64) {
+/// Suspends the calling task until the kernel time has increased by at least
+/// `duration`.
+pub fn sleep_for(duration: Duration) {
     // By definition, when we observe the kernel time as being some value T, we
     // are some amount of time into the tick that began at T (the time required
     // for us to make the observation). This means that some of that tick has
@@ -460,5 +457,5 @@ pub fn sleep_for(ticks: u64) {
     // `sleep_for(x)` will sleep for at least `x` full ticks. Note that the task
     // calling `sleep_for` may get woken arbitrarily later if preempted by
     // higher priority tasks, so at-least is generally the best we can do.
-    sleep_until(sys_get_timer().now + ticks + 1)
+    sleep_until(Instant::now() + duration + Duration::from_ticks(1))
 }
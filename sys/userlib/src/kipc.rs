@@ -24,6 +24,111 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
 
+pub fn read_task_stats(task: usize) -> abi::TaskStats {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::TaskStats>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStats as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+pub fn read_stack_usage(task: usize) -> abi::StackUsage {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::StackUsage>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadStackUsage as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the oldest entry in the kernel's event log numbered `seq` or later,
+/// or returns `None` if there's nothing that new (or the kernel was built
+/// without the `klog` feature). To read the whole log in order, start at zero
+/// and continue from one past each entry's `seq`.
+pub fn read_kernel_log(seq: u32) -> Option<abi::KernelLogEntry> {
+    let mut response = [0; core::mem::size_of::<Option<abi::KernelLogEntry>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadKernelLog as u16,
+        seq.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the start of the message from `task`'s most recent panic into `buf`,
+/// returning the number of bytes written. This is zero if the task's most
+/// recent fault wasn't a panic, if it has never faulted, or if the kernel was
+/// built without the `fault-capture` feature.
+///
+/// The kernel keeps `abi::PANIC_MESSAGE_BYTES` of the message; a shorter `buf`
+/// gets a prefix of that.
+pub fn read_panic_message(task: usize, buf: &mut [u8]) -> usize {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadPanicMessage as u16,
+        task.as_bytes(),
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    len
+}
+
+/// Reads the processor state the kernel captured at `task`'s most recent
+/// fault, or `None` if it has never faulted or that fault was injected (or the
+/// kernel can't capture any on this architecture, or was built without the
+/// `fault-capture` feature).
+pub fn read_fault_registers(task: usize) -> Option<abi::FaultRegisters> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<Option<abi::FaultRegisters>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadFaultRegisters as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the static description of task `task` -- its name, priority and
+/// memory regions -- or returns `None` if there's no such task. To enumerate
+/// every task, count up from zero until this returns `None`.
+pub fn read_task_info(task: usize) -> Option<abi::TaskInfo> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<Option<abi::TaskInfo>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskInfo as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
 pub fn restart_task(task: usize, start: bool) {
     // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
     let msg = (task as u32, start);
@@ -23,8 +23,12 @@
 //! all registers.
 //!
 //! See: https://github.com/rust-lang/rust/issues/73450#issuecomment-650463347
+//!
+//! In hosted builds, for the kernel simulator, the `stub` functions are
+//! replaced by the ones in the `sim` module, which hand the same register
+//! values to the simulated kernel.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(asm_const)]
 #![feature(naked_functions)]
 
@@ -36,15 +40,27 @@ pub use num_derive::{FromPrimitive, ToPrimitive};
 pub use num_traits::{FromPrimitive, ToPrimitive};
 pub use unwrap_lite::UnwrapLite;
 
+#[cfg(target_os = "none")]
 use core::arch;
 use core::marker::PhantomData;
 
+#[cfg(feature = "exec")]
+pub mod exec;
 pub mod hl;
 pub mod kipc;
+pub mod shared;
 pub mod task_slot;
+pub mod time;
 pub mod units;
 pub mod util;
 
+#[cfg(not(target_os = "none"))]
+mod sim;
+#[cfg(not(target_os = "none"))]
+pub use sim::sim_println;
+#[cfg(not(target_os = "none"))]
+use sim::*;
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct Lease<'a> {
@@ -151,6 +167,7 @@ struct SendArgs<'a> {
 /// Core implementation of the SEND syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     cfg_if::cfg_if! {
@@ -220,6 +237,129 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     }
 }
 
+/// Sends a message and waits for a reply, like `sys_send`, but gives up at
+/// `deadline`.
+///
+/// If `target` hasn't replied by then, this returns `abi::TIMED_OUT` and a
+/// zero length. The same happens, without sending anything, if `deadline` has
+/// already passed. A reply that arrives after the deadline is discarded.
+#[inline(always)]
+pub fn sys_send_with_deadline(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+    deadline: time::Instant,
+) -> (u32, usize) {
+    let deadline = deadline.as_ticks();
+    let mut args = SendWithDeadlineArgs {
+        send: SendArgs {
+            packed_target_operation: u32::from(target.0) << 16
+                | u32::from(operation),
+            outgoing_ptr: outgoing.as_ptr(),
+            outgoing_len: outgoing.len(),
+            incoming_ptr: incoming.as_mut_ptr(),
+            incoming_len: incoming.len(),
+            lease_ptr: leases.as_ptr(),
+            lease_len: leases.len(),
+        },
+        deadline_lo: deadline as u32,
+        deadline_hi: (deadline >> 32) as u32,
+    };
+    unsafe { sys_send_with_deadline_stub(&mut args).into() }
+}
+
+#[allow(dead_code)] // this gets used from asm
+#[repr(C)] // field order matters
+struct SendWithDeadlineArgs<'a> {
+    send: SendArgs<'a>,
+    deadline_lo: u32,
+    deadline_hi: u32,
+}
+
+/// Core implementation of the SEND_WITH_DEADLINE syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
+#[naked]
+unsafe extern "C" fn sys_send_with_deadline_stub(
+    _args: &mut SendWithDeadlineArgs<'_>,
+) -> RcLen {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r7, lr}}
+                mov r4, r8
+                mov r5, r9
+                mov r6, r10
+                mov r7, r11
+                push {{r4-r7}}
+                @ Load the constant syscall number.
+                eors r4, r4
+                adds r4, #{sysnum}
+                mov r11, r4
+                @ Load in args from the struct.
+                ldm r0!, {{r4-r7}}
+                ldm r0!, {{r1-r3}}
+                mov r8, r1
+                mov r9, r2
+                mov r10, r3
+                @ The deadline goes in r0-r1, which the kernel reads back
+                @ from the exception frame.
+                ldm r0, {{r0, r1}}
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r7}}
+                mov r8, r4
+                mov r9, r5
+                mov r10, r6
+                mov r11, r7
+                pop {{r4-r7, pc}}
+                ",
+                sysnum = const Sysnum::SendWithDeadline as u32,
+                options(noreturn),
+            )
+        } else if #[cfg(any(armv7m, armv8m))] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r11}}
+                @ Load in args from the struct.
+                ldm r0, {{r4-r10}}
+                @ The deadline goes in r0-r1, which the kernel reads back
+                @ from the exception frame.
+                ldr r1, [r0, #32]
+                ldr r0, [r0, #28]
+                @ Load the constant syscall number.
+                mov r11, {sysnum}
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r11}}
+                @ Fin.
+                bx lr
+                ",
+                sysnum = const Sysnum::SendWithDeadline as u32,
+                options(noreturn),
+            )
+        } else {
+            compile_error!("missing sys_send_with_deadline_stub for ARM profile");
+        }
+    }
+}
+
 /// Performs an "open" RECV that will accept messages from any task or
 /// notifications from the kernel.
 ///
@@ -328,6 +468,7 @@ pub struct RecvMessage {
 /// Core implementation of the RECV syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 #[must_use]
 unsafe extern "C" fn sys_recv_stub(
@@ -445,6 +586,7 @@ pub fn sys_reply(peer: TaskId, code: u32, message: &[u8]) {
 /// Core implementation of the REPLY syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_reply_stub(
     _peer: u32,
@@ -521,16 +663,16 @@ unsafe extern "C" fn sys_reply_stub(
 ///
 /// The timer is set to `deadline`. If `deadline` is `None`, the timer is
 /// disabled. Otherwise, the timer is configured to notify when the specified
-/// time (in ticks since boot) is reached. When that occurs, the `notifications`
-/// will get posted to this task, and the timer will be disabled.
+/// time is reached. When that occurs, the `notifications` will get posted to
+/// this task, and the timer will be disabled.
 ///
 /// If the deadline is chosen such that the timer *would have already fired*,
 /// had it been set earlier -- that is, if the deadline is `<=` the current time
 /// -- the `notifications` will be posted immediately and the timer will not be
 /// enabled.
 #[inline(always)]
-pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
-    let raw_deadline = deadline.unwrap_or(0);
+pub fn sys_set_timer(deadline: Option<time::Instant>, notifications: u32) {
+    let raw_deadline = deadline.map_or(0, time::Instant::as_ticks);
     unsafe {
         sys_set_timer_stub(
             deadline.is_some() as u32,
@@ -544,6 +686,7 @@ pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
 /// Core implementation of the SET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_set_timer_stub(
     _set_timer: u32,
@@ -632,6 +775,7 @@ pub fn sys_borrow_read(
 /// Core implementation of the BORROW_READ syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_borrow_read_stub(_args: *mut BorrowReadArgs) -> RcLen {
     cfg_if::cfg_if! {
@@ -727,6 +871,7 @@ pub fn sys_borrow_write(
 /// Core implementation of the BORROW_WRITE syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_borrow_write_stub(
     _args: *mut BorrowWriteArgs,
@@ -844,6 +989,7 @@ pub struct BorrowInfo {
 /// Core implementation of the BORROW_INFO syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_borrow_info_stub(
     _lender: u32,
@@ -920,6 +1066,7 @@ pub fn sys_irq_control(mask: u32, enable: bool) {
 /// Core implementation of the IRQ_CONTROL syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_irq_control_stub(_mask: u32, _enable: u32) {
     cfg_if::cfg_if! {
@@ -987,6 +1134,7 @@ pub fn sys_panic(msg: &[u8]) -> ! {
 /// Core implementation of the PANIC syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
     cfg_if::cfg_if! {
@@ -1044,7 +1192,8 @@ unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
 ///
 /// This returns three values in a `TimerState` struct:
 ///
-/// - `now` is the current time on the timer, in ticks since boot.
+/// - `now` is the current time on the timer, in ticks since boot. (Use
+///   `time::Instant::now` to get it with units attached.)
 /// - `deadline` is either `None`, meaning the timer notifications are disabled,
 ///   or `Some(t)`, meaning the timer will post notifications at time `t`.
 /// - `on_dl` are the notification bits that will be posted on deadline.
@@ -1097,6 +1246,7 @@ struct RawTimerState {
 /// Core implementation of the GET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
     cfg_if::cfg_if! {
@@ -1164,6 +1314,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
 #[doc(hidden)]
 #[no_mangle]
 #[link_section = ".text.start"]
+#[cfg(target_os = "none")]
 #[naked]
 pub unsafe extern "C" fn _start() -> ! {
     // Provided by the user program:
@@ -1294,7 +1445,7 @@ pub unsafe extern "C" fn _start() -> ! {
 /// task, to ensure that memory is available for the panic message, even if the
 /// resources have been trimmed aggressively using `xtask sizes` and `humility
 /// stackmargin`.
-#[cfg(feature = "panic-messages")]
+#[cfg(all(target_os = "none", feature = "panic-messages"))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
     // Implementation Note
@@ -1441,7 +1592,7 @@ fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
 /// Panic handler for tasks without the `panic-messages` feature enabled. This
 /// kills the task with a fixed message, `"PANIC"`. While this is less helpful
 /// than a proper panic message, the stack trace can still be informative.
-#[cfg(not(feature = "panic-messages"))]
+#[cfg(all(target_os = "none", not(feature = "panic-messages")))]
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo<'_>) -> ! {
     sys_panic(b"PANIC")
@@ -1456,6 +1607,7 @@ pub fn sys_refresh_task_id(task_id: TaskId) -> TaskId {
 /// Core implementation of the REFRESH_TASK_ID syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1525,6 +1677,7 @@ pub fn sys_post(task_id: TaskId, bits: u32) -> u32 {
 /// Core implementation of the POST syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1595,6 +1748,7 @@ pub fn sys_reply_fault(task_id: TaskId, reason: ReplyFaultReason) {
 /// Core implementation of the REPLY_FAULT syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_os = "none")]
 #[naked]
 unsafe extern "C" fn sys_reply_fault_stub(_tid: u32, _reason: u32) {
     cfg_if::cfg_if! {
@@ -6,7 +6,19 @@ pub use bstringify;
 pub use paste;
 
 cfg_if::cfg_if! {
-    if #[cfg(feature = "log-itm")] {
+    if #[cfg(not(target_os = "none"))] {
+        // Hosted builds, under the kernel simulator, log to its stdout
+        // regardless of the features selected for hardware.
+        #[macro_export]
+        macro_rules! sys_log {
+            ($s:expr) => {
+                $crate::sim_println(format_args!($s))
+            };
+            ($s:expr, $($tt:tt)*) => {
+                $crate::sim_println(format_args!($s, $($tt)*))
+            };
+        }
+    } else if #[cfg(feature = "log-itm")] {
         #[macro_export]
         macro_rules! sys_log {
             ($s:expr) => {
@@ -67,22 +79,36 @@ cfg_if::cfg_if! {
     }
 }
 
-#[macro_export]
-macro_rules! task_slot {
-    ($var:ident, $task_name:ident) => {
-        $crate::macros::paste::paste! {
-            #[used]
-            static $var: $crate::task_slot::TaskSlot =
-                $crate::task_slot::TaskSlot::UNBOUND;
+cfg_if::cfg_if! {
+    if #[cfg(not(target_os = "none"))] {
+        // The simulator has no post-link step to bind task slots, so they
+        // carry the name of their task, and look it up on first use.
+        #[macro_export]
+        macro_rules! task_slot {
+            ($var:ident, $task_name:ident) => {
+                static $var: $crate::task_slot::TaskSlot =
+                    $crate::task_slot::TaskSlot::named(stringify!($task_name));
+            };
+        }
+    } else {
+        #[macro_export]
+        macro_rules! task_slot {
+            ($var:ident, $task_name:ident) => {
+                $crate::macros::paste::paste! {
+                    #[used]
+                    static $var: $crate::task_slot::TaskSlot =
+                        $crate::task_slot::TaskSlot::UNBOUND;
 
-            #[used]
-            #[link_section = ".task_slot_table"]
-            static [< _TASK_SLOT_TABLE_ $var >]: $crate::task_slot::TaskSlotTableEntry<
-                { $crate::macros::bstringify::bstringify!($task_name).len() },
-            > = $crate::task_slot::TaskSlotTableEntry::for_task_slot(
-                $crate::macros::bstringify::bstringify!($task_name),
-                &$var,
-            );
+                    #[used]
+                    #[link_section = ".task_slot_table"]
+                    static [< _TASK_SLOT_TABLE_ $var >]: $crate::task_slot::TaskSlotTableEntry<
+                        { $crate::macros::bstringify::bstringify!($task_name).len() },
+                    > = $crate::task_slot::TaskSlotTableEntry::for_task_slot(
+                        $crate::macros::bstringify::bstringify!($task_name),
+                        &$var,
+                    );
+                }
+            };
         }
-    };
+    }
 }
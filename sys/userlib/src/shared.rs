@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Memory shared between tasks.
+//!
+//! The `[shared]` section of `app.toml` declares buffers in RAM that are mapped
+//! into several tasks at once, read-only or read-write for each. Tasks can hand
+//! data around through them without the copy that leasing memory over IPC
+//! implies:
+//!
+//! ```toml
+//! [shared.net-tx]
+//! size = 4096
+//! tasks = {net = "read-only", udpbroadcast = "read-write"}
+//! ```
+//!
+//! A task gets at its regions through code generated by
+//! `build_util::generate_shared_regions()`, which has a function per region
+//! returning a `SharedRegion`:
+//!
+//! ```ignore
+//! mod shared_regions {
+//!     include!(concat!(env!("OUT_DIR"), "/shared_regions.rs"));
+//! }
+//!
+//! let tx = shared_regions::net_tx();
+//! ```
+//!
+//! The kernel only controls who can map a region. It does nothing to stop two
+//! tasks using it at the same time, so tasks sharing a region need to agree,
+//! usually over IPC, on who may touch which part of it when.
+
+/// A memory region shared with other tasks.
+#[derive(Copy, Clone, Debug)]
+pub struct SharedRegion {
+    base: usize,
+    end: usize,
+    writable: bool,
+}
+
+impl SharedRegion {
+    /// Describes the region from `base` to `end`.
+    ///
+    /// # Safety
+    ///
+    /// The kernel must map `base..end` into this task for reading, and for
+    /// writing too if `writable` is set. The generated accessors take care of
+    /// this; there's no reason to call this otherwise.
+    pub const unsafe fn from_raw(
+        base: usize,
+        end: usize,
+        writable: bool,
+    ) -> Self {
+        Self {
+            base,
+            end,
+            writable,
+        }
+    }
+
+    /// Returns a pointer to the start of the region.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base as *mut u8
+    }
+
+    /// Returns the size of the region, in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.base
+    }
+
+    /// Checks whether the region is empty, which it never is.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks whether this task can write to the region.
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Borrows the region's contents.
+    ///
+    /// # Safety
+    ///
+    /// No task may write to the region while the returned slice is in use.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        // Safety: the region is mapped for reading, per `from_raw`'s contract,
+        // and our caller promises that nobody's writing to it.
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    /// Borrows the region's contents for writing.
+    ///
+    /// # Safety
+    ///
+    /// No other task may access the region, and no other slice of it may be in
+    /// use in this task, while the returned slice is in use.
+    ///
+    /// # Panics
+    ///
+    /// If this task can only read the region.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn as_mut_slice(&self) -> &mut [u8] {
+        assert!(self.writable);
+        // Safety: the region is mapped for writing, per `from_raw`'s contract
+        // and the check above, and our caller promises exclusive access.
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.len()) }
+    }
+}
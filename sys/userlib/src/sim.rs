@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Syscall stubs for hosted builds, running under the kernel simulator.
+//!
+//! Each stub here has the same signature as its assembly counterpart in the
+//! crate root, and loads the same values into the same (simulated) registers:
+//! `regs[0]` through `regs[6]` correspond to r4 through r10 on ARM, and `extra`
+//! to the r0-r1 used by the few syscalls with more arguments. This keeps
+//! the syscall ABI in one place -- the kernel's `ArchState` decoders -- rather
+//! than growing a second one for simulation.
+
+use super::*;
+
+// Provided by the kernel's `arch::sim` module.
+extern "Rust" {
+    fn hubris_sim_syscall(nr: u32, regs: &mut [u32; 7], extra: [u32; 2]);
+    fn hubris_sim_print(args: core::fmt::Arguments<'_>);
+    fn hubris_sim_task_index(name: &str) -> Option<u16>;
+}
+
+/// Writes a line to the simulator's standard output. This stands in for the
+/// debug channels (ITM, semihosting) that tasks write to on hardware, and is
+/// what `sys_log!` uses in hosted builds.
+pub fn sim_println(args: core::fmt::Arguments<'_>) {
+    // Safety: the simulator's print routine has no preconditions.
+    unsafe { hubris_sim_print(args) }
+}
+
+/// Looks up the index of the task named `name`, standing in for the task slot
+/// binding that the build system does on hardware.
+pub(crate) fn task_index(name: &str) -> Option<u16> {
+    // Safety: the simulator's lookup routine has no preconditions.
+    unsafe { hubris_sim_task_index(name) }
+}
+
+/// Converts a pointer to `len` items into the 32-bit form used by the syscall
+/// ABI.
+///
+/// # Panics
+///
+/// If the memory is not addressable by a task. In the simulator, this usually
+/// means that a buffer being passed to the kernel is not on the task's stack.
+fn addr<T>(p: *const T, len: usize) -> u32 {
+    match u32::try_from(p as usize) {
+        Ok(a) => a,
+        // Empty slices confer no authority, and the kernel ignores their
+        // address (which for a literal like `&[]` is often bogus anyway), so
+        // just preserve alignment.
+        Err(_) if len == 0 => p as usize as u32,
+        Err(_) => panic!("{:p} is outside the simulated address space", p),
+    }
+}
+
+fn syscall(nr: Sysnum, regs: [u32; 7]) -> [u32; 7] {
+    syscall_extra(nr, regs, [0; 2])
+}
+
+fn syscall_extra(nr: Sysnum, mut regs: [u32; 7], extra: [u32; 2]) -> [u32; 7] {
+    // Safety: we're a task thread (or the kernel will tell us otherwise), and
+    // the kernel validates everything in `regs` and `extra`.
+    unsafe {
+        hubris_sim_syscall(nr as u32, &mut regs, extra);
+    }
+    regs
+}
+
+fn rc_len(regs: [u32; 7]) -> RcLen {
+    RcLen(u64::from(regs[0]) | u64::from(regs[1]) << 32)
+}
+
+pub(crate) unsafe fn sys_send_stub(args: &mut SendArgs<'_>) -> RcLen {
+    rc_len(syscall(
+        Sysnum::Send,
+        [
+            args.packed_target_operation,
+            addr(args.outgoing_ptr, args.outgoing_len),
+            args.outgoing_len as u32,
+            addr(args.incoming_ptr, args.incoming_len),
+            args.incoming_len as u32,
+            addr(args.lease_ptr, args.lease_len),
+            args.lease_len as u32,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_send_with_deadline_stub(
+    args: &mut SendWithDeadlineArgs<'_>,
+) -> RcLen {
+    let send = &args.send;
+    rc_len(syscall_extra(
+        Sysnum::SendWithDeadline,
+        [
+            send.packed_target_operation,
+            addr(send.outgoing_ptr, send.outgoing_len),
+            send.outgoing_len as u32,
+            addr(send.incoming_ptr, send.incoming_len),
+            send.incoming_len as u32,
+            addr(send.lease_ptr, send.lease_len),
+            send.lease_len as u32,
+        ],
+        [args.deadline_lo, args.deadline_hi],
+    ))
+}
+
+pub(crate) unsafe fn sys_recv_stub(
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+    notification_mask: u32,
+    specific_sender: u32,
+    out: *mut RawRecvMessage,
+) -> u32 {
+    let regs = syscall(
+        Sysnum::Recv,
+        [
+            addr(buffer_ptr, buffer_len),
+            buffer_len as u32,
+            notification_mask,
+            specific_sender,
+            0,
+            0,
+            0,
+        ],
+    );
+    // Safety: our caller passes a pointer to an output struct, which we fully
+    // initialize.
+    unsafe {
+        out.write(RawRecvMessage {
+            sender: regs[1],
+            operation: regs[2],
+            message_len: regs[3] as usize,
+            response_capacity: regs[4] as usize,
+            lease_count: regs[5] as usize,
+        });
+    }
+    regs[0]
+}
+
+pub(crate) unsafe fn sys_reply_stub(
+    peer: u32,
+    code: u32,
+    message_ptr: *const u8,
+    message_len: usize,
+) {
+    syscall(
+        Sysnum::Reply,
+        [
+            peer,
+            code,
+            addr(message_ptr, message_len),
+            message_len as u32,
+            0,
+            0,
+            0,
+        ],
+    );
+}
+
+pub(crate) unsafe fn sys_set_timer_stub(
+    set_timer: u32,
+    deadline_lo: u32,
+    deadline_hi: u32,
+    notification: u32,
+) {
+    syscall(
+        Sysnum::SetTimer,
+        [set_timer, deadline_lo, deadline_hi, notification, 0, 0, 0],
+    );
+}
+
+pub(crate) unsafe fn sys_borrow_read_stub(args: *mut BorrowReadArgs) -> RcLen {
+    // Safety: our caller passes a valid argument struct.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowRead,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            addr(args.dest, args.dest_len),
+            args.dest_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_borrow_write_stub(
+    args: *mut BorrowWriteArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument struct.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowWrite,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            addr(args.src, args.src_len),
+            args.src_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_borrow_info_stub(
+    lender: u32,
+    index: usize,
+    out: *mut RawBorrowInfo,
+) {
+    let regs =
+        syscall(Sysnum::BorrowInfo, [lender, index as u32, 0, 0, 0, 0, 0]);
+    // Safety: our caller passes a pointer to an output struct, which we fully
+    // initialize.
+    unsafe {
+        out.write(RawBorrowInfo {
+            rc: regs[0],
+            atts: regs[1],
+            length: regs[2] as usize,
+        });
+    }
+}
+
+pub(crate) unsafe fn sys_irq_control_stub(mask: u32, enable: u32) {
+    syscall(Sysnum::IrqControl, [mask, enable, 0, 0, 0, 0, 0]);
+}
+
+pub(crate) unsafe fn sys_panic_stub(msg: *const u8, len: usize) -> ! {
+    syscall(Sysnum::Panic, [addr(msg, len), len as u32, 0, 0, 0, 0, 0]);
+    // The kernel doesn't schedule a panicked task again until it's restarted,
+    // which unwinds this thread instead of returning here.
+    unreachable!()
+}
+
+pub(crate) unsafe fn sys_get_timer_stub(out: *mut RawTimerState) {
+    let regs = syscall(Sysnum::GetTimer, [0; 7]);
+    // Safety: our caller passes a pointer to an output struct, which we fully
+    // initialize.
+    unsafe {
+        out.write(RawTimerState {
+            now_lo: regs[0],
+            now_hi: regs[1],
+            set: regs[2],
+            dl_lo: regs[3],
+            dl_hi: regs[4],
+            on_dl: regs[5],
+        });
+    }
+}
+
+pub(crate) unsafe fn sys_refresh_task_id_stub(tid: u32) -> u32 {
+    syscall(Sysnum::RefreshTaskId, [tid, 0, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe fn sys_post_stub(tid: u32, mask: u32) -> u32 {
+    syscall(Sysnum::Post, [tid, mask, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe fn sys_reply_fault_stub(tid: u32, reason: u32) {
+    syscall(Sysnum::ReplyFault, [tid, reason, 0, 0, 0, 0, 0]);
+}
@@ -58,14 +58,30 @@ mod volatile_const {
 /// are used to create compile-time placeholders that are filled in with a
 /// task's identifying information by a post-compile process.  These
 /// placeholders can then be converted into TaskId at runtime.
+///
+/// In hosted builds there's no post-compile step, so a slot instead records
+/// the name of its task, and the simulator resolves it at runtime.
 #[repr(C)]
-pub struct TaskSlot(VolatileConst<u16>);
+pub struct TaskSlot(
+    VolatileConst<u16>,
+    #[cfg(not(target_os = "none"))] &'static str,
+);
 
 impl TaskSlot {
     /// A TaskSlot that has not been resolved by a later processing step.
     ///
     /// Calling get_task_id() on an unbound TaskSlot will panic.
-    pub const UNBOUND: Self = Self(VolatileConst::new(TaskId::UNBOUND.0));
+    pub const UNBOUND: Self = Self(
+        VolatileConst::new(TaskId::UNBOUND.0),
+        #[cfg(not(target_os = "none"))]
+        "",
+    );
+
+    /// A TaskSlot bound to the task named `name` in the simulated image.
+    #[cfg(not(target_os = "none"))]
+    pub const fn named(name: &'static str) -> Self {
+        Self(VolatileConst::new(TaskId::UNBOUND.0), name)
+    }
 
     pub fn get_task_id(&self) -> TaskId {
         let task_index = self.get_task_index();
@@ -80,6 +96,10 @@ impl TaskSlot {
     }
 
     pub fn get_task_index(&self) -> u16 {
+        #[cfg(not(target_os = "none"))]
+        if let Some(index) = crate::sim::task_index(self.1) {
+            return index;
+        }
         self.0.get()
     }
 }
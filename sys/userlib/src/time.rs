@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel time, with units.
+//!
+//! The kernel counts time in ticks since boot, and the application decides how
+//! long a tick is (`tick-hz` in the `[kernel]` section of `app.toml`, 1000 by
+//! default). `Instant` and `Duration` keep track of which numbers are in ticks,
+//! and convert to and from real-world units at the rate the image was built
+//! with, so that `Duration::from_millis(100)` is 100 ms whatever the tick rate.
+//!
+//! Conversions into ticks round up, so that a timeout or sleep is never
+//! shorter than asked for; conversions out of ticks round down. The arithmetic
+//! is in the `tick-convert` crate.
+
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+include!(concat!(env!("OUT_DIR"), "/tick_hz.rs"));
+
+/// Converts `n` units of `1 / per_sec` seconds into ticks.
+const fn to_ticks(n: u64, per_sec: u64) -> u64 {
+    tick_convert::to_ticks(n, per_sec, TICK_HZ as u64)
+}
+
+/// Converts `ticks` into units of `1 / per_sec` seconds.
+const fn from_ticks(ticks: u64, per_sec: u64) -> u64 {
+    tick_convert::from_ticks(ticks, per_sec, TICK_HZ as u64)
+}
+
+/// A span of kernel time.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const ZERO: Self = Self(0);
+
+    /// The longest representable duration, which is as good as forever.
+    pub const MAX: Self = Self(u64::MAX);
+
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(to_ticks(secs, 1))
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(to_ticks(millis, 1_000))
+    }
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(to_ticks(micros, 1_000_000))
+    }
+
+    pub const fn as_ticks(self) -> u64 {
+        self.0
+    }
+
+    pub const fn as_secs(self) -> u64 {
+        from_ticks(self.0, 1)
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        from_ticks(self.0, 1_000)
+    }
+
+    pub const fn as_micros(self) -> u64 {
+        from_ticks(self.0, 1_000_000)
+    }
+
+    pub const fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    pub const fn saturating_mul(self, n: u64) -> Self {
+        Self(self.0.saturating_mul(n))
+    }
+}
+
+impl Add for Duration {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.saturating_add(other)
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.saturating_sub(other)
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+/// A moment in kernel time, measured from boot.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// Boot time.
+    pub const ZERO: Self = Self(0);
+
+    /// Reads the current kernel time.
+    pub fn now() -> Self {
+        Self(crate::sys_get_timer().now)
+    }
+
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// Returns the number of ticks since boot.
+    pub const fn as_ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time elapsed from boot to `self`.
+    pub const fn since_boot(self) -> Duration {
+        Duration(self.0)
+    }
+
+    /// Returns the time elapsed from `earlier` to `self`, or zero if `earlier`
+    /// is actually later.
+    pub const fn saturating_duration_since(self, earlier: Self) -> Duration {
+        Duration(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time elapsed between `self` and now.
+    pub fn elapsed(self) -> Duration {
+        Self::now() - self
+    }
+}
+
+/// Adding a duration saturates, so that `Duration::MAX` from now is never.
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    fn add(self, d: Duration) -> Self {
+        Self(self.0.saturating_add(d.0))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, d: Duration) {
+        *self = *self + d;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    fn sub(self, d: Duration) -> Self {
+        Self(self.0.saturating_sub(d.0))
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, d: Duration) {
+        *self = *self - d;
+    }
+}
+
+/// Subtracting instants saturates at zero, like `saturating_duration_since`.
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
@@ -18,6 +18,7 @@ use task_net_api::{
     Address, LargePayloadBehavior, Net, RecvError, SendError, SocketName,
     UdpMetadata,
 };
+use userlib::time::Instant;
 use userlib::{sys_set_timer, task_slot};
 
 mod inventory;
@@ -144,7 +145,7 @@ impl ServerImpl {
         }
     }
 
-    fn timer_deadline(&self) -> Option<u64> {
+    fn timer_deadline(&self) -> Option<Instant> {
         self.mgs_handler.timer_deadline()
     }
 }
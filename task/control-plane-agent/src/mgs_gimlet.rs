@@ -24,7 +24,8 @@ use idol_runtime::{Leased, RequestError};
 use ringbuf::ringbuf_entry_root;
 use task_control_plane_agent_api::ControlPlaneAgentError;
 use task_net_api::{Address, UdpMetadata};
-use userlib::{sys_get_timer, sys_irq_control, UnwrapLite};
+use userlib::time::{Duration, Instant};
+use userlib::{sys_irq_control, UnwrapLite};
 
 // We're included under a special `path` cfg from main.rs, which confuses rustc
 // about where our submodules live. Pass explicit paths to correct it.
@@ -66,7 +67,7 @@ const SP_TO_MGS_SERIAL_CONSOLE_BUFFER_SIZE: usize =
 
 /// Send any buffered serial console data to MGS when our oldest buffered byte
 /// is this old, even if our buffer isn't full yet.
-const SERIAL_CONSOLE_FLUSH_TIMEOUT_MILLIS: u64 = 500;
+const SERIAL_CONSOLE_FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
 
 userlib::task_slot!(HOST_FLASH, hf);
 userlib::task_slot!(GIMLET_SEQ, gimlet_seq);
@@ -111,7 +112,7 @@ impl MgsHandler {
     /// If we want to be woken by the system timer, we return a deadline here.
     /// `main()` is responsible for calling this method and actually setting the
     /// timer.
-    pub(crate) fn timer_deadline(&self) -> Option<u64> {
+    pub(crate) fn timer_deadline(&self) -> Option<Instant> {
         // If we're trying to prep for a host flash update, we have sectors that
         // need to be erased, but we break that work up across multiple steps to
         // avoid blocking while the entire erase happens. If we're in that case,
@@ -120,7 +121,7 @@ impl MgsHandler {
         if self.host_flash_update.is_preparing()
             || self.sp_update.is_preparing()
         {
-            Some(sys_get_timer().now + 1)
+            Some(Instant::now() + Duration::from_ticks(1))
         } else {
             match (
                 self.usart.from_rx_flush_deadline,
@@ -657,7 +658,7 @@ struct UsartHandler {
     usart: Usart,
     to_tx: &'static mut Deque<u8, MGS_TO_SP_SERIAL_CONSOLE_BUFFER_SIZE>,
     from_rx: &'static mut Deque<u8, SP_TO_MGS_SERIAL_CONSOLE_BUFFER_SIZE>,
-    from_rx_flush_deadline: Option<u64>,
+    from_rx_flush_deadline: Option<Instant>,
     from_rx_offset: u64,
 }
 
@@ -704,7 +705,7 @@ impl UsartHandler {
 
         // Otherwise, only flush if we're past our deadline.
         self.from_rx_flush_deadline
-            .map(|deadline| sys_get_timer().now >= deadline)
+            .map(|deadline| Instant::now() >= deadline)
             .unwrap_or(false)
     }
 
@@ -728,8 +729,7 @@ impl UsartHandler {
     fn set_from_rx_flush_deadline(&mut self) {
         assert!(self.from_rx_flush_deadline.is_none());
         assert!(!self.from_rx.is_empty());
-        let deadline =
-            sys_get_timer().now + SERIAL_CONSOLE_FLUSH_TIMEOUT_MILLIS;
+        let deadline = Instant::now() + SERIAL_CONSOLE_FLUSH_TIMEOUT;
         self.from_rx_flush_deadline = Some(deadline);
     }
 
@@ -9,7 +9,8 @@ use heapless::Vec;
 use idol_runtime::{Leased, RequestError};
 use task_control_plane_agent_api::ControlPlaneAgentError;
 use task_net_api::{Address, Ipv6Address, UdpMetadata};
-use userlib::{sys_get_timer, sys_post, TaskId, UnwrapLite};
+use userlib::time::{Duration, Instant};
+use userlib::{sys_post, TaskId, UnwrapLite};
 
 const SP_TO_MGS_MULTICAST_ADDR: Address = Address::Ipv6(Ipv6Address([
     0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xde, 0, 1,
@@ -51,8 +52,8 @@ const MGS_UDP_PORT: u16 = 22222;
 //    results in a call to `get_data()` below.
 // 6. `host-sp-comms` relays the data (or failure) back to the host over the
 //    uart.
-const DELAY_TRY_OTHER_MGS: u64 = 500;
-const DELAY_RETRY: u64 = 1_000;
+const DELAY_TRY_OTHER_MGS: Duration = Duration::from_millis(500);
+const DELAY_RETRY: Duration = Duration::from_millis(1_000);
 const MAX_ATTEMPTS: u8 = 6;
 
 pub(crate) struct HostPhase2Requester {
@@ -90,7 +91,7 @@ impl HostPhase2Requester {
         self.buffer.clear();
     }
 
-    pub(crate) fn timer_deadline(&self) -> Option<u64> {
+    pub(crate) fn timer_deadline(&self) -> Option<Instant> {
         self.current
             .as_ref()
             .and_then(|current| current.state.timer_deadline())
@@ -110,7 +111,7 @@ impl HostPhase2Requester {
     ) -> Option<UdpMetadata> {
         let current = self.current.as_mut()?;
 
-        let now = sys_get_timer().now;
+        let now = Instant::now();
 
         // Are we in a state where we should send a packet? If so, extract which
         // port we should use, and update `current.state` assuming that packet
@@ -284,8 +285,8 @@ impl CurrentRequest {
 
 enum State {
     NeedToSendFirstMgs(SpPort),
-    WaitingForFirstMgs { port: SpPort, deadline: u64 },
-    WaitingForSecondMgs { port: SpPort, deadline: u64 },
+    WaitingForFirstMgs { port: SpPort, deadline: Instant },
+    WaitingForSecondMgs { port: SpPort, deadline: Instant },
     Fetched,
 }
 
@@ -298,7 +299,7 @@ impl State {
             State::Fetched => None,
             State::WaitingForFirstMgs { deadline, port }
             | State::WaitingForSecondMgs { deadline, port } => {
-                if sys_get_timer().now >= *deadline {
+                if Instant::now() >= *deadline {
                     Some(*port)
                 } else {
                     None
@@ -307,9 +308,9 @@ impl State {
         }
     }
 
-    fn timer_deadline(&self) -> Option<u64> {
+    fn timer_deadline(&self) -> Option<Instant> {
         match self {
-            State::NeedToSendFirstMgs(_) => Some(sys_get_timer().now),
+            State::NeedToSendFirstMgs(_) => Some(Instant::now()),
             State::Fetched => None,
             State::WaitingForFirstMgs { deadline, .. }
             | State::WaitingForSecondMgs { deadline, .. } => Some(*deadline),
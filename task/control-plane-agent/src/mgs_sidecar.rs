@@ -16,7 +16,7 @@ use idol_runtime::{Leased, RequestError};
 use ringbuf::ringbuf_entry_root;
 use task_control_plane_agent_api::ControlPlaneAgentError;
 use task_net_api::UdpMetadata;
-use userlib::sys_get_timer;
+use userlib::time::{Duration, Instant};
 
 userlib::task_slot!(SIDECAR_SEQ, sequencer);
 
@@ -57,9 +57,9 @@ impl MgsHandler {
     /// If we want to be woken by the system timer, we return a deadline here.
     /// `main()` is responsible for calling this method and actually setting the
     /// timer.
-    pub(crate) fn timer_deadline(&self) -> Option<u64> {
+    pub(crate) fn timer_deadline(&self) -> Option<Instant> {
         if self.sp_update.is_preparing() {
-            Some(sys_get_timer().now + 1)
+            Some(Instant::now() + Duration::from_ticks(1))
         } else {
             None
         }
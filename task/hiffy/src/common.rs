@@ -4,8 +4,9 @@
 
 use hif::{Failure, Fault};
 use hubris_num_tasks::NUM_TASKS;
-#[allow(unused_imports)]
 use userlib::task_slot;
+#[allow(unused_imports)]
+use userlib::time::Duration;
 #[cfg(any(feature = "sprot", feature = "update"))]
 use userlib::FromPrimitive;
 use userlib::{sys_refresh_task_id, sys_send, Generation, TaskId};
@@ -46,7 +47,7 @@ pub(crate) fn sleep(
         _ => Err(Failure::Fault(Fault::BadParameter(0))),
     }?;
 
-    userlib::hl::sleep_for(ms.into());
+    userlib::hl::sleep_for(Duration::from_millis(ms.into()));
 
     Ok(0)
 }
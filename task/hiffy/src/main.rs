@@ -17,6 +17,7 @@
 
 use core::sync::atomic::{AtomicU32, Ordering};
 use hif::*;
+use userlib::time::Duration;
 use userlib::{util::StaticCell, *};
 
 #[cfg(armv6m)]
@@ -135,7 +136,7 @@ fn main() -> ! {
 
     loop {
         HIFFY_READY.fetch_add(1, Ordering::SeqCst);
-        hl::sleep_for(sleep_ms);
+        hl::sleep_for(Duration::from_millis(sleep_ms));
         HIFFY_READY.fetch_sub(1, Ordering::SeqCst);
 
         if HIFFY_KICK.load(Ordering::SeqCst) == 0 {
@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
+    build_util::generate_notifications()?;
 
     idol::server::build_server_support(
         "../../idl/host-sp-comms.idol",
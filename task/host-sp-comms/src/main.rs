@@ -23,6 +23,7 @@ use multitimer::{Multitimer, Repeat};
 use ringbuf::{ringbuf, ringbuf_entry};
 use task_control_plane_agent_api::ControlPlaneAgent;
 use task_host_sp_comms_api::HostSpCommsError;
+use userlib::time::{Duration, Instant};
 use userlib::{hl, sys_get_timer, sys_irq_control, task_slot, UnwrapLite};
 
 mod tx_buf;
@@ -38,12 +39,12 @@ task_slot!(SYS, sys);
 // to decay. We ought to do this properly by monitoring the rails, but for now,
 // we'll simply wait a fixed period of time. This time is a WAG - we should
 // fix this!
-const A2_REBOOT_DELAY: u64 = 5_000;
+const A2_REBOOT_DELAY: Duration = Duration::from_millis(5_000);
 
 // How frequently should we try to send 0x00 bytes to the host? This only
 // applies if our current tx_buf/rx_buf are empty (i.e., we don't have a real
 // response to send, and we haven't yet started to receive a request).
-const UART_ZERO_DELAY: u64 = 200;
+const UART_ZERO_DELAY: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Trace {
@@ -68,22 +69,6 @@ enum TimerDisposition {
 
 ringbuf!(Trace, 64, Trace::None);
 
-/// Notification bit for USART IRQ; must match configuration in app.toml.
-const USART_IRQ: u32 = 1 << 0;
-
-/// Notification bit for Jefe notifying us of state changes; must match Jefe's
-/// `on-state-change` config for us in app.toml.
-const JEFE_STATE_CHANGE_IRQ: u32 = 1 << 1;
-
-/// Notification bit for the timer we set for ourselves.
-const TIMER_IRQ_BIT: u8 = 2;
-const TIMER_IRQ: u32 = 1 << TIMER_IRQ_BIT;
-
-/// Notification bit for control-plane-agent to tell us the phase 2 data the
-/// host wants from MGS has arrived.
-const CONTROL_PLANE_AGENT_IRQ_BIT: u8 = 3;
-const CONTROL_PLANE_AGENT_IRQ: u32 = 1 << CONTROL_PLANE_AGENT_IRQ_BIT;
-
 /// We set the high bit of the sequence number before replying to host requests.
 const SEQ_REPLY: u64 = 0x8000_0000_0000_0000;
 
@@ -107,7 +92,7 @@ fn main() -> ! {
     // Set our restarted status, which interrupts the host to let them know.
     server.set_status_impl(Status::SP_TASK_RESTARTED);
 
-    sys_irq_control(USART_IRQ, true);
+    sys_irq_control(notifications::USART_MASK, true);
 
     let mut buffer = [0; idl::INCOMING_SIZE];
     loop {
@@ -145,10 +130,10 @@ impl ServerImpl {
         let uart = configure_uart_device(&sys);
         sp_to_sp3_interrupt_enable(&sys);
 
-        let mut timers = Multitimer::new(TIMER_IRQ_BIT);
+        let mut timers = Multitimer::new(notifications::TIMER_BIT);
         timers.set_timer(
             Timers::TxPeriodicZeroByte,
-            sys_get_timer().now,
+            Instant::now(),
             Some(Repeat::AfterWake(UART_ZERO_DELAY)),
         );
 
@@ -246,7 +231,7 @@ impl ServerImpl {
                         // reboot; set our reboot timer.
                         self.timers.set_timer(
                             Timers::WaitingInA2ToReboot,
-                            sys_get_timer().now + A2_REBOOT_DELAY,
+                            Instant::now() + A2_REBOOT_DELAY,
                             None,
                         );
                         self.reboot_state =
@@ -257,7 +242,7 @@ impl ServerImpl {
 
                 // A1 should be transitory; sleep then retry.
                 PowerState::A1 => {
-                    hl::sleep_for(1);
+                    hl::sleep_for(Duration::from_millis(1));
                     continue;
                 }
             }
@@ -265,8 +250,11 @@ impl ServerImpl {
     }
 
     fn handle_jefe_notification(&mut self, state: PowerState) {
-        let now = sys_get_timer().now;
-        ringbuf_entry!(Trace::JefeNotification { now, state });
+        let now = Instant::now();
+        ringbuf_entry!(Trace::JefeNotification {
+            now: now.as_ticks(),
+            state
+        });
         // If we're rebooting and jefe has notified us that we're now in A2,
         // move to A0. Otherwise, ignore this notification.
         match state {
@@ -446,7 +434,7 @@ impl ServerImpl {
             if self.rx_buf.is_empty() {
                 self.timers.set_timer(
                     Timers::TxPeriodicZeroByte,
-                    sys_get_timer().now,
+                    Instant::now(),
                     Some(Repeat::AfterWake(UART_ZERO_DELAY)),
                 );
             } else {
@@ -654,7 +642,7 @@ impl ServerImpl {
                     .fetch_host_phase2_data(
                         hash,
                         offset,
-                        CONTROL_PLANE_AGENT_IRQ_BIT,
+                        notifications::CONTROL_PLANE_AGENT_BIT,
                     )
                     .unwrap_lite();
                 None
@@ -689,24 +677,27 @@ impl ServerImpl {
 
 impl NotificationHandler for ServerImpl {
     fn current_notification_mask(&self) -> u32 {
-        USART_IRQ | JEFE_STATE_CHANGE_IRQ | TIMER_IRQ | CONTROL_PLANE_AGENT_IRQ
+        notifications::USART_MASK
+            | notifications::JEFE_STATE_CHANGE_MASK
+            | notifications::TIMER_MASK
+            | notifications::CONTROL_PLANE_AGENT_MASK
     }
 
     fn handle_notification(&mut self, bits: u32) {
         ringbuf_entry!(Trace::Notification { bits });
 
-        if bits & USART_IRQ != 0 {
+        if bits & notifications::USART_MASK != 0 {
             self.handle_usart_notification();
-            sys_irq_control(USART_IRQ, true);
+            sys_irq_control(notifications::USART_MASK, true);
         }
 
-        if bits & JEFE_STATE_CHANGE_IRQ != 0 {
+        if bits & notifications::JEFE_STATE_CHANGE_MASK != 0 {
             self.handle_jefe_notification(
                 self.sequencer.get_state().unwrap_lite(),
             );
         }
 
-        if bits & CONTROL_PLANE_AGENT_IRQ != 0 {
+        if bits & notifications::CONTROL_PLANE_AGENT_MASK != 0 {
             self.handle_control_plane_agent_notification();
         }
 
@@ -979,6 +970,16 @@ fn claim_uart_rx_buf() -> &'static mut Vec<u8, MAX_PACKET_SIZE> {
     unsafe { &mut UART_RX_BUF }
 }
 
+// Our notifications, from app.toml:
+// - usart: the USART IRQ
+// - jefe-state-change: Jefe telling us the power state changed
+// - timer: the timer we set for ourselves
+// - control-plane-agent: control-plane-agent telling us the phase 2 data the
+//   host wants from MGS has arrived
+mod notifications {
+    include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
+}
+
 mod idl {
     use task_host_sp_comms_api::{HostSpCommsError, Status};
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
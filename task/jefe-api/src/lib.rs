@@ -6,6 +6,7 @@
 
 #![no_std]
 
+use derive_idol_err::IdolError;
 use serde::{Deserialize, Serialize};
 use userlib::*;
 
@@ -22,7 +23,51 @@ pub enum ResetReason {
     LowPowerSecurity,
     ExitStandby,
     Other(u32),
+    /// Jefe reset the system, or let the hardware watchdog do it, because the
+    /// task with this index missed its watchdog check-in.
+    TaskWatchdog(u16),
+    /// Jefe reset the system because the task with this index kept faulting
+    /// and its restart policy says to escalate.
+    CrashLoop(u16),
+    /// Jefe reset the system at the request of the task with this index.
+    Requested(u16),
     Unknown, // TODO remove and use `Option<ResetReason>` once we switch to hubpack
 }
 
+/// Jefe's restart bookkeeping for a single task.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct RestartStats {
+    /// Number of times jefe has restarted the task after a fault since boot.
+    pub restarts: u32,
+    /// Number of faults counted against the task's restart policy in its
+    /// current window. Always zero for tasks without a policy.
+    pub window_faults: u32,
+    /// Kernel time, in ticks, at which jefe will restart the task, if a
+    /// restart is being held off by backoff.
+    pub next_restart: Option<u64>,
+    /// Set if jefe decided the task was crash-looping and is holding it.
+    pub held: bool,
+}
+
+/// One entry in jefe's reset log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResetLogEntry {
+    /// Number of the boot this reset started, counting from when the log was
+    /// last started afresh (normally at power-on).
+    pub boot: u32,
+    /// How long the system had been up when it reset, in milliseconds, or
+    /// zero if not known. For resets jefe didn't start itself, this is only
+    /// as accurate as jefe's timer tick.
+    pub uptime: u64,
+    pub reason: ResetReason,
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+pub enum ResetLogError {
+    /// The log doesn't go back that far.
+    NoSuchEntry = 1,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::io::Write;
@@ -22,6 +22,7 @@ fn main() -> Result<()> {
     .unwrap();
 
     build_util::expose_m_profile();
+    build_util::generate_notifications()?;
 
     let out_dir = build_util::out_dir();
     let dest_path = out_dir.join("jefe_config.rs");
@@ -36,11 +37,168 @@ fn main() -> Result<()> {
         "pub(crate) const MAILING_LIST: [({}, u32); {}] = [",
         task, count
     )?;
+    let notifications = build_util::notifications()?;
     for (name, rec) in cfg.on_state_change {
-        writeln!(out, "    ({}::{}, 1 << {}),", task, name, rec.bit_number)?;
+        let bit = match (rec.bit_number, &rec.notification) {
+            (Some(bit), None) => bit,
+            (None, Some(n)) => *notifications
+                .get(&name)
+                .and_then(|bits| bits.get(n))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "on-state-change: task {name} has no notification {n}"
+                    )
+                })?,
+            _ => bail!(
+                "on-state-change for task {name} needs exactly one of \
+                 bit-number and notification"
+            ),
+        };
+        writeln!(out, "    ({}::{}, 1 << {}),", task, name, bit)?;
     }
     writeln!(out, "];")?;
 
+    match cfg.confirm_boot {
+        Some(rec) => {
+            let bit = *notifications
+                .get(&rec.task)
+                .and_then(|bits| bits.get(&rec.notification))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "confirm-boot: task {} has no notification {}",
+                        rec.task,
+                        rec.notification
+                    )
+                })?;
+            if rec.after_ms == 0 {
+                bail!("confirm-boot after-ms must be nonzero");
+            }
+            writeln!(
+                out,
+                "pub(crate) const CONFIRM_BOOT: Option<({}, u32, u64)> = \
+                    Some(({}::{}, 1 << {}, {}));",
+                task, task, rec.task, bit, rec.after_ms
+            )?;
+            writeln!(
+                out,
+                "pub(crate) const CONFIRM_BOOT_IGNORES: [{}; {}] = [",
+                task,
+                rec.ignore_faults.len()
+            )?;
+            for name in rec.ignore_faults {
+                writeln!(out, "    {}::{},", task, name)?;
+            }
+            writeln!(out, "];")?;
+        }
+        None => {
+            writeln!(
+                out,
+                "pub(crate) const CONFIRM_BOOT: Option<({}, u32, u64)> = None;",
+                task
+            )?;
+            writeln!(
+                out,
+                "pub(crate) const CONFIRM_BOOT_IGNORES: [{}; 0] = [];",
+                task
+            )?;
+        }
+    }
+
+    let watchdog = cfg.watchdog.unwrap_or_default();
+
+    // Longest timeout each hardware watchdog can be programmed for, given
+    // the clock jefe runs it from.
+    let hw_max_ms = if build_util::has_feature("iwdg-stm32f3")
+        || build_util::has_feature("iwdg-stm32f4")
+        || build_util::has_feature("iwdg-stm32g0")
+        || build_util::has_feature("iwdg-stm32h7")
+    {
+        Some(32_000)
+    } else if build_util::has_feature("wwdt-lpc55") {
+        Some(67_000)
+    } else {
+        None
+    };
+    match (hw_max_ms, watchdog.timeout_ms) {
+        (Some(max), Some(t)) if t > max => {
+            bail!("watchdog.timeout-ms is {t}, but the limit is {max}");
+        }
+        (Some(_), None) => {
+            bail!("hardware watchdog enabled without watchdog.timeout-ms");
+        }
+        (None, Some(_)) => {
+            bail!("watchdog.timeout-ms set without a hardware watchdog");
+        }
+        _ => (),
+    }
+    writeln!(
+        out,
+        "pub(crate) const WATCHDOG_TIMEOUT_MS: Option<u32> = {:?};",
+        watchdog.timeout_ms
+    )?;
+
+    writeln!(
+        out,
+        "pub(crate) const CHECK_INS: [({}, u64, crate::OnTimeout); {}] = [",
+        task,
+        watchdog.tasks.len()
+    )?;
+    for (name, rec) in watchdog.tasks {
+        if rec.deadline_ms == 0 {
+            bail!("watchdog deadline-ms for task {name} must be nonzero");
+        }
+        writeln!(
+            out,
+            "    ({}::{}, {}, crate::OnTimeout::{:?}),",
+            task, name, rec.deadline_ms, rec.on_timeout
+        )?;
+    }
+    writeln!(out, "];")?;
+
+    writeln!(
+        out,
+        "pub(crate) const RESTART_POLICIES: \
+            [({}, restart_policy::RestartPolicy); {}] = [",
+        task,
+        cfg.restart_policy.len()
+    )?;
+    for (name, rec) in cfg.restart_policy {
+        if rec.window_ms == 0 {
+            bail!("restart-policy window-ms for task {name} must be nonzero");
+        }
+        let max_backoff_ms = rec.max_backoff_ms.unwrap_or(rec.backoff_ms);
+        if max_backoff_ms < rec.backoff_ms {
+            bail!("restart-policy for {name} has max-backoff-ms < backoff-ms");
+        }
+        writeln!(
+            out,
+            "    ({}::{}, restart_policy::RestartPolicy {{ \
+                max_restarts: {}, \
+                window_ms: {}, \
+                backoff_ms: {}, \
+                max_backoff_ms: {}, \
+                on_crash_loop: restart_policy::OnCrashLoop::{:?} \
+            }}),",
+            task,
+            name,
+            rec.max_restarts,
+            rec.window_ms,
+            rec.backoff_ms,
+            max_backoff_ms,
+            rec.on_crash_loop,
+        )?;
+    }
+    writeln!(out, "];")?;
+
+    let reset_log_entries = cfg.reset_log_entries.unwrap_or(8);
+    if reset_log_entries == 0 {
+        bail!("reset-log-entries must be nonzero");
+    }
+    writeln!(
+        out,
+        "pub(crate) const RESET_LOG_ENTRIES: usize = {reset_log_entries};"
+    )?;
+
     Ok(())
 }
 
@@ -55,6 +213,69 @@ struct Config {
     /// Map of operation names to tasks allowed to call them.
     #[serde(default)]
     allowed_callers: BTreeMap<String, Vec<String>>,
+    /// Watchdog and task check-in settings. If omitted, jefe leaves the
+    /// hardware watchdog alone and doesn't expect any check-ins.
+    #[serde(default)]
+    watchdog: Option<Watchdog>,
+    /// Restart policies, as a map from task name to `RestartPolicy` record.
+    /// Tasks without one are restarted immediately every time they fault.
+    #[serde(default)]
+    restart_policy: BTreeMap<String, RestartPolicy>,
+    /// Who to tell once this image has shown that it works, so that they can
+    /// confirm it to the bootloader. If omitted, jefe tells nobody.
+    #[serde(default)]
+    confirm_boot: Option<ConfirmBoot>,
+    /// Number of resets remembered by the reset log, which costs 20 bytes of
+    /// RAM apiece (on top of 28 for the rest of the log). Defaults to 8.
+    reset_log_entries: Option<usize>,
+}
+
+/// Watchdog configuration.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Watchdog {
+    /// Hardware watchdog timeout in milliseconds. Required if (and only if)
+    /// jefe is built with one of its hardware watchdog features.
+    timeout_ms: Option<u32>,
+    /// Tasks required to check in, as a map from task name to `CheckIn`
+    /// record.
+    #[serde(default)]
+    tasks: BTreeMap<String, CheckIn>,
+}
+
+/// Liveness requirement for a single task.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CheckIn {
+    /// Longest the task may go between check-ins, in milliseconds.
+    deadline_ms: u32,
+    /// What to do when the task misses its deadline.
+    on_timeout: OnTimeout,
+}
+
+/// Mirrors `OnTimeout` in the task.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum OnTimeout {
+    Restart,
+    Reset,
+}
+
+/// When, and whom, to tell that this image works.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfirmBoot {
+    /// Task to notify, normally the update server.
+    task: String,
+    /// Name of the notification to post, from the task's `notifications` in
+    /// app.toml.
+    notification: String,
+    /// How long every task must run without faulting first, in milliseconds.
+    after_ms: u32,
+    /// Tasks whose faults don't count against the image, such as ones that
+    /// fault on purpose.
+    #[serde(default)]
+    ignore_faults: Vec<String>,
 }
 
 /// Description of something a task wants done on state change.
@@ -62,5 +283,36 @@ struct Config {
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct StateChange {
     /// Number of notification bit to signal (_not_ mask).
-    bit_number: u8,
+    bit_number: Option<u8>,
+    /// Name of the notification to signal, from the task's `notifications` in
+    /// app.toml. An alternative to `bit_number`.
+    notification: Option<String>,
+}
+
+/// How to restart a task that keeps faulting.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    /// Number of restarts allowed within `window_ms` before the task is
+    /// considered to be crash-looping.
+    max_restarts: u32,
+    /// Length of the window over which restarts are counted, in milliseconds.
+    window_ms: u32,
+    /// Delay before the first restart in a window, in milliseconds; it
+    /// doubles with each further restart in the same window.
+    #[serde(default)]
+    backoff_ms: u32,
+    /// Cap on the restart delay, in milliseconds. Defaults to `backoff_ms`,
+    /// i.e. no growth.
+    max_backoff_ms: Option<u32>,
+    /// What to do once the task is crash-looping.
+    on_crash_loop: OnCrashLoop,
+}
+
+/// Mirrors `restart_policy::OnCrashLoop`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum OnCrashLoop {
+    Hold,
+    Reset,
 }
@@ -7,31 +7,38 @@
 //! The supervisor is responsible for:
 //!
 //! - Maintaining the system console output (currently via semihosting).
-//! - Monitoring tasks for failures and restarting them.
+//! - Monitoring tasks for failures and restarting them, optionally with
+//!   backoff and crash-loop limits (see `restart-policy` in the task config).
+//! - Keeping a log of recent system resets that survives them.
+//! - Managing the hardware watchdog, and holding selected tasks to periodic
+//!   check-ins (see `watchdog` in the task config).
 //!
 //! It will probably become responsible for:
 //!
 //! - Evacuating kernel log information.
 //! - Coordinating certain shared resources, such as the RCC and GPIO muxing.
-//! - Managing a watchdog timer.
 //!
-//! It's unwise for the supervisor to use `SEND`, ever, except to talk to the
-//! kernel. This is because a `SEND` to a misbehaving task could block forever,
-//! taking out the supervisor. The long-term idea is to provide some sort of
-//! asynchronous messaging from the supervisor to less-trusted tasks, but that
-//! doesn't exist yet, so we're mostly using RECV/REPLY and notifications. This
-//! means that hardware drivers required for this task must be built in instead
-//! of running in separate tasks.
+//! It's unwise for the supervisor to use plain `SEND`, ever, except to talk to
+//! the kernel. This is because a `SEND` to a misbehaving task could block
+//! forever, taking out the supervisor. If the supervisor must message another
+//! task, it should use `sys_send_with_deadline`, which the kernel will cut short
+//! if the task doesn't answer in time. Even so, we're mostly using RECV/REPLY
+//! and notifications, and hardware drivers required for this task are built in
+//! instead of running in separate tasks.
 
 #![no_std]
 #![no_main]
 
 mod external;
+mod reset_log;
+mod watchdog;
 
 use core::convert::Infallible;
 
 use hubris_num_tasks::NUM_TASKS;
-use task_jefe_api::ResetReason;
+use restart_policy::{Action, OnCrashLoop, RestartPolicy};
+use task_jefe_api::{ResetLogEntry, ResetLogError, ResetReason, RestartStats};
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 fn log_fault(t: usize, fault: &abi::FaultInfo) {
@@ -96,6 +103,69 @@ fn log_fault(t: usize, fault: &abi::FaultInfo) {
             );
         }
     }
+
+    // Say where the task was, so the fault can be symbolized later.
+    if let Some(regs) = kipc::read_fault_registers(t) {
+        match regs.stacked {
+            Some(stacked) => {
+                sys_log!(
+                    "Task #{} PC={:#010x} LR={:#010x} SP={:#010x} \
+                    xPSR={:#010x} CFSR={:#010x} HFSR={:#010x}",
+                    t,
+                    stacked.pc,
+                    stacked.lr,
+                    regs.sp,
+                    stacked.xpsr,
+                    regs.cfsr,
+                    regs.hfsr
+                );
+            }
+            None => {
+                sys_log!(
+                    "Task #{} SP={:#010x} CFSR={:#010x} HFSR={:#010x} \
+                    (registers not stacked)",
+                    t,
+                    regs.sp,
+                    regs.cfsr,
+                    regs.hfsr
+                );
+            }
+        }
+    }
+}
+
+/// What to do about a task that misses its watchdog check-in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OnTimeout {
+    /// Fault the task, and restart it as if it had faulted on its own (that
+    /// is, according to its disposition and restart policy).
+    Restart,
+    /// Stop petting the hardware watchdog and let it reset the system.
+    Reset,
+}
+
+/// Restart bookkeeping for one task.
+#[derive(Copy, Clone, Default)]
+struct Restarts {
+    stats: RestartStats,
+    /// Start of the task's current restart-policy window.
+    window_start: Instant,
+}
+
+fn restart_policy_for(index: usize) -> Option<RestartPolicy> {
+    for (task, policy) in generated::RESTART_POLICIES {
+        if task as usize == index {
+            return Some(policy);
+        }
+    }
+    None
+}
+
+/// Checks whether faults in task `index` hold up confirming the boot.
+fn faults_block_confirm_boot(index: usize) -> bool {
+    !generated::CONFIRM_BOOT_IGNORES
+        .into_iter()
+        .any(|task| task as usize == index)
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -109,12 +179,16 @@ pub enum Disposition {
 // We install a timeout to periodcally check for an external direction
 // of our task disposition (e.g., via Humility).  This timeout should
 // generally be fast for a human but slow for a computer; we pick a
-// value of ~100 ms.  Our timer mask can't conflict with our fault
-// notification, but can otherwise be arbitrary.
-const TIMER_INTERVAL: u64 = 100;
-const TIMER_MASK: u32 = 1 << 1;
-// We'll have notification 0 wired up to receive information about task faults.
-const FAULT_MASK: u32 = 1 << 0;
+// value of ~100 ms.  The timer and fault notifications (the latter wired up to
+// receive information about task faults) are declared in app.toml.
+const TIMER_INTERVAL: Duration = Duration::from_millis(100);
+
+// We only get to pet the hardware watchdog from our timer tick, so it needs to
+// survive at least one late tick.
+const _: () = match generated::WATCHDOG_TIMEOUT_MS {
+    Some(t) => assert!(t as u64 >= 2 * TIMER_INTERVAL.as_millis()),
+    None => (),
+};
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -124,9 +198,17 @@ fn main() -> ! {
         [Disposition::Restart; hubris_num_tasks::NUM_TASKS];
     let mut logged: [bool; hubris_num_tasks::NUM_TASKS] =
         [false; hubris_num_tasks::NUM_TASKS];
-    let deadline = sys_get_timer().now + TIMER_INTERVAL;
-
-    sys_set_timer(Some(deadline), TIMER_MASK);
+    let mut restarts: [Restarts; hubris_num_tasks::NUM_TASKS] =
+        [Restarts::default(); hubris_num_tasks::NUM_TASKS];
+    let reset_log = reset_log::ResetLog::claim();
+    let now = Instant::now();
+    let deadline = now + TIMER_INTERVAL;
+    let check_in_deadlines =
+        generated::CHECK_INS.map(|(_, ms, _)| now + Duration::from_millis(ms));
+    let confirm_boot_at = generated::CONFIRM_BOOT
+        .map(|(_, _, ms)| now + Duration::from_millis(ms));
+
+    sys_set_timer(Some(deadline), notifications::TIMER_MASK);
 
     external::set_ready();
 
@@ -135,7 +217,12 @@ fn main() -> ! {
         deadline,
         disposition: &mut disposition,
         logged: &mut logged,
-        reset_reason: ResetReason::Unknown,
+        restarts: &mut restarts,
+        reset_log,
+        check_in_deadlines,
+        watchdog_running: false,
+        starving_watchdog: false,
+        confirm_boot_at,
     };
     let mut buf = [0u8; idl::INCOMING_SIZE];
 
@@ -148,15 +235,222 @@ struct ServerImpl<'s> {
     state: u32,
     disposition: &'s mut [Disposition; NUM_TASKS],
     logged: &'s mut [bool; NUM_TASKS],
-    deadline: u64,
-    reset_reason: ResetReason,
+    restarts: &'s mut [Restarts; NUM_TASKS],
+    deadline: Instant,
+    reset_log: reset_log::ResetLog,
+    /// Time by which each task in `CHECK_INS` must next check in.
+    check_in_deadlines: [Instant; generated::CHECK_INS.len()],
+    /// Set once we've started the hardware watchdog.
+    watchdog_running: bool,
+    /// Set once we've decided to let the hardware watchdog reset us.
+    starving_watchdog: bool,
+    /// Time at which we'll declare this image healthy, if we're going to and
+    /// haven't yet. Each task fault pushes it back.
+    confirm_boot_at: Option<Instant>,
+}
+
+impl ServerImpl<'_> {
+    /// Restarts faulted task `index` now.
+    fn restart(&mut self, index: usize, now: Instant) {
+        kipc::restart_task(index, true);
+        self.logged[index] = false;
+        let r = &mut self.restarts[index];
+        r.stats.restarts = r.stats.restarts.wrapping_add(1);
+        r.stats.next_restart = None;
+        r.stats.held = false;
+        self.extend_check_in(index, now);
+    }
+
+    /// Applies task `index`'s restart policy to a fresh fault: restarts it
+    /// now, schedules a restart after a backoff delay, or handles a crash
+    /// loop.
+    fn handle_fault(&mut self, index: usize, now: Instant) {
+        let policy = match restart_policy_for(index) {
+            Some(policy) => policy,
+            None => return self.restart(index, now),
+        };
+
+        let r = &mut self.restarts[index];
+        // If we're seeing the fault again after holding the task for crash
+        // looping, someone has released it; give it a clean slate.
+        if r.stats.held
+            || now - r.window_start >= Duration::from_millis(policy.window_ms)
+        {
+            r.stats.held = false;
+            r.window_start = now;
+            r.stats.window_faults = 0;
+        }
+        r.stats.window_faults = r.stats.window_faults.saturating_add(1);
+
+        match policy.action(r.stats.window_faults) {
+            Action::Restart { delay_ms: 0 } => self.restart(index, now),
+            Action::Restart { delay_ms } => {
+                let t = now + Duration::from_millis(delay_ms);
+                r.stats.next_restart = Some(t.as_ticks());
+            }
+            Action::CrashLoop(OnCrashLoop::Hold) => {
+                sys_log!("Task #{} is crash-looping; holding", index);
+                r.stats.held = true;
+                self.disposition[index] = Disposition::Hold;
+            }
+            Action::CrashLoop(OnCrashLoop::Reset) => {
+                sys_log!("Task #{} is crash-looping; resetting", index);
+                self.reset_log
+                    .note_pending(ResetReason::CrashLoop(index as u16), now);
+                kipc::system_restart();
+            }
+        }
+    }
+
+    /// Restarts any task whose backoff delay has run out.
+    fn restart_backed_off(&mut self, now: Instant) {
+        for i in 0..NUM_TASKS {
+            match self.restarts[i].stats.next_restart {
+                Some(t) if Instant::from_ticks(t) <= now => (),
+                _ => continue,
+            }
+            self.restarts[i].stats.next_restart = None;
+
+            // Someone may have changed the task's disposition, or restarted
+            // it themselves, while we were waiting.
+            if self.disposition[i] == Disposition::Restart
+                && matches!(
+                    kipc::read_task_status(i),
+                    abi::TaskState::Faulted { .. }
+                )
+            {
+                self.restart(i, now);
+            }
+        }
+    }
+
+    /// Starts the wait before we declare the image healthy over again, since
+    /// task `index` has just faulted.
+    fn postpone_confirm_boot(&mut self, index: usize, now: Instant) {
+        if !faults_block_confirm_boot(index) {
+            return;
+        }
+        if let (Some(at), Some((_, _, ms))) =
+            (&mut self.confirm_boot_at, generated::CONFIRM_BOOT)
+        {
+            *at = now + Duration::from_millis(ms);
+        }
+    }
+
+    /// Tells the task in `CONFIRM_BOOT` that this image works, once every task
+    /// has run for long enough without faulting.
+    fn check_confirm_boot(&mut self, now: Instant) {
+        let (task, mask) = match (generated::CONFIRM_BOOT, self.confirm_boot_at)
+        {
+            (Some((task, mask, _)), Some(at)) if at <= now => (task, mask),
+            _ => return,
+        };
+
+        // A task that's still down, because it's held or waiting out a
+        // backoff delay, isn't working either.
+        let faulted = (0..NUM_TASKS).any(|i| {
+            faults_block_confirm_boot(i)
+                && matches!(
+                    kipc::read_task_status(i),
+                    abi::TaskState::Faulted { .. }
+                )
+        });
+        if faulted {
+            return;
+        }
+
+        sys_log!("Tasks are running; confirming boot");
+        let taskid = TaskId::for_index_and_gen(task as usize, Generation::ZERO);
+        let taskid = sys_refresh_task_id(taskid);
+        sys_post(taskid, mask);
+        self.confirm_boot_at = None;
+    }
+
+    /// Gives the watched task `index` a fresh check-in deadline, if it's
+    /// being watched.
+    fn extend_check_in(&mut self, index: usize, now: Instant) {
+        for (i, (task, ms, _)) in generated::CHECK_INS.into_iter().enumerate() {
+            if task as usize == index {
+                self.check_in_deadlines[i] = now + Duration::from_millis(ms);
+            }
+        }
+    }
+
+    /// Deals with any watched task that has missed its check-in, and pets the
+    /// hardware watchdog unless one of them has asked for a reset.
+    fn check_watchdog(&mut self, now: Instant) {
+        if self.starving_watchdog {
+            return;
+        }
+
+        // The watchdog may depend on other tasks to start (see `watchdog`), so
+        // we start it from our timer tick, when they've had a chance to come
+        // up, and keep trying until it works.
+        if !self.watchdog_running {
+            if let Some(timeout_ms) = generated::WATCHDOG_TIMEOUT_MS {
+                self.watchdog_running = watchdog::start(timeout_ms);
+            }
+        }
+
+        for (i, (task, ms, on_timeout)) in
+            generated::CHECK_INS.into_iter().enumerate()
+        {
+            if self.check_in_deadlines[i] > now {
+                continue;
+            }
+            let index = task as usize;
+            self.check_in_deadlines[i] = now + Duration::from_millis(ms);
+
+            // A task that's faulted or stopped can't check in; whether it
+            // comes back is up to its disposition, not the watchdog.
+            match kipc::read_task_status(index) {
+                abi::TaskState::Healthy(abi::SchedState::Stopped)
+                | abi::TaskState::Faulted { .. } => continue,
+                abi::TaskState::Healthy(_) => (),
+            }
+
+            match on_timeout {
+                OnTimeout::Restart => {
+                    sys_log!("Task #{} missed watchdog check-in", index);
+                    // Fault the task rather than restarting it here, so that
+                    // it's dealt with like any other fault: the kernel tells
+                    // us about it, and its disposition and restart policy
+                    // decide what happens next.
+                    kipc::fault_task(index);
+                }
+                OnTimeout::Reset => {
+                    sys_log!(
+                        "Task #{} missed watchdog check-in; resetting",
+                        index
+                    );
+                    self.reset_log.note_pending(
+                        ResetReason::TaskWatchdog(index as u16),
+                        now,
+                    );
+                    if !self.watchdog_running {
+                        kipc::system_restart();
+                    }
+                    self.starving_watchdog = true;
+                    return;
+                }
+            }
+        }
+
+        if self.watchdog_running {
+            watchdog::pet();
+        }
+    }
 }
 
 impl idl::InOrderJefeImpl for ServerImpl<'_> {
     fn request_reset(
         &mut self,
-        _msg: &userlib::RecvMessage,
+        msg: &userlib::RecvMessage,
     ) -> Result<(), idol_runtime::RequestError<Infallible>> {
+        self.reset_log.note_pending(
+            ResetReason::Requested(msg.sender.index() as u16),
+            Instant::now(),
+        );
         // If we wanted to broadcast to other tasks that a restart is occuring
         // here is where we would do so!
         kipc::system_restart();
@@ -166,7 +460,7 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         &mut self,
         _msg: &userlib::RecvMessage,
     ) -> Result<ResetReason, idol_runtime::RequestError<Infallible>> {
-        Ok(self.reset_reason)
+        Ok(self.reset_log.reason())
     }
 
     fn set_reset_reason(
@@ -174,7 +468,38 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         _msg: &userlib::RecvMessage,
         reason: ResetReason,
     ) -> Result<(), idol_runtime::RequestError<Infallible>> {
-        self.reset_reason = reason;
+        self.reset_log.note_hardware_reason(reason);
+        Ok(())
+    }
+
+    fn read_reset_log(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        index: u8,
+    ) -> Result<ResetLogEntry, idol_runtime::RequestError<ResetLogError>> {
+        self.reset_log
+            .entry(usize::from(index))
+            .ok_or_else(|| ResetLogError::NoSuchEntry.into())
+    }
+
+    fn get_restart_stats(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u16,
+    ) -> Result<RestartStats, idol_runtime::RequestError<Infallible>> {
+        self.restarts.get(usize::from(task)).map(|r| r.stats).ok_or(
+            idol_runtime::RequestError::Fail(
+                idol_runtime::ClientError::BadMessageContents,
+            ),
+        )
+    }
+
+    fn check_in(
+        &mut self,
+        msg: &userlib::RecvMessage,
+    ) -> Result<(), idol_runtime::RequestError<Infallible>> {
+        // Check-ins from tasks we aren't watching are harmless; ignore them.
+        self.extend_check_in(msg.sender.index(), Instant::now());
         Ok(())
     }
 
@@ -206,7 +531,7 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
 
 impl idol_runtime::NotificationHandler for ServerImpl<'_> {
     fn current_notification_mask(&self) -> u32 {
-        FAULT_MASK | TIMER_MASK
+        notifications::FAULT_MASK | notifications::TIMER_MASK
     }
 
     fn handle_notification(&mut self, bits: u32) {
@@ -214,32 +539,41 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
         let changed = external::check(self.disposition);
 
         // If our timer went off, we need to reestablish it
-        if bits & TIMER_MASK != 0 {
+        if bits & notifications::TIMER_MASK != 0 {
             self.deadline += TIMER_INTERVAL;
-            sys_set_timer(Some(self.deadline), TIMER_MASK);
+            sys_set_timer(Some(self.deadline), notifications::TIMER_MASK);
+            let now = Instant::now();
+            self.reset_log.note_uptime(now);
+            self.restart_backed_off(now);
+            self.check_watchdog(now);
+            self.check_confirm_boot(now);
         }
 
         // If our disposition has changed or if we have been notified of
         // a faulting task, we need to iterate over all of our tasks.
-        if changed || (bits & FAULT_MASK) != 0 {
+        if changed || (bits & notifications::FAULT_MASK) != 0 {
             for i in 0..NUM_TASKS {
                 match kipc::read_task_status(i) {
                     abi::TaskState::Faulted { fault, .. } => {
                         if !self.logged[i] {
                             log_fault(i, &fault);
                             self.logged[i] = true;
+                            self.postpone_confirm_boot(i, Instant::now());
                         }
 
-                        if self.disposition[i] == Disposition::Restart {
-                            // Stand it back up
-                            kipc::restart_task(i, true);
-                            self.logged[i] = false;
+                        // Stand it back up, unless we're already waiting to
+                        // do that once its backoff delay is up.
+                        if self.disposition[i] == Disposition::Restart
+                            && self.restarts[i].stats.next_restart.is_none()
+                        {
+                            self.handle_fault(i, Instant::now());
                         }
                     }
 
                     abi::TaskState::Healthy(abi::SchedState::Stopped) => {
                         if self.disposition[i] == Disposition::Start {
                             kipc::restart_task(i, true);
+                            self.extend_check_in(i, Instant::now());
                         }
                     }
 
@@ -259,8 +593,14 @@ mod generated {
     include!(concat!(env!("OUT_DIR"), "/jefe_config.rs"));
 }
 
+mod notifications {
+    include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
+}
+
 // And the Idol bits
 mod idl {
-    use task_jefe_api::ResetReason;
+    use task_jefe_api::{
+        ResetLogEntry, ResetLogError, ResetReason, RestartStats,
+    };
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -0,0 +1,205 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Log of recent system resets, kept in RAM that survives a warm reset.
+//!
+//! The log lives in `.uninit`, which neither the task startup code nor a warm
+//! reset clears. Power loss (or anything else that scrambles that RAM) is
+//! caught by a checksum, and the log starts over.
+//!
+//! Each boot adds an entry for the reset that started it. The entry's reason
+//! starts out as whatever jefe said it was about to do before the reset -- let
+//! the watchdog bite, escalate a crash loop, honor a reset request -- or
+//! `Unknown` if jefe didn't see it coming. The `sys` task then tells us what
+//! the hardware saw (via `set_reset_reason`), which fills in the gaps: a
+//! brownout, say, or a reset pin.
+
+use core::mem::{size_of, MaybeUninit};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(armv6m)]
+use armv6m_atomic_hack::AtomicBoolExt;
+
+use task_jefe_api::{ResetLogEntry, ResetReason};
+use userlib::time::Instant;
+use zerocopy::{AsBytes, FromBytes};
+
+/// Number of resets remembered, from the `reset-log-entries` config.
+const ENTRIES: usize = crate::generated::RESET_LOG_ENTRIES;
+
+/// Marks a log laid out as below. Change this if the layout (or the
+/// encoding of `ResetReason`) changes, so that new firmware doesn't misread
+/// an old log after an update. The number of entries is folded in for the
+/// same reason.
+const MAGIC: u32 = 0x1ef3_0001 ^ ((ENTRIES as u32) << 16);
+
+/// Room for an ssmarshal-encoded `ResetReason`.
+type RawReason = [u8; 8];
+
+#[derive(Copy, Clone, AsBytes, FromBytes)]
+#[repr(C)]
+struct RawEntry {
+    boot: u32,
+    uptime_lo: u32,
+    uptime_hi: u32,
+    reason: RawReason,
+}
+
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+struct Retained {
+    magic: u32,
+    /// Number of the current boot; entry `boot % ENTRIES` is its entry.
+    boot: u32,
+    /// Uptime of the current boot, as of the last `note_uptime`.
+    uptime_lo: u32,
+    uptime_hi: u32,
+    /// Why jefe is about to reset the system, if it is.
+    pending: RawReason,
+    entries: [RawEntry; ENTRIES],
+    /// Checksum over all of the above.
+    checksum: u32,
+}
+
+fn encode(reason: ResetReason) -> RawReason {
+    let mut raw = RawReason::default();
+    // ResetReason always fits; if it someday doesn't, we'd rather record
+    // Unknown than take jefe down.
+    let _ = ssmarshal::serialize(&mut raw, &reason);
+    raw
+}
+
+fn decode(raw: &RawReason) -> ResetReason {
+    ssmarshal::deserialize(raw)
+        .map(|(reason, _)| reason)
+        .unwrap_or(ResetReason::Unknown)
+}
+
+impl Retained {
+    fn compute_checksum(&self) -> u32 {
+        let bytes = self.as_bytes();
+        bytes[..bytes.len() - size_of::<u32>()]
+            .chunks_exact(4)
+            .fold(MAGIC, |sum, w| {
+                sum.rotate_left(5)
+                    ^ u32::from_le_bytes([w[0], w[1], w[2], w[3]])
+            })
+    }
+
+    fn seal(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    fn current(&mut self) -> &mut RawEntry {
+        &mut self.entries[self.boot as usize % ENTRIES]
+    }
+}
+
+pub struct ResetLog(&'static mut Retained);
+
+impl ResetLog {
+    /// Claims the log, starting it afresh if RAM doesn't hold a valid one, and
+    /// adds an entry for the reset that got us here. Panics if called twice.
+    pub fn claim() -> Self {
+        #[link_section = ".uninit"]
+        static mut RETAINED: MaybeUninit<Retained> = MaybeUninit::uninit();
+        static TAKEN: AtomicBool = AtomicBool::new(false);
+
+        if TAKEN.swap(true, Ordering::Relaxed) {
+            panic!();
+        }
+
+        // Safety: the swap above ensures we only produce this reference once.
+        // The RAM behind it holds whatever it held before this boot, and
+        // `Retained` is `FromBytes`, so every bit pattern is a valid value.
+        let r = unsafe { &mut *RETAINED.as_mut_ptr() };
+
+        let (boot, uptime, reason) = if r.magic == MAGIC
+            && r.checksum == r.compute_checksum()
+        {
+            let uptime = u64::from(r.uptime_hi) << 32 | u64::from(r.uptime_lo);
+            (r.boot.wrapping_add(1), uptime, r.pending)
+        } else {
+            r.entries = [RawEntry {
+                boot: 0,
+                uptime_lo: 0,
+                uptime_hi: 0,
+                reason: RawReason::default(),
+            }; ENTRIES];
+            // Nobody has been counting, so this boot is the first.
+            (0, 0, encode(ResetReason::Unknown))
+        };
+
+        r.magic = MAGIC;
+        r.boot = boot;
+        r.uptime_lo = 0;
+        r.uptime_hi = 0;
+        r.pending = encode(ResetReason::Unknown);
+        *r.current() = RawEntry {
+            boot,
+            uptime_lo: uptime as u32,
+            uptime_hi: (uptime >> 32) as u32,
+            reason,
+        };
+        r.seal();
+
+        Self(r)
+    }
+
+    /// Reason for the reset that started this boot, as best we know it.
+    pub fn reason(&self) -> ResetReason {
+        decode(&self.0.entries[self.0.boot as usize % ENTRIES].reason)
+    }
+
+    /// Folds in the reset reason reported by the hardware, and returns the
+    /// resulting reason for this boot.
+    pub fn note_hardware_reason(&mut self, hw: ResetReason) -> ResetReason {
+        let ours = self.reason();
+        let merged = match (ours, hw) {
+            // The hardware can't tell why software reset it, or why a
+            // watchdog we stopped petting fired.
+            (
+                ResetReason::TaskWatchdog(_)
+                | ResetReason::CrashLoop(_)
+                | ResetReason::Requested(_),
+                ResetReason::SystemCall
+                | ResetReason::SystemWatchdog
+                | ResetReason::IndependentWatchdog,
+            ) => ours,
+            _ => hw,
+        };
+        self.0.current().reason = encode(merged);
+        self.0.seal();
+        merged
+    }
+
+    /// Records how long we've been up, in case we reset without warning.
+    pub fn note_uptime(&mut self, now: Instant) {
+        let now = now.since_boot().as_millis();
+        self.0.uptime_lo = now as u32;
+        self.0.uptime_hi = (now >> 32) as u32;
+        self.0.seal();
+    }
+
+    /// Records that we're about to reset the system, and why.
+    pub fn note_pending(&mut self, reason: ResetReason, now: Instant) {
+        self.0.pending = encode(reason);
+        self.note_uptime(now);
+    }
+
+    /// Returns the `index`th most recent entry; index 0 is the reset that
+    /// started this boot.
+    pub fn entry(&self, index: usize) -> Option<ResetLogEntry> {
+        if index >= ENTRIES || index as u32 > self.0.boot {
+            return None;
+        }
+        let boot = self.0.boot - index as u32;
+        let e = &self.0.entries[boot as usize % ENTRIES];
+        Some(ResetLogEntry {
+            boot: e.boot,
+            uptime: u64::from(e.uptime_hi) << 32 | u64::from(e.uptime_lo),
+            reason: decode(&e.reason),
+        })
+    }
+}
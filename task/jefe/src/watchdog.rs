@@ -0,0 +1,180 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware watchdog support.
+//!
+//! If the image enables one of the watchdog features, jefe starts the
+//! corresponding hardware watchdog on its first timer tick, and pets it from
+//! each tick after that for as long as the tasks it's watching keep checking
+//! in. The task needs the watchdog peripheral in its `uses` list. On the
+//! LPC55, it also needs a slot for the syscon driver, which turns on the
+//! watchdog's clock for us; since the driver runs at a lower priority, that
+//! slot has to be listed in `ipc-exceptions`. For example:
+//!
+//! ```toml
+//! [tasks.jefe]
+//! features = ["wwdt-lpc55"]
+//! uses = ["wwdt"]
+//! task-slots = ["syscon_driver"]
+//! # Only sent to with a deadline, to start the watchdog.
+//! ipc-exceptions = ["syscon_driver"]
+//!
+//! [tasks.jefe.config.watchdog]
+//! timeout-ms = 1000
+//!
+//! [tasks.jefe.config.watchdog.tasks]
+//! net = {deadline-ms = 500, on-timeout = "restart"}
+//! thermal = {deadline-ms = 2000, on-timeout = "reset"}
+//! ```
+//!
+//! Each listed task has to call `Jefe::check_in` at least once per
+//! `deadline-ms`. Deadlines are only checked on jefe's timer tick, so they're
+//! rounded up to the next tick. The task list works without a hardware
+//! watchdog, too.
+//!
+//! The watchdog keeps counting while the core is halted in a debugger, so
+//! expect a reset if you sit at a breakpoint for longer than the timeout.
+//!
+//! Without a watchdog feature, `start` and `pet` do nothing. Then (or if the
+//! watchdog hasn't started yet) jefe resets the system itself when it would
+//! otherwise have let the watchdog bite.
+
+cfg_if::cfg_if! {
+    if #[cfg(any(
+        feature = "iwdg-stm32f3",
+        feature = "iwdg-stm32f4",
+        feature = "iwdg-stm32g0",
+        feature = "iwdg-stm32h7",
+    ))] {
+        pub use iwdg::{pet, start};
+    } else if #[cfg(feature = "wwdt-lpc55")] {
+        pub use wwdt::{pet, start};
+    } else {
+        pub fn start(_timeout_ms: u32) -> bool {
+            true
+        }
+
+        pub fn pet() {}
+    }
+}
+
+/// STM32 independent watchdog, clocked from the ~32 kHz LSI. Starting the
+/// watchdog turns on the LSI, so there's nothing to ask of the RCC.
+#[cfg(any(
+    feature = "iwdg-stm32f3",
+    feature = "iwdg-stm32f4",
+    feature = "iwdg-stm32g0",
+    feature = "iwdg-stm32h7",
+))]
+mod iwdg {
+    // The IWDG is the same across each family, so any model will do.
+    #[cfg(feature = "iwdg-stm32f3")]
+    use stm32f3::stm32f303 as device;
+    #[cfg(feature = "iwdg-stm32f4")]
+    use stm32f4::stm32f407 as device;
+    #[cfg(feature = "iwdg-stm32g0")]
+    use stm32g0::stm32g031 as device;
+    #[cfg(feature = "iwdg-stm32h7")]
+    use stm32h7::stm32h743 as device;
+
+    const KEY_RELOAD: u32 = 0xAAAA;
+    const KEY_UNLOCK: u32 = 0x5555;
+    const KEY_START: u32 = 0xCCCC;
+
+    const LSI_TICKS_PER_MS: u32 = 32;
+
+    /// Starts the watchdog. This can't fail, so it always returns true.
+    pub fn start(timeout_ms: u32) -> bool {
+        // Use the smallest prescaler (divide by 4 << pr) that gets the count
+        // into the 12-bit reload register.
+        let ticks = timeout_ms.saturating_mul(LSI_TICKS_PER_MS);
+        let mut pr = 0;
+        while pr < 6 && ticks / (4 << pr) > 0x1000 {
+            pr += 1;
+        }
+        let reload = (ticks / (4 << pr)).clamp(1, 0x1000) - 1;
+
+        // Safety: jefe is the only task that maps the IWDG.
+        let iwdg = unsafe { &*device::IWDG::ptr() };
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_START) });
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+        iwdg.pr.write(|w| unsafe { w.bits(pr) });
+        iwdg.rlr.write(|w| unsafe { w.bits(reload) });
+        // The new values have to cross into the LSI domain before a reload
+        // will use them.
+        while iwdg.sr.read().bits() != 0 {}
+        pet();
+        true
+    }
+
+    pub fn pet() {
+        // Safety: see `start`.
+        let iwdg = unsafe { &*device::IWDG::ptr() };
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_RELOAD) });
+    }
+}
+
+/// LPC55 windowed watchdog, clocked from `fro_1m` through its fixed
+/// divide-by-4 prescaler. We leave the window wide open.
+///
+/// The syscon driver sets up the watchdog's clock divider at boot, and turns
+/// the clock on when we ask.
+#[cfg(feature = "wwdt-lpc55")]
+mod wwdt {
+    use drv_lpc55_syscon_api::{Peripheral, SysconOperation};
+    use lpc55_pac as device;
+    use userlib::time::{Duration, Instant};
+    use userlib::*;
+    use zerocopy::AsBytes;
+
+    task_slot!(SYSCON, syscon_driver);
+
+    const MOD_WDEN: u32 = 1 << 0;
+    const MOD_WDRESET: u32 = 1 << 1;
+
+    const WDCLK_TICKS_PER_MS: u32 = 250;
+
+    /// How long we'll wait for the syscon driver to answer.
+    const SYSCON_DEADLINE: Duration = Duration::from_millis(10);
+
+    /// Starts the watchdog, returning false if the syscon driver didn't turn
+    /// its clock on in time; call this again later if so.
+    pub fn start(timeout_ms: u32) -> bool {
+        // The syscon driver's Idol client would block us for as long as the
+        // driver took to answer, so we do the send ourselves.
+        let syscon = sys_refresh_task_id(SYSCON.get_task_id());
+        let (rc, _) = sys_send_with_deadline(
+            syscon,
+            SysconOperation::enable_clock as u16,
+            Peripheral::Wwdt.as_bytes(),
+            &mut [],
+            &[],
+            Instant::now() + SYSCON_DEADLINE,
+        );
+        if rc != 0 {
+            return false;
+        }
+
+        let ticks = timeout_ms
+            .saturating_mul(WDCLK_TICKS_PER_MS)
+            .clamp(0xFF, 0xFF_FFFF);
+
+        // Safety: jefe is the only task that maps the WWDT.
+        let wwdt = unsafe { &*device::WWDT::ptr() };
+        wwdt.tc.write(|w| unsafe { w.bits(ticks) });
+        wwdt.mod_
+            .write(|w| unsafe { w.bits(MOD_WDEN | MOD_WDRESET) });
+        // The watchdog doesn't actually start until its first feed.
+        pet();
+        true
+    }
+
+    pub fn pet() {
+        // Safety: see `start`. The two writes must not be separated by any
+        // other WWDT access, and jefe is single-threaded.
+        let wwdt = unsafe { &*device::WWDT::ptr() };
+        wwdt.feed.write(|w| unsafe { w.bits(0xAA) });
+        wwdt.feed.write(|w| unsafe { w.bits(0x55) });
+    }
+}
@@ -5,6 +5,7 @@
 use drv_sidecar_front_io::phy_smi::PhySmi;
 use drv_sidecar_seq_api::{SeqError, Sequencer};
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::{hl::sleep_for, task_slot};
 use vsc7448::{
     config::Speed, miim_phy::Vsc7448MiimPhy, Vsc7448, Vsc7448Rw, VscError,
@@ -16,7 +17,7 @@ task_slot!(SEQ, seq);
 task_slot!(FRONT_IO, ecp5_front_io);
 
 /// Interval at which `Bsp::wake()` is called by the main loop
-pub const WAKE_INTERVAL: Option<u64> = Some(500);
+pub const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
@@ -133,7 +134,7 @@ pub fn preinit() {
     // Wait for the sequencer to turn on the clock
     let seq = Sequencer::from(SEQ.get_task_id());
     while !seq.is_clock_config_loaded().unwrap_or(false) {
-        sleep_for(10);
+        sleep_for(Duration::from_millis(10));
     }
     // Wait for the front IO board to be configured (or for the board to
     // be reported as missing).
@@ -141,7 +142,7 @@ pub fn preinit() {
         let ready = seq.front_io_phy_ready();
         match ready {
             Ok(true) | Err(SeqError::NoFrontIOBoard) => break,
-            _ => sleep_for(10),
+            _ => sleep_for(Duration::from_millis(10)),
         }
     }
 }
@@ -334,7 +335,7 @@ impl<'a, R: Vsc7448Rw> Bsp<'a, R> {
             phy_rw
                 .set_phy_power_enabled(false)
                 .map_err(|e| VscError::ProxyError(e.into()))?;
-            sleep_for(10);
+            sleep_for(Duration::from_millis(10));
             phy_rw
                 .set_phy_power_enabled(true)
                 .map_err(|e| VscError::ProxyError(e.into()))?;
@@ -342,7 +343,7 @@ impl<'a, R: Vsc7448Rw> Bsp<'a, R> {
                 .phy_powered_up_and_ready()
                 .map_err(|e| VscError::ProxyError(e.into()))?
             {
-                sleep_for(20);
+                sleep_for(Duration::from_millis(20));
             }
             for p in 0..2 {
                 let mut phy = vsc85xx::Phy::new(p, phy_rw);
@@ -4,6 +4,7 @@
 
 use drv_user_leds_api::UserLeds;
 use ringbuf::*;
+use userlib::time::Duration;
 use userlib::*;
 use vsc7448::{miim_phy::Vsc7448MiimPhy, Vsc7448, Vsc7448Rw, VscError};
 use vsc7448_pac::{phy, *};
@@ -16,7 +17,7 @@ pub const REFCLK2_SEL: Option<vsc7448::RefClockFreq> =
     Some(vsc7448::RefClockFreq::Clk25MHz);
 
 /// Interval at which `Bsp::wake()` is called by the main loop
-pub const WAKE_INTERVAL: Option<u64> = Some(500);
+pub const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
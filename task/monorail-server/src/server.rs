@@ -8,7 +8,8 @@ use monorail_api::{
     LinkStatus, MacTableEntry, MonorailError, PacketCount, PhyStatus, PhyType,
     PortCounters, PortDev, PortStatus, VscError,
 };
-use userlib::{sys_get_timer, sys_set_timer};
+use userlib::sys_set_timer;
+use userlib::time::Instant;
 use vsc7448::{
     config::{PortMap, PortMode},
     DevGeneric, Vsc7448, Vsc7448Rw,
@@ -19,7 +20,7 @@ pub struct ServerImpl<'a, R> {
     bsp: Bsp<'a, R>,
     vsc7448: &'a Vsc7448<'a, R>,
     map: &'a PortMap,
-    wake_target_time: u64,
+    wake_target_time: Instant,
 }
 
 /// Notification mask for optional periodic logging
@@ -35,8 +36,8 @@ impl<'a, R: Vsc7448Rw> ServerImpl<'a, R> {
         // Some of the BSPs include a 'wake' function which allows for periodic
         // logging.  We schedule a wake-up before entering the idol_runtime dispatch
         // loop, to make sure that this gets called periodically.
-        let wake_target_time = sys_get_timer().now;
-        sys_set_timer(Some(0), WAKE_IRQ); // Trigger a wake IRQ right away
+        let wake_target_time = Instant::now();
+        sys_set_timer(Some(Instant::ZERO), WAKE_IRQ); // Trigger a wake IRQ right away
         Self {
             bsp,
             wake_target_time,
@@ -46,7 +47,7 @@ impl<'a, R: Vsc7448Rw> ServerImpl<'a, R> {
     }
 
     pub fn wake(&mut self) -> Result<(), VscError> {
-        let now = sys_get_timer().now;
+        let now = Instant::now();
         if let Some(wake_interval) = bsp::WAKE_INTERVAL {
             if now >= self.wake_target_time {
                 let out = self.bsp.wake();
@@ -14,6 +14,7 @@ use task_jefe_api::Jefe;
 use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
+use userlib::time::Duration;
 use userlib::{sys_recv_closed, task_slot, FromPrimitive, TaskId};
 use vsc7448_pac::types::PhyRegisterAddress;
 
@@ -26,7 +27,7 @@ pub struct BspImpl(mgmt::Bsp);
 
 impl crate::bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
-    const WAKE_INTERVAL: Option<u64> = Some(500);
+    const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
     /// Stateless function to configure ethernet pins before the Bsp struct
     /// is actually constructed
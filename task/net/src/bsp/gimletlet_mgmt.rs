@@ -18,6 +18,7 @@ use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
 use userlib::task_slot;
+use userlib::time::Duration;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 use vsc85xx::VscError;
 
@@ -92,7 +93,7 @@ pub struct BspImpl {
 
 impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
-    const WAKE_INTERVAL: Option<u64> = Some(500);
+    const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
     fn configure_ethernet_pins(sys: &Sys) {
         pins::RmiiPins {
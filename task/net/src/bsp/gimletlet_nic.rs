@@ -15,6 +15,7 @@ use ksz8463::{
 };
 use ringbuf::*;
 use task_net_api::PhyError;
+use userlib::time::Duration;
 use userlib::{hl::sleep_for, task_slot};
 use vsc7448_pac::types::PhyRegisterAddress;
 
@@ -40,7 +41,7 @@ pub struct BspImpl {
 
 impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
-    const WAKE_INTERVAL: Option<u64> = Some(5000);
+    const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(5000));
 
     fn configure_ethernet_pins(sys: &Sys) {
         pins::RmiiPins {
@@ -73,7 +74,7 @@ impl bsp_support::Bsp for BspImpl {
             match ksz8463.configure(ksz8463::Mode::Copper, vlan_mode) {
                 Err(err) => {
                     ringbuf_entry!(Trace::KszErr { err });
-                    sleep_for(100);
+                    sleep_for(Duration::from_millis(100));
                 }
                 _ => break ksz8463,
             }
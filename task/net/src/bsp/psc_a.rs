@@ -13,6 +13,7 @@ use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
 use userlib::task_slot;
+use userlib::time::Duration;
 use vsc7448_pac::types::PhyRegisterAddress;
 
 task_slot!(SPI, spi_driver);
@@ -23,7 +24,7 @@ pub struct BspImpl(mgmt::Bsp);
 
 impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
-    const WAKE_INTERVAL: Option<u64> = Some(500);
+    const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
     /// Stateless function to configure ethernet pins before the Bsp struct
     /// is actually constructed
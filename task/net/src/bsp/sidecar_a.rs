@@ -13,6 +13,7 @@ use drv_stm32xx_sys_api::{Alternate, Port, Sys};
 use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
+use userlib::time::Duration;
 use userlib::{hl::sleep_for, task_slot};
 use vsc7448_pac::types::PhyRegisterAddress;
 
@@ -25,7 +26,7 @@ pub struct BspImpl(mgmt::Bsp);
 
 impl bsp_support::Bsp for BspImpl {
     // This system wants to be woken periodically to do logging
-    const WAKE_INTERVAL: Option<u64> = Some(500);
+    const WAKE_INTERVAL: Option<Duration> = Some(Duration::from_millis(500));
 
     /// Stateless function to configure ethernet pins before the Bsp struct
     /// is actually constructed
@@ -54,7 +55,7 @@ impl bsp_support::Bsp for BspImpl {
         // Wait for the sequencer to turn on the clock
         let seq = Sequencer::from(SEQ.get_task_id());
         while !seq.is_clock_config_loaded().unwrap_or(false) {
-            sleep_for(10);
+            sleep_for(Duration::from_millis(10));
         }
     }
 
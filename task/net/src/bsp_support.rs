@@ -17,6 +17,7 @@
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::Sys;
 use task_net_api::PhyError;
+use userlib::time::Duration;
 use vsc7448_pac::types::PhyRegisterAddress;
 
 #[cfg(feature = "mgmt")]
@@ -32,7 +33,7 @@ pub trait Bsp: Sized {
     ///
     /// The default is `None`, which goes along with the default impl for
     /// `wake`. If you change one, change the other.
-    const WAKE_INTERVAL: Option<u64> = None;
+    const WAKE_INTERVAL: Option<Duration> = None;
 
     /// Opportunity to do any work before the Ethernet peripheral is turned on.
     /// By default this does nothing, override it if necessary.
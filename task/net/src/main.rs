@@ -58,6 +58,7 @@ use stm32h7::stm32h753 as device;
 
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::Sys;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 use crate::bsp::BspImpl;
@@ -149,7 +150,7 @@ const NEIGHBORS: usize = 4;
 
 /// How long to wait with no received packets before we decide the driver is
 /// b0rked and restart it.
-const RX_WATCHDOG_INTERVAL: u64 = 60_000;
+const RX_WATCHDOG_INTERVAL: Duration = Duration::from_millis(60_000);
 
 /////////////////////////////////////////////////////////////////////////////
 // Main driver loop.
@@ -227,7 +228,7 @@ fn main() -> ! {
     }
     let mut multitimer = Multitimer::<Timers>::new(WAKE_IRQ_BIT);
 
-    let now = sys_get_timer().now;
+    let now = Instant::now();
     if let Some(wake_interval) = BspImpl::WAKE_INTERVAL {
         // Some of the BSPs include a 'wake' function which allows for periodic
         // logging.  We schedule a wake-up before entering the idol_runtime
@@ -247,7 +248,7 @@ fn main() -> ! {
         ITER_COUNT.fetch_add(1, Ordering::Relaxed);
 
         // Call into smoltcp.
-        let now = sys_get_timer().now;
+        let now = Instant::now();
         let poll_result = server.poll(now);
         // If smoltcp reported an error we'll treat the activity flags as true
         // so that we immediately retry.
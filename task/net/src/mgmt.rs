@@ -14,6 +14,7 @@ use task_net_api::{
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
 };
 use userlib::hl::sleep_for;
+use userlib::time::Duration;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 use vsc85xx::{vsc85x2::Vsc85x2, Counter, VscError};
 
@@ -145,7 +146,7 @@ impl Config {
         // TODO: sleep for PG lines going high here
 
         sys.gpio_set(self.vsc85x2_nrst).unwrap();
-        sleep_for(120); // Wait for the chip to come out of reset
+        sleep_for(Duration::from_millis(120)); // Wait for the chip to come out of reset
 
         // Build handle for the VSC85x2 PHY, then initialize it
         let rw = &mut MiimBridge::new(eth);
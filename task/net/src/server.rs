@@ -25,6 +25,7 @@ use heapless::Vec;
 use smoltcp::iface::{Interface, Neighbor, SocketHandle, SocketStorage};
 use smoltcp::socket::UdpSocket;
 use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv6Cidr};
+use userlib::time::Instant;
 use userlib::{sys_post, sys_refresh_task_id, UnwrapLite};
 
 /// Implementation of the Net Idol interface.
@@ -385,8 +386,13 @@ where
         }
     }
 
-    pub(crate) fn poll(&mut self, t: u64) -> smoltcp::Result<crate::Activity> {
-        let t = smoltcp::time::Instant::from_millis(t as i64);
+    pub(crate) fn poll(
+        &mut self,
+        t: Instant,
+    ) -> smoltcp::Result<crate::Activity> {
+        let t = smoltcp::time::Instant::from_millis(
+            t.since_boot().as_millis() as i64
+        );
         // Do not be tempted to use `Iterator::any` here, it short circuits and
         // we really do want to poll all of them.
         let mut ip = false;
@@ -5,6 +5,7 @@
 #![no_std]
 #![no_main]
 
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 task_slot!(USER_LEDS, user_leds);
@@ -12,7 +13,7 @@ task_slot!(USER_LEDS, user_leds);
 #[export_name = "main"]
 pub fn main() -> ! {
     const TIMER_NOTIFICATION: u32 = 1;
-    const INTERVAL: u64 = 500;
+    const INTERVAL: Duration = Duration::from_millis(500);
 
     let mut response: u32 = 0;
 
@@ -20,7 +21,7 @@ pub fn main() -> ! {
 
     let mut current = 0;
     let mut msg = [0; 16];
-    let mut dl = INTERVAL;
+    let mut dl = Instant::ZERO + INTERVAL;
     sys_set_timer(Some(dl), TIMER_NOTIFICATION);
     loop {
         let msginfo = sys_recv_open(&mut msg, TIMER_NOTIFICATION);
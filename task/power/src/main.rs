@@ -18,6 +18,7 @@ use drv_i2c_devices::mwocp68::*;
 use drv_i2c_devices::raa229618::*;
 use drv_i2c_devices::tps546b24a::*;
 use task_sensor_api as sensor_api;
+use userlib::time::Duration;
 use userlib::units::*;
 use userlib::*;
 
@@ -387,7 +388,7 @@ fn main() -> ! {
     let devices = claim_devices(i2c_task);
 
     loop {
-        hl::sleep_for(1000);
+        hl::sleep_for(Duration::from_millis(1000));
 
         let state = get_state();
 
@@ -15,6 +15,7 @@ use userlib::*;
 static TZ_TABLE: SecureTable = SecureTable {
     magic: TABLE_MAGIC,
     write_to_flash: Some(write_to_flash),
+    confirm_boot: Some(confirm_boot),
 };
 
 #[export_name = "main"]
@@ -50,3 +51,20 @@ pub unsafe extern "C" fn write_to_flash(
         options(noreturn)
     );
 }
+
+#[naked]
+#[no_mangle]
+#[link_section = ".nsc"]
+pub unsafe extern "C" fn confirm_boot() -> HypoStatus {
+    // See write_to_flash above
+    core::arch::asm!(
+        "
+        sg
+        push {{lr}}
+        bl __confirm_boot
+        pop {{lr}}
+        bxns lr
+        ",
+        options(noreturn)
+    );
+}
@@ -10,6 +10,7 @@
 use drv_i2c_devices::mwocp68::{Error as Mwocp68Error, Mwocp68};
 use ringbuf::*;
 use task_sensor_api::{Sensor, SensorError, SensorId};
+use userlib::time::Duration;
 use userlib::*;
 
 task_slot!(I2C, i2c_driver);
@@ -120,7 +121,7 @@ ringbuf!(Trace, 32, Trace::None);
 
 ////////////////////////////////////////////////////////////////////////////////
 
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: Duration = Duration::from_millis(1000);
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -9,6 +9,7 @@
 
 use idol_runtime::{NotificationHandler, RequestError};
 use task_sensor_api::{NoData, Reading, SensorError, SensorId};
+use userlib::time::{Duration, Instant};
 use userlib::*;
 
 // This is only included to determine the number of sensors
@@ -19,11 +20,11 @@ use sensors::NUM_SENSORS;
 
 struct ServerImpl {
     data: [Reading; NUM_SENSORS],
-    deadline: u64,
+    deadline: Instant,
 }
 
 const TIMER_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: Duration = Duration::from_millis(1000);
 
 impl idl::InOrderSensorImpl for ServerImpl {
     fn get(
@@ -93,7 +94,7 @@ impl NotificationHandler for ServerImpl {
 
 #[export_name = "main"]
 fn main() -> ! {
-    let deadline = sys_get_timer().now;
+    let deadline = Instant::now();
 
     //
     // This will put our timer in the past, and should immediately kick us.
@@ -32,6 +32,7 @@ use idol_runtime::{NotificationHandler, RequestError};
 use ringbuf::*;
 use task_sensor_api::{Sensor as SensorApi, SensorError, SensorId};
 use task_thermal_api::{ThermalAutoState, ThermalError, ThermalMode};
+use userlib::time::{Duration, Instant};
 use userlib::units::PWMDuty;
 use userlib::*;
 
@@ -71,11 +72,11 @@ ringbuf!(Trace, 32, Trace::None);
 struct ServerImpl<'a> {
     mode: ThermalMode,
     control: ThermalControl<'a>,
-    deadline: u64,
+    deadline: Instant,
 }
 
 const TIMER_MASK: u32 = 1 << 0;
-const TIMER_INTERVAL: u64 = 1000;
+const TIMER_INTERVAL: Duration = Duration::from_millis(1000);
 
 impl<'a> ServerImpl<'a> {
     /// Configures the control loop to run in manual mode, loading the given
@@ -241,8 +242,9 @@ impl<'a> NotificationHandler for ServerImpl<'a> {
     }
 
     fn handle_notification(&mut self, _bits: u32) {
-        let now = sys_get_timer().now;
+        let now = Instant::now();
         if now >= self.deadline {
+            let now_ms = now.since_boot().as_millis();
             match self.mode {
                 ThermalMode::Auto => {
                     // The thermal loop handles most failures, but will return
@@ -251,13 +253,13 @@ impl<'a> NotificationHandler for ServerImpl<'a> {
                     //
                     // (if things actually overheat, `run_control` will cut
                     //  power to the system)
-                    if let Err(e) = self.control.run_control(now) {
+                    if let Err(e) = self.control.run_control(now_ms) {
                         ringbuf_entry!(Trace::ControlError(e));
                     }
                 }
                 ThermalMode::Manual => {
                     // Read sensors and post them to the `sensors` task
-                    self.control.read_sensors(now);
+                    self.control.read_sensors(now_ms);
                 }
                 ThermalMode::Off => {
                     panic!("Mode must not be 'Off' when server is running")
@@ -282,7 +284,7 @@ fn main() -> ! {
     let control = ThermalControl::new(&bsp, i2c_task, sensor_api);
 
     // This will put our timer in the past, and should immediately kick us.
-    let deadline = sys_get_timer().now;
+    let deadline = Instant::now();
     sys_set_timer(Some(deadline), TIMER_MASK);
 
     let mut server = ServerImpl {
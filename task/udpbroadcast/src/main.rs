@@ -6,6 +6,7 @@
 #![no_main]
 
 use task_net_api::*;
+use userlib::time::Duration;
 use userlib::*;
 use zerocopy::AsBytes;
 
@@ -46,7 +47,7 @@ fn main() -> ! {
             vid: vid_iter.next().unwrap(),
         };
 
-        hl::sleep_for(500);
+        hl::sleep_for(Duration::from_millis(500));
         match net.send_packet(SOCKET, meta, &out[..]) {
             Ok(()) => UDP_BROADCAST_COUNT
                 .fetch_add(1, core::sync::atomic::Ordering::Relaxed),
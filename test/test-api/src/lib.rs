@@ -36,6 +36,12 @@ pub enum AssistOp {
     RefreshTaskIdOffByOne = 21,
     RefreshTaskIdOffByMany = 22,
     ReadNotifications = 23,
+    PostForbidden = 24,
+    ReadStackUsage = 25,
+    ReadPanicMessage = 26,
+    SlowReply = 27,
+    ReadShared = 28,
+    WriteShared = 29,
 }
 
 /// Operations that are performed by the test-suite
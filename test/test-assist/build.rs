@@ -4,5 +4,9 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
+
+    #[cfg(feature = "shared-region")]
+    build_util::generate_shared_regions()?;
+
     Ok(())
 }
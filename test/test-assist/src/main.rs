@@ -7,13 +7,20 @@
 #![no_std]
 #![no_main]
 
+#[cfg(target_os = "none")]
 use core::arch::asm;
 use hubris_num_tasks::NUM_TASKS;
 use test_api::*;
 use userlib::*;
 use zerocopy::AsBytes;
 
+#[cfg(feature = "shared-region")]
+mod shared_regions {
+    include!(concat!(env!("OUT_DIR"), "/shared_regions.rs"));
+}
+
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn badread(arg: u32) {
     unsafe {
         (arg as *const u8).read_volatile();
@@ -25,12 +32,14 @@ fn panic(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn stackblow(_arg: u32) {
     let c = [0xdeu8; 8192];
     panic!("val is {}", c[2000]);
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn execdata(_arg: u32) {
     unsafe {
         let c = [0x4770u16]; // bx lr
@@ -45,9 +54,21 @@ fn execdata(_arg: u32) {
     }
 }
 
+#[inline(never)]
+#[cfg(all(target_os = "none", feature = "shared-region"))]
+fn writeshared(arg: u32) {
+    // We can only read the region, so this should fault.
+    let region = shared_regions::test_shared();
+    unsafe {
+        region.as_ptr().add(arg as usize).write_volatile(0);
+    }
+}
+
+#[cfg(target_os = "none")]
 static BXLR: [u16; 1] = [0x4770u16];
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn illop(_arg: u32) {
     unsafe {
         // This should attempt to execute with the Thumb bit clear, so
@@ -58,6 +79,7 @@ fn illop(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn badexec(arg: u32) {
     unsafe {
         let val: u32 = arg | 1;
@@ -67,6 +89,7 @@ fn badexec(arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn textoob(_arg: u32) {
     unsafe {
         // fly off the end of our text -- which will either induce
@@ -82,6 +105,7 @@ fn textoob(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn stackoob(_arg: u32) {
     let c = [0xdeu8; 16];
 
@@ -97,6 +121,7 @@ fn stackoob(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn busfault(_arg: u32) {
     unsafe {
         // unprivileged software reading CSFR is a bus error
@@ -105,6 +130,7 @@ fn busfault(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(target_os = "none")]
 fn illinst(_arg: u32) {
     unsafe {
         // an illegal instruction
@@ -112,6 +138,16 @@ fn illinst(_arg: u32) {
     }
 }
 
+#[inline(never)]
+fn postforbidden(arg: u32) {
+    // Our app.toml only lets us talk back to the suite, so posting to anyone
+    // else (even no bits at all) should get us faulted.
+    sys_post(
+        TaskId::for_index_and_gen(arg as usize, Generation::default()),
+        0,
+    );
+}
+
 #[inline(never)]
 #[cfg(any(armv7m, armv8m))]
 fn divzero(_arg: u32) {
@@ -142,8 +178,8 @@ fn eat_some_pi(highregs: bool) {
     }
 }
 
-#[export_name = "main"]
-fn main() -> ! {
+#[cfg_attr(target_os = "none", export_name = "main")]
+pub fn main() -> ! {
     sys_log!("assistant starting");
     let mut buffer = [0; 4];
     let mut last_reply = 0u32;
@@ -151,19 +187,33 @@ fn main() -> ! {
     let mut borrow_buffer = [0u8; 16];
     let mut posted_bits = 0;
 
+    // Under the simulator, only the faults that the kernel itself detects are
+    // available; the rest rely on the hardware catching the task in the act.
     let fatalops = [
+        #[cfg(target_os = "none")]
         (AssistOp::BadMemory, badread as fn(u32)),
-        (AssistOp::Panic, panic),
+        (AssistOp::Panic, panic as fn(u32)),
         #[cfg(any(armv7m, armv8m))]
         (AssistOp::DivZero, divzero),
+        #[cfg(target_os = "none")]
         (AssistOp::StackOverflow, stackblow),
+        #[cfg(target_os = "none")]
         (AssistOp::ExecData, execdata),
+        #[cfg(target_os = "none")]
         (AssistOp::IllegalOperation, illop),
+        #[cfg(target_os = "none")]
         (AssistOp::BadExec, badexec),
+        #[cfg(target_os = "none")]
         (AssistOp::TextOutOfBounds, textoob),
+        #[cfg(target_os = "none")]
         (AssistOp::StackOutOfBounds, stackoob),
+        #[cfg(target_os = "none")]
         (AssistOp::BusError, busfault),
+        #[cfg(target_os = "none")]
         (AssistOp::IllegalInstruction, illinst),
+        (AssistOp::PostForbidden, postforbidden),
+        #[cfg(all(target_os = "none", feature = "shared-region"))]
+        (AssistOp::WriteShared, writeshared),
     ];
 
     const ALL_NOTIFICATIONS: u32 = !0;
@@ -205,6 +255,27 @@ fn main() -> ! {
                     AssistOp::LastReply => {
                         caller.reply(last_reply);
                     }
+                    AssistOp::SlowReply => {
+                        // Hold on to the message for the given number of ticks
+                        // before replying.
+                        hl::sleep_for(time::Duration::from_ticks(
+                            (*msg).into(),
+                        ));
+                        caller.reply(!*msg);
+                    }
+                    #[cfg(feature = "shared-region")]
+                    AssistOp::ReadShared => {
+                        // Read the word at the given offset into the region.
+                        let region = shared_regions::test_shared();
+                        let offset = *msg as usize;
+                        let mut word = [0; 4];
+                        // Safety: the suite doesn't write to the region while
+                        // it's waiting for us to reply.
+                        word.copy_from_slice(
+                            &unsafe { region.as_slice() }[offset..offset + 4],
+                        );
+                        caller.reply(u32::from_le_bytes(word));
+                    }
                     AssistOp::Store => {
                         caller.reply(stored_value);
                         stored_value = *msg;
@@ -213,6 +284,9 @@ fn main() -> ! {
                         // Immediately resume the caller...
                         let task_id = caller.task_id();
                         caller.reply(*msg);
+                        // Lent from the stack, rather than a static, so that
+                        // the simulator can address it.
+                        let hello = *b"hello";
                         // ...and then send them a message back, recording any
                         // reply as last_reply
                         sys_send(
@@ -224,7 +298,7 @@ fn main() -> ! {
                                 // Lease 0 is writable.
                                 Lease::from(&mut borrow_buffer[..]),
                                 // Lease 1 is not.
-                                Lease::from(&b"hello"[..]),
+                                Lease::from(&hello[..]),
                             ],
                         );
                         // Ignore the result.
@@ -258,6 +332,17 @@ fn main() -> ! {
                         let _ = kipc::restart_task(*msg as usize, true);
                     }
 
+                    AssistOp::ReadStackUsage => {
+                        caller.reply(0);
+                        let _ = kipc::read_stack_usage(*msg as usize);
+                    }
+
+                    AssistOp::ReadPanicMessage => {
+                        caller.reply(0);
+                        let _ =
+                            kipc::read_panic_message(*msg as usize, &mut []);
+                    }
+
                     AssistOp::RefreshTaskIdOffByOne => {
                         caller.reply(0);
                         let _ = sys_refresh_task_id(TaskId::for_index_and_gen(
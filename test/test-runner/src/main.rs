@@ -61,9 +61,10 @@
 //!
 //! # Output
 //!
-//! Output is produced on ITM stimulus port 8. Output is in a line-oriented
-//! human-readable format modeled after report formats like TAP, but avoiding
-//! some issues.
+//! Output is produced on ITM stimulus port 8, or by semihosting on ARMv6-M
+//! (which lacks ITM) and when the `semihosting` feature is enabled (as it is
+//! under emulation), or on standard output under the kernel simulator. Output is in a line-oriented human-readable format modeled
+//! after report formats like TAP, but avoiding some issues.
 //!
 //! A test report consists of the following lines:
 //!
@@ -93,7 +94,17 @@ use zerocopy::AsBytes;
 use armv6m_atomic_hack::*;
 
 cfg_if::cfg_if! {
-    if #[cfg(armv6m)] {
+    if #[cfg(not(target_os = "none"))] {
+        /// Helper macro for producing output under the kernel simulator.
+        macro_rules! test_output {
+            ($s:expr) => {
+                userlib::sim_println(format_args!($s));
+            };
+            ($s:expr, $($tt:tt)*) => {
+                userlib::sim_println(format_args!($s, $($tt)*));
+            };
+        }
+    } else if #[cfg(any(armv6m, feature = "semihosting"))] {
         /// Helper macro for producing output by semihosting :-(
         macro_rules! test_output {
             ($s:expr) => {
@@ -241,8 +252,8 @@ fn test_run() {
     }
 }
 
-#[export_name = "main"]
-fn main() -> ! {
+#[cfg_attr(target_os = "none", export_name = "main")]
+pub fn main() -> ! {
     loop {
         test_run();
         TEST_RUNS.fetch_add(1, Ordering::SeqCst);
@@ -293,7 +304,7 @@ fn get_case_count() -> usize {
     let op = SuiteOp::GetCaseCount as u16;
     let (rc, len) = sys_send(tid, op, &[], response.as_bytes_mut(), &[]);
     assert_eq!(rc, 0);
-    assert_eq!(len, 4);
+    assert_eq!(len, core::mem::size_of_val(&response));
     response
 }
 
@@ -11,6 +11,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(feature = "i2c-devices")]
     build_i2c::codegen(build_i2c::Disposition::Devices)?;
 
+    #[cfg(feature = "shared-region")]
+    build_util::generate_shared_regions()?;
+
     generate_consts()?;
     Ok(())
 }
@@ -24,6 +24,7 @@
 
 use hubris_num_tasks::NUM_TASKS;
 use test_api::*;
+use userlib::time::{Duration, Instant};
 use userlib::*;
 use zerocopy::AsBytes;
 
@@ -49,8 +50,15 @@ task_config::task_config! {
 }
 
 // Actual list of functions with their names.
+//
+// Cases gated on `target_os = "none"` don't run under the kernel simulator:
+// they either need the hardware to catch a faulting task, or talk to the Idol
+// server, which the simulated image doesn't include.
 test_cases! {
     test_send,
+    test_send_with_deadline,
+    test_send_with_deadline_past,
+    test_send_with_deadline_late_reply,
     test_recv_reply,
     test_recv_reply_fault,
     #[cfg(any(armv7m, armv8m))]
@@ -59,14 +67,23 @@ test_cases! {
     test_floating_point_highregs,
     #[cfg(any(armv7m, armv8m))]
     test_floating_point_fault,
+    #[cfg(target_os = "none")]
     test_fault_badmem,
+    #[cfg(target_os = "none")]
     test_fault_stackoverflow,
+    #[cfg(target_os = "none")]
     test_fault_execdata,
+    #[cfg(target_os = "none")]
     test_fault_illop,
+    #[cfg(target_os = "none")]
     test_fault_nullexec,
+    #[cfg(target_os = "none")]
     test_fault_textoob,
+    #[cfg(target_os = "none")]
     test_fault_stackoob,
+    #[cfg(target_os = "none")]
     test_fault_buserror,
+    #[cfg(target_os = "none")]
     test_fault_illinst,
     #[cfg(any(armv7m, armv8m))]
     test_fault_divzero,
@@ -74,6 +91,10 @@ test_cases! {
     test_fault_badstatus,
     test_fault_maxrestart,
     test_fault_badrestart,
+    test_fault_maxstackusage,
+    test_fault_badstackusage,
+    test_fault_maxpanicmessage,
+    test_fault_badpanicmessage,
     test_fault_maxinjection,
     test_fault_badinjection,
     test_fault_superinjection,
@@ -91,18 +112,38 @@ test_cases! {
     test_timer_notify_past,
     test_task_config,
     test_task_status,
+    test_task_stats,
+    test_stack_usage,
+    test_task_info,
+    #[cfg(feature = "klog")]
+    test_kernel_log,
+    #[cfg(feature = "fault-capture")]
+    test_panic_message,
+    #[cfg(all(target_os = "none", feature = "fault-capture"))]
+    test_fault_registers,
     test_task_fault_injection,
+    #[cfg(feature = "shared-region")]
+    test_shared_region,
     test_refresh_task_id_basic,
     test_refresh_task_id_off_by_one,
     test_refresh_task_id_off_by_many,
     test_post,
+    test_post_forbidden,
+    #[cfg(target_os = "none")]
     test_idol_basic,
+    #[cfg(target_os = "none")]
     test_idol_bool_arg,
+    #[cfg(target_os = "none")]
     test_idol_bool_ret,
+    #[cfg(target_os = "none")]
     test_idol_bool_xor,
+    #[cfg(target_os = "none")]
     test_idol_err_ret,
+    #[cfg(target_os = "none")]
     test_idol_ssmarshal,
+    #[cfg(target_os = "none")]
     test_idol_ssmarshal_multiarg,
+    #[cfg(target_os = "none")]
     test_idol_ssmarshal_multiarg_enum,
     #[cfg(feature = "fru-id-eeprom")]
     at24csw080::test_at24csw080,
@@ -127,6 +168,109 @@ fn test_send() {
     assert_eq!(response, !0xDEADBEEF);
 }
 
+/// Tests that a send with a deadline behaves like a plain send if the reply
+/// comes in time.
+fn test_send_with_deadline() {
+    let assist = assist_task_id();
+    let challenge = 0xDEADBEEF_u32;
+    let mut response = 0_u32;
+    let (rc, len) = sys_send_with_deadline(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        Instant::now() + Duration::from_ticks(1000),
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !0xDEADBEEF);
+}
+
+/// Tests that a send whose deadline has already passed times out without
+/// being delivered.
+fn test_send_with_deadline_past() {
+    let assist = assist_task_id();
+    let challenge = 0xDEADBEEF_u32;
+    let mut response = 0_u32;
+
+    // Make sure the assistant is storing zero to begin with.
+    let (rc, _) = sys_send(
+        assist,
+        AssistOp::Store as u16,
+        &0_u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+
+    let (rc, len) = sys_send_with_deadline(
+        assist,
+        AssistOp::Store as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        Instant::now(),
+    );
+    assert_eq!(rc, TIMED_OUT);
+    assert_eq!(len, 0);
+
+    // The assistant never saw the message, so it's still storing zero.
+    let (rc, _) = sys_send(
+        assist,
+        AssistOp::Store as u16,
+        &0_u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(response, 0);
+}
+
+/// Tests that a send with a deadline gives up if the server has received the
+/// message but doesn't reply in time, and that the server's late reply is
+/// dropped rather than delivered to our next send.
+fn test_send_with_deadline_late_reply() {
+    let assist = assist_task_id();
+    let delay = 100_u32;
+    let mut response = 0_u32;
+
+    let start = Instant::now();
+    let (rc, len) = sys_send_with_deadline(
+        assist,
+        AssistOp::SlowReply as u16,
+        &delay.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        start + Duration::from_ticks(2),
+    );
+    assert_eq!(rc, TIMED_OUT);
+    assert_eq!(len, 0);
+    assert_eq!(response, 0);
+    // We stopped waiting at the deadline, not when the reply came.
+    assert!(Instant::now() < start + Duration::from_ticks(delay.into()));
+
+    // The assistant is still holding the message. This send waits until it
+    // has replied to the old one and come back around to RECV, and gets the
+    // reply to this message, not the old one.
+    let challenge = 0xDEADBEEF_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !challenge);
+    assert!(Instant::now() >= start + Duration::from_ticks(delay.into()));
+
+    // Replying to a sender that had given up isn't the server's fault.
+    let status = kipc::read_task_status(ASSIST.get_task_index().into());
+    assert_eq!(status, TaskState::Healthy(SchedState::InRecv(None)));
+}
+
 /// Tests that we can receive a message from the assistant and reply.
 fn test_recv_reply() {
     let assist = assist_task_id();
@@ -255,6 +399,7 @@ fn test_fault(op: AssistOp, arg: u32) -> FaultInfo {
     }
 }
 
+#[cfg(target_os = "none")]
 cfg_if::cfg_if! {
     if #[cfg(armv6m)] {
         macro_rules! assert_fault_eq {
@@ -273,6 +418,7 @@ cfg_if::cfg_if! {
 
 /// Tests a memory fault, which ensures that the address reporting is correct,
 /// and that the MPU is on.
+#[cfg(target_os = "none")]
 fn test_fault_badmem() {
     let bad_address = BAD_ADDRESS;
     let fault = test_fault(AssistOp::BadMemory, bad_address);
@@ -286,6 +432,7 @@ fn test_fault_badmem() {
     );
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_stackoverflow() {
     let fault = test_fault(AssistOp::StackOverflow, 0);
 
@@ -299,10 +446,12 @@ fn test_fault_stackoverflow() {
     }
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_execdata() {
     assert_fault_eq!(test_fault(AssistOp::ExecData, 0), FaultInfo::IllegalText);
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_illop() {
     let fault = test_fault(AssistOp::IllegalOperation, 0);
 
@@ -314,6 +463,7 @@ fn test_fault_illop() {
     }
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_nullexec() {
     assert_fault_eq!(
         test_fault(AssistOp::BadExec, BAD_ADDRESS),
@@ -321,6 +471,7 @@ fn test_fault_nullexec() {
     );
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_textoob() {
     let fault = test_fault(AssistOp::TextOutOfBounds, BAD_ADDRESS);
 
@@ -334,6 +485,7 @@ fn test_fault_textoob() {
     }
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_stackoob() {
     let fault = test_fault(AssistOp::StackOutOfBounds, 0);
     match fault {
@@ -346,6 +498,7 @@ fn test_fault_stackoob() {
     }
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_buserror() {
     let fault = test_fault(AssistOp::BusError, 0);
 
@@ -359,6 +512,7 @@ fn test_fault_buserror() {
     }
 }
 
+#[cfg(target_os = "none")]
 fn test_fault_illinst() {
     assert_fault_eq!(
         test_fault(AssistOp::IllegalInstruction, 0),
@@ -376,7 +530,9 @@ fn test_fault_badtaskop(op: AssistOp, id: usize) {
     match op {
         AssistOp::ReadTaskStatus
         | AssistOp::FaultTask
-        | AssistOp::RestartTask => {}
+        | AssistOp::RestartTask
+        | AssistOp::ReadStackUsage
+        | AssistOp::ReadPanicMessage => {}
         _ => {
             panic!("illegal task operation");
         }
@@ -404,6 +560,22 @@ fn test_fault_badrestart() {
     test_fault_badtaskop(AssistOp::RestartTask, NUM_TASKS);
 }
 
+fn test_fault_maxstackusage() {
+    test_fault_badtaskop(AssistOp::ReadStackUsage, usize::MAX);
+}
+
+fn test_fault_badstackusage() {
+    test_fault_badtaskop(AssistOp::ReadStackUsage, NUM_TASKS);
+}
+
+fn test_fault_maxpanicmessage() {
+    test_fault_badtaskop(AssistOp::ReadPanicMessage, usize::MAX);
+}
+
+fn test_fault_badpanicmessage() {
+    test_fault_badtaskop(AssistOp::ReadPanicMessage, NUM_TASKS);
+}
+
 fn test_fault_maxinjection() {
     test_fault_badtaskop(AssistOp::FaultTask, usize::MAX);
 }
@@ -455,12 +627,14 @@ fn test_panic() {
     restart_assistant();
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_basic() {
     let idol = idol_handle();
     let r = idol.increment(1);
     assert_eq!(r, Ok(2));
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_bool_arg() {
     let idol = idol_handle();
     let r = idol.maybe_increment(5, true);
@@ -470,6 +644,7 @@ fn test_idol_bool_arg() {
     assert_eq!(r, Ok(5));
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_bool_ret() {
     let idol = idol_handle();
     let r = idol.bool_not(true);
@@ -479,6 +654,7 @@ fn test_idol_bool_ret() {
     assert_eq!(r, Ok(true));
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_bool_xor() {
     let idol = idol_handle();
     let r = idol.bool_xor(true, true);
@@ -494,6 +670,7 @@ fn test_idol_bool_xor() {
     assert_eq!(r, Ok(false));
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_err_ret() {
     let idol = idol_handle();
     let r = idol.return_err_if_true(false);
@@ -502,6 +679,7 @@ fn test_idol_err_ret() {
     assert_eq!(r, Err(test_idol_api::IdolTestError::YouAskedForThis));
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_ssmarshal() {
     let idol = idol_handle();
     let r = idol
@@ -531,6 +709,7 @@ fn test_idol_ssmarshal() {
     assert!(r.f == 1.0);
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_ssmarshal_multiarg() {
     use test_idol_api::*;
     let idol = idol_handle();
@@ -551,6 +730,7 @@ fn test_idol_ssmarshal_multiarg() {
     assert_eq!(r, 12);
 }
 
+#[cfg(target_os = "none")]
 fn test_idol_ssmarshal_multiarg_enum() {
     use test_idol_api::*;
     let idol = idol_handle();
@@ -1145,9 +1325,9 @@ fn test_timer_advance() {
 fn test_timer_notify() {
     const ARBITRARY_NOTIFICATION: u32 = 1 << 16;
 
-    let start_time = sys_get_timer().now;
+    let start_time = Instant::now();
     // We'll arbitrarily set our deadline 2 ticks in the future.
-    let deadline = start_time + 2;
+    let deadline = start_time + Duration::from_ticks(2);
     sys_set_timer(Some(deadline), ARBITRARY_NOTIFICATION);
 
     let rm = sys_recv_closed(&mut [], ARBITRARY_NOTIFICATION, TaskId::KERNEL)
@@ -1161,14 +1341,14 @@ fn test_timer_notify() {
 
     // In the interest of not making this test performance-sensitive, we merely
     // verify that the timer is at _or beyond_ our deadline.
-    assert!(sys_get_timer().now >= deadline);
+    assert!(Instant::now() >= deadline);
 }
 
 /// Tests that we can set a timer in the past and get immediate notification.
 fn test_timer_notify_past() {
     const ARBITRARY_NOTIFICATION: u32 = 1 << 16;
 
-    let start_time = sys_get_timer().now;
+    let start_time = Instant::now();
     let deadline = start_time;
     sys_set_timer(Some(deadline), ARBITRARY_NOTIFICATION);
 
@@ -1292,6 +1472,246 @@ fn test_task_status() {
     }
 }
 
+/// Tests that the kernel keeps per-task execution counters.
+fn test_task_stats() {
+    let me = SUITE.get_task_index().into();
+    let assist = ASSIST.get_task_index().into();
+
+    // Reading our own counters is a syscall, so it shows up in them.
+    let before = kipc::read_task_stats(me);
+    let after = kipc::read_task_stats(me);
+    assert!(after.syscalls > before.syscalls);
+
+    // A round trip through the assistant switches to it and back, and has it
+    // REPLY and RECV again.
+    let before = kipc::read_task_stats(assist);
+    test_send();
+    let after = kipc::read_task_stats(assist);
+    assert!(after.switches > before.switches);
+    assert!(after.syscalls >= before.syscalls + 2);
+
+    // Spinning on the CPU gets us charged for timer ticks. This fails by
+    // hanging, like `test_timer_advance`.
+    let start = kipc::read_task_stats(me).ticks;
+    while kipc::read_task_stats(me).ticks == start {}
+}
+
+/// Tests that the kernel reports how deep a task's stack has been.
+fn test_stack_usage() {
+    let me = SUITE.get_task_index().into();
+
+    let before = kipc::read_stack_usage(me);
+    assert!(before.size > 0);
+    assert!(before.max_depth > 0);
+    assert!(before.max_depth <= before.size);
+
+    // Writing a buffer on the stack takes us at least that deep, and the
+    // high-water mark never comes back down while we're running.
+    dirty_stack();
+    let after = kipc::read_stack_usage(me);
+    assert_eq!(after.size, before.size);
+    assert!(after.max_depth >= before.max_depth);
+    assert!(after.max_depth >= 256);
+    assert!(after.max_depth <= after.size);
+}
+
+/// Tests that the kernel describes every task, and that reading past the end of
+/// the task table finds nothing rather than faulting.
+fn test_task_info() {
+    for i in 0..NUM_TASKS {
+        assert!(kipc::read_task_info(i).is_some());
+    }
+    assert_eq!(kipc::read_task_info(NUM_TASKS), None);
+    assert_eq!(kipc::read_task_info(usize::MAX), None);
+
+    let suite = kipc::read_task_info(SUITE.get_task_index().into()).unwrap();
+    let assist = kipc::read_task_info(ASSIST.get_task_index().into()).unwrap();
+    assert_eq!(suite.name(), b"suite");
+    assert_eq!(assist.name(), b"assist");
+    // The assistant is more important than we are, so that it's ready for
+    // each message we send it.
+    assert!(assist.priority < suite.priority);
+    // Both are started at boot.
+    assert_eq!(suite.flags & 1, 1);
+    assert_eq!(assist.flags & 1, 1);
+
+    // Regions come first, and the unused slots after them are empty.
+    let (used, unused) = suite.regions.split_at(suite.region_count.into());
+    assert!(!used.is_empty());
+    for region in used {
+        assert!(region.size > 0);
+        assert_ne!(region.attributes, 0);
+    }
+    for region in unused {
+        assert_eq!(*region, TaskRegion::default());
+    }
+
+    // One of those regions holds our stack.
+    let here = &suite as *const _ as u32;
+    assert!(used
+        .iter()
+        .any(|r| r.base <= here && here - r.base < r.size));
+}
+
+/// Writes 256 bytes of stack, in a way the compiler can't skip.
+#[inline(never)]
+fn dirty_stack() {
+    let mut buf = [0u8; 256];
+    for b in &mut buf {
+        // Safety: `b` is a valid reference to a byte.
+        unsafe { core::ptr::write_volatile(b, 0x5a) };
+    }
+}
+
+/// Tests that the kernel logs a task's panic and restart, in order.
+#[cfg(feature = "klog")]
+fn test_kernel_log() {
+    // Skip past whatever earlier tests have logged.
+    let mut next = 0;
+    while let Some(entry) = kipc::read_kernel_log(next) {
+        next = entry.seq + 1;
+    }
+
+    // This has the assistant panic, and then restarts it.
+    test_panic();
+
+    let assist = ASSIST.get_task_index();
+    let panic = kipc::read_kernel_log(next).unwrap();
+    assert_eq!(panic.seq, next);
+    match panic.event {
+        KernelEvent::Panic { task, message } => {
+            assert_eq!(task, assist);
+            // Depending on the platform, this may be prefixed with where
+            // the panic happened.
+            let expected = b"wow this blew up";
+            assert!(message.windows(expected.len()).any(|w| w == expected));
+        }
+        e => panic!("expected panic, got {:?}", e),
+    }
+
+    let restart = kipc::read_kernel_log(next + 1).unwrap();
+    assert_eq!(restart.seq, next + 1);
+    assert!(restart.timestamp >= panic.timestamp);
+    match restart.event {
+        KernelEvent::Restart { task, by, .. } => {
+            assert_eq!(task, assist);
+            assert_eq!(by, SUITE.get_task_index());
+        }
+        e => panic!("expected restart, got {:?}", e),
+    }
+
+    assert_eq!(kipc::read_kernel_log(next + 2), None);
+}
+
+/// Tests that the kernel keeps the start of a task's most recent panic message,
+/// until it faults some other way.
+#[cfg(feature = "fault-capture")]
+fn test_panic_message() {
+    let assist = ASSIST.get_task_index().into();
+
+    // We've never panicked.
+    let mut buf = [0; PANIC_MESSAGE_BYTES];
+    assert_eq!(
+        kipc::read_panic_message(SUITE.get_task_index().into(), &mut buf),
+        0
+    );
+
+    // This has the assistant panic, and then restarts it; the message outlives
+    // the restart.
+    test_panic();
+    let len = kipc::read_panic_message(assist, &mut buf);
+    // Depending on the platform, this may be prefixed with where the panic
+    // happened.
+    let expected = b"wow this blew up";
+    assert!(buf[..len].windows(expected.len()).any(|w| w == expected));
+
+    // A short buffer gets a prefix.
+    let mut short = [0; 4];
+    assert_eq!(kipc::read_panic_message(assist, &mut short), 4);
+    assert_eq!(short, buf[..4]);
+
+    // Any other fault means the message no longer explains anything.
+    kipc::fault_task(assist);
+    assert_eq!(kipc::read_panic_message(assist, &mut buf), 0);
+    restart_assistant();
+}
+
+/// Tests that the kernel records where a task was when it took a fault, and
+/// nothing for a fault injected into it.
+#[cfg(all(target_os = "none", feature = "fault-capture"))]
+fn test_fault_registers() {
+    let assist = ASSIST.get_task_index().into();
+    let info = kipc::read_task_info(assist).unwrap();
+    let (used, _) = info.regions.split_at(info.region_count.into());
+    let owned = |addr: u32| {
+        used.iter()
+            .any(|r| r.base <= addr && addr - r.base < r.size)
+    };
+
+    // Both the faulting instruction and the stack pointer are the assistant's
+    // own.
+    test_fault(AssistOp::IllegalOperation, 0);
+    let regs = kipc::read_fault_registers(assist).unwrap();
+    assert!(owned(regs.sp));
+    assert!(owned(regs.stacked.unwrap().pc));
+    restart_assistant();
+
+    kipc::fault_task(assist);
+    assert_eq!(kipc::read_fault_registers(assist), None);
+    restart_assistant();
+}
+
+/// Tests the region of memory we share with the assistant, which can only read
+/// it.
+#[cfg(feature = "shared-region")]
+fn test_shared_region() {
+    let region = shared_regions::test_shared();
+    assert!(region.is_writable());
+    assert_eq!(region.len(), 256);
+
+    // The assistant sees what we write.
+    {
+        // Safety: the assistant only reads the region when we ask it to.
+        let buf = unsafe { region.as_mut_slice() };
+        buf[4..8].copy_from_slice(&0xdead_beef_u32.to_le_bytes());
+    }
+    assert_eq!(read_shared(4), 0xdead_beef);
+
+    // What's in the region outlives the assistant being restarted.
+    restart_assistant();
+    assert_eq!(read_shared(4), 0xdead_beef);
+
+    // But the assistant can't change it.
+    #[cfg(target_os = "none")]
+    {
+        let address = region.as_ptr() as u32 + 8;
+        assert_fault_eq!(
+            test_fault(AssistOp::WriteShared, 8),
+            FaultInfo::MemoryAccess {
+                address: Some(address),
+                source: FaultSource::User,
+            }
+        );
+        restart_assistant();
+    }
+}
+
+/// Asks the assistant for the word at `offset` into the shared region.
+#[cfg(feature = "shared-region")]
+fn read_shared(offset: u32) -> u32 {
+    let mut response = 0u32;
+    let (rc, len) = sys_send(
+        assist_task_id(),
+        AssistOp::ReadShared as u16,
+        offset.as_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    response
+}
+
 fn test_task_fault_injection() {
     // Assistant should be fine
     let status = kipc::read_task_status(ASSIST.get_task_index().into());
@@ -1390,12 +1810,22 @@ fn test_post() {
     assert_eq!(response, ARBITRARY_MASK);
 }
 
+/// Tests that the kernel enforces the app's IPC policy: the assistant may
+/// only talk back to us, so posting to the runner faults it.
+fn test_post_forbidden() {
+    let fault =
+        test_fault(AssistOp::PostForbidden, RUNNER.get_task_index().into());
+
+    assert_eq!(fault, FaultInfo::SyscallUsage(UsageError::IpcNotPermitted));
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 // Frameworky bits follow
 
 // Identity of our "assistant task" that we require in the image.
 task_slot!(ASSIST, assist);
 // Identity of the Idol server that we require in the image
+#[cfg(target_os = "none")]
 task_slot!(IDOL, idol);
 
 // Our own identity
@@ -1407,6 +1837,7 @@ fn assist_task_id() -> TaskId {
     ASSIST.get_task_id()
 }
 
+#[cfg(target_os = "none")]
 fn idol_handle() -> test_idol_api::IdolTest {
     test_idol_api::IdolTest::from(IDOL.get_task_id())
 }
@@ -1429,8 +1860,8 @@ fn read_runner_notifications() -> u32 {
 }
 
 /// Actual entry point.
-#[export_name = "main"]
-fn main() -> ! {
+#[cfg_attr(target_os = "none", export_name = "main")]
+pub fn main() -> ! {
     // Work out the assistant generation. Restart it to ensure it's running
     // before we try talking to it. TODO: this is kind of gross, we need a way
     // to just ask.
@@ -1451,10 +1882,11 @@ fn main() -> ! {
         }
     }
 
-    let mut buffer = [0; 4];
+    // Incoming messages are at most a case index, which we also need aligned.
+    let mut buffer = 0usize;
     loop {
         hl::recv_without_notification(
-            &mut buffer,
+            buffer.as_bytes_mut(),
             |op, msg| -> Result<(), u32> {
                 match op {
                     SuiteOp::GetCaseCount => {
@@ -1495,4 +1927,10 @@ fn main() -> ! {
     }
 }
 
+#[cfg(target_os = "none")]
 include!(concat!(env!("OUT_DIR"), "/consts.rs"));
+
+#[cfg(feature = "shared-region")]
+mod shared_regions {
+    include!(concat!(env!("OUT_DIR"), "/shared_regions.rs"));
+}
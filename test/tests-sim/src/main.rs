@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Simulated test image.
+//!
+//! This runs the kernel test suite on the host, under the kernel simulator
+//! (`kern::arch::sim`), so that it can be run by `cargo test` from this
+//! directory. The tasks are compiled into this program from the same sources
+//! as their hardware builds; the image's configuration is in
+//! `.cargo/config.toml`.
+//!
+//! The process prints the test runner's report on standard output, and keeps
+//! running once the suite is done. `tests/suite.rs` runs it and checks the
+//! report.
+
+#[allow(unused_attributes)] // for the tasks' `#![no_std]` and `#![no_main]`
+#[path = "../../test-runner/src/main.rs"]
+mod runner;
+
+#[allow(unused_attributes)]
+#[path = "../../test-suite/src/main.rs"]
+mod suite;
+
+#[allow(unused_attributes)]
+#[path = "../../test-assist/src/main.rs"]
+mod assist;
+
+/// Task entry points, in the order given in `HUBRIS_TASKS`.
+static ENTRY_POINTS: [fn() -> !; 4] =
+    [runner::main, suite::main, assist::main, idle];
+
+/// Stands in for `task-idle`: on the host, there's no need to spin or sleep
+/// the CPU, so the idle task's thread just gets out of the way.
+fn idle() -> ! {
+    loop {
+        std::thread::park();
+    }
+}
+
+fn main() {
+    kern::arch::set_task_entry_points(&ENTRY_POINTS);
+
+    // The simulator's tick divisor is in microseconds; this matches the
+    // image's `tick_hz`.
+    const TICK_MICROS: u32 = 1000;
+
+    // Safety: we only do this once.
+    unsafe { kern::startup::start_kernel(TICK_MICROS) }
+}
@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runs the simulated test image and checks the runner's report; see
+//! `test/test-runner` for its format.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long the whole suite may take. This is generous, since the timer tests
+/// run in wall-clock time on a possibly busy machine.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Kills the image when dropped: it runs forever once the suite is done, and
+/// we don't want to leave it behind if we panic first.
+struct Image(Child);
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    expected: Vec<String>,
+    passed: Vec<String>,
+    failed: Vec<String>,
+    done: Option<String>,
+}
+
+#[test]
+fn test_suite() {
+    let mut image = Image(
+        Command::new(env!("CARGO_BIN_EXE_tests-sim"))
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("starting simulated image"),
+    );
+
+    // Read lines on another thread, so that a hung image can't hang us.
+    let (tx, rx) = mpsc::channel();
+    let stdout = image.0.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut report = Report::default();
+    let mut current = None;
+    while report.done.is_none() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(left) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                panic!("suite timed out in {current:?}; so far: {report:#?}")
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                panic!("image exited in {current:?}; so far: {report:#?}")
+            }
+        };
+        println!("{line}");
+
+        // Anything else is a task's log output.
+        let (word, rest) = line.split_once(' ').unwrap_or((&line, ""));
+        match word {
+            "case" => report.expected.push(rest.to_string()),
+            "start" => current = Some(rest.to_string()),
+            "finish" => match rest.split_once(' ') {
+                Some(("ok", name)) => report.passed.push(name.to_string()),
+                Some(("FAIL", name)) => report.failed.push(name.to_string()),
+                _ => panic!("bad finish line: {line:?}"),
+            },
+            "done" => report.done = Some(rest.to_string()),
+            _ => {}
+        }
+    }
+
+    assert!(
+        report.failed.is_empty(),
+        "failed cases: {:?}",
+        report.failed
+    );
+    assert_eq!(report.passed, report.expected);
+    assert!(!report.passed.is_empty(), "no cases ran");
+    assert_eq!(report.done.as_deref(), Some("pass"));
+}